@@ -0,0 +1,376 @@
+use source::source_map::Span;
+use std::fmt;
+
+/*
+The abstract syntax of C0 programs as described by the grammar in syntax.txt.
+
+Every node remembers its span. Expressions and identifiers also carry a
+`NodeId`, unique within a compilation, which later passes use as a key when
+they need to attach information (resolved declarations, types) to the tree.
+ */
+pub type NodeId = u32;
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct Ident {
+    pub id: NodeId,
+    pub name: String,
+    pub span: Span,
+}
+
+// <prog> ::= (<gdecl> | <gdefn>)*
+#[derive(Clone, Debug, PartialEq, Default)]
+pub struct Program {
+    pub decls: Vec<GDecl>,
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct GDecl {
+    pub kind: GDeclKind,
+    pub span: Span,
+}
+
+/*
+<gdecl> ::= struct <sid> ;
+          | <tp> <vid> ( [<tp> <vid> (, <tp> <vid>)*] ) ;
+          | #use <liblit> \n | #use <strlit> \n
+<gdefn> ::= struct <sid> { (<tp> <fid> ;)* } ;
+          | <tp> <vid> ( [<tp> <vid> (, <tp> <vid>)*] ) { <stmt>* }
+          | typedef <tp> <aid> ;
+ */
+#[derive(Clone, Debug, PartialEq)]
+pub enum GDeclKind {
+    Use(Use),
+    StructDecl(Ident),
+    StructDef(Ident, Vec<Field>),
+    // A prototype when the body is `None`
+    Function(Function),
+    Typedef(Tp, Ident),
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub enum Use {
+    Library(String),
+    File(String),
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct Field {
+    pub tp: Tp,
+    pub name: Ident,
+    pub span: Span,
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct Function {
+    pub ret: Tp,
+    pub name: Ident,
+    pub params: Vec<Param>,
+    pub specs: Vec<Spec>,
+    pub body: Option<Block>,
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct Param {
+    pub tp: Tp,
+    pub name: Ident,
+    pub span: Span,
+}
+
+// Contracts, written in annotations as `//@requires <exp>;`
+#[derive(Clone, Debug, PartialEq)]
+pub struct Spec {
+    pub kind: SpecKind,
+    pub exp: Exp,
+    pub span: Span,
+}
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Hash)]
+pub enum SpecKind {
+    Requires,
+    Ensures,
+    LoopInvariant,
+    Assert,
+}
+
+impl fmt::Display for SpecKind {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let s = match *self {
+            SpecKind::Requires => "requires",
+            SpecKind::Ensures => "ensures",
+            SpecKind::LoopInvariant => "loop_invariant",
+            SpecKind::Assert => "assert",
+        };
+        write!(f, "{}", s)
+    }
+}
+
+// `{ <stmt>* }`, the span covers both braces
+#[derive(Clone, Debug, PartialEq)]
+pub struct Block {
+    pub stmts: Vec<Stmt>,
+    pub span: Span,
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct Stmt {
+    pub kind: StmtKind,
+    pub span: Span,
+}
+
+/*
+<stmt> ::= <simple> ;
+         | if ( <exp> ) <stmt> [ else <stmt> ]
+         | while ( <exp> ) <stmt>
+         | for ( [<simple>] ; <exp> ; [<simple>] ) <stmt>
+         | return [<exp>] ;
+         | break ;
+         | continue ;
+         | { <stmt>* }
+         | assert ( <exp> ) ;
+         | error ( <exp> ) ;
+Loops carry the `@loop_invariant`s written between their header and body, and
+`@assert` annotations inside a block become `Annotation` statements.
+ */
+#[derive(Clone, Debug, PartialEq)]
+pub enum StmtKind {
+    Simple(Simple),
+    If(Exp, Box<Stmt>, Option<Box<Stmt>>),
+    While(Exp, Vec<Spec>, Box<Stmt>),
+    For(Option<Box<Simple>>, Exp, Option<Box<Simple>>, Vec<Spec>, Box<Stmt>),
+    Return(Option<Exp>),
+    Break,
+    Continue,
+    Block(Block),
+    Assert(Exp),
+    Error(Exp),
+    Annotation(Vec<Spec>),
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct Simple {
+    pub kind: SimpleKind,
+    pub span: Span,
+}
+
+/*
+<simple> ::= <lv> <asnop> <exp>
+           | <lv> ++
+           | <lv> --
+           | <exp>
+           | <tp> <vid> [= <exp>]
+l-values are represented as expressions, the parser checks they have the
+shape required by <lv>.
+ */
+#[derive(Clone, Debug, PartialEq)]
+pub enum SimpleKind {
+    Assign(AsnOp, Exp, Exp),
+    Post(PostOp, Exp),
+    Exp(Exp),
+    Decl(Tp, Ident, Option<Exp>),
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct Tp {
+    pub kind: TpKind,
+    pub span: Span,
+}
+
+// <tp> ::= int | bool | string | char | void
+//        | <tp> * | <tp> [ ] | struct <sid> | <aid>
+#[derive(Clone, Debug, PartialEq)]
+pub enum TpKind {
+    Int,
+    Bool,
+    String,
+    Char,
+    Void,
+    Pointer(Box<Tp>),
+    Array(Box<Tp>),
+    Struct(Ident),
+    Name(Ident),
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct Exp {
+    pub id: NodeId,
+    pub kind: ExpKind,
+    pub span: Span,
+}
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum Radix {
+    Dec,
+    Hex,
+}
+
+/*
+<exp> ::= ( <exp> )
+        | <num> | true | false | NULL
+        | <vid> | <exp> <binop> <exp> | <unop> <exp>
+        | <exp> ? <exp> : <exp>
+        | <vid> ( [<exp> (, <exp>)*] )
+        | <exp> . <fid> | <exp> -> <fid>
+        | <exp> [ <exp> ]
+        | alloc ( <tp> ) | alloc_array ( <tp> , <exp> )
+together with string and character literals, and `\result` and `\length`
+which may only appear in contracts.
+ */
+#[derive(Clone, Debug, PartialEq)]
+pub enum ExpKind {
+    // Literals are stored as the 32 bit value they denote, 2147483648 is -2^31
+    Num(i32, Radix),
+    Bool(bool),
+    Null,
+    Char(char),
+    Str(String),
+    Var(Ident),
+    Unop(UnOp, Box<Exp>),
+    Binop(BinOp, Box<Exp>, Box<Exp>),
+    Ternary(Box<Exp>, Box<Exp>, Box<Exp>),
+    Call(Ident, Vec<Exp>),
+    Field(Box<Exp>, Ident),
+    Arrow(Box<Exp>, Ident),
+    Index(Box<Exp>, Box<Exp>),
+    Alloc(Tp),
+    AllocArray(Tp, Box<Exp>),
+    Result,
+    Length(Box<Exp>),
+}
+
+// <unop> ::= ! | ~ | - | *
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Hash)]
+pub enum UnOp {
+    Not,
+    BitNot,
+    Neg,
+    Deref,
+}
+
+impl fmt::Display for UnOp {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let s = match *self {
+            UnOp::Not => "!",
+            UnOp::BitNot => "~",
+            UnOp::Neg => "-",
+            UnOp::Deref => "*",
+        };
+        write!(f, "{}", s)
+    }
+}
+
+// The binary operators of <binop> other than field access and the conditional
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Hash)]
+pub enum BinOp {
+    Mul,
+    Div,
+    Mod,
+    Add,
+    Sub,
+    Shl,
+    Shr,
+    Lt,
+    Le,
+    Ge,
+    Gt,
+    Eq,
+    Ne,
+    BitAnd,
+    BitXor,
+    BitOr,
+    And,
+    Or,
+}
+
+impl BinOp {
+    /*
+    Binding strength following the C0 reference, higher binds tighter. All
+    binary operators are left associative.
+     */
+    pub fn precedence(self) -> u8 {
+        match self {
+            BinOp::Mul | BinOp::Div | BinOp::Mod => 10,
+            BinOp::Add | BinOp::Sub => 9,
+            BinOp::Shl | BinOp::Shr => 8,
+            BinOp::Lt | BinOp::Le | BinOp::Ge | BinOp::Gt => 7,
+            BinOp::Eq | BinOp::Ne => 6,
+            BinOp::BitAnd => 5,
+            BinOp::BitXor => 4,
+            BinOp::BitOr => 3,
+            BinOp::And => 2,
+            BinOp::Or => 1,
+        }
+    }
+}
+
+impl fmt::Display for BinOp {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let s = match *self {
+            BinOp::Mul => "*",
+            BinOp::Div => "/",
+            BinOp::Mod => "%",
+            BinOp::Add => "+",
+            BinOp::Sub => "-",
+            BinOp::Shl => "<<",
+            BinOp::Shr => ">>",
+            BinOp::Lt => "<",
+            BinOp::Le => "<=",
+            BinOp::Ge => ">=",
+            BinOp::Gt => ">",
+            BinOp::Eq => "==",
+            BinOp::Ne => "!=",
+            BinOp::BitAnd => "&",
+            BinOp::BitXor => "^",
+            BinOp::BitOr => "|",
+            BinOp::And => "&&",
+            BinOp::Or => "||",
+        };
+        write!(f, "{}", s)
+    }
+}
+
+// <asnop> ::= = | += | -= | *= | /= | %= | <<= | >>= | &= | ^= | |=
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Hash)]
+pub enum AsnOp {
+    Assign,
+    Compound(BinOp),
+}
+
+impl fmt::Display for AsnOp {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            AsnOp::Assign => write!(f, "="),
+            AsnOp::Compound(op) => write!(f, "{}=", op),
+        }
+    }
+}
+
+// <postop> ::= -- | ++
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Hash)]
+pub enum PostOp {
+    Incr,
+    Decr,
+}
+
+impl fmt::Display for PostOp {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            PostOp::Incr => write!(f, "++"),
+            PostOp::Decr => write!(f, "--"),
+        }
+    }
+}
+
+impl Exp {
+    // Whether the expression has the shape of an <lv>
+    // <lv> ::= <vid> | <lv> . <fid> | <lv> -> <fid> | * <lv> | <lv> [ <exp> ] | ( <lv> )
+    pub fn is_lvalue(&self) -> bool {
+        match self.kind {
+            ExpKind::Var(_) => true,
+            ExpKind::Field(ref e, _)
+            | ExpKind::Arrow(ref e, _)
+            | ExpKind::Unop(UnOp::Deref, ref e)
+            | ExpKind::Index(ref e, _) => e.is_lvalue(),
+            _ => false,
+        }
+    }
+}
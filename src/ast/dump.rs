@@ -0,0 +1,361 @@
+use ast::ast::*;
+use ast::visit::{self, Visitor};
+use lexer::lexer::{escape_char, escape_str};
+use source::source_map::{SourceMap, Span};
+use std::fmt::Write;
+
+/*
+Debugging dumps of the syntax tree. The tree is first flattened into generic
+nodes, a kind, an optional value and children, which are then rendered as an
+indented tree, a JSON document or an s-expression.
+
+The tree and JSON formats include spans, the s-expression format leaves them
+out so that dumps of differently formatted but equivalent sources compare equal.
+ */
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum Format {
+    Tree,
+    Json,
+    Sexp,
+}
+
+impl Format {
+    pub fn parse(s: &str) -> Option<Format> {
+        match s {
+            "tree" => Some(Format::Tree),
+            "json" => Some(Format::Json),
+            "sexp" => Some(Format::Sexp),
+            _ => None,
+        }
+    }
+}
+
+pub struct Node {
+    pub kind: &'static str,
+    pub value: Option<String>,
+    pub span: Span,
+    pub children: Vec<Node>,
+}
+
+fn node(kind: &'static str, span: Span, children: Vec<Node>) -> Node {
+    Node {
+        kind,
+        value: None,
+        span,
+        children,
+    }
+}
+
+fn leaf(kind: &'static str, value: String, span: Span) -> Node {
+    Node {
+        kind,
+        value: Some(value),
+        span,
+        children: Vec::new(),
+    }
+}
+
+fn valued(kind: &'static str, value: String, span: Span, children: Vec<Node>) -> Node {
+    Node {
+        kind,
+        value: Some(value),
+        span,
+        children,
+    }
+}
+
+fn ident(kind: &'static str, x: &Ident) -> Node {
+    leaf(kind, x.name.clone(), x.span)
+}
+
+pub fn program(p: &Program) -> Node {
+    let span = match (p.decls.first(), p.decls.last()) {
+        (Some(first), Some(last)) => first.span.to(last.span),
+        _ => Span::default(),
+    };
+    let mut dumper = Dumper { levels: Vec::new() };
+    let decls = dumper.children(|d| visit::walk_program(d, p));
+    node("Program", span, decls)
+}
+
+/*
+Nodes are made by a visitor, which collects the nodes of a node's children
+while walking them and then makes the node from those.
+ */
+struct Dumper {
+    // The children made so far of each node being walked, innermost last
+    levels: Vec<Vec<Node>>,
+}
+
+impl Dumper {
+    fn children<F: FnOnce(&mut Dumper)>(&mut self, walk: F) -> Vec<Node> {
+        self.levels.push(Vec::new());
+        walk(self);
+        self.levels.pop().unwrap()
+    }
+
+    fn push(&mut self, n: Node) {
+        self.levels.last_mut().unwrap().push(n);
+    }
+
+    // The `Init` or `Step` of a for loop, empty if there is none
+    fn optional(&mut self, kind: &'static str, simple: &Option<Box<Simple>>, span: Span) -> Node {
+        match *simple {
+            Some(ref simple) => node(kind, simple.span, self.children(|d| d.visit_simple(simple))),
+            None => node(kind, span, Vec::new()),
+        }
+    }
+}
+
+impl Visitor for Dumper {
+    fn visit_gdecl(&mut self, d: &GDecl) {
+        let children = self.children(|v| visit::walk_gdecl(v, d));
+        let n = match d.kind {
+            GDeclKind::Use(Use::Library(ref lib)) => leaf("UseLibrary", lib.clone(), d.span),
+            GDeclKind::Use(Use::File(ref file)) => leaf("UseFile", file.clone(), d.span),
+            GDeclKind::StructDecl(ref name) => leaf("StructDecl", name.name.clone(), d.span),
+            GDeclKind::StructDef(ref name, _) => valued("StructDef", name.name.clone(), d.span, children),
+            GDeclKind::Typedef(_, ref name) => valued("Typedef", name.name.clone(), d.span, children),
+            GDeclKind::Function(ref f) => {
+                let kind = if f.body.is_some() { "Function" } else { "Prototype" };
+                valued(kind, f.name.name.clone(), d.span, children)
+            }
+        };
+        self.push(n);
+    }
+
+    fn visit_field(&mut self, f: &Field) {
+        let children = self.children(|v| visit::walk_field(v, f));
+        self.push(valued("Field", f.name.name.clone(), f.span, children));
+    }
+
+    fn visit_param(&mut self, p: &Param) {
+        let children = self.children(|v| visit::walk_param(v, p));
+        self.push(valued("Param", p.name.name.clone(), p.span, children));
+    }
+
+    fn visit_spec(&mut self, s: &Spec) {
+        let children = self.children(|v| visit::walk_spec(v, s));
+        self.push(valued("Spec", s.kind.to_string(), s.span, children));
+    }
+
+    fn visit_block(&mut self, b: &Block) {
+        let children = self.children(|v| visit::walk_block(v, b));
+        self.push(node("Block", b.span, children));
+    }
+
+    fn visit_stmt(&mut self, s: &Stmt) {
+        let kind = match s.kind {
+            // Blocks and simple statements are their own nodes
+            StmtKind::Simple(_) | StmtKind::Block(_) => return visit::walk_stmt(self, s),
+            StmtKind::For(ref init, ref cond, ref step, ref invariants, ref body) => {
+                let mut children = vec![self.optional("Init", init, s.span)];
+                children.extend(self.children(|v| v.visit_exp(cond)));
+                children.push(self.optional("Step", step, s.span));
+                children.extend(self.children(|v| {
+                    for i in invariants {
+                        v.visit_spec(i);
+                    }
+                    v.visit_stmt(body);
+                }));
+                return self.push(node("For", s.span, children));
+            }
+            StmtKind::If(..) => "If",
+            StmtKind::While(..) => "While",
+            StmtKind::Return(_) => "Return",
+            StmtKind::Break => "Break",
+            StmtKind::Continue => "Continue",
+            StmtKind::Assert(_) => "Assert",
+            StmtKind::Error(_) => "Error",
+            StmtKind::Annotation(_) => "Annotation",
+        };
+        let children = self.children(|v| visit::walk_stmt(v, s));
+        self.push(node(kind, s.span, children));
+    }
+
+    fn visit_simple(&mut self, s: &Simple) {
+        let children = self.children(|v| visit::walk_simple(v, s));
+        self.push(match s.kind {
+            SimpleKind::Assign(op, _, _) => valued("Assign", op.to_string(), s.span, children),
+            SimpleKind::Post(op, _) => valued("Post", op.to_string(), s.span, children),
+            SimpleKind::Exp(_) => node("Exp", s.span, children),
+            SimpleKind::Decl(_, ref name, _) => valued("Decl", name.name.clone(), s.span, children),
+        });
+    }
+
+    fn visit_tp(&mut self, t: &Tp) {
+        let children = self.children(|v| visit::walk_tp(v, t));
+        self.push(match t.kind {
+            TpKind::Int => leaf("Tp", String::from("int"), t.span),
+            TpKind::Bool => leaf("Tp", String::from("bool"), t.span),
+            TpKind::String => leaf("Tp", String::from("string"), t.span),
+            TpKind::Char => leaf("Tp", String::from("char"), t.span),
+            TpKind::Void => leaf("Tp", String::from("void"), t.span),
+            TpKind::Pointer(_) => node("Pointer", t.span, children),
+            TpKind::Array(_) => node("Array", t.span, children),
+            TpKind::Struct(ref name) => ident("Struct", name),
+            TpKind::Name(ref name) => ident("TypeName", name),
+        });
+    }
+
+    fn visit_exp(&mut self, e: &Exp) {
+        let children = self.children(|v| visit::walk_exp(v, e));
+        self.push(match e.kind {
+            ExpKind::Num(n, Radix::Dec) => leaf("Num", n.to_string(), e.span),
+            ExpKind::Num(n, Radix::Hex) => leaf("Num", format!("0x{:X}", n as u32), e.span),
+            ExpKind::Bool(b) => leaf("Bool", b.to_string(), e.span),
+            ExpKind::Null => node("Null", e.span, children),
+            ExpKind::Char(c) => leaf("Char", format!("'{}'", escape_char(c)), e.span),
+            ExpKind::Str(ref s) => leaf("Str", format!("\"{}\"", escape_str(s)), e.span),
+            ExpKind::Var(ref x) => ident("Var", x),
+            ExpKind::Unop(op, _) => valued("Unop", op.to_string(), e.span, children),
+            ExpKind::Binop(op, _, _) => valued("Binop", op.to_string(), e.span, children),
+            ExpKind::Ternary(..) => node("Ternary", e.span, children),
+            ExpKind::Call(ref f, _) => valued("Call", f.name.clone(), e.span, children),
+            ExpKind::Field(_, ref f) => valued("Field", f.name.clone(), e.span, children),
+            ExpKind::Arrow(_, ref f) => valued("Arrow", f.name.clone(), e.span, children),
+            ExpKind::Index(..) => node("Index", e.span, children),
+            ExpKind::Alloc(_) => node("Alloc", e.span, children),
+            ExpKind::AllocArray(..) => node("AllocArray", e.span, children),
+            ExpKind::Result => node("Result", e.span, children),
+            ExpKind::Length(_) => node("Length", e.span, children),
+        });
+    }
+}
+
+fn range(sources: &SourceMap, span: Span) -> String {
+    let file = sources.file(span.file);
+    let (l1, c1) = file.position(span.lo);
+    let (l2, c2) = file.position(span.hi);
+    format!("{}:{}-{}:{}", l1, c1, l2, c2)
+}
+
+pub fn render(n: &Node, format: Format, sources: &SourceMap) -> String {
+    let mut out = String::new();
+    match format {
+        Format::Tree => tree(n, 0, sources, &mut out),
+        Format::Json => {
+            json(n, 0, sources, &mut out);
+            out.push('\n');
+        }
+        Format::Sexp => {
+            sexp(n, 0, &mut out);
+            out.push('\n');
+        }
+    }
+    out
+}
+
+/*
+Program [1:1-12:2]
+  Function main [1:1-12:2]
+    Tp int [1:1-1:4]
+    ...
+ */
+fn tree(n: &Node, depth: usize, sources: &SourceMap, out: &mut String) {
+    let _ = write!(out, "{}{}", "  ".repeat(depth), n.kind);
+    if let Some(ref value) = n.value {
+        let _ = write!(out, " {}", value);
+    }
+    let _ = writeln!(out, " [{}]", range(sources, n.span));
+    for child in &n.children {
+        tree(child, depth + 1, sources, out);
+    }
+}
+
+fn json_string(s: &str) -> String {
+    let mut out = String::from("\"");
+    for c in s.chars() {
+        match c {
+            '"' => out.push_str("\\\""),
+            '\\' => out.push_str("\\\\"),
+            '\n' => out.push_str("\\n"),
+            '\t' => out.push_str("\\t"),
+            '\r' => out.push_str("\\r"),
+            c if (c as u32) < 0x20 => out.push_str(&format!("\\u{:04x}", c as u32)),
+            c => out.push(c),
+        }
+    }
+    out.push('"');
+    out
+}
+
+fn json(n: &Node, depth: usize, sources: &SourceMap, out: &mut String) {
+    let indent = "  ".repeat(depth + 1);
+    let _ = write!(out, "{{\n{}\"kind\": {}", indent, json_string(n.kind));
+    if let Some(ref value) = n.value {
+        let _ = write!(out, ",\n{}\"value\": {}", indent, json_string(value));
+    }
+    let _ = write!(out, ",\n{}\"span\": {}", indent, json_string(&range(sources, n.span)));
+    if !n.children.is_empty() {
+        let _ = write!(out, ",\n{}\"children\": [", indent);
+        for (i, child) in n.children.iter().enumerate() {
+            if i > 0 {
+                out.push(',');
+            }
+            let _ = write!(out, "\n{}  ", indent);
+            json(child, depth + 2, sources, out);
+        }
+        let _ = write!(out, "\n{}]", indent);
+    }
+    let _ = write!(out, "\n{}}}", "  ".repeat(depth));
+}
+
+// (Binop + (Var x) (Num 1)), one top level declaration per line
+fn sexp(n: &Node, depth: usize, out: &mut String) {
+    out.push('(');
+    out.push_str(n.kind);
+    if let Some(ref value) = n.value {
+        out.push(' ');
+        out.push_str(value);
+    }
+    for child in &n.children {
+        if depth == 0 {
+            out.push_str("\n  ");
+        } else {
+            out.push(' ');
+        }
+        sexp(child, depth + 1, out);
+    }
+    out.push(')');
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use lexer::lexer::Lexer;
+    use parser::parser::Parser;
+    use std::fs;
+
+    fn dump(path: &str, format: Format) -> String {
+        let mut sources = SourceMap::new();
+        let file = sources.load(path).unwrap();
+        let tokens = Lexer::new(file, &sources.file(file).text).tokens().unwrap();
+        let prog = Parser::new(tokens).parseProgram().unwrap();
+        render(&program(&prog), format, &sources)
+    }
+
+    fn golden(path: &str, format: Format, expected: &str) {
+        assert_eq!(dump(path, format), fs::read_to_string(expected).unwrap());
+    }
+
+    #[test]
+    fn dumping_tree() {
+        golden("./src/ast/tests/pow.c0", Format::Tree, "./src/ast/tests/pow.tree");
+    }
+
+    #[test]
+    fn dumping_json() {
+        golden("./src/ast/tests/pow.c0", Format::Json, "./src/ast/tests/pow.json");
+    }
+
+    #[test]
+    fn dumping_sexp() {
+        golden("./src/ast/tests/pow.c0", Format::Sexp, "./src/ast/tests/pow.sexp");
+        // Formatting does not change the s-expression
+        assert_eq!(
+            dump("./src/ast/tests/pow.c0", Format::Sexp),
+            dump("./src/ast/tests/pow_compact.c0", Format::Sexp)
+        );
+    }
+}
@@ -0,0 +1,294 @@
+use ast::ast::*;
+
+/*
+Rebuilds a syntax tree, taking it apart and putting it back together. Like
+`ast::visit`, every method defaults to the function of the same name here,
+which folds the node's children in source order and keeps everything else,
+so a pass overrides only the nodes it rewrites.
+
+Folding consumes the tree, use it for passes producing a changed copy, as
+elaboration does of contracts with `semant::contracts::Rebind`. Changing a
+tree in place is simpler with `VisitorMut`.
+ */
+pub trait Fold {
+    fn fold_program(&mut self, p: Program) -> Program {
+        fold_program(self, p)
+    }
+
+    fn fold_gdecl(&mut self, d: GDecl) -> GDecl {
+        fold_gdecl(self, d)
+    }
+
+    fn fold_field(&mut self, f: Field) -> Field {
+        fold_field(self, f)
+    }
+
+    fn fold_function(&mut self, f: Function) -> Function {
+        fold_function(self, f)
+    }
+
+    fn fold_param(&mut self, p: Param) -> Param {
+        fold_param(self, p)
+    }
+
+    fn fold_spec(&mut self, s: Spec) -> Spec {
+        fold_spec(self, s)
+    }
+
+    fn fold_block(&mut self, b: Block) -> Block {
+        fold_block(self, b)
+    }
+
+    fn fold_stmt(&mut self, s: Stmt) -> Stmt {
+        fold_stmt(self, s)
+    }
+
+    fn fold_simple(&mut self, s: Simple) -> Simple {
+        fold_simple(self, s)
+    }
+
+    fn fold_tp(&mut self, t: Tp) -> Tp {
+        fold_tp(self, t)
+    }
+
+    fn fold_exp(&mut self, e: Exp) -> Exp {
+        fold_exp(self, e)
+    }
+
+    fn fold_ident(&mut self, x: Ident) -> Ident {
+        x
+    }
+}
+
+pub fn fold_program<F: Fold + ?Sized>(f: &mut F, p: Program) -> Program {
+    Program {
+        decls: p.decls.into_iter().map(|d| f.fold_gdecl(d)).collect(),
+    }
+}
+
+pub fn fold_gdecl<F: Fold + ?Sized>(f: &mut F, d: GDecl) -> GDecl {
+    let kind = match d.kind {
+        GDeclKind::Use(u) => GDeclKind::Use(u),
+        GDeclKind::StructDecl(name) => GDeclKind::StructDecl(f.fold_ident(name)),
+        GDeclKind::StructDef(name, fields) => GDeclKind::StructDef(
+            f.fold_ident(name),
+            fields.into_iter().map(|field| f.fold_field(field)).collect(),
+        ),
+        GDeclKind::Function(function) => GDeclKind::Function(f.fold_function(function)),
+        GDeclKind::Typedef(t, name) => {
+            let t = f.fold_tp(t);
+            GDeclKind::Typedef(t, f.fold_ident(name))
+        }
+    };
+    GDecl { kind, span: d.span }
+}
+
+pub fn fold_field<F: Fold + ?Sized>(f: &mut F, field: Field) -> Field {
+    Field {
+        tp: f.fold_tp(field.tp),
+        name: f.fold_ident(field.name),
+        span: field.span,
+    }
+}
+
+pub fn fold_function<F: Fold + ?Sized>(f: &mut F, function: Function) -> Function {
+    Function {
+        ret: f.fold_tp(function.ret),
+        name: f.fold_ident(function.name),
+        params: function.params.into_iter().map(|p| f.fold_param(p)).collect(),
+        specs: function.specs.into_iter().map(|s| f.fold_spec(s)).collect(),
+        body: function.body.map(|b| f.fold_block(b)),
+    }
+}
+
+pub fn fold_param<F: Fold + ?Sized>(f: &mut F, p: Param) -> Param {
+    Param {
+        tp: f.fold_tp(p.tp),
+        name: f.fold_ident(p.name),
+        span: p.span,
+    }
+}
+
+pub fn fold_spec<F: Fold + ?Sized>(f: &mut F, s: Spec) -> Spec {
+    Spec {
+        kind: s.kind,
+        exp: f.fold_exp(s.exp),
+        span: s.span,
+    }
+}
+
+pub fn fold_block<F: Fold + ?Sized>(f: &mut F, b: Block) -> Block {
+    Block {
+        stmts: b.stmts.into_iter().map(|s| f.fold_stmt(s)).collect(),
+        span: b.span,
+    }
+}
+
+fn fold_specs<F: Fold + ?Sized>(f: &mut F, specs: Vec<Spec>) -> Vec<Spec> {
+    specs.into_iter().map(|s| f.fold_spec(s)).collect()
+}
+
+// Boxed children are folded in their box
+fn fold_box_stmt<F: Fold + ?Sized>(f: &mut F, mut s: Box<Stmt>) -> Box<Stmt> {
+    *s = f.fold_stmt(*s);
+    s
+}
+
+fn fold_box_exp<F: Fold + ?Sized>(f: &mut F, mut e: Box<Exp>) -> Box<Exp> {
+    *e = f.fold_exp(*e);
+    e
+}
+
+pub fn fold_stmt<F: Fold + ?Sized>(f: &mut F, s: Stmt) -> Stmt {
+    let kind = match s.kind {
+        StmtKind::Simple(simple) => StmtKind::Simple(f.fold_simple(simple)),
+        StmtKind::If(cond, then, otherwise) => {
+            let cond = f.fold_exp(cond);
+            let then = fold_box_stmt(f, then);
+            StmtKind::If(cond, then, otherwise.map(|s| fold_box_stmt(f, s)))
+        }
+        StmtKind::While(cond, invariants, body) => {
+            let cond = f.fold_exp(cond);
+            let invariants = fold_specs(f, invariants);
+            StmtKind::While(cond, invariants, fold_box_stmt(f, body))
+        }
+        StmtKind::For(init, cond, step, invariants, body) => {
+            let init = init.map(|s| Box::new(f.fold_simple(*s)));
+            let cond = f.fold_exp(cond);
+            let step = step.map(|s| Box::new(f.fold_simple(*s)));
+            let invariants = fold_specs(f, invariants);
+            StmtKind::For(init, cond, step, invariants, fold_box_stmt(f, body))
+        }
+        StmtKind::Return(e) => StmtKind::Return(e.map(|e| f.fold_exp(e))),
+        StmtKind::Break => StmtKind::Break,
+        StmtKind::Continue => StmtKind::Continue,
+        StmtKind::Block(b) => StmtKind::Block(f.fold_block(b)),
+        StmtKind::Assert(e) => StmtKind::Assert(f.fold_exp(e)),
+        StmtKind::Error(e) => StmtKind::Error(f.fold_exp(e)),
+        StmtKind::Annotation(specs) => StmtKind::Annotation(fold_specs(f, specs)),
+    };
+    Stmt { kind, span: s.span }
+}
+
+pub fn fold_simple<F: Fold + ?Sized>(f: &mut F, s: Simple) -> Simple {
+    let kind = match s.kind {
+        SimpleKind::Assign(op, lhs, rhs) => {
+            let lhs = f.fold_exp(lhs);
+            SimpleKind::Assign(op, lhs, f.fold_exp(rhs))
+        }
+        SimpleKind::Post(op, lhs) => SimpleKind::Post(op, f.fold_exp(lhs)),
+        SimpleKind::Exp(e) => SimpleKind::Exp(f.fold_exp(e)),
+        SimpleKind::Decl(t, name, init) => {
+            let t = f.fold_tp(t);
+            let name = f.fold_ident(name);
+            SimpleKind::Decl(t, name, init.map(|e| f.fold_exp(e)))
+        }
+    };
+    Simple { kind, span: s.span }
+}
+
+pub fn fold_tp<F: Fold + ?Sized>(f: &mut F, t: Tp) -> Tp {
+    let kind = match t.kind {
+        TpKind::Pointer(inner) => TpKind::Pointer(Box::new(f.fold_tp(*inner))),
+        TpKind::Array(inner) => TpKind::Array(Box::new(f.fold_tp(*inner))),
+        TpKind::Struct(name) => TpKind::Struct(f.fold_ident(name)),
+        TpKind::Name(name) => TpKind::Name(f.fold_ident(name)),
+        kind => kind,
+    };
+    Tp { kind, span: t.span }
+}
+
+pub fn fold_exp<F: Fold + ?Sized>(f: &mut F, e: Exp) -> Exp {
+    let kind = match e.kind {
+        ExpKind::Var(x) => ExpKind::Var(f.fold_ident(x)),
+        ExpKind::Unop(op, operand) => ExpKind::Unop(op, fold_box_exp(f, operand)),
+        ExpKind::Binop(op, l, r) => {
+            let l = fold_box_exp(f, l);
+            ExpKind::Binop(op, l, fold_box_exp(f, r))
+        }
+        ExpKind::Ternary(c, t, e) => {
+            let c = fold_box_exp(f, c);
+            let t = fold_box_exp(f, t);
+            ExpKind::Ternary(c, t, fold_box_exp(f, e))
+        }
+        ExpKind::Call(name, args) => {
+            let name = f.fold_ident(name);
+            ExpKind::Call(name, args.into_iter().map(|arg| f.fold_exp(arg)).collect())
+        }
+        ExpKind::Field(s, name) => {
+            let s = fold_box_exp(f, s);
+            ExpKind::Field(s, f.fold_ident(name))
+        }
+        ExpKind::Arrow(s, name) => {
+            let s = fold_box_exp(f, s);
+            ExpKind::Arrow(s, f.fold_ident(name))
+        }
+        ExpKind::Index(a, i) => {
+            let a = fold_box_exp(f, a);
+            ExpKind::Index(a, fold_box_exp(f, i))
+        }
+        ExpKind::Alloc(t) => ExpKind::Alloc(f.fold_tp(t)),
+        ExpKind::AllocArray(t, len) => {
+            let t = f.fold_tp(t);
+            ExpKind::AllocArray(t, fold_box_exp(f, len))
+        }
+        ExpKind::Length(a) => ExpKind::Length(fold_box_exp(f, a)),
+        kind => kind,
+    };
+    Exp {
+        id: e.id,
+        kind,
+        span: e.span,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use ast::pretty;
+    use lexer::lexer::Lexer;
+    use parser::parser::Parser;
+
+    fn parse(text: &str) -> Program {
+        Parser::new(Lexer::new(0, text).tokens().unwrap())
+            .parseProgram()
+            .unwrap()
+    }
+
+    // Replaces `x - 0` by `x` and renames `tmp` to `t`
+    struct Simplify;
+
+    impl Fold for Simplify {
+        fn fold_exp(&mut self, e: Exp) -> Exp {
+            let e = fold_exp(self, e);
+            match e.kind {
+                ExpKind::Binop(BinOp::Sub, l, ref r) if r.kind == ExpKind::Num(0, Radix::Dec) => *l,
+                kind => Exp { kind, ..e },
+            }
+        }
+
+        fn fold_ident(&mut self, mut x: Ident) -> Ident {
+            if x.name == "tmp" {
+                x.name = String::from("t");
+            }
+            x
+        }
+    }
+
+    #[test]
+    fn folding() {
+        let p = parse("int f(int tmp) { int[] a = alloc_array(int, tmp - 0); return a[(tmp - 0) - 0] - 1; }");
+        assert_eq!(
+            pretty::program(&Simplify.fold_program(p)),
+            "int f(int t) {\n  int[] a = alloc_array(int, t);\n  return a[t] - 1;\n}\n"
+        );
+    }
+
+    #[test]
+    fn folding_nothing_keeps_the_tree() {
+        struct Identity;
+        impl Fold for Identity {}
+        let p = parse(&::std::fs::read_to_string("./src/parser/tests/program.c0").unwrap());
+        assert_eq!(Identity.fold_program(p.clone()), p);
+    }
+}
@@ -0,0 +1,5 @@
+pub mod ast;
+pub mod visit;
+pub mod fold;
+pub mod dump;
+pub mod pretty;
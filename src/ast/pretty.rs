@@ -0,0 +1,881 @@
+use ast::ast::*;
+use lexer::lexer::{escape_char, escape_str, Comment};
+use source::source_map::{SourceFile, Span};
+
+/*
+Turns syntax trees back into C0 source in a canonical style
+
+- two space indentation, opening braces at the end of the line
+- contracts on their own lines between a function header and its body, or
+  a loop header and its body, with the body's brace on the next line
+- a blank line between top level declarations
+- expressions with the fewest parentheses that preserve their structure
+
+Parsing the printed source gives back an equal tree. Given a width, lines
+which would be longer are broken after binary operators, `?` and `:`, and
+the commas of argument and parameter lists, continuing four columns further
+in. Contracts are never broken, a line annotation ends at the newline.
+ */
+pub fn program(p: &Program) -> String {
+    let mut printer = Printer::new(None, None);
+    printer.program(p);
+    printer.out
+}
+
+/*
+The source of a whole file in the canonical style, kept within `width`
+columns where possible, with the comments of the file and single blank lines
+between statements put back where they were.
+ */
+pub fn format(p: &Program, file: &SourceFile, comments: &[Comment], width: usize) -> String {
+    let trivia = Trivia {
+        file,
+        comments,
+        next: 0,
+        last_line: 0,
+    };
+    let mut printer = Printer::new(Some(width), Some(trivia));
+    printer.program(p);
+    printer.comments_before(usize::MAX, false);
+    printer.out
+}
+
+pub fn gdecl(d: &GDecl) -> String {
+    let mut printer = Printer::new(None, None);
+    printer.gdecl(d);
+    printer.out
+}
+
+pub fn stmt(s: &Stmt) -> String {
+    let mut printer = Printer::new(None, None);
+    printer.stmt(s);
+    printer.out
+}
+
+pub fn exp(e: &Exp) -> String {
+    flat(&exp_doc(e, 0))
+}
+
+pub fn tp(t: &Tp) -> String {
+    let mut out = String::new();
+    write_tp(t, &mut out);
+    out
+}
+
+pub fn spec(s: &Spec) -> String {
+    format!("//@{} {};", s.kind, exp(&s.exp))
+}
+
+pub fn simple(s: &Simple) -> String {
+    flat(&simple_doc(s))
+}
+
+fn write_tp(t: &Tp, out: &mut String) {
+    match t.kind {
+        TpKind::Int => out.push_str("int"),
+        TpKind::Bool => out.push_str("bool"),
+        TpKind::String => out.push_str("string"),
+        TpKind::Char => out.push_str("char"),
+        TpKind::Void => out.push_str("void"),
+        TpKind::Pointer(ref inner) => {
+            write_tp(inner, out);
+            out.push('*');
+        }
+        TpKind::Array(ref inner) => {
+            write_tp(inner, out);
+            out.push_str("[]");
+        }
+        TpKind::Struct(ref name) => {
+            out.push_str("struct ");
+            out.push_str(&name.name);
+        }
+        TpKind::Name(ref name) => out.push_str(&name.name),
+    }
+}
+
+/*
+Line breaking follows Wadler's "A prettier printer". A document is text with
+places it may break, grouped so that a group is either laid out on the rest
+of the line with its breaks as spaces, or has all of them as newlines. Outer
+groups break before inner ones.
+ */
+enum Doc {
+    Text(String),
+    // A space, or a newline when its group does not fit
+    Line,
+    // A newline always, after a line comment
+    Break,
+    Nest(usize, Box<Doc>),
+    Group(Box<Doc>),
+    Concat(Vec<Doc>),
+}
+
+const CONTINUATION: usize = 4;
+
+fn text<S: Into<String>>(s: S) -> Doc {
+    Doc::Text(s.into())
+}
+
+fn cat(docs: Vec<Doc>) -> Doc {
+    Doc::Concat(docs)
+}
+
+// A group whose lines after a break are indented as continuations
+fn group(docs: Vec<Doc>) -> Doc {
+    Doc::Group(Box::new(Doc::Nest(CONTINUATION, Box::new(Doc::Concat(docs)))))
+}
+
+// `( a, b, c )` breaking after the commas
+fn list(items: Vec<Doc>) -> Doc {
+    let mut docs = Vec::new();
+    for (i, item) in items.into_iter().enumerate() {
+        if i > 0 {
+            docs.push(text(","));
+            docs.push(Doc::Line);
+        }
+        docs.push(item);
+    }
+    cat(vec![text("("), group(docs), text(")")])
+}
+
+fn flat(doc: &Doc) -> String {
+    render(doc, None, 0)
+}
+
+#[derive(Copy, Clone, PartialEq)]
+enum Mode {
+    Flat,
+    Break,
+}
+
+// Lays out `doc` starting at column `indent`, all on one line without a width
+fn render(doc: &Doc, width: Option<usize>, indent: usize) -> String {
+    let mode = if width.is_some() { Mode::Break } else { Mode::Flat };
+    let width = width.unwrap_or(usize::MAX) as isize;
+    let mut out = String::new();
+    let mut column = indent;
+    let mut stack = vec![(indent, mode, doc)];
+    while let Some((i, mode, doc)) = stack.pop() {
+        match *doc {
+            Doc::Text(ref s) => {
+                out.push_str(s);
+                column += s.chars().count();
+            }
+            Doc::Line if mode == Mode::Flat => {
+                out.push(' ');
+                column += 1;
+            }
+            Doc::Line | Doc::Break => {
+                out.push('\n');
+                out.push_str(&" ".repeat(i));
+                column = i;
+            }
+            Doc::Nest(j, ref doc) => stack.push((i + j, mode, doc)),
+            Doc::Concat(ref docs) => {
+                for doc in docs.iter().rev() {
+                    stack.push((i, mode, doc));
+                }
+            }
+            Doc::Group(ref doc) => {
+                let flat = mode == Mode::Flat || fits(width - column as isize, (i, doc), &stack);
+                stack.push((i, if flat { Mode::Flat } else { Mode::Break }, doc));
+            }
+        }
+    }
+    out
+}
+
+// Whether the group `first` laid out flat, and what follows up to the next break, fits in `room`
+fn fits(mut room: isize, first: (usize, &Doc), rest: &[(usize, Mode, &Doc)]) -> bool {
+    let mut rest = rest.iter().rev();
+    let mut stack = vec![(first.0, Mode::Flat, first.1)];
+    while room >= 0 {
+        let (i, mode, doc) = match stack.pop() {
+            Some(item) => item,
+            None => match rest.next() {
+                Some(&item) => item,
+                None => return true,
+            },
+        };
+        match *doc {
+            Doc::Text(ref s) => room -= s.chars().count() as isize,
+            Doc::Line if mode == Mode::Flat => room -= 1,
+            Doc::Line => return true,
+            // A group holding a line comment cannot be laid out flat
+            Doc::Break => return mode == Mode::Break,
+            Doc::Nest(j, ref doc) => stack.push((i + j, mode, doc)),
+            Doc::Concat(ref docs) => {
+                for doc in docs.iter().rev() {
+                    stack.push((i, mode, doc));
+                }
+            }
+            Doc::Group(ref doc) => stack.push((i, mode, doc)),
+        }
+    }
+    false
+}
+
+/*
+Binding strength of an expression, extending `BinOp::precedence` with the
+conditional below every binary operator and prefix and postfix operators
+above them.
+ */
+const TERNARY: u8 = 0;
+const PREFIX: u8 = 11;
+const POSTFIX: u8 = 12;
+
+pub fn precedence(e: &Exp) -> u8 {
+    match e.kind {
+        ExpKind::Ternary(..) => TERNARY,
+        ExpKind::Binop(op, _, _) => op.precedence(),
+        ExpKind::Unop(..) => PREFIX,
+        _ => POSTFIX,
+    }
+}
+
+// `e`, parenthesised if it binds less tightly than `min`
+fn exp_doc(e: &Exp, min: u8) -> Doc {
+    if precedence(e) < min {
+        return cat(vec![text("("), exp_doc(e, 0), text(")")]);
+    }
+    match e.kind {
+        ExpKind::Num(n, Radix::Dec) => text((n as u32).to_string()),
+        ExpKind::Num(n, Radix::Hex) => text(format!("0x{:X}", n as u32)),
+        ExpKind::Bool(b) => text(if b { "true" } else { "false" }),
+        ExpKind::Null => text("NULL"),
+        ExpKind::Char(c) => text(format!("'{}'", escape_char(c))),
+        ExpKind::Str(ref s) => text(format!("\"{}\"", escape_str(s))),
+        ExpKind::Var(ref x) => text(x.name.clone()),
+        ExpKind::Result => text("\\result"),
+        ExpKind::Unop(op, ref operand) => {
+            let inner = exp_doc(operand, PREFIX);
+            // `- -x` must not become the decrement operator `--x`
+            let space = op == UnOp::Neg && flat(&inner).starts_with('-');
+            cat(vec![text(format!("{}{}", op, if space { " " } else { "" })), inner])
+        }
+        ExpKind::Binop(op, _, _) => {
+            // A chain of operators of the same precedence breaks as a whole
+            let mut operands = Vec::new();
+            let mut left = e;
+            while let ExpKind::Binop(o, ref l, ref r) = left.kind {
+                if o.precedence() != op.precedence() {
+                    break;
+                }
+                operands.push((o, r));
+                left = l;
+            }
+            let mut docs = vec![exp_doc(left, op.precedence())];
+            for &(o, r) in operands.iter().rev() {
+                docs.push(text(format!(" {}", o)));
+                docs.push(Doc::Line);
+                docs.push(exp_doc(r, op.precedence() + 1));
+            }
+            group(docs)
+        }
+        ExpKind::Ternary(ref c, ref t, ref f) => group(vec![
+            exp_doc(c, TERNARY + 1),
+            text(" ?"),
+            Doc::Line,
+            exp_doc(t, TERNARY),
+            text(" :"),
+            Doc::Line,
+            exp_doc(f, TERNARY),
+        ]),
+        ExpKind::Call(ref f, ref args) => cat(vec![
+            text(f.name.clone()),
+            list(args.iter().map(|arg| exp_doc(arg, 0)).collect()),
+        ]),
+        ExpKind::Field(ref s, ref f) => cat(vec![exp_doc(s, POSTFIX), text(format!(".{}", f.name))]),
+        ExpKind::Arrow(ref s, ref f) => cat(vec![exp_doc(s, POSTFIX), text(format!("->{}", f.name))]),
+        ExpKind::Index(ref a, ref i) => cat(vec![exp_doc(a, POSTFIX), text("["), exp_doc(i, 0), text("]")]),
+        ExpKind::Alloc(ref t) => text(format!("alloc({})", tp(t))),
+        ExpKind::AllocArray(ref t, ref len) => cat(vec![
+            text("alloc_array"),
+            list(vec![text(tp(t)), exp_doc(len, 0)]),
+        ]),
+        ExpKind::Length(ref a) => cat(vec![text("\\length("), exp_doc(a, 0), text(")")]),
+    }
+}
+
+fn simple_doc(s: &Simple) -> Doc {
+    match s.kind {
+        SimpleKind::Assign(op, ref lhs, ref rhs) => group(vec![
+            exp_doc(lhs, 0),
+            text(format!(" {}", op)),
+            Doc::Line,
+            exp_doc(rhs, 0),
+        ]),
+        SimpleKind::Post(op, ref lhs) => cat(vec![exp_doc(lhs, 0), text(op.to_string())]),
+        SimpleKind::Exp(ref e) => exp_doc(e, 0),
+        SimpleKind::Decl(ref t, ref name, None) => text(format!("{} {}", tp(t), name.name)),
+        SimpleKind::Decl(ref t, ref name, Some(ref init)) => group(vec![
+            text(format!("{} {} =", tp(t), name.name)),
+            Doc::Line,
+            exp_doc(init, 0),
+        ]),
+    }
+}
+
+// Whether the last of `comments` is a line comment, after which the line has to end
+fn ends_line(comments: &[&Comment]) -> bool {
+    comments.last().is_some_and(|c| c.text.starts_with("//"))
+}
+
+// Comments following something on its line, each after a space or the newline ending a line comment
+fn trailing_comments(comments: &[&Comment]) -> Vec<Doc> {
+    let mut docs = Vec::new();
+    for (i, c) in comments.iter().enumerate() {
+        docs.push(if ends_line(&comments[..i]) { Doc::Break } else { text(" ") });
+        docs.push(text(c.text.clone()));
+    }
+    if ends_line(comments) {
+        docs.push(Doc::Break);
+    }
+    docs
+}
+
+// An if statement whose last nested if has no else, and would steal ours
+fn dangles(s: &Stmt) -> bool {
+    match s.kind {
+        StmtKind::If(_, _, None) => true,
+        StmtKind::If(_, _, Some(ref otherwise)) => dangles(otherwise),
+        StmtKind::While(_, _, ref body) | StmtKind::For(_, _, _, _, ref body) => dangles(body),
+        _ => false,
+    }
+}
+
+/*
+Comments are not part of the tree, `format` places them by position. Those
+before a declaration, field or statement go on lines of their own ahead of
+it, and those left over at the end of a block come before its closing
+brace. One following a statement, a contract, a function or loop header or
+an opening brace on the same line stays at the end of the line that token
+is printed on, and those inside a parameter list stay next to the parameter
+they precede. Comments inside expressions move out to one of these places.
+ */
+struct Trivia<'a> {
+    file: &'a SourceFile,
+    comments: &'a [Comment],
+    next: usize,
+    // The source line of the last thing printed
+    last_line: usize,
+}
+
+impl<'a> Trivia<'a> {
+    fn line(&self, offset: usize) -> usize {
+        self.file.position(offset).0
+    }
+
+    fn next_before(&self, hi: usize) -> Option<&'a Comment> {
+        self.comments.get(self.next).filter(|c| c.span.lo < hi)
+    }
+
+    // The end of the last token before `lo`, skipping comments
+    fn token_end_before(&self, lo: usize) -> usize {
+        let mut end = lo;
+        loop {
+            end = self.file.text[..end].trim_end().len();
+            match self.comments.iter().find(|c| c.span.lo < end && end <= c.span.hi) {
+                Some(c) => end = c.span.lo,
+                None => return end,
+            }
+        }
+    }
+
+    // The end of the last token before the annotation holding `first`, the first contract in it
+    fn token_end_before_spec(&self, first: &Spec) -> usize {
+        let start = self.token_end_before(first.span.lo) - "//@".len();
+        self.token_end_before(start)
+    }
+}
+
+struct Printer<'a> {
+    out: String,
+    indent: usize,
+    width: Option<usize>,
+    trivia: Option<Trivia<'a>>,
+    // Nothing has been printed in the current block, so no blank line goes here
+    fresh: bool,
+    // The last line printed ends in a line comment, nothing more can follow on it
+    commented: bool,
+}
+
+impl<'a> Printer<'a> {
+    fn new(width: Option<usize>, trivia: Option<Trivia<'a>>) -> Printer<'a> {
+        Printer {
+            out: String::new(),
+            indent: 0,
+            width,
+            trivia,
+            fresh: true,
+            commented: false,
+        }
+    }
+
+    fn line(&mut self, text: &str) {
+        for _ in 0..self.indent {
+            self.out.push_str("  ");
+        }
+        self.out.push_str(text);
+        self.out.push('\n');
+        self.fresh = false;
+        self.commented = false;
+    }
+
+    fn line_doc(&mut self, doc: Doc) {
+        let text = render(&doc, self.width, 2 * self.indent);
+        self.line(&text);
+    }
+
+    // A blank line, unless at the start of a block
+    fn separate(&mut self, blank: bool) {
+        if blank && !self.fresh {
+            self.out.push('\n');
+        }
+    }
+
+    /*
+    Prints the comments before `hi`, separated by blank lines where the
+    source has them, and a blank line before the first if `blank`. Returns
+    whether the next thing printed should be preceded by a blank line.
+     */
+    fn comments_before(&mut self, hi: usize, mut blank: bool) -> bool {
+        let mut trivia = match self.trivia.take() {
+            Some(trivia) => trivia,
+            None => return blank,
+        };
+        while let Some(c) = trivia.next_before(hi) {
+            if trivia.line(c.span.lo) > trivia.last_line + 1 {
+                blank = true;
+            }
+            self.separate(blank);
+            blank = false;
+            // Later lines of a block comment are kept as written
+            let mut lines = c.text.lines();
+            if let Some(first) = lines.next() {
+                self.line(first.trim_end());
+            }
+            for l in lines {
+                self.out.push_str(l.trim_end());
+                self.out.push('\n');
+            }
+            trivia.next += 1;
+            trivia.last_line = trivia.line(c.span.hi);
+        }
+        self.trivia = Some(trivia);
+        blank
+    }
+
+    // Before the item starting at `lo`, its comments and any blank line
+    fn leading(&mut self, lo: usize, blank: bool) {
+        let mut blank = self.comments_before(lo, blank);
+        if let Some(ref trivia) = self.trivia {
+            if trivia.line(lo) > trivia.last_line + 1 {
+                blank = true;
+            }
+        }
+        self.separate(blank);
+    }
+
+    // After the item ending at `hi`, the comments following it on its last line
+    fn trailing(&mut self, hi: usize) {
+        if let Some(ref mut trivia) = self.trivia {
+            let line = trivia.line(hi);
+            trivia.last_line = line;
+            while let Some(c) = trivia.comments.get(trivia.next) {
+                let follows = trivia.line(c.span.lo) == line && trivia.token_end_before(c.span.lo) <= hi;
+                if self.commented || !follows || c.text.contains('\n') {
+                    break;
+                }
+                self.out.pop();
+                self.out.push(' ');
+                self.out.push_str(&c.text);
+                self.out.push('\n');
+                self.commented = c.text.starts_with("//");
+                trivia.next += 1;
+            }
+        }
+    }
+
+    // After a line ending in the last token before `lo`, the comments following that token
+    fn trailing_before(&mut self, lo: usize) {
+        if let Some(hi) = self.trivia.as_ref().map(|trivia| trivia.token_end_before(lo)) {
+            self.trailing(hi);
+        }
+    }
+
+    // The comments before `hi` to go inside a line being built, given a line only those starting on it
+    fn inline_comments(&mut self, hi: usize, line: Option<usize>) -> Vec<&'a Comment> {
+        let mut comments = Vec::new();
+        if let Some(ref mut trivia) = self.trivia {
+            while let Some(c) = trivia.next_before(hi) {
+                if line.is_some_and(|line| trivia.line(c.span.lo) != line) {
+                    break;
+                }
+                comments.push(c);
+                trivia.next += 1;
+            }
+        }
+        comments
+    }
+
+    // Contracts on lines of their own, each keeping the comments after it
+    fn specs(&mut self, specs: &[Spec]) {
+        for (i, s) in specs.iter().enumerate() {
+            self.line(&spec(s));
+            let next_on_line = match (&self.trivia, specs.get(i + 1)) {
+                (Some(trivia), Some(next)) => trivia.line(next.span.lo) == trivia.line(s.span.hi),
+                _ => false,
+            };
+            if !next_on_line {
+                self.trailing(s.span.hi);
+            }
+        }
+    }
+
+    fn program(&mut self, p: &Program) {
+        for (i, d) in p.decls.iter().enumerate() {
+            let both_uses = i > 0
+                && matches!(
+                    (&p.decls[i - 1].kind, &d.kind),
+                    (&GDeclKind::Use(_), &GDeclKind::Use(_))
+                );
+            self.leading(d.span.lo, i > 0 && !both_uses);
+            self.gdecl(d);
+            self.trailing(d.span.hi);
+        }
+    }
+
+    fn gdecl(&mut self, d: &GDecl) {
+        match d.kind {
+            GDeclKind::Use(Use::Library(ref lib)) => self.line(&format!("#use <{}>", lib)),
+            GDeclKind::Use(Use::File(ref file)) => self.line(&format!("#use \"{}\"", escape_str(file))),
+            GDeclKind::StructDecl(ref name) => self.line(&format!("struct {};", name.name)),
+            GDeclKind::StructDef(ref name, ref fields) => {
+                self.open(Some(text(format!("struct {}", name.name))));
+                if let Some(f) = fields.first() {
+                    self.trailing_before(f.span.lo);
+                }
+                self.indent += 1;
+                for f in fields {
+                    self.leading(f.span.lo, false);
+                    self.line(&format!("{} {};", tp(&f.tp), f.name.name));
+                    self.trailing(f.span.hi);
+                }
+                self.comments_before(d.span.hi, false);
+                self.indent -= 1;
+                self.line("};");
+            }
+            GDeclKind::Typedef(ref t, ref name) => self.line(&format!("typedef {} {};", tp(t), name.name)),
+            GDeclKind::Function(ref f) => self.function(f, d.span),
+        }
+    }
+
+    fn function(&mut self, f: &Function, span: Span) {
+        // The end of the closing parenthesis, the token before the contracts, body or semicolon
+        let close = self.trivia.as_ref().map_or(0, |trivia| match (f.specs.first(), &f.body) {
+            (Some(s), _) => trivia.token_end_before_spec(s),
+            (None, Some(body)) => trivia.token_end_before(body.span.lo),
+            (None, None) => trivia.token_end_before(span.hi - 1),
+        });
+        // The parameters as `list` lays them out, with the comments among them
+        let mut params = Vec::new();
+        let mut previous: Option<usize> = None;
+        for p in &f.params {
+            if let Some(hi) = previous {
+                params.push(text(","));
+                let line = self.trivia.as_ref().map(|trivia| trivia.line(hi));
+                let after_comma = self.inline_comments(p.span.lo, line);
+                params.extend(trailing_comments(&after_comma));
+                if !ends_line(&after_comma) {
+                    params.push(Doc::Line);
+                }
+            }
+            for c in self.inline_comments(p.span.lo, None) {
+                params.push(text(c.text.clone()));
+                params.push(if ends_line(&[c]) { Doc::Break } else { text(" ") });
+            }
+            params.push(text(format!("{} {}", tp(&p.tp), p.name.name)));
+            previous = Some(p.span.hi);
+        }
+        let rest = self.inline_comments(close, None);
+        let mut rest = trailing_comments(&rest);
+        if f.params.is_empty() && !rest.is_empty() {
+            rest.remove(0);
+        }
+        params.extend(rest);
+        let header = cat(vec![
+            text(format!("{} {}(", tp(&f.ret), f.name.name)),
+            group(params),
+            text(")"),
+        ]);
+        match f.body {
+            None if f.specs.is_empty() => {
+                self.line_doc(cat(vec![header, text(";")]));
+                self.trailing(close);
+            }
+            None => {
+                self.line_doc(header);
+                self.trailing(close);
+                self.specs(&f.specs);
+                self.line(";");
+            }
+            Some(ref body) => {
+                if f.specs.is_empty() {
+                    self.open(Some(header));
+                } else {
+                    self.line_doc(header);
+                    self.trailing(close);
+                    self.specs(&f.specs);
+                    self.open(None);
+                }
+                self.block_body(body);
+                self.line("}");
+            }
+        }
+    }
+
+    // Opens a block at the end of `header`, or on a line of its own
+    fn open(&mut self, header: Option<Doc>) {
+        match header {
+            Some(header) => self.line_doc(cat(vec![header, text(" {")])),
+            None => self.line("{"),
+        }
+        self.fresh = true;
+    }
+
+    fn block_body(&mut self, b: &Block) {
+        // Comments after the header and after the opening brace stay on its line
+        self.trailing_before(b.span.lo);
+        self.trailing(b.span.lo + 1);
+        self.indent += 1;
+        for s in &b.stmts {
+            self.stmt(s);
+        }
+        self.comments_before(b.span.hi, false);
+        self.indent -= 1;
+    }
+
+    // A statement on its own line below the header it belongs to
+    fn indented(&mut self, s: &Stmt) {
+        self.indent += 1;
+        self.fresh = true;
+        self.stmt(s);
+        self.indent -= 1;
+    }
+
+    /*
+    Writes a statement following `header`, braces stay on the header line
+    unless `contracts` have to come between them.
+     */
+    fn body(&mut self, header: Doc, contracts: &[Spec], body: &Stmt) {
+        let header = if contracts.is_empty() {
+            Some(header)
+        } else {
+            self.line_doc(header);
+            if let Some(hi) = self.trivia.as_ref().map(|trivia| trivia.token_end_before_spec(&contracts[0])) {
+                self.trailing(hi);
+            }
+            self.specs(contracts);
+            None
+        };
+        match body.kind {
+            StmtKind::Block(ref b) => {
+                self.open(header);
+                self.block_body(b);
+                self.line("}");
+            }
+            _ => {
+                if let Some(header) = header {
+                    self.line_doc(header);
+                    self.trailing_before(body.span.lo);
+                }
+                self.indented(body);
+            }
+        }
+    }
+
+    fn stmt(&mut self, s: &Stmt) {
+        self.leading(s.span.lo, false);
+        match s.kind {
+            StmtKind::Simple(ref simple) => self.line_doc(cat(vec![simple_doc(simple), text(";")])),
+            StmtKind::If(..) => self.if_stmt(s),
+            StmtKind::While(ref cond, ref invariants, ref body) => {
+                let header = cat(vec![text("while ("), exp_doc(cond, 0), text(")")]);
+                self.body(header, invariants, body)
+            }
+            StmtKind::For(ref init, ref cond, ref step, ref invariants, ref body) => {
+                let mut header = vec![text("for (")];
+                if let Some(ref init) = *init {
+                    header.push(simple_doc(init));
+                }
+                header.push(text("; "));
+                header.push(exp_doc(cond, 0));
+                header.push(text(";"));
+                if let Some(ref step) = *step {
+                    header.push(text(" "));
+                    header.push(simple_doc(step));
+                }
+                header.push(text(")"));
+                self.body(cat(header), invariants, body)
+            }
+            StmtKind::Return(None) => self.line("return;"),
+            StmtKind::Return(Some(ref e)) => self.line_doc(cat(vec![text("return "), exp_doc(e, 0), text(";")])),
+            StmtKind::Break => self.line("break;"),
+            StmtKind::Continue => self.line("continue;"),
+            StmtKind::Block(ref b) => {
+                self.open(None);
+                self.block_body(b);
+                self.line("}");
+            }
+            StmtKind::Assert(ref e) => self.line_doc(cat(vec![text("assert("), exp_doc(e, 0), text(");")])),
+            StmtKind::Error(ref e) => self.line_doc(cat(vec![text("error("), exp_doc(e, 0), text(");")])),
+            StmtKind::Annotation(ref specs) => {
+                self.specs(specs);
+                // A line annotation ends with the newline, so its last token is the contract before
+                self.trailing_before(s.span.hi);
+                return;
+            }
+        }
+        self.trailing(s.span.hi);
+    }
+
+    /*
+    if (a) {
+      ...
+    } else if (b) {
+      ...
+    } else {
+      ...
+    }
+    Branches which are not blocks go on their own indented line, unless the
+    branch would capture the else of the statement being printed.
+     */
+    fn if_stmt(&mut self, s: &Stmt) {
+        let mut current = s;
+        let mut prefix = "";
+        loop {
+            let (cond, then, otherwise) = match current.kind {
+                StmtKind::If(ref cond, ref then, ref otherwise) => (cond, then, otherwise),
+                _ => unreachable!(),
+            };
+            let header = cat(vec![text(format!("{}if (", prefix)), exp_doc(cond, 0), text(")")]);
+            // Whether we have opened a brace the else has to close
+            let braced = match then.kind {
+                StmtKind::Block(ref b) => {
+                    self.open(Some(header));
+                    self.block_body(b);
+                    true
+                }
+                _ if otherwise.is_some() && dangles(then) => {
+                    self.open(Some(header));
+                    self.indented(then);
+                    true
+                }
+                _ => {
+                    self.line_doc(header);
+                    self.trailing_before(then.span.lo);
+                    self.indented(then);
+                    false
+                }
+            };
+            let else_header = if braced { "} else" } else { "else" };
+            let otherwise = match *otherwise {
+                Some(ref otherwise) => otherwise,
+                None => {
+                    if braced {
+                        self.line("}");
+                    }
+                    return;
+                }
+            };
+            match otherwise.kind {
+                StmtKind::If(..) => {
+                    prefix = if braced { "} else " } else { "else " };
+                    current = otherwise;
+                }
+                StmtKind::Block(ref b) => {
+                    self.open(Some(text(else_header)));
+                    self.block_body(b);
+                    self.line("}");
+                    return;
+                }
+                _ => {
+                    self.line(else_header);
+                    self.trailing_before(otherwise.span.lo);
+                    self.indented(otherwise);
+                    return;
+                }
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use lexer::lexer::Lexer;
+    use parser::parser::Parser;
+    use std::fs;
+
+    fn parse(text: &str) -> Program {
+        Parser::new(Lexer::new(0, text).tokens().unwrap())
+            .parseProgram()
+            .unwrap()
+    }
+
+    fn round_trip(path: &str) -> String {
+        let original = parse(&fs::read_to_string(path).unwrap());
+        let printed = program(&original);
+        let reparsed = parse(&printed);
+        assert_eq!(original, reparsed, "{}", printed);
+        // Printing is idempotent
+        assert_eq!(printed, program(&reparsed));
+        printed
+    }
+
+    #[test]
+    fn round_tripping() {
+        round_trip("./src/parser/tests/program.c0");
+        round_trip("./src/ast/tests/pow.c0");
+        round_trip("./src/ast/tests/pow_compact.c0");
+        round_trip("./src/lexer/tests/exp.c0");
+    }
+
+    #[test]
+    fn printing_canonically() {
+        let printed = round_trip("./src/ast/tests/messy.c0");
+        assert_eq!(printed, fs::read_to_string("./src/ast/tests/messy.pretty.c0").unwrap());
+    }
+
+    #[test]
+    fn minimal_parentheses() {
+        let cases = vec![
+            ("(1 + 2) * 3", "(1 + 2) * 3"),
+            ("1 + (2 * 3)", "1 + 2 * 3"),
+            ("(1 - 2) - 3", "1 - 2 - 3"),
+            ("1 - (2 - 3)", "1 - (2 - 3)"),
+            ("(a ? b : c) ? d : e", "(a ? b : c) ? d : e"),
+            ("a ? b : (c ? d : e)", "a ? b : c ? d : e"),
+            ("(*p).x", "(*p).x"),
+            ("*(p->x)", "*p->x"),
+            ("-(-x)", "- -x"),
+            ("-(-2147483648)", "- -2147483648"),
+            ("!(a && b)", "!(a && b)"),
+            ("(a[1])[2]", "a[1][2]"),
+            ("'\\n' == (\"a\\\"b\" == s)", "'\\n' == (\"a\\\"b\" == s)"),
+            ("0xff & (x << 2)", "0xFF & x << 2"),
+        ];
+        for (source, expected) in cases {
+            let e = parse(&format!("int f() {{ return {}; }}", source));
+            let body = match e.decls[0].kind {
+                GDeclKind::Function(ref f) => f.body.clone().unwrap(),
+                _ => unreachable!(),
+            };
+            match body.stmts[0].kind {
+                StmtKind::Return(Some(ref e)) => assert_eq!(exp(e), expected),
+                _ => unreachable!(),
+            }
+        }
+    }
+}
@@ -0,0 +1,523 @@
+use ast::ast::*;
+
+/*
+Traversals of the syntax tree. A pass implements `Visitor` to read the tree
+or `VisitorMut` to change it in place, overriding the methods for the nodes
+it cares about. Every method defaults to the matching `walk` function, which
+visits the node's children in source order, so an override that still wants
+the children visited calls the walk function itself.
+
+    struct Calls(usize);
+
+    impl Visitor for Calls {
+        fn visit_exp(&mut self, e: &Exp) {
+            if let ExpKind::Call(..) = e.kind {
+                self.0 += 1;
+            }
+            walk_exp(self, e)
+        }
+    }
+
+`ast::fold` rebuilds a tree instead. The semantic passes and `ast::dump` are
+visitors, and the interpreter rebinds the contracts of its functions in place.
+`ast::pretty` recurses by itself, as how it lays out a node depends on where
+the node is.
+ */
+pub trait Visitor {
+    fn visit_program(&mut self, p: &Program) {
+        walk_program(self, p)
+    }
+
+    fn visit_gdecl(&mut self, d: &GDecl) {
+        walk_gdecl(self, d)
+    }
+
+    fn visit_field(&mut self, f: &Field) {
+        walk_field(self, f)
+    }
+
+    fn visit_function(&mut self, f: &Function) {
+        walk_function(self, f)
+    }
+
+    fn visit_param(&mut self, p: &Param) {
+        walk_param(self, p)
+    }
+
+    fn visit_spec(&mut self, s: &Spec) {
+        walk_spec(self, s)
+    }
+
+    fn visit_block(&mut self, b: &Block) {
+        walk_block(self, b)
+    }
+
+    fn visit_stmt(&mut self, s: &Stmt) {
+        walk_stmt(self, s)
+    }
+
+    fn visit_simple(&mut self, s: &Simple) {
+        walk_simple(self, s)
+    }
+
+    fn visit_tp(&mut self, t: &Tp) {
+        walk_tp(self, t)
+    }
+
+    fn visit_exp(&mut self, e: &Exp) {
+        walk_exp(self, e)
+    }
+
+    // Every identifier, whether it declares or uses a name
+    fn visit_ident(&mut self, _x: &Ident) {}
+}
+
+pub fn walk_program<V: Visitor + ?Sized>(v: &mut V, p: &Program) {
+    for d in &p.decls {
+        v.visit_gdecl(d);
+    }
+}
+
+pub fn walk_gdecl<V: Visitor + ?Sized>(v: &mut V, d: &GDecl) {
+    match d.kind {
+        GDeclKind::Use(_) => (),
+        GDeclKind::StructDecl(ref name) => v.visit_ident(name),
+        GDeclKind::StructDef(ref name, ref fields) => {
+            v.visit_ident(name);
+            for f in fields {
+                v.visit_field(f);
+            }
+        }
+        GDeclKind::Function(ref f) => v.visit_function(f),
+        GDeclKind::Typedef(ref t, ref name) => {
+            v.visit_tp(t);
+            v.visit_ident(name);
+        }
+    }
+}
+
+pub fn walk_field<V: Visitor + ?Sized>(v: &mut V, f: &Field) {
+    v.visit_tp(&f.tp);
+    v.visit_ident(&f.name);
+}
+
+pub fn walk_function<V: Visitor + ?Sized>(v: &mut V, f: &Function) {
+    v.visit_tp(&f.ret);
+    v.visit_ident(&f.name);
+    for p in &f.params {
+        v.visit_param(p);
+    }
+    for s in &f.specs {
+        v.visit_spec(s);
+    }
+    if let Some(ref body) = f.body {
+        v.visit_block(body);
+    }
+}
+
+pub fn walk_param<V: Visitor + ?Sized>(v: &mut V, p: &Param) {
+    v.visit_tp(&p.tp);
+    v.visit_ident(&p.name);
+}
+
+pub fn walk_spec<V: Visitor + ?Sized>(v: &mut V, s: &Spec) {
+    v.visit_exp(&s.exp);
+}
+
+pub fn walk_block<V: Visitor + ?Sized>(v: &mut V, b: &Block) {
+    for s in &b.stmts {
+        v.visit_stmt(s);
+    }
+}
+
+pub fn walk_stmt<V: Visitor + ?Sized>(v: &mut V, s: &Stmt) {
+    match s.kind {
+        StmtKind::Simple(ref simple) => v.visit_simple(simple),
+        StmtKind::If(ref cond, ref then, ref otherwise) => {
+            v.visit_exp(cond);
+            v.visit_stmt(then);
+            if let Some(ref otherwise) = *otherwise {
+                v.visit_stmt(otherwise);
+            }
+        }
+        StmtKind::While(ref cond, ref invariants, ref body) => {
+            v.visit_exp(cond);
+            for s in invariants {
+                v.visit_spec(s);
+            }
+            v.visit_stmt(body);
+        }
+        StmtKind::For(ref init, ref cond, ref step, ref invariants, ref body) => {
+            if let Some(ref init) = *init {
+                v.visit_simple(init);
+            }
+            v.visit_exp(cond);
+            if let Some(ref step) = *step {
+                v.visit_simple(step);
+            }
+            for s in invariants {
+                v.visit_spec(s);
+            }
+            v.visit_stmt(body);
+        }
+        StmtKind::Return(ref e) => {
+            if let Some(ref e) = *e {
+                v.visit_exp(e);
+            }
+        }
+        StmtKind::Block(ref b) => v.visit_block(b),
+        StmtKind::Break | StmtKind::Continue => (),
+        StmtKind::Assert(ref e) | StmtKind::Error(ref e) => v.visit_exp(e),
+        StmtKind::Annotation(ref specs) => {
+            for s in specs {
+                v.visit_spec(s);
+            }
+        }
+    }
+}
+
+pub fn walk_simple<V: Visitor + ?Sized>(v: &mut V, s: &Simple) {
+    match s.kind {
+        SimpleKind::Assign(_, ref lhs, ref rhs) => {
+            v.visit_exp(lhs);
+            v.visit_exp(rhs);
+        }
+        SimpleKind::Post(_, ref lhs) => v.visit_exp(lhs),
+        SimpleKind::Exp(ref e) => v.visit_exp(e),
+        SimpleKind::Decl(ref t, ref name, ref init) => {
+            v.visit_tp(t);
+            v.visit_ident(name);
+            if let Some(ref init) = *init {
+                v.visit_exp(init);
+            }
+        }
+    }
+}
+
+pub fn walk_tp<V: Visitor + ?Sized>(v: &mut V, t: &Tp) {
+    match t.kind {
+        TpKind::Int | TpKind::Bool | TpKind::String | TpKind::Char | TpKind::Void => (),
+        TpKind::Pointer(ref inner) | TpKind::Array(ref inner) => v.visit_tp(inner),
+        TpKind::Struct(ref name) | TpKind::Name(ref name) => v.visit_ident(name),
+    }
+}
+
+pub fn walk_exp<V: Visitor + ?Sized>(v: &mut V, e: &Exp) {
+    match e.kind {
+        ExpKind::Num(..)
+        | ExpKind::Bool(_)
+        | ExpKind::Null
+        | ExpKind::Char(_)
+        | ExpKind::Str(_)
+        | ExpKind::Result => (),
+        ExpKind::Var(ref x) => v.visit_ident(x),
+        ExpKind::Unop(_, ref operand) => v.visit_exp(operand),
+        ExpKind::Binop(_, ref l, ref r) => {
+            v.visit_exp(l);
+            v.visit_exp(r);
+        }
+        ExpKind::Ternary(ref c, ref t, ref f) => {
+            v.visit_exp(c);
+            v.visit_exp(t);
+            v.visit_exp(f);
+        }
+        ExpKind::Call(ref f, ref args) => {
+            v.visit_ident(f);
+            for arg in args {
+                v.visit_exp(arg);
+            }
+        }
+        ExpKind::Field(ref s, ref f) | ExpKind::Arrow(ref s, ref f) => {
+            v.visit_exp(s);
+            v.visit_ident(f);
+        }
+        ExpKind::Index(ref a, ref i) => {
+            v.visit_exp(a);
+            v.visit_exp(i);
+        }
+        ExpKind::Alloc(ref t) => v.visit_tp(t),
+        ExpKind::AllocArray(ref t, ref len) => {
+            v.visit_tp(t);
+            v.visit_exp(len);
+        }
+        ExpKind::Length(ref a) => v.visit_exp(a),
+    }
+}
+
+// `Visitor` over a tree that may be changed in place
+pub trait VisitorMut {
+    fn visit_program_mut(&mut self, p: &mut Program) {
+        walk_program_mut(self, p)
+    }
+
+    fn visit_gdecl_mut(&mut self, d: &mut GDecl) {
+        walk_gdecl_mut(self, d)
+    }
+
+    fn visit_field_mut(&mut self, f: &mut Field) {
+        walk_field_mut(self, f)
+    }
+
+    fn visit_function_mut(&mut self, f: &mut Function) {
+        walk_function_mut(self, f)
+    }
+
+    fn visit_param_mut(&mut self, p: &mut Param) {
+        walk_param_mut(self, p)
+    }
+
+    fn visit_spec_mut(&mut self, s: &mut Spec) {
+        walk_spec_mut(self, s)
+    }
+
+    fn visit_block_mut(&mut self, b: &mut Block) {
+        walk_block_mut(self, b)
+    }
+
+    fn visit_stmt_mut(&mut self, s: &mut Stmt) {
+        walk_stmt_mut(self, s)
+    }
+
+    fn visit_simple_mut(&mut self, s: &mut Simple) {
+        walk_simple_mut(self, s)
+    }
+
+    fn visit_tp_mut(&mut self, t: &mut Tp) {
+        walk_tp_mut(self, t)
+    }
+
+    fn visit_exp_mut(&mut self, e: &mut Exp) {
+        walk_exp_mut(self, e)
+    }
+
+    fn visit_ident_mut(&mut self, _x: &mut Ident) {}
+}
+
+pub fn walk_program_mut<V: VisitorMut + ?Sized>(v: &mut V, p: &mut Program) {
+    for d in &mut p.decls {
+        v.visit_gdecl_mut(d);
+    }
+}
+
+pub fn walk_gdecl_mut<V: VisitorMut + ?Sized>(v: &mut V, d: &mut GDecl) {
+    match d.kind {
+        GDeclKind::Use(_) => (),
+        GDeclKind::StructDecl(ref mut name) => v.visit_ident_mut(name),
+        GDeclKind::StructDef(ref mut name, ref mut fields) => {
+            v.visit_ident_mut(name);
+            for f in fields {
+                v.visit_field_mut(f);
+            }
+        }
+        GDeclKind::Function(ref mut f) => v.visit_function_mut(f),
+        GDeclKind::Typedef(ref mut t, ref mut name) => {
+            v.visit_tp_mut(t);
+            v.visit_ident_mut(name);
+        }
+    }
+}
+
+pub fn walk_field_mut<V: VisitorMut + ?Sized>(v: &mut V, f: &mut Field) {
+    v.visit_tp_mut(&mut f.tp);
+    v.visit_ident_mut(&mut f.name);
+}
+
+pub fn walk_function_mut<V: VisitorMut + ?Sized>(v: &mut V, f: &mut Function) {
+    v.visit_tp_mut(&mut f.ret);
+    v.visit_ident_mut(&mut f.name);
+    for p in &mut f.params {
+        v.visit_param_mut(p);
+    }
+    for s in &mut f.specs {
+        v.visit_spec_mut(s);
+    }
+    if let Some(ref mut body) = f.body {
+        v.visit_block_mut(body);
+    }
+}
+
+pub fn walk_param_mut<V: VisitorMut + ?Sized>(v: &mut V, p: &mut Param) {
+    v.visit_tp_mut(&mut p.tp);
+    v.visit_ident_mut(&mut p.name);
+}
+
+pub fn walk_spec_mut<V: VisitorMut + ?Sized>(v: &mut V, s: &mut Spec) {
+    v.visit_exp_mut(&mut s.exp);
+}
+
+pub fn walk_block_mut<V: VisitorMut + ?Sized>(v: &mut V, b: &mut Block) {
+    for s in &mut b.stmts {
+        v.visit_stmt_mut(s);
+    }
+}
+
+pub fn walk_stmt_mut<V: VisitorMut + ?Sized>(v: &mut V, s: &mut Stmt) {
+    match s.kind {
+        StmtKind::Simple(ref mut simple) => v.visit_simple_mut(simple),
+        StmtKind::If(ref mut cond, ref mut then, ref mut otherwise) => {
+            v.visit_exp_mut(cond);
+            v.visit_stmt_mut(then);
+            if let Some(ref mut otherwise) = *otherwise {
+                v.visit_stmt_mut(otherwise);
+            }
+        }
+        StmtKind::While(ref mut cond, ref mut invariants, ref mut body) => {
+            v.visit_exp_mut(cond);
+            for s in invariants {
+                v.visit_spec_mut(s);
+            }
+            v.visit_stmt_mut(body);
+        }
+        StmtKind::For(ref mut init, ref mut cond, ref mut step, ref mut invariants, ref mut body) => {
+            if let Some(ref mut init) = *init {
+                v.visit_simple_mut(init);
+            }
+            v.visit_exp_mut(cond);
+            if let Some(ref mut step) = *step {
+                v.visit_simple_mut(step);
+            }
+            for s in invariants {
+                v.visit_spec_mut(s);
+            }
+            v.visit_stmt_mut(body);
+        }
+        StmtKind::Return(ref mut e) => {
+            if let Some(ref mut e) = *e {
+                v.visit_exp_mut(e);
+            }
+        }
+        StmtKind::Block(ref mut b) => v.visit_block_mut(b),
+        StmtKind::Break | StmtKind::Continue => (),
+        StmtKind::Assert(ref mut e) | StmtKind::Error(ref mut e) => v.visit_exp_mut(e),
+        StmtKind::Annotation(ref mut specs) => {
+            for s in specs {
+                v.visit_spec_mut(s);
+            }
+        }
+    }
+}
+
+pub fn walk_simple_mut<V: VisitorMut + ?Sized>(v: &mut V, s: &mut Simple) {
+    match s.kind {
+        SimpleKind::Assign(_, ref mut lhs, ref mut rhs) => {
+            v.visit_exp_mut(lhs);
+            v.visit_exp_mut(rhs);
+        }
+        SimpleKind::Post(_, ref mut lhs) => v.visit_exp_mut(lhs),
+        SimpleKind::Exp(ref mut e) => v.visit_exp_mut(e),
+        SimpleKind::Decl(ref mut t, ref mut name, ref mut init) => {
+            v.visit_tp_mut(t);
+            v.visit_ident_mut(name);
+            if let Some(ref mut init) = *init {
+                v.visit_exp_mut(init);
+            }
+        }
+    }
+}
+
+pub fn walk_tp_mut<V: VisitorMut + ?Sized>(v: &mut V, t: &mut Tp) {
+    match t.kind {
+        TpKind::Int | TpKind::Bool | TpKind::String | TpKind::Char | TpKind::Void => (),
+        TpKind::Pointer(ref mut inner) | TpKind::Array(ref mut inner) => v.visit_tp_mut(inner),
+        TpKind::Struct(ref mut name) | TpKind::Name(ref mut name) => v.visit_ident_mut(name),
+    }
+}
+
+pub fn walk_exp_mut<V: VisitorMut + ?Sized>(v: &mut V, e: &mut Exp) {
+    match e.kind {
+        ExpKind::Num(..)
+        | ExpKind::Bool(_)
+        | ExpKind::Null
+        | ExpKind::Char(_)
+        | ExpKind::Str(_)
+        | ExpKind::Result => (),
+        ExpKind::Var(ref mut x) => v.visit_ident_mut(x),
+        ExpKind::Unop(_, ref mut operand) => v.visit_exp_mut(operand),
+        ExpKind::Binop(_, ref mut l, ref mut r) => {
+            v.visit_exp_mut(l);
+            v.visit_exp_mut(r);
+        }
+        ExpKind::Ternary(ref mut c, ref mut t, ref mut f) => {
+            v.visit_exp_mut(c);
+            v.visit_exp_mut(t);
+            v.visit_exp_mut(f);
+        }
+        ExpKind::Call(ref mut f, ref mut args) => {
+            v.visit_ident_mut(f);
+            for arg in args {
+                v.visit_exp_mut(arg);
+            }
+        }
+        ExpKind::Field(ref mut s, ref mut f) | ExpKind::Arrow(ref mut s, ref mut f) => {
+            v.visit_exp_mut(s);
+            v.visit_ident_mut(f);
+        }
+        ExpKind::Index(ref mut a, ref mut i) => {
+            v.visit_exp_mut(a);
+            v.visit_exp_mut(i);
+        }
+        ExpKind::Alloc(ref mut t) => v.visit_tp_mut(t),
+        ExpKind::AllocArray(ref mut t, ref mut len) => {
+            v.visit_tp_mut(t);
+            v.visit_exp_mut(len);
+        }
+        ExpKind::Length(ref mut a) => v.visit_exp_mut(a),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use ast::pretty;
+    use lexer::lexer::Lexer;
+    use parser::parser::Parser;
+
+    fn parse(text: &str) -> Program {
+        Parser::new(Lexer::new(0, text).tokens().unwrap())
+            .parseProgram()
+            .unwrap()
+    }
+
+    // The names of every identifier, in the order visited
+    struct Names(Vec<String>);
+
+    impl Visitor for Names {
+        fn visit_ident(&mut self, x: &Ident) {
+            self.0.push(x.name.clone());
+        }
+    }
+
+    #[test]
+    fn visiting_in_source_order() {
+        let p = parse(
+            "typedef struct s* t;\n\
+             int f(t x, int n)\n//@requires n > 0;\n\
+             { for (int i = 0; i < n; i++) x->a[i] = g(n);\n//@assert \\length(x->a) > 0;\n}",
+        );
+        let mut names = Names(Vec::new());
+        names.visit_program(&p);
+        assert_eq!(
+            names.0,
+            vec!["s", "t", "f", "t", "x", "n", "n", "i", "i", "n", "i", "x", "a", "i", "g", "n", "x", "a"]
+        );
+    }
+
+    // Swaps the operands of every addition
+    struct Commute;
+
+    impl VisitorMut for Commute {
+        fn visit_exp_mut(&mut self, e: &mut Exp) {
+            walk_exp_mut(self, e);
+            if let ExpKind::Binop(BinOp::Add, ref mut l, ref mut r) = e.kind {
+                ::std::mem::swap(l, r);
+            }
+        }
+    }
+
+    #[test]
+    fn changing_in_place() {
+        let mut p = parse("int f(int a) { return a + (1 + f(a + 2)); }");
+        Commute.visit_program_mut(&mut p);
+        assert_eq!(pretty::program(&p), "int f(int a) {\n  return f(2 + a) + 1 + a;\n}\n");
+    }
+}
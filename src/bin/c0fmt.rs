@@ -0,0 +1,9 @@
+extern crate c0;
+
+use std::env;
+use std::process;
+
+fn main() {
+    let args: Vec<String> = env::args().skip(1).collect();
+    process::exit(c0::driver::fmt::main(&args))
+}
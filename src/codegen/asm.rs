@@ -0,0 +1,479 @@
+use std::fmt;
+
+/*
+x86-64 instructions over virtual registers, as instruction selection
+produces them and register allocation rewrites them.
+
+Instructions have the operand order of AT&T syntax, source before
+destination. A virtual register holds any value: `int`s and bools and chars
+are kept in the low 32 bits, and what is above them is never read, so
+32-bit instructions work on all of them and only memory is accessed by the
+byte. Registers are assigned, and stack slots placed, before the function
+is printed.
+ */
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub enum Reg {
+    Rax,
+    Rcx,
+    Rdx,
+    Rbx,
+    Rsi,
+    Rdi,
+    R8,
+    R9,
+    R10,
+    R11,
+    R12,
+    R13,
+    R14,
+    R15,
+    Rsp,
+    Rbp,
+}
+
+// Where the System V ABI passes the first six integer arguments
+pub const ARGS: [Reg; 6] = [Reg::Rdi, Reg::Rsi, Reg::Rdx, Reg::Rcx, Reg::R8, Reg::R9];
+
+// The registers a call may overwrite
+pub const CALLER_SAVED: [Reg; 9] =
+    [Reg::Rax, Reg::Rcx, Reg::Rdx, Reg::Rsi, Reg::Rdi, Reg::R8, Reg::R9, Reg::R10, Reg::R11];
+
+// The registers a function must give back as it found them, besides %rsp and %rbp
+pub const CALLEE_SAVED: [Reg; 5] = [Reg::Rbx, Reg::R12, Reg::R13, Reg::R14, Reg::R15];
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Hash)]
+pub enum Width {
+    B,
+    L,
+    Q,
+}
+
+impl Width {
+    fn suffix(self) -> &'static str {
+        match self {
+            Width::B => "b",
+            Width::L => "l",
+            Width::Q => "q",
+        }
+    }
+}
+
+impl Reg {
+    pub fn name(self, width: Width) -> &'static str {
+        const NAMES: [[&str; 3]; 16] = [
+            ["%al", "%eax", "%rax"],
+            ["%cl", "%ecx", "%rcx"],
+            ["%dl", "%edx", "%rdx"],
+            ["%bl", "%ebx", "%rbx"],
+            ["%sil", "%esi", "%rsi"],
+            ["%dil", "%edi", "%rdi"],
+            ["%r8b", "%r8d", "%r8"],
+            ["%r9b", "%r9d", "%r9"],
+            ["%r10b", "%r10d", "%r10"],
+            ["%r11b", "%r11d", "%r11"],
+            ["%r12b", "%r12d", "%r12"],
+            ["%r13b", "%r13d", "%r13"],
+            ["%r14b", "%r14d", "%r14"],
+            ["%r15b", "%r15d", "%r15"],
+            ["%spl", "%esp", "%rsp"],
+            ["%bpl", "%ebp", "%rbp"],
+        ];
+        let i = match width {
+            Width::B => 0,
+            Width::L => 1,
+            Width::Q => 2,
+        };
+        NAMES[self as usize][i]
+    }
+}
+
+pub type Virt = usize;
+
+// A register, before or after allocation
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub enum Loc {
+    Reg(Reg),
+    Virt(Virt),
+}
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Hash)]
+pub enum Operand {
+    Loc(Loc),
+    Imm(i64),
+    // disp(base, index, scale)
+    Mem {
+        base: Loc,
+        index: Option<(Loc, u8)>,
+        disp: i64,
+    },
+    // The n'th stack slot, holding a spilled virtual register
+    Slot(usize),
+    // The n'th argument passed on the stack, to this function or to the one it calls next
+    InArg(usize),
+    OutArg(usize),
+    // The address of the n'th string constant
+    Str(usize),
+}
+
+impl Operand {
+    pub fn virt(v: Virt) -> Operand {
+        Operand::Loc(Loc::Virt(v))
+    }
+
+    pub fn reg(r: Reg) -> Operand {
+        Operand::Loc(Loc::Reg(r))
+    }
+
+    // The memory at `base`, offset by `disp`
+    pub fn at(base: Loc, disp: i64) -> Operand {
+        Operand::Mem { base, index: None, disp }
+    }
+
+    // The registers the operand reads, to be an address or a value
+    fn locs(&self) -> Vec<Loc> {
+        match *self {
+            Operand::Loc(l) => vec![l],
+            Operand::Mem { base, index, .. } => {
+                let mut locs = vec![base];
+                locs.extend(index.map(|(i, _)| i));
+                locs
+            }
+            _ => Vec::new(),
+        }
+    }
+
+    fn locs_mut(&mut self) -> Vec<&mut Loc> {
+        match *self {
+            Operand::Loc(ref mut l) => vec![l],
+            Operand::Mem { ref mut base, ref mut index, .. } => {
+                let mut locs = vec![base];
+                locs.extend(index.as_mut().map(|&mut (ref mut i, _)| i));
+                locs
+            }
+            _ => Vec::new(),
+        }
+    }
+}
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum Alu {
+    Add,
+    Sub,
+    Imul,
+    And,
+    Or,
+    Xor,
+}
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum Shift {
+    Sal,
+    Sar,
+}
+
+// Conditions on the flags of a comparison, signed but for `B`, `Be`, `A` and `Ae`
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum Cond {
+    E,
+    Ne,
+    L,
+    Le,
+    G,
+    Ge,
+    B,
+    Be,
+    A,
+    Ae,
+}
+
+pub type Label = usize;
+
+#[derive(Clone, Debug, PartialEq)]
+pub enum Instr {
+    Mov(Width, Operand, Operand),
+    // movzbl, zero-extending a byte
+    Movzb(Operand, Loc),
+    // movslq, sign-extending an int
+    Movsl(Operand, Loc),
+    Lea(Operand, Loc),
+    Alu(Alu, Width, Operand, Loc),
+    Neg(Width, Loc),
+    Not(Width, Loc),
+    // By an immediate or %cl
+    Shift(Shift, Width, Operand, Loc),
+    // Sign-extends %eax into %edx
+    Cltd,
+    // Divides %edx:%eax, the quotient in %eax and remainder in %edx
+    Idiv(Width, Loc),
+    // Sets the flags for the second operand minus the first
+    Cmp(Width, Operand, Operand),
+    // Sets the low byte
+    Set(Cond, Loc),
+    Jmp(Label),
+    J(Cond, Label),
+    Label(Label),
+    // A function with its first n arguments in registers
+    Call(String, usize),
+    // A runtime function which stops the program
+    Trap(&'static str, usize),
+    // With the result in %rax, if there is one
+    Ret(bool),
+}
+
+impl Instr {
+    // The registers read
+    pub fn uses(&self) -> Vec<Loc> {
+        let regs = |rs: &[Reg]| rs.iter().map(|&r| Loc::Reg(r)).collect::<Vec<Loc>>();
+        match *self {
+            Instr::Mov(_, ref s, ref d) => {
+                let mut locs = s.locs();
+                if let Operand::Mem { .. } = *d {
+                    locs.extend(d.locs());
+                }
+                locs
+            }
+            Instr::Movzb(ref s, _) | Instr::Movsl(ref s, _) => s.locs(),
+            Instr::Lea(ref s, _) => s.locs(),
+            Instr::Alu(_, _, ref s, d) | Instr::Shift(_, _, ref s, d) => {
+                let mut locs = s.locs();
+                locs.push(d);
+                locs
+            }
+            Instr::Neg(_, d) | Instr::Not(_, d) => vec![d],
+            Instr::Cltd => regs(&[Reg::Rax]),
+            Instr::Idiv(_, d) => {
+                let mut locs = regs(&[Reg::Rax, Reg::Rdx]);
+                locs.push(d);
+                locs
+            }
+            Instr::Cmp(_, ref a, ref b) => {
+                let mut locs = a.locs();
+                locs.extend(b.locs());
+                locs
+            }
+            Instr::Call(_, n) | Instr::Trap(_, n) => regs(&ARGS[..n]),
+            Instr::Ret(true) => regs(&[Reg::Rax]),
+            Instr::Set(..) | Instr::Jmp(_) | Instr::J(..) | Instr::Label(_) | Instr::Ret(false) => Vec::new(),
+        }
+    }
+
+    // The registers written
+    pub fn defs(&self) -> Vec<Loc> {
+        match *self {
+            Instr::Mov(_, _, Operand::Loc(d))
+            | Instr::Movzb(_, d)
+            | Instr::Movsl(_, d)
+            | Instr::Lea(_, d)
+            | Instr::Alu(_, _, _, d)
+            | Instr::Neg(_, d)
+            | Instr::Not(_, d)
+            | Instr::Shift(_, _, _, d)
+            | Instr::Set(_, d) => vec![d],
+            Instr::Cltd => vec![Loc::Reg(Reg::Rdx)],
+            Instr::Idiv(..) => vec![Loc::Reg(Reg::Rax), Loc::Reg(Reg::Rdx)],
+            Instr::Call(..) => CALLER_SAVED.iter().map(|&r| Loc::Reg(r)).collect(),
+            _ => Vec::new(),
+        }
+    }
+
+    // Every register mentioned, to be renamed
+    pub fn locs_mut(&mut self) -> Vec<&mut Loc> {
+        match *self {
+            Instr::Mov(_, ref mut s, ref mut d) | Instr::Cmp(_, ref mut s, ref mut d) => {
+                let mut locs = s.locs_mut();
+                locs.extend(d.locs_mut());
+                locs
+            }
+            Instr::Movzb(ref mut s, ref mut d)
+            | Instr::Movsl(ref mut s, ref mut d)
+            | Instr::Lea(ref mut s, ref mut d)
+            | Instr::Alu(_, _, ref mut s, ref mut d)
+            | Instr::Shift(_, _, ref mut s, ref mut d) => {
+                let mut locs = s.locs_mut();
+                locs.push(d);
+                locs
+            }
+            Instr::Neg(_, ref mut d)
+            | Instr::Not(_, ref mut d)
+            | Instr::Idiv(_, ref mut d)
+            | Instr::Set(_, ref mut d) => vec![d],
+            _ => Vec::new(),
+        }
+    }
+}
+
+// A function's instructions and what is needed to lay out its frame
+#[derive(Clone, Debug, PartialEq)]
+pub struct Function {
+    // The symbol
+    pub name: String,
+    pub instrs: Vec<Instr>,
+    pub virts: usize,
+    pub labels: usize,
+    // The most arguments a call passes on the stack
+    pub out_args: usize,
+    // Set by allocation: the slots used, and the callee-saved registers to save
+    pub slots: usize,
+    pub saved: Vec<Reg>,
+}
+
+impl Function {
+    pub fn virt(&mut self) -> Virt {
+        self.virts += 1;
+        self.virts - 1
+    }
+
+    pub fn label(&mut self) -> Label {
+        self.labels += 1;
+        self.labels - 1
+    }
+}
+
+impl fmt::Display for Cond {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let s = match *self {
+            Cond::E => "e",
+            Cond::Ne => "ne",
+            Cond::L => "l",
+            Cond::Le => "le",
+            Cond::G => "g",
+            Cond::Ge => "ge",
+            Cond::B => "b",
+            Cond::Be => "be",
+            Cond::A => "a",
+            Cond::Ae => "ae",
+        };
+        write!(f, "{}", s)
+    }
+}
+
+impl fmt::Display for Alu {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let s = match *self {
+            Alu::Add => "add",
+            Alu::Sub => "sub",
+            Alu::Imul => "imul",
+            Alu::And => "and",
+            Alu::Or => "or",
+            Alu::Xor => "xor",
+        };
+        write!(f, "{}", s)
+    }
+}
+
+impl fmt::Display for Shift {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            Shift::Sal => write!(f, "sal"),
+            Shift::Sar => write!(f, "sar"),
+        }
+    }
+}
+
+// How a function's instructions are printed, once registers are allocated
+pub struct Frame<'a> {
+    pub function: &'a Function,
+    // The label prefix which makes the function's labels unique
+    pub prefix: &'a str,
+}
+
+impl<'a> Frame<'a> {
+    // The bytes below the saved registers, keeping %rsp 16-byte aligned at calls
+    pub fn size(&self) -> usize {
+        let f = self.function;
+        let bytes = 8 * (f.slots + f.out_args);
+        let saved = 8 * f.saved.len();
+        (bytes + saved).div_ceil(16) * 16 - saved
+    }
+
+    fn loc(&self, l: Loc, width: Width) -> String {
+        match l {
+            Loc::Reg(r) => String::from(r.name(width)),
+            Loc::Virt(v) => panic!("virtual register {} is not allocated", v),
+        }
+    }
+
+    fn operand(&self, a: &Operand, width: Width) -> String {
+        match *a {
+            Operand::Loc(l) => self.loc(l, width),
+            Operand::Imm(n) => format!("${}", n),
+            Operand::Mem { base, index, disp } => {
+                let disp = if disp == 0 { String::new() } else { disp.to_string() };
+                match index {
+                    None => format!("{}({})", disp, self.loc(base, Width::Q)),
+                    Some((i, scale)) => {
+                        format!("{}({}, {}, {})", disp, self.loc(base, Width::Q), self.loc(i, Width::Q), scale)
+                    }
+                }
+            }
+            Operand::Slot(n) => format!("{}(%rbp)", -8 * (self.function.saved.len() + n + 1) as i64),
+            Operand::InArg(n) => format!("{}(%rbp)", 16 + 8 * n),
+            Operand::OutArg(n) => format!("{}(%rsp)", 8 * n),
+            Operand::Str(n) => format!(".LC{}(%rip)", n),
+        }
+    }
+
+    fn label(&self, l: Label) -> String {
+        format!(".L{}_{}", self.prefix, l)
+    }
+
+    // The function as assembly, with its prologue and an epilogue at each return
+    pub fn print(&self) -> String {
+        let f = self.function;
+        let mut out = format!("{}:\n\tpushq %rbp\n\tmovq %rsp, %rbp\n", f.name);
+        for r in &f.saved {
+            out.push_str(&format!("\tpushq {}\n", r.name(Width::Q)));
+        }
+        let size = self.size();
+        if size > 0 {
+            out.push_str(&format!("\tsubq ${}, %rsp\n", size));
+        }
+        for (n, i) in f.instrs.iter().enumerate() {
+            // A jump to the next instruction
+            if let (&Instr::Jmp(l), Some(&Instr::Label(next))) = (i, f.instrs.get(n + 1)) {
+                if l == next {
+                    continue;
+                }
+            }
+            out.push_str(&self.instr(i));
+        }
+        out
+    }
+
+    fn instr(&self, i: &Instr) -> String {
+        let text = match *i {
+            Instr::Mov(w, ref s, ref d) => format!("mov{} {}, {}", w.suffix(), self.operand(s, w), self.operand(d, w)),
+            Instr::Movzb(ref s, d) => format!("movzbl {}, {}", self.operand(s, Width::B), self.loc(d, Width::L)),
+            Instr::Movsl(ref s, d) => format!("movslq {}, {}", self.operand(s, Width::L), self.loc(d, Width::Q)),
+            Instr::Lea(ref s, d) => format!("leaq {}, {}", self.operand(s, Width::Q), self.loc(d, Width::Q)),
+            Instr::Alu(op, w, ref s, d) => format!("{}{} {}, {}", op, w.suffix(), self.operand(s, w), self.loc(d, w)),
+            Instr::Neg(w, d) => format!("neg{} {}", w.suffix(), self.loc(d, w)),
+            Instr::Not(w, d) => format!("not{} {}", w.suffix(), self.loc(d, w)),
+            Instr::Shift(op, w, ref s, d) => {
+                format!("{}{} {}, {}", op, w.suffix(), self.operand(s, Width::B), self.loc(d, w))
+            }
+            Instr::Cltd => String::from("cltd"),
+            Instr::Idiv(w, d) => format!("idiv{} {}", w.suffix(), self.loc(d, w)),
+            Instr::Cmp(w, ref a, ref b) => format!("cmp{} {}, {}", w.suffix(), self.operand(a, w), self.operand(b, w)),
+            Instr::Set(c, d) => format!("set{} {}", c, self.loc(d, Width::B)),
+            Instr::Jmp(l) => format!("jmp {}", self.label(l)),
+            Instr::J(c, l) => format!("j{} {}", c, self.label(l)),
+            Instr::Label(l) => return format!("{}:\n", self.label(l)),
+            Instr::Call(ref name, _) => format!("call {}", name),
+            Instr::Trap(name, _) => format!("call {}", name),
+            Instr::Ret(_) => {
+                let mut text = String::new();
+                if !self.function.saved.is_empty() {
+                    text.push_str(&format!("leaq {}(%rbp), %rsp\n", -8 * self.function.saved.len() as i64));
+                    for r in self.function.saved.iter().rev() {
+                        text.push_str(&format!("\tpopq {}\n", r.name(Width::Q)));
+                    }
+                    text.push('\t');
+                }
+                text.push_str("leave\n\tret");
+                text
+            }
+        };
+        format!("\t{}\n", text)
+    }
+}
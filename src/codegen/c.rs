@@ -0,0 +1,525 @@
+use ast::ast::{BinOp, UnOp};
+use codegen::select::symbol;
+use elab::core::*;
+use lexer::lexer::{escape_char, escape_str};
+use semant::types::Type;
+use std::collections::{HashMap, HashSet};
+use std::mem;
+
+/*
+C99 for a program, from the core language, as cc0 would write it
+
+    #include "c0rt.h"
+
+    int32_t _c0_sum(c0_array *_c0v_A, int32_t _c0v_n);
+
+    int32_t _c0_sum(c0_array *_c0v_A, int32_t _c0v_n) {
+        int32_t _c0v_s;
+        ...
+        while (_c0v_i < _c0v_n) {
+            _c0v_s = c0_add(_c0v_s, *((int32_t *)c0_elem(_c0v_A, _c0v_i)));
+
+Names are prefixed, `_c0_` for functions, structs and fields and `_c0v_`
+for variables, so they cannot clash with C's keywords or the runtime's.
+Values are represented as the runtime library describes, so structs are C
+structs laid out as C0 lays them out and library functions can be called
+directly. Arithmetic which may overflow, and everything C0 checks at
+runtime, are calls of inline functions of the runtime's header.
+
+C leaves the order operands are evaluated in unspecified, where C0 goes left
+to right. An operand which calls a function or may fail is computed into a
+temporary first when an operand after it may do so too. Every expression
+is written as a primary or unary one of C, binary operators parenthesized,
+so it can be an operand as it is.
+ */
+pub fn program(p: &Program) -> String {
+    let externs: HashSet<&str> = p.externs.iter().map(|x| x.name.as_str()).collect();
+    let mut out = String::from("#include \"c0rt.h\"\n");
+    let structs = structs(p);
+    if !structs.is_empty() {
+        out.push('\n');
+        out.push_str(&structs);
+    }
+    out.push('\n');
+    for x in &p.externs {
+        let params: Vec<String> = x.sig.params.iter().map(c_type).collect();
+        let name = format!("{}({})", symbol(&x.name, true), list(params));
+        out.push_str(&format!("{};\n", declare(&x.sig.ret, &name)));
+    }
+    for f in &p.functions {
+        out.push_str(&format!("{};\n", prototype(f, &names(f))));
+    }
+    for f in &p.functions {
+        out.push('\n');
+        out.push_str(&function(f, &externs));
+    }
+    out
+}
+
+fn c_type(ty: &Type) -> String {
+    match *ty {
+        Type::Int => String::from("int32_t"),
+        Type::Bool => String::from("bool"),
+        Type::Char => String::from("char"),
+        Type::String => String::from("c0_string"),
+        Type::Void => String::from("void"),
+        Type::Pointer(ref t) => {
+            let t = c_type(t);
+            if t.ends_with('*') {
+                format!("{}*", t)
+            } else {
+                format!("{} *", t)
+            }
+        }
+        Type::Array(_) => String::from("c0_array *"),
+        Type::Struct(ref s) => format!("struct _c0_{}", s),
+        Type::Null => String::from("void *"),
+    }
+}
+
+// `name` declared with type `ty`, as in `int32_t *p`
+fn declare(ty: &Type, name: &str) -> String {
+    let ty = c_type(ty);
+    if ty.ends_with('*') {
+        format!("{}{}", ty, name)
+    } else {
+        format!("{} {}", ty, name)
+    }
+}
+
+// A parameter list, `void` when there are none
+fn list(params: Vec<String>) -> String {
+    if params.is_empty() {
+        String::from("void")
+    } else {
+        params.join(", ")
+    }
+}
+
+// Structs are declared first, then defined with those they contain before them
+fn structs(p: &Program) -> String {
+    let mut names: Vec<&String> = p.layouts.structs.keys().collect();
+    let mut used = Vec::new();
+    for x in &p.externs {
+        used.extend(x.sig.params.iter().chain(Some(&x.sig.ret)));
+    }
+    for f in &p.functions {
+        used.extend(f.vars.iter().map(|v| &v.ty).chain(Some(&f.ret)));
+    }
+    for ty in used {
+        if let Some(s) = pointee_struct(ty) {
+            names.push(s);
+        }
+    }
+    names.sort();
+    names.dedup();
+    let mut out = String::new();
+    for s in &names {
+        out.push_str(&format!("struct _c0_{};\n", s));
+    }
+    let mut defined = HashSet::new();
+    for s in names {
+        define(p, s, &mut defined, &mut out);
+    }
+    out
+}
+
+// The struct a type refers to, through any pointers and arrays
+fn pointee_struct(ty: &Type) -> Option<&String> {
+    match *ty {
+        Type::Struct(ref s) => Some(s),
+        Type::Pointer(ref t) | Type::Array(ref t) => pointee_struct(t),
+        _ => None,
+    }
+}
+
+fn define<'a>(p: &'a Program, s: &'a str, defined: &mut HashSet<&'a str>, out: &mut String) {
+    let layout = match p.layouts.structs.get(s) {
+        Some(layout) if !defined.contains(s) => layout,
+        _ => return,
+    };
+    defined.insert(s);
+    for field in &layout.fields {
+        if let Type::Struct(ref inner) = field.ty {
+            define(p, inner, defined, out);
+        }
+    }
+    out.push_str(&format!("\nstruct _c0_{} {{\n", s));
+    for field in &layout.fields {
+        out.push_str(&format!("    {};\n", declare(&field.ty, &format!("_c0_{}", field.name))));
+    }
+    // C has no empty structs
+    if layout.fields.is_empty() {
+        out.push_str("    char _c0_empty;\n");
+    }
+    out.push_str("};\n");
+}
+
+// The C name of each variable, told apart by number when names are shared
+fn names(f: &Function) -> Vec<String> {
+    let mut count = HashMap::new();
+    for v in &f.vars {
+        *count.entry(v.name.trim_start_matches('\\')).or_insert(0) += 1;
+    }
+    f.vars
+        .iter()
+        .enumerate()
+        .map(|(i, v)| {
+            let name = v.name.trim_start_matches('\\');
+            if count[name] > 1 {
+                format!("_c0v{}_{}", i, name)
+            } else {
+                format!("_c0v_{}", name)
+            }
+        })
+        .collect()
+}
+
+fn prototype(f: &Function, names: &[String]) -> String {
+    let params: Vec<String> = f.params.iter().map(|&v| declare(&f.vars[v].ty, &names[v])).collect();
+    declare(&f.ret, &format!("{}({})", symbol(&f.name, false), list(params)))
+}
+
+fn function(f: &Function, externs: &HashSet<&str>) -> String {
+    let mut g = Generator {
+        externs,
+        names: names(f),
+        out: String::new(),
+        indent: 0,
+        temps: 0,
+    };
+    let header = format!("{} {{", prototype(f, &g.names));
+    g.line(&header);
+    g.indent += 1;
+    for v in (0..f.vars.len()).filter(|v| !f.params.contains(v)) {
+        let decl = format!("{};", declare(&f.vars[v].ty, &g.names[v]));
+        g.line(&decl);
+    }
+    g.block(&f.body);
+    g.indent -= 1;
+    g.line("}");
+    g.out
+}
+
+struct Generator<'a> {
+    externs: &'a HashSet<&'a str>,
+    names: Vec<String>,
+    out: String,
+    indent: usize,
+    // The temporaries made so far for operands evaluated early
+    temps: usize,
+}
+
+// Whether evaluating the expression calls a function or may fail
+fn effects(e: &Exp) -> bool {
+    match e.kind {
+        ExpKind::Int(_)
+        | ExpKind::Bool(_)
+        | ExpKind::Char(_)
+        | ExpKind::Str(_)
+        | ExpKind::Null
+        | ExpKind::Var(_)
+        | ExpKind::Alloc(_) => false,
+        ExpKind::Call(..) | ExpKind::Load(_) | ExpKind::AddrOf(_) | ExpKind::AllocArray(..) => true,
+        ExpKind::Unop(_, ref a) | ExpKind::Length(ref a) => effects(a),
+        ExpKind::Binop(op, ref a, ref b) => {
+            let fails = match (op, &b.kind) {
+                (BinOp::Div | BinOp::Mod, &ExpKind::Int(n)) => n == 0 || n == -1,
+                (BinOp::Shl | BinOp::Shr, &ExpKind::Int(n)) => !(0..32).contains(&n),
+                (op, _) => matches!(op, BinOp::Div | BinOp::Mod | BinOp::Shl | BinOp::Shr),
+            };
+            fails || effects(a) || effects(b)
+        }
+    }
+}
+
+impl<'a> Generator<'a> {
+    fn line(&mut self, text: &str) {
+        for _ in 0..self.indent {
+            self.out.push_str("    ");
+        }
+        self.out.push_str(text);
+        self.out.push('\n');
+    }
+
+    fn block(&mut self, b: &Block) {
+        for s in b {
+            self.stmt(s);
+        }
+    }
+
+    fn nested(&mut self, b: &Block) {
+        self.indent += 1;
+        self.block(b);
+        self.indent -= 1;
+    }
+
+    // A new temporary holding `value`, computed now
+    fn temp(&mut self, ty: &Type, value: String) -> String {
+        let name = format!("_c0t{}", self.temps);
+        self.temps += 1;
+        let decl = format!("{} = {};", declare(ty, &name), value);
+        self.line(&decl);
+        name
+    }
+
+    fn stmt(&mut self, s: &Stmt) {
+        let text = match s.kind {
+            StmtKind::Assign(v, ref e) => {
+                let e = self.exp(e);
+                format!("{} = {};", self.names[v], e)
+            }
+            StmtKind::Store(ref a, ref e) => {
+                // The address is computed, and checked, before the value
+                let target = if effects(e) {
+                    let pointer = Type::Pointer(Box::new(a.ty.clone()));
+                    let address = self.address(a);
+                    format!("*{}", self.temp(&pointer, address))
+                } else {
+                    self.lvalue(a)
+                };
+                format!("{} = {};", target, self.exp(e))
+            }
+            StmtKind::Eval(ref e) => match e.kind {
+                ExpKind::Call(..) => format!("{};", self.exp(e)),
+                _ => format!("(void){};", self.exp(e)),
+            },
+            StmtKind::If(ref c, ref yes, ref no) => {
+                let header = format!("if ({}) {{", self.condition(c));
+                self.line(&header);
+                self.nested(yes);
+                if !no.is_empty() {
+                    self.line("} else {");
+                    self.nested(no);
+                }
+                return self.line("}");
+            }
+            StmtKind::While(ref c, ref body) => {
+                // A condition which needs statements of its own is tested inside the loop
+                self.indent += 1;
+                let before = mem::take(&mut self.out);
+                let condition = self.condition(c);
+                let setup = mem::replace(&mut self.out, before);
+                self.indent -= 1;
+                if setup.is_empty() {
+                    self.line(&format!("while ({}) {{", condition));
+                } else {
+                    self.line("while (true) {");
+                    self.out.push_str(&setup);
+                    self.indent += 1;
+                    self.line(&format!("if (!({})) break;", condition));
+                    self.indent -= 1;
+                }
+                self.nested(body);
+                return self.line("}");
+            }
+            StmtKind::Break => String::from("break;"),
+            StmtKind::Continue => String::from("continue;"),
+            StmtKind::Return(None) => String::from("return;"),
+            StmtKind::Return(Some(ref e)) => format!("return {};", self.exp(e)),
+            StmtKind::Assert(ref c, _, ref message) => {
+                let c = self.exp(c);
+                format!("if (!{}) c0_assert_failed(\"{}\");", c, c_str(message))
+            }
+            StmtKind::Error(ref e) => format!("c0_error({});", self.exp(e)),
+        };
+        self.line(&text);
+    }
+
+    // An expression tested by `if` or `while`, without the parentheses of a binary operator
+    fn condition(&mut self, e: &Exp) -> String {
+        let c = self.exp(e);
+        match e.kind {
+            ExpKind::Binop(..) if c.starts_with('(') => String::from(&c[1..c.len() - 1]),
+            _ => c,
+        }
+    }
+
+    // The operands in order, those evaluated early held in temporaries
+    fn operands(&mut self, es: &[&Exp]) -> Vec<String> {
+        let mut out = Vec::new();
+        for (n, e) in es.iter().enumerate() {
+            let mut value = self.exp(e);
+            if effects(e) && es[n + 1..].iter().any(|e| effects(e)) {
+                value = self.temp(&e.ty, value);
+            }
+            out.push(value);
+        }
+        out
+    }
+
+    fn exp(&mut self, e: &Exp) -> String {
+        match e.kind {
+            ExpKind::Int(i32::MIN) => String::from("INT32_MIN"),
+            ExpKind::Int(n) => n.to_string(),
+            ExpKind::Bool(b) => b.to_string(),
+            ExpKind::Char(c) => format!("'{}'", escape_char(c)),
+            ExpKind::Str(ref s) => format!("\"{}\"", c_str(s)),
+            ExpKind::Null => String::from("NULL"),
+            ExpKind::Var(v) => self.names[v].clone(),
+            ExpKind::Unop(op, ref a) => {
+                let a = self.exp(a);
+                match op {
+                    UnOp::Neg => format!("c0_neg({})", a),
+                    UnOp::Not => format!("!{}", a),
+                    UnOp::BitNot => format!("~{}", a),
+                    UnOp::Deref => panic!("the core language has loads instead of `*`"),
+                }
+            }
+            ExpKind::Binop(op, ref a, ref b) => {
+                let operands = self.operands(&[a, b]);
+                let (a, b) = (&operands[0], &operands[1]);
+                let checked = match op {
+                    BinOp::Add => "c0_add",
+                    BinOp::Sub => "c0_sub",
+                    BinOp::Mul => "c0_mul",
+                    BinOp::Div => "c0_div",
+                    BinOp::Mod => "c0_mod",
+                    BinOp::Shl => "c0_shl",
+                    BinOp::Shr => "c0_shr",
+                    _ => return format!("({} {} {})", a, op, b),
+                };
+                format!("{}({}, {})", checked, a, b)
+            }
+            ExpKind::Call(ref name, ref args) => {
+                let args = self.operands(&args.iter().collect::<Vec<&Exp>>());
+                format!("{}({})", symbol(name, self.externs.contains(name.as_str())), args.join(", "))
+            }
+            ExpKind::Load(ref a) => self.lvalue(a),
+            ExpKind::AddrOf(ref a) => self.address(a),
+            ExpKind::Alloc(ref ty) => {
+                let pointer = Type::Pointer(Box::new(ty.clone()));
+                format!("({})c0_alloc(sizeof({}))", c_type(&pointer), c_type(ty))
+            }
+            ExpKind::AllocArray(ref ty, ref n) => format!("c0_alloc_array(sizeof({}), {})", c_type(ty), self.exp(n)),
+            ExpKind::Length(ref a) => format!("c0_length({})", self.exp(a)),
+        }
+    }
+
+    // The location itself, its checks made
+    fn lvalue(&mut self, a: &Addr) -> String {
+        match a.kind {
+            AddrKind::Field(ref base, ref field, _) => match base.kind {
+                AddrKind::Field(..) => format!("{}._c0_{}", self.lvalue(base), field),
+                _ => format!("{}->_c0_{}", self.address(base), field),
+            },
+            _ => format!("*{}", self.address(a)),
+        }
+    }
+
+    // A pointer to the location, its checks made
+    fn address(&mut self, a: &Addr) -> String {
+        let pointer = c_type(&Type::Pointer(Box::new(a.ty.clone())));
+        match a.kind {
+            AddrKind::Deref(ref p) => format!("(({})c0_deref({}))", pointer, self.exp(p)),
+            AddrKind::Element(ref array, ref i) => {
+                let operands = self.operands(&[array, i]);
+                format!("(({})c0_elem({}, {}))", pointer, operands[0], operands[1])
+            }
+            AddrKind::Field(..) => format!("&{}", self.lvalue(a)),
+        }
+    }
+}
+
+// A string literal's contents, with `?` escaped so no trigraph is formed
+fn c_str(s: &str) -> String {
+    escape_str(s).replace('?', "\\?")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use codegen::test_util::run;
+    use driver::options::Backend;
+    use elab::tests::elaborated;
+
+    fn generated(text: &str, dyn_check: bool) -> String {
+        program(&elaborated(text, dyn_check))
+    }
+
+    #[test]
+    fn generating_functions() {
+        let cases = vec![
+            (
+                "int f(int x, int y) { return x / y + (x << 2) - -2147483648; }",
+                "int32_t _c0_f(int32_t _c0v_x, int32_t _c0v_y) {\n    \
+                 return c0_sub(c0_add(c0_div(_c0v_x, _c0v_y), c0_shl(_c0v_x, 2)), c0_neg(INT32_MIN));\n}\n",
+            ),
+            (
+                "struct p { int x; struct p* next; };\nvoid f(struct p* p) { p->next->x = 3; }",
+                "void _c0_f(struct _c0_p *_c0v_p) {\n    int32_t *_c0v__t0;\n    \
+                 _c0v__t0 = &((struct _c0_p *)c0_deref(((struct _c0_p *)c0_deref(_c0v_p))->_c0_next))->_c0_x;\n    \
+                 *((int32_t *)c0_deref(_c0v__t0)) = 3;\n    return;\n}\n",
+            ),
+            (
+                "bool f(int[] A, int i, int n) { return i < n && A[i] == 0; }",
+                "bool _c0_f(c0_array *_c0v_A, int32_t _c0v_i, int32_t _c0v_n) {\n    bool _c0v__t0;\n    \
+                 if (_c0v_i < _c0v_n) {\n        \
+                 _c0v__t0 = (*((int32_t *)c0_elem(_c0v_A, _c0v_i)) == 0);\n    } else {\n        \
+                 _c0v__t0 = false;\n    }\n    return _c0v__t0;\n}\n",
+            ),
+            // Calls are made left to right
+            (
+                "int g(int x) { return x; }\nint f() { while (g(1) < g(2)) { } return g(3) * g(4) + g(5); }",
+                "int32_t _c0_f(void) {\n    while (true) {\n        int32_t _c0t0 = _c0_g(1);\n        \
+                 if (!(_c0t0 < _c0_g(2))) break;\n    }\n    int32_t _c0t1 = _c0_g(3);\n    \
+                 int32_t _c0t2 = c0_mul(_c0t1, _c0_g(4));\n    return c0_add(_c0t2, _c0_g(5));\n}\n",
+            ),
+        ];
+        for (source, expected) in cases {
+            let c = generated(source, false);
+            assert!(c.ends_with(expected), "{}\n{}", source, c);
+        }
+    }
+
+    #[test]
+    fn declaring_types() {
+        let c = generated(
+            "struct b { struct a* p; };\nstruct a { int x; char c; };\nstruct c { struct a a; string[] s; };\n\
+             int main() { struct c* c = alloc(struct c); return c->a.x; }",
+            false,
+        );
+        let expected = "#include \"c0rt.h\"\n\nstruct _c0_a;\nstruct _c0_b;\nstruct _c0_c;\n\n\
+                        struct _c0_a {\n    int32_t _c0_x;\n    char _c0_c;\n};\n\n\
+                        struct _c0_b {\n    struct _c0_a *_c0_p;\n};\n\n\
+                        struct _c0_c {\n    struct _c0_a _c0_a;\n    c0_array *_c0_s;\n};\n\n\
+                        int32_t _c0_main(void);\n";
+        assert!(c.starts_with(expected), "{}", c);
+    }
+
+    #[test]
+    fn checking_contracts() {
+        let program = "int f(int x)\n//@requires x > 0;\n{ return x; }\nint main() { return f(0); }";
+        let check = "if (!(_c0v_x > 0)) c0_assert_failed(\"@requires `x > 0` failed\");";
+        assert!(generated(program, true).contains(check));
+        assert!(!generated(program, false).contains("c0_assert_failed"));
+        assert_eq!(run(Backend::C, program, false), (Some(0), None, String::new()));
+        assert_eq!(run(Backend::C, program, true), (None, Some(6), String::new()));
+    }
+
+    #[test]
+    fn compiling_through_c() {
+        const SIGFPE: i32 = 8;
+        const SIGSEGV: i32 = 11;
+        let cases = vec![
+            ("int main() { int x = 2147483647; return x + 1 == -2147483648 && x * x == 1 ? 3 : 4; }",
+             (Some(3), None)),
+            ("int main() { return (-7 / 2) * 10 + -7 % 2 + (-16 >> 2) + (-1 << 31 >> 31); }",
+             (Some(-36 & 0xff), None)),
+            ("struct pt { int x; bool b; struct pt* next; };\n\
+              int main() { struct pt[] A = alloc_array(struct pt, 2); A[1].next = alloc(struct pt);\n\
+              A[1].next->x = 7; A[0].b = true; return A[1].next->x + (A[0].b ? 10 : 0); }", (Some(17), None)),
+            ("int main() { int x = 0; return 1 % x; }", (None, Some(SIGFPE))),
+            ("int main() { int x = 32; return 1 >> x; }", (None, Some(SIGFPE))),
+            ("int main() { int[] A = alloc_array(int, 2); return A[2]; }", (None, Some(SIGSEGV))),
+            ("int main() { int*[] A = alloc_array(int*, 2); return *A[1]; }", (None, Some(SIGSEGV))),
+        ];
+        for (source, (code, signal)) in cases {
+            assert_eq!(run(Backend::C, source, false), (code, signal, String::new()), "{}", source);
+        }
+        let ordered = "void printint(int n);\nint f(int x) { printint(x); return x; }\n\
+                       int main() { int* p = alloc(int); int x = f(1) - f(2) * f(3);\n\
+                       *p = f(4); return x + *p; }";
+        assert_eq!(run(Backend::C, ordered, false), (Some(255), None, String::from("1234")));
+    }
+}
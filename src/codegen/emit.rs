@@ -0,0 +1,125 @@
+use codegen::asm::Frame;
+use codegen::regalloc::{self, Stats};
+use codegen::select;
+use ir::ir::Program;
+use std::collections::HashSet;
+
+/*
+The assembly of a whole program, in AT&T syntax for the GNU assembler
+
+        .text
+        .globl _c0_main
+    _c0_main:
+        pushq %rbp
+        ...
+        .section .rodata
+    .LC0:
+        .string "hello"
+
+Each function is selected and allocated on its own, its labels made unique
+by its position. Only `main` is global, for the runtime to call. What
+allocation did to each function is returned with the assembly.
+ */
+pub fn program(p: &Program) -> (String, Vec<(String, Stats)>) {
+    let externs: HashSet<String> = p.externs.iter().map(|x| x.name.clone()).collect();
+    let mut strings = Vec::new();
+    let mut stats = Vec::new();
+    let mut out = String::from("\t.text\n");
+    for (n, f) in p.functions.iter().enumerate() {
+        let mut code = select::select(f, &externs, &mut strings);
+        stats.push((f.name.clone(), regalloc::allocate(&mut code)));
+        if f.name == "main" {
+            out.push_str(&format!("\t.globl {}\n", code.name));
+        }
+        let prefix = n.to_string();
+        out.push_str(&Frame { function: &code, prefix: &prefix }.print());
+        out.push('\n');
+    }
+    if !strings.is_empty() {
+        out.push_str("\t.section .rodata\n");
+        for (n, s) in strings.iter().enumerate() {
+            out.push_str(&format!(".LC{}:\n\t.string \"{}\"\n", n, escape(s)));
+        }
+    }
+    // The stack need not be executable
+    out.push_str("\t.section .note.GNU-stack,\"\",@progbits\n");
+    (out, stats)
+}
+
+// A string for `.string`, which adds the terminating NUL
+fn escape(s: &str) -> String {
+    let mut out = String::new();
+    for b in s.bytes() {
+        match b {
+            b'"' => out.push_str("\\\""),
+            b'\\' => out.push_str("\\\\"),
+            0x20..=0x7e => out.push(b as char),
+            _ => out.push_str(&format!("\\{:03o}", b)),
+        }
+    }
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use codegen::test_util::run;
+    use driver::options::Backend;
+
+    #[test]
+    fn compiling_programs() {
+        let cases = vec![
+            ("int main() { return 2147483647 + 1 == -2147483648 ? 3 : 4; }", 3),
+            ("int main() { int x = -7; return (x / 2) * 10 + x % 2 + (-16 >> 2) + (1 << 4); }", -30 - 1 - 4 + 16),
+            ("int f(int n) { return n < 2 ? n : f(n - 1) + f(n - 2); }\nint main() { return f(12); }", 144),
+            ("int f(int a, int b, int c, int d, int e, int f, int g, int h)\n\
+              { return a - b + c - d + e - f + g * h; }\n\
+              int main() { return f(1, 2, 3, 4, 5, 6, 7, 8); }", 53),
+            ("struct pt { int x; char c; struct pt* next; };\n\
+              int main() { struct pt* p = alloc(struct pt); p->next = alloc(struct pt); p->next->x = 4;\n\
+              struct pt[] A = alloc_array(struct pt, 3); A[2].x = p->next->x; A[1].c = 'a';\n\
+              return A[2].x * 10 + 3 + (A[1].c == 'a' ? 100 : 0) + (p->next->next == NULL ? 1 : 0); }",
+             144),
+            ("int main() { bool[] B = alloc_array(bool, 5); int s = 0;\n\
+              for (int i = 0; i < 5; i++) { B[i] = i % 2 == 0; if (B[i]) s += i; }\nreturn s; }", 6),
+            // More values live across calls than there are callee-saved registers
+            ("int id(int x) { return x; }\n\
+              int main() { int a = id(1); int b = id(2); int c = id(3); int d = id(4); int e = id(5);\n\
+              int f = id(6); int g = id(7); int h = id(8); int s = id(0);\n\
+              for (int i = 0; i < 4; i++) { s += a * id(i) + b - c + d * e - f + g * h; a++; h--; }\n\
+              return s; }", 254),
+        ];
+        for (source, result) in cases {
+            assert_eq!(run(Backend::Asm, source, true), (Some(result & 0xff), None, String::new()), "{}", source);
+        }
+    }
+
+    #[test]
+    fn calling_the_runtime() {
+        let program = "void printint(int n);\nvoid println(string s);\n\
+                       string string_join(string a, string b);\nstring string_fromint(int n);\n\
+                       int main() { printint(-3); println(string_join(\", \\\"\", string_fromint(7))); return 0; }";
+        assert_eq!(run(Backend::Asm, program, true), (Some(0), None, String::from("-3, \"7\n")));
+    }
+
+    #[test]
+    fn trapping() {
+        const SIGABRT: i32 = 6;
+        const SIGFPE: i32 = 8;
+        const SIGSEGV: i32 = 11;
+        let cases = vec![
+            ("int main() { int x = 0; return 1 / x; }", SIGFPE),
+            ("int main() { int x = -1; return -2147483648 % x; }", SIGFPE),
+            ("int main() { int x = 32; return 1 << x; }", SIGFPE),
+            ("int main() { return 1 >> -1; }", SIGFPE),
+            ("int main() { int[] A = alloc_array(int, 3); return A[3]; }", SIGSEGV),
+            ("int main() { int[] A = alloc_array(int, 3); A[-1] = 0; return 0; }", SIGSEGV),
+            ("int main() { int[] A = alloc_array(int, -1); return 0; }", SIGSEGV),
+            ("int main() { int* p = NULL; return *p; }", SIGSEGV),
+            ("int main() { assert(1 > 2); return 0; }", SIGABRT),
+            ("int main() { error(\"the end\"); }", SIGABRT),
+        ];
+        for (source, signal) in cases {
+            assert_eq!(run(Backend::Asm, source, true), (None, Some(signal), String::new()), "{}", source);
+        }
+    }
+}
@@ -0,0 +1,64 @@
+use elab::core::Program;
+use std::env;
+use std::fs;
+use std::path::PathBuf;
+use std::process::{self, Command};
+use std::sync::atomic::{AtomicUsize, Ordering};
+
+/*
+Building executables with the system C compiler, `cc` or whatever `CC`
+names, which assembles the program and compiles the runtime library with
+it. The sources are written to a directory of their own under the system's
+temporary directory, removed afterwards.
+ */
+pub const RUNTIME_C: &str = include_str!("runtime/c0rt.c");
+pub const RUNTIME_H: &str = include_str!("runtime/c0rt.h");
+
+// The library functions the program calls which the runtime does not provide
+pub fn missing(p: &Program) -> Vec<String> {
+    p.externs.iter().map(|x| x.name.clone()).filter(|name| !LIBRARY.contains(&name.as_str())).collect()
+}
+
+// The library functions the runtime implements, as `c0_lib_name`
+const LIBRARY: &[&str] = &[
+    "print", "println", "printint", "printbool", "printchar", "flush", "eof", "readline",
+    "string_length", "string_charat", "string_join", "string_sub", "string_equal", "string_compare",
+    "string_fromint", "string_frombool", "string_fromchar", "string_tolower", "string_terminated",
+    "string_to_chararray", "string_from_chararray", "char_ord", "char_chr",
+    "int_size", "int_max", "int_min", "abs", "max", "min", "int2hex",
+    "parse_bool", "parse_int", "num_tokens", "int_tokens", "parse_tokens", "parse_ints",
+    "init_rand", "rand",
+    "args_flag", "args_int", "args_string", "args_parse",
+    "file_closed", "file_read", "file_close", "file_eof", "file_readline",
+];
+
+// Compiles the named sources, `.s` or `.c`, with the runtime into the executable `output`
+pub fn link(sources: &[(&str, &str)], output: &str) -> Result<(), String> {
+    static BUILDS: AtomicUsize = AtomicUsize::new(0);
+    let n = BUILDS.fetch_add(1, Ordering::SeqCst);
+    let dir = env::temp_dir().join(format!("c0c-{}-{}", process::id(), n));
+    let result = build(&dir, sources, output);
+    let _ = fs::remove_dir_all(&dir);
+    result
+}
+
+fn build(dir: &PathBuf, sources: &[(&str, &str)], output: &str) -> Result<(), String> {
+    let write = |name: &str, text: &str| {
+        let path = dir.join(name);
+        fs::write(&path, text).map_err(|e| format!("cannot write `{}`: {}", path.display(), e))?;
+        Ok::<PathBuf, String>(path)
+    };
+    fs::create_dir_all(dir).map_err(|e| format!("cannot create `{}`: {}", dir.display(), e))?;
+    write("c0rt.h", RUNTIME_H)?;
+    let mut files = vec![write("c0rt.c", RUNTIME_C)?];
+    for &(name, text) in sources {
+        files.push(write(name, text)?);
+    }
+    let cc = env::var("CC").unwrap_or_else(|_| String::from("cc"));
+    let result = Command::new(&cc).args(["-std=c99", "-O2", "-o", output]).args(&files).output();
+    match result {
+        Ok(ref out) if out.status.success() => Ok(()),
+        Ok(out) => Err(format!("`{}` failed:\n{}", cc, String::from_utf8_lossy(&out.stderr).trim_end())),
+        Err(e) => Err(format!("cannot run `{}`: {}", cc, e)),
+    }
+}
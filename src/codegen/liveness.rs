@@ -0,0 +1,106 @@
+use codegen::asm::{Function, Instr, Loc};
+use std::collections::{BTreeSet, HashMap};
+
+/*
+Liveness of registers, virtual and physical, after each instruction of a
+function, found by iterating backwards to a fixed point. Control leaves an
+instruction for the next one, for the targets of its jumps, or nowhere
+after a return or a trap.
+ */
+pub struct Liveness {
+    live_out: Vec<BTreeSet<Loc>>,
+}
+
+impl Liveness {
+    pub fn new(f: &Function) -> Liveness {
+        let succs = successors(f);
+        let uses: Vec<Vec<Loc>> = f.instrs.iter().map(|i| i.uses()).collect();
+        let defs: Vec<Vec<Loc>> = f.instrs.iter().map(|i| i.defs()).collect();
+        let mut live_in = vec![BTreeSet::new(); f.instrs.len()];
+        let mut live_out = vec![BTreeSet::new(); f.instrs.len()];
+        let mut changed = true;
+        while changed {
+            changed = false;
+            for n in (0..f.instrs.len()).rev() {
+                let mut out = BTreeSet::new();
+                for &s in &succs[n] {
+                    out.extend(live_in[s].iter().cloned());
+                }
+                let mut live: BTreeSet<Loc> = out.iter().filter(|l| !defs[n].contains(l)).cloned().collect();
+                live.extend(uses[n].iter().cloned());
+                if live != live_in[n] {
+                    live_in[n] = live;
+                    changed = true;
+                }
+                live_out[n] = out;
+            }
+        }
+        Liveness { live_out }
+    }
+
+    // The registers live after the n'th instruction
+    pub fn live_out(&self, n: usize) -> &BTreeSet<Loc> {
+        &self.live_out[n]
+    }
+}
+
+fn successors(f: &Function) -> Vec<Vec<usize>> {
+    let mut labels = HashMap::new();
+    for (n, i) in f.instrs.iter().enumerate() {
+        if let Instr::Label(l) = *i {
+            labels.insert(l, n);
+        }
+    }
+    let next = |n: usize| if n + 1 < f.instrs.len() { vec![n + 1] } else { Vec::new() };
+    f.instrs
+        .iter()
+        .enumerate()
+        .map(|(n, i)| match *i {
+            Instr::Jmp(l) => vec![labels[&l]],
+            Instr::J(_, l) => {
+                let mut succs = next(n);
+                succs.push(labels[&l]);
+                succs
+            }
+            Instr::Ret(_) | Instr::Trap(..) => Vec::new(),
+            _ => next(n),
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use codegen::asm::{Alu, Cond, Operand, Reg, Width};
+
+    #[test]
+    fn following_jumps() {
+        // %0 = 0; L0: %0 += %1; cmp; jl L0; ret %0
+        let f = Function {
+            name: String::from("f"),
+            instrs: vec![
+                Instr::Mov(Width::L, Operand::reg(Reg::Rdi), Operand::virt(1)),
+                Instr::Mov(Width::L, Operand::Imm(0), Operand::virt(0)),
+                Instr::Label(0),
+                Instr::Alu(Alu::Add, Width::L, Operand::virt(1), Loc::Virt(0)),
+                Instr::Cmp(Width::L, Operand::Imm(10), Operand::virt(0)),
+                Instr::J(Cond::L, 0),
+                Instr::Mov(Width::Q, Operand::virt(0), Operand::reg(Reg::Rax)),
+                Instr::Ret(true),
+            ],
+            virts: 2,
+            labels: 1,
+            out_args: 0,
+            slots: 0,
+            saved: Vec::new(),
+        };
+        let live = Liveness::new(&f);
+        let virts = |n: usize| live.live_out(n).iter().cloned().collect::<Vec<Loc>>();
+        assert_eq!(virts(0), vec![Loc::Virt(1)]);
+        assert_eq!(virts(1), vec![Loc::Virt(0), Loc::Virt(1)]);
+        // Around the loop %1 stays live
+        assert_eq!(virts(5), vec![Loc::Virt(0), Loc::Virt(1)]);
+        assert_eq!(virts(6), vec![Loc::Reg(Reg::Rax)]);
+        assert!(virts(7).is_empty());
+    }
+}
@@ -0,0 +1,10 @@
+pub mod asm;
+pub mod select;
+pub mod liveness;
+pub mod regalloc;
+pub mod spill;
+pub mod emit;
+pub mod c;
+pub mod link;
+#[cfg(test)]
+pub mod test_util;
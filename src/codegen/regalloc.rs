@@ -0,0 +1,379 @@
+use codegen::asm::{Function, Instr, Loc, Operand, Reg, Virt, CALLEE_SAVED};
+use codegen::liveness::Liveness;
+use codegen::spill;
+use std::collections::{BTreeSet, HashSet};
+use std::fmt;
+use std::mem;
+
+/*
+Register allocation by graph coloring, after Chaitin and Briggs.
+
+Two registers interfere when one is written while the other is live, except
+that a move's destination does not interfere with its source. Physical
+registers are nodes too, precolored: a call writes every caller-saved
+register, so what is live across it can only go in a callee-saved one, and
+division and shifts and the calling convention tie values to %rax, %rdx,
+%rcx and the argument registers by moves.
+
+Moves are coalesced conservatively, by Briggs's test between virtual
+registers and George's between a virtual and a physical one, so coalescing
+never makes the graph uncolorable. Nodes of fewer than K neighbours are then
+removed, or when there are none the cheapest to spill, and colored in reverse
+order, optimistically: a spill candidate may still find a color. Those which
+do not are spilled and allocation starts again on the rewritten code.
+Moves whose ends get the same register are removed.
+ */
+pub fn allocate(f: &mut Function) -> Stats {
+    let mut unspillable: HashSet<Virt> = HashSet::new();
+    let mut spilled = 0;
+    loop {
+        let mut g = Graph::new(f, &unspillable);
+        g.coalesce();
+        match g.color() {
+            Ok(colors) => {
+                let (moves, removed) = assign(f, &g, &colors);
+                return Stats { spilled, moves, removed };
+            }
+            Err(nodes) => {
+                let members: Vec<Virt> = (0..f.virts).filter(|&v| nodes.contains(&g.find(Loc::Virt(v)))).collect();
+                spilled += members.len();
+                unspillable.extend(spill::spill(f, &members));
+            }
+        }
+    }
+}
+
+// The registers allocation may use, those a call may overwrite first, which need not be saved
+pub const ALLOCATABLE: [Reg; 14] = [
+    Reg::Rax,
+    Reg::Rcx,
+    Reg::Rdx,
+    Reg::Rsi,
+    Reg::Rdi,
+    Reg::R8,
+    Reg::R9,
+    Reg::R10,
+    Reg::R11,
+    Reg::Rbx,
+    Reg::R12,
+    Reg::R13,
+    Reg::R14,
+    Reg::R15,
+];
+
+const K: usize = ALLOCATABLE.len();
+
+// What allocation did to a function
+#[derive(Copy, Clone, Debug, PartialEq, Default)]
+pub struct Stats {
+    // The virtual registers spilled to the stack
+    pub spilled: usize,
+    // The moves between registers selection and spilling left, and those removed
+    pub moves: usize,
+    pub removed: usize,
+}
+
+impl fmt::Display for Stats {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{} spilled, {} of {} moves removed", self.spilled, self.removed, self.moves)
+    }
+}
+
+// The interference graph, with the virtual registers coalesced so far
+struct Graph {
+    // The neighbours of each virtual register, which are representatives
+    adj: Vec<BTreeSet<Loc>>,
+    // What each virtual register was coalesced with, itself if nothing
+    alias: Vec<Loc>,
+    moves: Vec<(Loc, Loc)>,
+    // How often each virtual register is read or written
+    uses: Vec<usize>,
+    unspillable: Vec<bool>,
+}
+
+// A move between registers, which coalescing may remove
+fn copy(i: &Instr) -> Option<(Loc, Loc)> {
+    match *i {
+        Instr::Mov(_, Operand::Loc(s), Operand::Loc(d)) if s != d => Some((s, d)),
+        _ => None,
+    }
+}
+
+impl Graph {
+    fn new(f: &Function, unspillable: &HashSet<Virt>) -> Graph {
+        let mut g = Graph {
+            adj: vec![BTreeSet::new(); f.virts],
+            alias: (0..f.virts).map(Loc::Virt).collect(),
+            moves: Vec::new(),
+            uses: vec![0; f.virts],
+            unspillable: (0..f.virts).map(|v| unspillable.contains(&v)).collect(),
+        };
+        let live = Liveness::new(f);
+        for (n, i) in f.instrs.iter().enumerate() {
+            let mut live = live.live_out(n).clone();
+            if let Some((s, d)) = copy(i) {
+                live.remove(&s);
+                g.moves.push((s, d));
+            }
+            for d in i.defs() {
+                for &l in &live {
+                    g.add_edge(d, l);
+                }
+            }
+            // The divisor is read after %rax and %rdx are written
+            if let Instr::Idiv(_, d) = *i {
+                g.add_edge(d, Loc::Reg(Reg::Rax));
+                g.add_edge(d, Loc::Reg(Reg::Rdx));
+            }
+            for l in i.uses().into_iter().chain(i.defs()) {
+                if let Loc::Virt(v) = l {
+                    g.uses[v] += 1;
+                }
+            }
+        }
+        g
+    }
+
+    fn find(&self, l: Loc) -> Loc {
+        match l {
+            Loc::Virt(v) if self.alias[v] != l => self.find(self.alias[v]),
+            _ => l,
+        }
+    }
+
+    fn add_edge(&mut self, a: Loc, b: Loc) {
+        if a == b {
+            return;
+        }
+        if let Loc::Virt(v) = a {
+            self.adj[v].insert(b);
+        }
+        if let Loc::Virt(v) = b {
+            self.adj[v].insert(a);
+        }
+    }
+
+    fn interferes(&self, a: Loc, b: Loc) -> bool {
+        match (a, b) {
+            (Loc::Virt(v), _) => self.adj[v].contains(&b),
+            (_, Loc::Virt(v)) => self.adj[v].contains(&a),
+            _ => a != b,
+        }
+    }
+
+    // Physical registers have as many neighbours as can be
+    fn degree(&self, l: Loc) -> usize {
+        match l {
+            Loc::Virt(v) => self.adj[v].len(),
+            Loc::Reg(_) => usize::MAX,
+        }
+    }
+
+    fn coalesce(&mut self) {
+        let mut changed = true;
+        while changed {
+            changed = false;
+            for n in 0..self.moves.len() {
+                let (s, d) = self.moves[n];
+                let (a, b) = (self.find(s), self.find(d));
+                let (u, v) = match (a, b) {
+                    _ if a == b => continue,
+                    (_, Loc::Virt(v)) => (a, v),
+                    (Loc::Virt(v), Loc::Reg(_)) => (b, v),
+                    _ => continue,
+                };
+                if self.unspillable[v] || self.interferes(u, Loc::Virt(v)) {
+                    continue;
+                }
+                let safe = match u {
+                    Loc::Reg(_) => self.george(u, v),
+                    Loc::Virt(w) => !self.unspillable[w] && self.briggs(w, v),
+                };
+                if safe {
+                    self.combine(u, v);
+                    changed = true;
+                }
+            }
+        }
+    }
+
+    // Coalescing with a physical register is safe if each neighbour already interferes with it or is of low degree
+    fn george(&self, r: Loc, v: Virt) -> bool {
+        self.adj[v].iter().all(|&t| self.degree(t) < K || self.interferes(t, r))
+    }
+
+    // Coalescing two nodes is safe if the result has fewer than K neighbours of significant degree
+    fn briggs(&self, u: Virt, v: Virt) -> bool {
+        let neighbours: BTreeSet<Loc> = self.adj[u].union(&self.adj[v]).cloned().collect();
+        neighbours.iter().filter(|&&t| self.degree(t) >= K).count() < K
+    }
+
+    fn combine(&mut self, u: Loc, v: Virt) {
+        self.alias[v] = u;
+        for t in mem::take(&mut self.adj[v]) {
+            if let Loc::Virt(w) = t {
+                self.adj[w].remove(&Loc::Virt(v));
+            }
+            self.add_edge(u, t);
+        }
+        if let Loc::Virt(w) = u {
+            self.uses[w] += self.uses[v];
+        }
+    }
+
+    // A register for every representative, or the representatives which must be spilled
+    fn color(&self) -> Result<Vec<Option<Reg>>, Vec<Loc>> {
+        let n = self.adj.len();
+        let nodes: Vec<Virt> = (0..n).filter(|&v| self.find(Loc::Virt(v)) == Loc::Virt(v)).collect();
+        let mut degree: Vec<usize> = (0..n).map(|v| self.adj[v].len()).collect();
+        let mut removed = vec![false; n];
+        let mut stack = Vec::new();
+        while stack.len() < nodes.len() {
+            let remaining = nodes.iter().cloned().filter(|&v| !removed[v]);
+            let v = match remaining.clone().find(|&v| degree[v] < K) {
+                Some(v) => v,
+                // The cheapest to spill, by uses per neighbour
+                None => remaining
+                    .min_by_key(|&v| (self.unspillable[v], self.uses[v] * 1000 / (degree[v] + 1)))
+                    .unwrap(),
+            };
+            removed[v] = true;
+            stack.push(v);
+            for &t in &self.adj[v] {
+                if let Loc::Virt(w) = t {
+                    degree[w] -= 1;
+                }
+            }
+        }
+        let mut colors: Vec<Option<Reg>> = vec![None; n];
+        let mut spilled = Vec::new();
+        while let Some(v) = stack.pop() {
+            let taken: HashSet<Reg> = self.adj[v]
+                .iter()
+                .filter_map(|&t| match t {
+                    Loc::Reg(r) => Some(r),
+                    Loc::Virt(w) => colors[w],
+                })
+                .collect();
+            // The register of something it is moved to or from, so the move goes
+            let related = self.moves.iter().filter_map(|&(s, d)| {
+                match (self.find(s), self.find(d)) {
+                    (a, b) if a == Loc::Virt(v) => Some(b),
+                    (a, b) if b == Loc::Virt(v) => Some(a),
+                    _ => None,
+                }
+            });
+            let preferred = related.filter_map(|l| match l {
+                Loc::Reg(r) => Some(r),
+                Loc::Virt(w) => colors[w],
+            });
+            let free = |r: &Reg| ALLOCATABLE.contains(r) && !taken.contains(r);
+            colors[v] = preferred.clone().find(free).or_else(|| ALLOCATABLE.iter().cloned().find(free));
+            if colors[v].is_none() {
+                assert!(!self.unspillable[v], "a register introduced by spilling could not be allocated");
+                spilled.push(Loc::Virt(v));
+            }
+        }
+        if spilled.is_empty() {
+            Ok(colors)
+        } else {
+            Err(spilled)
+        }
+    }
+}
+
+// Rewrites the function to the registers chosen, removing moves made redundant
+fn assign(f: &mut Function, g: &Graph, colors: &[Option<Reg>]) -> (usize, usize) {
+    let reg = |v: Virt| match g.find(Loc::Virt(v)) {
+        Loc::Reg(r) => r,
+        Loc::Virt(w) => colors[w].unwrap(),
+    };
+    let mut moves = 0;
+    let mut removed = 0;
+    let mut instrs = Vec::new();
+    for mut i in mem::take(&mut f.instrs) {
+        for l in i.locs_mut() {
+            if let Loc::Virt(v) = *l {
+                *l = Loc::Reg(reg(v));
+            }
+        }
+        if let Instr::Mov(_, Operand::Loc(s), Operand::Loc(d)) = i {
+            moves += 1;
+            if s == d {
+                removed += 1;
+                continue;
+            }
+        }
+        instrs.push(i);
+    }
+    f.instrs = instrs;
+    let used: HashSet<Loc> = f.instrs.iter().flat_map(|i| i.defs()).collect();
+    f.saved = CALLEE_SAVED.iter().cloned().filter(|&r| used.contains(&Loc::Reg(r))).collect();
+    (moves, removed)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use codegen::asm::Frame;
+    use codegen::select::select;
+    use ir::parse::parse;
+
+    fn allocated(text: &str) -> (Function, Stats) {
+        let f = parse(0, text).unwrap().functions.remove(0);
+        let mut code = select(&f, &HashSet::new(), &mut Vec::new());
+        let stats = allocate(&mut code);
+        (code, stats)
+    }
+
+    #[test]
+    fn coalescing_moves() {
+        let (f, stats) = allocated(
+            "function g(%0 i32, %1 i32) -> i32 {\n  temps %2 i32, %3 i32\nL0:\n  %2 = call f(%1, %0)\n  \
+             %3 = add i32 %2, %0\n  return %3\n}\n",
+        );
+        assert_eq!(stats, Stats { spilled: 0, moves: 7, removed: 4 });
+        // %0 is live across the call, so it is kept in a callee-saved register
+        let expected = "_c0_g:\n\tpushq %rbp\n\tmovq %rsp, %rbp\n\tpushq %rbx\n\tsubq $8, %rsp\n\tmovq %rdi, %rbx\n\
+                        .L0_0:\n\tmovq %rsi, %rdi\n\tmovq %rbx, %rsi\n\tcall _c0_f\n\taddl %ebx, %eax\n\
+                        \tleaq -8(%rbp), %rsp\n\tpopq %rbx\n\tleave\n\tret\n";
+        assert_eq!(Frame { function: &f, prefix: "0" }.print(), expected);
+    }
+
+    #[test]
+    fn respecting_division() {
+        let (f, _) = allocated(
+            "function f(%0 i32, %1 i32) -> i32 {\n  temps %2 i32, %3 i32\nL0:\n  %2 = div i32 %0, 7\n  \
+             %3 = mod i32 %2, %1\n  return %3\n}\n",
+        );
+        for i in &f.instrs {
+            if let Instr::Idiv(_, Loc::Reg(r)) = *i {
+                assert!(r != Reg::Rax && r != Reg::Rdx, "the divisor is in {:?}", r);
+            }
+        }
+    }
+
+    #[test]
+    fn spilling() {
+        // Eight values live across a call, with five callee-saved registers to keep them in
+        let mut text = String::from("function f() -> i32 {\n  temps");
+        for t in 0..17 {
+            text.push_str(&format!(" %{} i32{}", t, if t < 16 { "," } else { "\n" }));
+        }
+        text.push_str("L0:\n");
+        for t in 0..8 {
+            text.push_str(&format!("  %{} = call g()\n", t));
+        }
+        text.push_str("  %8 = call g()\n");
+        for t in 0..8 {
+            text.push_str(&format!("  %{} = add i32 %{}, %{}\n", t + 9, t + 8, t));
+        }
+        text.push_str("  return %16\n}\n");
+        let (f, stats) = allocated(&text);
+        assert_eq!(stats.spilled, 3);
+        assert_eq!(f.slots, 3);
+        assert_eq!(f.saved, CALLEE_SAVED.to_vec());
+        let mut locs = f.instrs.iter().flat_map(|i| i.uses().into_iter().chain(i.defs()));
+        assert!(locs.all(|l| matches!(l, Loc::Reg(_))));
+    }
+}
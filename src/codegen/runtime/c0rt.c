@@ -0,0 +1,569 @@
+/*
+The runtime library of compiled C0 programs, see c0rt.h. Errors print what
+went wrong to standard error and stop the program with the signal cc0's
+runtime would, after flushing what it printed. Library functions check the
+preconditions which would be undefined in C, as the interpreter does.
+*/
+#define _POSIX_C_SOURCE 200809L
+#define C0RT_LIBRARIES
+
+#include "c0rt.h"
+
+#include <ctype.h>
+#include <limits.h>
+#include <signal.h>
+#include <stdarg.h>
+#include <stdio.h>
+#include <stdlib.h>
+#include <string.h>
+
+static int c0_argc;
+static char **c0_argv;
+
+int main(int argc, char **argv) {
+    c0_argc = argc;
+    c0_argv = argv;
+    int32_t result = _c0_main();
+    fflush(stdout);
+    return result;
+}
+
+static C0_NORETURN void stop(int sig, const char *kind, const char *format, ...) {
+    va_list args;
+    fflush(stdout);
+    fprintf(stderr, "%s: ", kind);
+    va_start(args, format);
+    vfprintf(stderr, format, args);
+    va_end(args);
+    fputc('\n', stderr);
+    fflush(stderr);
+    signal(sig, SIG_DFL);
+    raise(sig);
+    abort();
+}
+
+#define library_error(...) stop(SIGABRT, "library error", __VA_ARGS__)
+
+static const char *str(c0_string s) {
+    return s == NULL ? "" : s;
+}
+
+void *c0_alloc(size_t bytes) {
+    void *p = calloc(1, bytes == 0 ? 1 : bytes);
+    if (p == NULL) {
+        stop(SIGSEGV, "memory error", "out of memory");
+    }
+    return p;
+}
+
+c0_array *c0_alloc_array(size_t elt_size, int32_t n) {
+    if (n < 0) {
+        stop(SIGSEGV, "memory error", "array size %d is negative", n);
+    }
+    c0_array *A = c0_alloc(sizeof(c0_array) + elt_size * (size_t)n);
+    A->length = n;
+    A->elt_size = (int32_t)elt_size;
+    return A;
+}
+
+C0_NORETURN void c0_null_error(void) {
+    stop(SIGSEGV, "memory error", "NULL pointer dereference");
+}
+
+C0_NORETURN void c0_bounds_error(void) {
+    stop(SIGSEGV, "memory error", "array index out of bounds");
+}
+
+C0_NORETURN void c0_div_error(void) {
+    stop(SIGFPE, "arithmetic error", "division by zero, or of INT_MIN by -1");
+}
+
+C0_NORETURN void c0_shift_error(void) {
+    stop(SIGFPE, "arithmetic error", "shift by less than 0 or more than 31");
+}
+
+C0_NORETURN void c0_assert_failed(c0_string message) {
+    stop(SIGABRT, "assertion failure", "%s", str(message));
+}
+
+C0_NORETURN void c0_error(c0_string message) {
+    stop(SIGABRT, "error", "%s", str(message));
+}
+
+/* A new string of n characters, to be filled in */
+static char *string(size_t n) {
+    return c0_alloc(n + 1);
+}
+
+static c0_string copy(const char *s) {
+    char *t = string(strlen(s));
+    strcpy(t, s);
+    return t;
+}
+
+/* <conio> */
+
+void c0_lib_print(c0_string s) {
+    fputs(str(s), stdout);
+}
+
+void c0_lib_println(c0_string s) {
+    puts(str(s));
+}
+
+void c0_lib_printint(int32_t i) {
+    printf("%d", i);
+}
+
+void c0_lib_printbool(bool b) {
+    fputs(b ? "true" : "false", stdout);
+}
+
+void c0_lib_printchar(char c) {
+    putchar(c);
+}
+
+void c0_lib_flush(void) {
+    fflush(stdout);
+}
+
+static bool at_eof(FILE *f) {
+    int c = getc(f);
+    if (c == EOF) {
+        return true;
+    }
+    ungetc(c, f);
+    return false;
+}
+
+bool c0_lib_eof(void) {
+    return at_eof(stdin);
+}
+
+/* A line without its newline, NULL at the end of the input */
+static c0_string line(FILE *f) {
+    size_t size = 0;
+    char *buffer = NULL;
+    ssize_t n = getline(&buffer, &size, f);
+    if (n < 0) {
+        free(buffer);
+        return NULL;
+    }
+    if (n > 0 && buffer[n - 1] == '\n') {
+        buffer[n - 1] = '\0';
+    }
+    c0_string s = copy(buffer);
+    free(buffer);
+    return s;
+}
+
+c0_string c0_lib_readline(void) {
+    c0_string s = line(stdin);
+    if (s == NULL) {
+        library_error("readline: end of input");
+    }
+    return s;
+}
+
+/* <string> */
+
+int32_t c0_lib_string_length(c0_string s) {
+    return (int32_t)strlen(str(s));
+}
+
+char c0_lib_string_charat(c0_string s, int32_t idx) {
+    int32_t n = c0_lib_string_length(s);
+    if (idx < 0 || idx >= n) {
+        library_error("string_charat: index %d is out of bounds for a string of length %d", idx, n);
+    }
+    return s[idx];
+}
+
+c0_string c0_lib_string_join(c0_string a, c0_string b) {
+    size_t n = strlen(str(a));
+    char *s = string(n + strlen(str(b)));
+    strcpy(s, str(a));
+    strcpy(s + n, str(b));
+    return s;
+}
+
+c0_string c0_lib_string_sub(c0_string a, int32_t start, int32_t end) {
+    int32_t n = c0_lib_string_length(a);
+    if (start < 0 || start > end || end > n) {
+        library_error("string_sub: substring %d..%d is out of bounds for a string of length %d", start, end, n);
+    }
+    char *s = string((size_t)(end - start));
+    memcpy(s, str(a) + start, (size_t)(end - start));
+    return s;
+}
+
+bool c0_lib_string_equal(c0_string a, c0_string b) {
+    return strcmp(str(a), str(b)) == 0;
+}
+
+int32_t c0_lib_string_compare(c0_string a, c0_string b) {
+    int c = strcmp(str(a), str(b));
+    return c < 0 ? -1 : c > 0 ? 1 : 0;
+}
+
+c0_string c0_lib_string_fromint(int32_t i) {
+    char buffer[16];
+    snprintf(buffer, sizeof buffer, "%d", i);
+    return copy(buffer);
+}
+
+c0_string c0_lib_string_frombool(bool b) {
+    return b ? "true" : "false";
+}
+
+c0_string c0_lib_string_fromchar(char c) {
+    char *s = string(1);
+    s[0] = c;
+    return s;
+}
+
+c0_string c0_lib_string_tolower(c0_string s) {
+    char *t = (char *)copy(str(s));
+    for (char *c = t; *c != '\0'; c++) {
+        *c = (char)tolower((unsigned char)*c);
+    }
+    return t;
+}
+
+bool c0_lib_string_terminated(c0_array *A, int32_t n) {
+    for (int32_t i = 0; i < n && i < c0_length(A); i++) {
+        if (A->elems[i] == '\0') {
+            return true;
+        }
+    }
+    return false;
+}
+
+c0_array *c0_lib_string_to_chararray(c0_string s) {
+    int32_t n = c0_lib_string_length(s);
+    c0_array *A = c0_alloc_array(1, n + 1);
+    memcpy(A->elems, str(s), (size_t)n);
+    return A;
+}
+
+c0_string c0_lib_string_from_chararray(c0_array *A) {
+    int32_t n = 0;
+    while (n < c0_length(A) && A->elems[n] != '\0') {
+        n++;
+    }
+    char *s = string((size_t)n);
+    if (n > 0) {
+        memcpy(s, A->elems, (size_t)n);
+    }
+    return s;
+}
+
+int32_t c0_lib_char_ord(char c) {
+    return c;
+}
+
+char c0_lib_char_chr(int32_t n) {
+    if (n < 0 || n > 127) {
+        library_error("char_chr: %d is not an ASCII character", n);
+    }
+    return (char)n;
+}
+
+/* <util> */
+
+int32_t c0_lib_int_size(void) {
+    return 4;
+}
+
+int32_t c0_lib_int_max(void) {
+    return INT32_MAX;
+}
+
+int32_t c0_lib_int_min(void) {
+    return INT32_MIN;
+}
+
+int32_t c0_lib_abs(int32_t x) {
+    return x == INT32_MIN ? x : x < 0 ? -x : x;
+}
+
+int32_t c0_lib_max(int32_t x, int32_t y) {
+    return x > y ? x : y;
+}
+
+int32_t c0_lib_min(int32_t x, int32_t y) {
+    return x < y ? x : y;
+}
+
+c0_string c0_lib_int2hex(int32_t x) {
+    char buffer[16];
+    snprintf(buffer, sizeof buffer, "%08X", (uint32_t)x);
+    return copy(buffer);
+}
+
+/* <parse> */
+
+static int radix(const char *function, int32_t base) {
+    if (base < 2 || base > 36) {
+        library_error("%s: base %d is not between 2 and 36", function, base);
+    }
+    return base;
+}
+
+/* An int, with an optional sign, which must be in range, from the n characters at s */
+static bool parse_int(const char *s, size_t n, int base, int32_t *result) {
+    size_t i = 0;
+    bool negative = false;
+    if (n > 0 && (s[0] == '+' || s[0] == '-')) {
+        negative = s[0] == '-';
+        i++;
+    }
+    if (i == n) {
+        return false;
+    }
+    int64_t value = 0;
+    for (; i < n; i++) {
+        int c = tolower((unsigned char)s[i]);
+        int digit = isdigit(c) ? c - '0' : islower(c) ? c - 'a' + 10 : base;
+        if (digit >= base) {
+            return false;
+        }
+        value = value * base + digit;
+        if (value > (int64_t)INT32_MAX + 1) {
+            return false;
+        }
+    }
+    value = negative ? -value : value;
+    if (value > INT32_MAX) {
+        return false;
+    }
+    *result = (int32_t)value;
+    return true;
+}
+
+bool *c0_lib_parse_bool(c0_string s) {
+    bool *b = NULL;
+    if (strcmp(str(s), "true") == 0 || strcmp(str(s), "false") == 0) {
+        b = c0_alloc(sizeof(bool));
+        *b = str(s)[0] == 't';
+    }
+    return b;
+}
+
+int32_t *c0_lib_parse_int(c0_string s, int32_t base) {
+    int32_t n;
+    if (!parse_int(str(s), strlen(str(s)), radix("parse_int", base), &n)) {
+        return NULL;
+    }
+    int32_t *p = c0_alloc(sizeof(int32_t));
+    *p = n;
+    return p;
+}
+
+/* The start and length of the token after *s, moving *s past it, false when there are no more */
+static bool token(const char **s, const char **start, size_t *n) {
+    while (isspace((unsigned char)**s)) {
+        (*s)++;
+    }
+    if (**s == '\0') {
+        return false;
+    }
+    *start = *s;
+    while (**s != '\0' && !isspace((unsigned char)**s)) {
+        (*s)++;
+    }
+    *n = (size_t)(*s - *start);
+    return true;
+}
+
+int32_t c0_lib_num_tokens(c0_string s) {
+    const char *rest = str(s), *start;
+    size_t n;
+    int32_t count = 0;
+    while (token(&rest, &start, &n)) {
+        count++;
+    }
+    return count;
+}
+
+bool c0_lib_int_tokens(c0_string s, int32_t base) {
+    int b = radix("int_tokens", base);
+    const char *rest = str(s), *start;
+    size_t n;
+    int32_t value;
+    while (token(&rest, &start, &n)) {
+        if (!parse_int(start, n, b, &value)) {
+            return false;
+        }
+    }
+    return true;
+}
+
+c0_array *c0_lib_parse_tokens(c0_string s) {
+    c0_array *A = c0_alloc_array(sizeof(c0_string), c0_lib_num_tokens(s));
+    c0_string *tokens = (c0_string *)A->elems;
+    const char *rest = str(s), *start;
+    size_t n;
+    for (int32_t i = 0; token(&rest, &start, &n); i++) {
+        char *t = string(n);
+        memcpy(t, start, n);
+        tokens[i] = t;
+    }
+    return A;
+}
+
+c0_array *c0_lib_parse_ints(c0_string s, int32_t base) {
+    int b = radix("parse_ints", base);
+    c0_array *A = c0_alloc_array(sizeof(int32_t), c0_lib_num_tokens(s));
+    int32_t *ints = (int32_t *)A->elems;
+    const char *rest = str(s), *start;
+    size_t n;
+    for (int32_t i = 0; token(&rest, &start, &n); i++) {
+        if (!parse_int(start, n, b, &ints[i])) {
+            library_error("parse_ints: `%.*s` is not an int", (int)n, start);
+        }
+    }
+    return A;
+}
+
+/* <rand>, the generator of the interpreter */
+
+struct c0_rand {
+    uint32_t state;
+};
+
+struct c0_rand *c0_lib_init_rand(int32_t seed) {
+    struct c0_rand *gen = c0_alloc(sizeof(struct c0_rand));
+    gen->state = (uint32_t)seed;
+    return gen;
+}
+
+int32_t c0_lib_rand(struct c0_rand *gen) {
+    if (gen == NULL) {
+        library_error("rand: the generator is NULL");
+    }
+    gen->state = gen->state * 1664525u + 1013904223u;
+    return (int32_t)gen->state;
+}
+
+/* <args>, the options registered so far */
+
+enum option_kind { FLAG, INT, STRING };
+
+struct option {
+    c0_string name;
+    enum option_kind kind;
+    void *ptr;
+    struct option *next;
+};
+
+static struct option *options;
+
+static void option(c0_string name, enum option_kind kind, void *ptr) {
+    if (ptr == NULL) {
+        library_error("args: the option %s is stored at NULL", str(name));
+    }
+    struct option *o = c0_alloc(sizeof(struct option));
+    o->name = name;
+    o->kind = kind;
+    o->ptr = ptr;
+    o->next = options;
+    options = o;
+}
+
+void c0_lib_args_flag(c0_string name, bool *ptr) {
+    option(name, FLAG, ptr);
+}
+
+void c0_lib_args_int(c0_string name, int32_t *ptr) {
+    option(name, INT, ptr);
+}
+
+void c0_lib_args_string(c0_string name, c0_string *ptr) {
+    option(name, STRING, ptr);
+}
+
+struct c0_args *c0_lib_args_parse(void) {
+    c0_array *rest = c0_alloc_array(sizeof(c0_string), c0_argc);
+    c0_string *argv = (c0_string *)rest->elems;
+    int32_t argc = 0;
+    for (int i = 1; i < c0_argc; i++) {
+        const char *arg = c0_argv[i];
+        struct option *o = options;
+        while (o != NULL && strcmp(arg, str(o->name)) != 0) {
+            o = o->next;
+        }
+        if (o == NULL) {
+            argv[argc++] = arg;
+            continue;
+        }
+        if (o->kind == FLAG) {
+            *(bool *)o->ptr = true;
+            continue;
+        }
+        if (++i == c0_argc) {
+            return NULL;
+        }
+        if (o->kind == STRING) {
+            *(c0_string *)o->ptr = c0_argv[i];
+        } else if (!parse_int(c0_argv[i], strlen(c0_argv[i]), 10, (int32_t *)o->ptr)) {
+            return NULL;
+        }
+    }
+    rest->length = argc;
+    struct c0_args *args = c0_alloc(sizeof(struct c0_args));
+    args->argc = argc;
+    args->argv = rest;
+    return args;
+}
+
+/* <file> */
+
+struct c0_file {
+    FILE *handle;
+    bool closed;
+};
+
+static struct c0_file *open_file(const char *function, struct c0_file *f) {
+    if (f == NULL) {
+        library_error("%s: the file is NULL", function);
+    }
+    if (f->closed) {
+        library_error("%s: the file is closed", function);
+    }
+    return f;
+}
+
+bool c0_lib_file_closed(struct c0_file *f) {
+    if (f == NULL) {
+        library_error("file_closed: the file is NULL");
+    }
+    return f->closed;
+}
+
+struct c0_file *c0_lib_file_read(c0_string path) {
+    FILE *handle = fopen(str(path), "r");
+    if (handle == NULL) {
+        return NULL;
+    }
+    struct c0_file *f = c0_alloc(sizeof(struct c0_file));
+    f->handle = handle;
+    return f;
+}
+
+void c0_lib_file_close(struct c0_file *f) {
+    fclose(open_file("file_close", f)->handle);
+    f->closed = true;
+}
+
+bool c0_lib_file_eof(struct c0_file *f) {
+    return at_eof(open_file("file_eof", f)->handle);
+}
+
+c0_string c0_lib_file_readline(struct c0_file *f) {
+    c0_string s = line(open_file("file_readline", f)->handle);
+    if (s == NULL) {
+        library_error("file_readline: end of file");
+    }
+    return s;
+}
@@ -0,0 +1,198 @@
+/*
+The runtime library compiled C0 programs are linked with: allocation, the
+errors C0 stops programs with, and the bundled libraries.
+
+Values are represented as
+    bool      bool, 0 or 1
+    char      char, ASCII
+    int       int32_t, wrapping
+    string    c0_string, NUL terminated, where NULL is the empty string
+    t*        a pointer to zeroed memory from c0_alloc, or NULL
+    t[]       a c0_array, where NULL is an array of length 0
+    struct s  its fields in order, as C lays them out
+
+C0 functions are the symbols `_c0_name`, library functions `c0_lib_name`.
+Programs compiled to C declare the library functions they call with their
+own types, so the declarations here are only seen by the runtime itself.
+*/
+#ifndef C0RT_H
+#define C0RT_H
+
+#include <stdbool.h>
+#include <stddef.h>
+#include <stdint.h>
+
+#if defined(__STDC_VERSION__) && __STDC_VERSION__ >= 201112L
+#define C0_NORETURN _Noreturn
+#elif defined(__GNUC__)
+#define C0_NORETURN __attribute__((noreturn))
+#else
+#define C0_NORETURN
+#endif
+
+typedef const char *c0_string;
+
+/* The elements follow the header, at offset 8 */
+typedef struct c0_array {
+    int32_t length;
+    int32_t elt_size;
+    char elems[];
+} c0_array;
+
+void *c0_alloc(size_t bytes);
+c0_array *c0_alloc_array(size_t elt_size, int32_t n);
+
+/* Stop the program, with SIGSEGV, SIGFPE or SIGABRT */
+C0_NORETURN void c0_null_error(void);
+C0_NORETURN void c0_bounds_error(void);
+C0_NORETURN void c0_div_error(void);
+C0_NORETURN void c0_shift_error(void);
+C0_NORETURN void c0_assert_failed(c0_string message);
+C0_NORETURN void c0_error(c0_string message);
+
+/* The program, which the runtime's main calls */
+int32_t _c0_main(void);
+
+/* C0's arithmetic and checks, for programs compiled to C */
+
+/* An int from its bits, without overflowing */
+static inline int32_t c0_wrap(uint32_t x) {
+    return x <= INT32_MAX ? (int32_t)x : -(int32_t)(UINT32_MAX - x) - 1;
+}
+
+static inline int32_t c0_add(int32_t a, int32_t b) {
+    return c0_wrap((uint32_t)a + (uint32_t)b);
+}
+
+static inline int32_t c0_sub(int32_t a, int32_t b) {
+    return c0_wrap((uint32_t)a - (uint32_t)b);
+}
+
+static inline int32_t c0_mul(int32_t a, int32_t b) {
+    return c0_wrap((uint32_t)a * (uint32_t)b);
+}
+
+static inline int32_t c0_neg(int32_t a) {
+    return c0_wrap(0u - (uint32_t)a);
+}
+
+static inline int32_t c0_div(int32_t a, int32_t b) {
+    if (b == 0 || (a == INT32_MIN && b == -1)) {
+        c0_div_error();
+    }
+    return a / b;
+}
+
+static inline int32_t c0_mod(int32_t a, int32_t b) {
+    if (b == 0 || (a == INT32_MIN && b == -1)) {
+        c0_div_error();
+    }
+    return a % b;
+}
+
+static inline int32_t c0_shl(int32_t a, int32_t b) {
+    if (b < 0 || b > 31) {
+        c0_shift_error();
+    }
+    return c0_wrap((uint32_t)a << b);
+}
+
+/* Arithmetic, even where C leaves shifting negative numbers to the compiler */
+static inline int32_t c0_shr(int32_t a, int32_t b) {
+    if (b < 0 || b > 31) {
+        c0_shift_error();
+    }
+    return a < 0 ? ~(~a >> b) : a >> b;
+}
+
+static inline void *c0_deref(void *p) {
+    if (p == NULL) {
+        c0_null_error();
+    }
+    return p;
+}
+
+/* The address of A[i] */
+static inline void *c0_elem(c0_array *A, int32_t i) {
+    if (A == NULL || i < 0 || i >= A->length) {
+        c0_bounds_error();
+    }
+    return A->elems + (size_t)i * (size_t)A->elt_size;
+}
+
+static inline int32_t c0_length(c0_array *A) {
+    return A == NULL ? 0 : A->length;
+}
+
+#ifdef C0RT_LIBRARIES
+
+/* <conio> */
+void c0_lib_print(c0_string s);
+void c0_lib_println(c0_string s);
+void c0_lib_printint(int32_t i);
+void c0_lib_printbool(bool b);
+void c0_lib_printchar(char c);
+void c0_lib_flush(void);
+bool c0_lib_eof(void);
+c0_string c0_lib_readline(void);
+
+/* <string> */
+int32_t c0_lib_string_length(c0_string s);
+char c0_lib_string_charat(c0_string s, int32_t idx);
+c0_string c0_lib_string_join(c0_string a, c0_string b);
+c0_string c0_lib_string_sub(c0_string a, int32_t start, int32_t end);
+bool c0_lib_string_equal(c0_string a, c0_string b);
+int32_t c0_lib_string_compare(c0_string a, c0_string b);
+c0_string c0_lib_string_fromint(int32_t i);
+c0_string c0_lib_string_frombool(bool b);
+c0_string c0_lib_string_fromchar(char c);
+c0_string c0_lib_string_tolower(c0_string s);
+bool c0_lib_string_terminated(c0_array *A, int32_t n);
+c0_array *c0_lib_string_to_chararray(c0_string s);
+c0_string c0_lib_string_from_chararray(c0_array *A);
+int32_t c0_lib_char_ord(char c);
+char c0_lib_char_chr(int32_t n);
+
+/* <util> */
+int32_t c0_lib_int_size(void);
+int32_t c0_lib_int_max(void);
+int32_t c0_lib_int_min(void);
+int32_t c0_lib_abs(int32_t x);
+int32_t c0_lib_max(int32_t x, int32_t y);
+int32_t c0_lib_min(int32_t x, int32_t y);
+c0_string c0_lib_int2hex(int32_t x);
+
+/* <parse> */
+bool *c0_lib_parse_bool(c0_string s);
+int32_t *c0_lib_parse_int(c0_string s, int32_t base);
+int32_t c0_lib_num_tokens(c0_string s);
+bool c0_lib_int_tokens(c0_string s, int32_t base);
+c0_array *c0_lib_parse_tokens(c0_string s);
+c0_array *c0_lib_parse_ints(c0_string s, int32_t base);
+
+/* <rand> */
+struct c0_rand;
+struct c0_rand *c0_lib_init_rand(int32_t seed);
+int32_t c0_lib_rand(struct c0_rand *gen);
+
+/* <args>, struct args as C0 lays it out */
+struct c0_args {
+    int32_t argc;
+    c0_array *argv;
+};
+void c0_lib_args_flag(c0_string name, bool *ptr);
+void c0_lib_args_int(c0_string name, int32_t *ptr);
+void c0_lib_args_string(c0_string name, c0_string *ptr);
+struct c0_args *c0_lib_args_parse(void);
+
+/* <file> */
+struct c0_file;
+bool c0_lib_file_closed(struct c0_file *f);
+struct c0_file *c0_lib_file_read(c0_string path);
+void c0_lib_file_close(struct c0_file *f);
+bool c0_lib_file_eof(struct c0_file *f);
+c0_string c0_lib_file_readline(struct c0_file *f);
+
+#endif
+
+#endif
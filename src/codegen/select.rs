@@ -0,0 +1,392 @@
+use codegen::asm::{self, Alu, Cond, Instr, Loc, Operand, Reg, Shift, Width, ARGS};
+use ir::ir::{self as ir, Binop, Size, Terminator, Unop};
+use std::collections::HashSet;
+
+/*
+Instruction selection, from the IR out of SSA form to x86-64 over virtual
+registers. Temporary n of the IR is virtual register n. Arguments and
+results are moved to and from the registers the calling convention puts
+them in, and division to and from %eax and %edx, leaving it to register
+allocation to remove the moves it can.
+
+The checks of the IR become a comparison and a jump to a call of the
+runtime function which stops the program, placed after the function's
+code, so the code which does not fail runs straight through.
+ */
+pub fn select(f: &ir::Function, externs: &HashSet<String>, strings: &mut Vec<String>) -> asm::Function {
+    let mut s = Selector {
+        f: asm::Function {
+            name: symbol(&f.name, false),
+            instrs: Vec::new(),
+            virts: f.temps.len(),
+            labels: f.blocks.len(),
+            out_args: 0,
+            slots: 0,
+            saved: Vec::new(),
+        },
+        temps: &f.temps,
+        externs,
+        strings,
+        traps: Vec::new(),
+        asserts: Vec::new(),
+    };
+    for (n, &p) in f.params.iter().enumerate() {
+        let arg = match ARGS.get(n) {
+            Some(&r) => Operand::reg(r),
+            None => Operand::InArg(n - ARGS.len()),
+        };
+        s.emit(Instr::Mov(Width::Q, arg, Operand::virt(p)));
+    }
+    for (l, b) in f.blocks.iter().enumerate() {
+        s.emit(Instr::Label(l));
+        for i in &b.instrs {
+            s.instr(i);
+        }
+        s.terminator(&b.term);
+    }
+    for (label, runtime) in s.traps.clone() {
+        s.emit(Instr::Label(label));
+        s.emit(Instr::Trap(runtime, 0));
+    }
+    for (label, message) in s.asserts.clone() {
+        s.emit(Instr::Label(label));
+        s.emit(Instr::Lea(Operand::Str(message), Loc::Reg(Reg::Rdi)));
+        s.emit(Instr::Trap("c0_assert_failed", 1));
+    }
+    s.f
+}
+
+// The symbol of a C0 function, or of a library function
+pub fn symbol(name: &str, library: bool) -> String {
+    if library {
+        format!("c0_lib_{}", name)
+    } else {
+        format!("_c0_{}", name)
+    }
+}
+
+struct Selector<'a> {
+    f: asm::Function,
+    temps: &'a [Size],
+    externs: &'a HashSet<String>,
+    strings: &'a mut Vec<String>,
+    // The label of each runtime function jumped to when a check fails
+    traps: Vec<(asm::Label, &'static str)>,
+    // The label of each failing assertion, with its message
+    asserts: Vec<(asm::Label, usize)>,
+}
+
+// Bools, chars and ints are computed on 32 bits, pointers on 64
+fn width(size: Size) -> Width {
+    match size {
+        Size::I8 | Size::I32 => Width::L,
+        Size::I64 => Width::Q,
+    }
+}
+
+// How memory of a size is accessed
+fn memory_width(size: Size) -> Width {
+    match size {
+        Size::I8 => Width::B,
+        Size::I32 => Width::L,
+        Size::I64 => Width::Q,
+    }
+}
+
+fn operand(a: ir::Operand) -> Operand {
+    match a {
+        ir::Operand::Temp(t) => Operand::virt(t),
+        ir::Operand::Imm(n) => Operand::Imm(n),
+    }
+}
+
+fn virt(t: ir::Temp) -> Loc {
+    Loc::Virt(t)
+}
+
+impl<'a> Selector<'a> {
+    fn emit(&mut self, i: Instr) {
+        self.f.instrs.push(i);
+    }
+
+    // The operand in a register, moving an immediate into a new one
+    fn loc(&mut self, a: ir::Operand, w: Width) -> Loc {
+        match a {
+            ir::Operand::Temp(t) => virt(t),
+            ir::Operand::Imm(n) => {
+                let v = Loc::Virt(self.f.virt());
+                self.emit(Instr::Mov(w, Operand::Imm(n), Operand::Loc(v)));
+                v
+            }
+        }
+    }
+
+    // The label jumping to which stops the program through `runtime`
+    fn trap(&mut self, runtime: &'static str) -> asm::Label {
+        if let Some(&(l, _)) = self.traps.iter().find(|&&(_, r)| r == runtime) {
+            return l;
+        }
+        let l = self.f.label();
+        self.traps.push((l, runtime));
+        l
+    }
+
+    fn string(&mut self, s: &str) -> usize {
+        self.strings.push(String::from(s));
+        self.strings.len() - 1
+    }
+
+    fn instr(&mut self, i: &ir::Instr) {
+        match *i {
+            ir::Instr::Move(d, a) => self.emit(Instr::Mov(width(self.temps[d]), operand(a), Operand::virt(d))),
+            ir::Instr::Unop(d, op, a) => {
+                self.emit(Instr::Mov(Width::L, operand(a), Operand::virt(d)));
+                self.emit(match op {
+                    Unop::Neg => Instr::Neg(Width::L, virt(d)),
+                    Unop::BitNot => Instr::Not(Width::L, virt(d)),
+                    Unop::Not => Instr::Alu(Alu::Xor, Width::L, Operand::Imm(1), virt(d)),
+                });
+            }
+            ir::Instr::Binop(d, op, size, a, b) => self.binop(d, op, width(size), a, b),
+            ir::Instr::Call(d, ref name, ref args) => {
+                let library = self.externs.contains(name);
+                self.call(symbol(name, library), args.iter().map(|&a| operand(a)).collect());
+                if let Some(d) = d {
+                    self.result(d);
+                }
+            }
+            ir::Instr::Load(d, size, a) => {
+                let address = Operand::at(self.loc(a, Width::Q), 0);
+                self.emit(match size {
+                    Size::I8 => Instr::Movzb(address, virt(d)),
+                    _ => Instr::Mov(width(size), address, Operand::virt(d)),
+                });
+            }
+            ir::Instr::Store(size, a, v) => {
+                let address = Operand::at(self.loc(a, Width::Q), 0);
+                self.emit(Instr::Mov(memory_width(size), operand(v), address));
+            }
+            ir::Instr::Alloc(d, bytes) => {
+                self.call(String::from("c0_alloc"), vec![Operand::Imm(bytes as i64)]);
+                self.result(d);
+            }
+            ir::Instr::AllocArray(d, bytes, n) => {
+                self.call(String::from("c0_alloc_array"), vec![Operand::Imm(bytes as i64), operand(n)]);
+                self.result(d);
+            }
+            ir::Instr::Length(d, a) => {
+                // NULL is an array of length 0
+                let a = self.loc(a, Width::Q);
+                let done = self.f.label();
+                self.emit(Instr::Mov(Width::L, Operand::Imm(0), Operand::virt(d)));
+                self.emit(Instr::Cmp(Width::Q, Operand::Imm(0), Operand::Loc(a)));
+                self.emit(Instr::J(Cond::E, done));
+                self.emit(Instr::Mov(Width::L, Operand::at(a, 0), Operand::virt(d)));
+                self.emit(Instr::Label(done));
+            }
+            ir::Instr::Elem(d, bytes, a, i) => {
+                let a = self.loc(a, Width::Q);
+                let index = Loc::Virt(self.f.virt());
+                self.emit(match i {
+                    ir::Operand::Imm(n) => Instr::Mov(Width::Q, Operand::Imm(n), Operand::Loc(index)),
+                    ir::Operand::Temp(t) => Instr::Movsl(Operand::virt(t), index),
+                });
+                let scale = match bytes {
+                    1 | 2 | 4 | 8 => bytes as u8,
+                    _ => {
+                        self.emit(Instr::Alu(Alu::Imul, Width::Q, Operand::Imm(bytes as i64), index));
+                        1
+                    }
+                };
+                let address = Operand::Mem {
+                    base: a,
+                    index: Some((index, scale)),
+                    disp: 8,
+                };
+                self.emit(Instr::Lea(address, virt(d)));
+            }
+            ir::Instr::Str(d, ref s) => {
+                let n = self.string(s);
+                self.emit(Instr::Lea(Operand::Str(n), virt(d)));
+            }
+            ir::Instr::CheckNull(a) => {
+                let trap = self.trap("c0_null_error");
+                match a {
+                    ir::Operand::Imm(0) => self.emit(Instr::Jmp(trap)),
+                    ir::Operand::Imm(_) => (),
+                    ir::Operand::Temp(t) => {
+                        self.emit(Instr::Cmp(Width::Q, Operand::Imm(0), Operand::virt(t)));
+                        self.emit(Instr::J(Cond::E, trap));
+                    }
+                }
+            }
+            ir::Instr::CheckBounds(a, i) => {
+                let trap = self.trap("c0_bounds_error");
+                let a = self.loc(a, Width::Q);
+                self.emit(Instr::Cmp(Width::Q, Operand::Imm(0), Operand::Loc(a)));
+                self.emit(Instr::J(Cond::E, trap));
+                // Unsigned, so a negative index is above the length
+                self.emit(Instr::Cmp(Width::L, operand(i), Operand::at(a, 0)));
+                self.emit(Instr::J(Cond::Be, trap));
+            }
+            ir::Instr::CheckDiv(a, b) => self.check_div(a, b),
+            ir::Instr::CheckShift(b) => {
+                let trap = self.trap("c0_shift_error");
+                match b {
+                    ir::Operand::Imm(0..=31) => (),
+                    ir::Operand::Imm(_) => self.emit(Instr::Jmp(trap)),
+                    ir::Operand::Temp(t) => {
+                        self.emit(Instr::Cmp(Width::L, Operand::Imm(31), Operand::virt(t)));
+                        self.emit(Instr::J(Cond::A, trap));
+                    }
+                }
+            }
+            ir::Instr::Assert(c, ref message) => {
+                let label = self.f.label();
+                let message = self.string(message);
+                self.asserts.push((label, message));
+                match c {
+                    ir::Operand::Imm(0) => self.emit(Instr::Jmp(label)),
+                    ir::Operand::Imm(_) => (),
+                    ir::Operand::Temp(t) => {
+                        self.emit(Instr::Cmp(Width::L, Operand::Imm(0), Operand::virt(t)));
+                        self.emit(Instr::J(Cond::E, label));
+                    }
+                }
+            }
+            ir::Instr::Phi(..) => panic!("phis must be removed before instruction selection"),
+        }
+    }
+
+    // Division by zero, or of INT_MIN by -1, is an error
+    fn check_div(&mut self, a: ir::Operand, b: ir::Operand) {
+        let trap = self.trap("c0_div_error");
+        let min = Operand::Imm(i64::from(i32::MIN));
+        match b {
+            ir::Operand::Imm(0) => return self.emit(Instr::Jmp(trap)),
+            ir::Operand::Imm(-1) => (),
+            ir::Operand::Imm(_) => return,
+            ir::Operand::Temp(t) => {
+                self.emit(Instr::Cmp(Width::L, Operand::Imm(0), Operand::virt(t)));
+                self.emit(Instr::J(Cond::E, trap));
+            }
+        }
+        let ok = self.f.label();
+        if let ir::Operand::Temp(t) = b {
+            self.emit(Instr::Cmp(Width::L, Operand::Imm(-1), Operand::virt(t)));
+            self.emit(Instr::J(Cond::Ne, ok));
+        }
+        match a {
+            ir::Operand::Imm(n) if n == i64::from(i32::MIN) => self.emit(Instr::Jmp(trap)),
+            ir::Operand::Imm(_) => (),
+            ir::Operand::Temp(t) => {
+                self.emit(Instr::Cmp(Width::L, min, Operand::virt(t)));
+                self.emit(Instr::J(Cond::E, trap));
+            }
+        }
+        self.emit(Instr::Label(ok));
+    }
+
+    fn binop(&mut self, d: ir::Temp, op: Binop, w: Width, a: ir::Operand, b: ir::Operand) {
+        let alu = match op {
+            Binop::Add => Some(Alu::Add),
+            Binop::Sub => Some(Alu::Sub),
+            Binop::Mul => Some(Alu::Imul),
+            Binop::And => Some(Alu::And),
+            Binop::Or => Some(Alu::Or),
+            Binop::Xor => Some(Alu::Xor),
+            _ => None,
+        };
+        if let Some(alu) = alu {
+            // `%d = a - %d` must not overwrite %d before reading it
+            let target = if b == ir::Operand::Temp(d) && a != b { Loc::Virt(self.f.virt()) } else { virt(d) };
+            self.emit(Instr::Mov(w, operand(a), Operand::Loc(target)));
+            self.emit(Instr::Alu(alu, w, operand(b), target));
+            if target != virt(d) {
+                self.emit(Instr::Mov(w, Operand::Loc(target), Operand::virt(d)));
+            }
+            return;
+        }
+        match op {
+            Binop::Div | Binop::Mod => {
+                self.emit(Instr::Mov(Width::L, operand(a), Operand::reg(Reg::Rax)));
+                let divisor = self.loc(b, Width::L);
+                self.emit(Instr::Cltd);
+                self.emit(Instr::Idiv(Width::L, divisor));
+                let result = if op == Binop::Div { Reg::Rax } else { Reg::Rdx };
+                self.emit(Instr::Mov(Width::L, Operand::reg(result), Operand::virt(d)));
+            }
+            Binop::Shl | Binop::Shr => {
+                let shift = if op == Binop::Shl { Shift::Sal } else { Shift::Sar };
+                let count = match b {
+                    ir::Operand::Imm(n) => Operand::Imm(n & 31),
+                    ir::Operand::Temp(t) => {
+                        self.emit(Instr::Mov(Width::L, Operand::virt(t), Operand::reg(Reg::Rcx)));
+                        Operand::reg(Reg::Rcx)
+                    }
+                };
+                self.emit(Instr::Mov(Width::L, operand(a), Operand::virt(d)));
+                self.emit(Instr::Shift(shift, Width::L, count, virt(d)));
+            }
+            _ => {
+                let cond = match op {
+                    Binop::Lt => Cond::L,
+                    Binop::Le => Cond::Le,
+                    Binop::Gt => Cond::G,
+                    Binop::Ge => Cond::Ge,
+                    Binop::Eq => Cond::E,
+                    _ => Cond::Ne,
+                };
+                let a = self.loc(a, w);
+                self.emit(Instr::Cmp(w, operand(b), Operand::Loc(a)));
+                self.emit(Instr::Set(cond, virt(d)));
+                self.emit(Instr::Movzb(Operand::virt(d), virt(d)));
+            }
+        }
+    }
+
+    // Passes the arguments as the System V ABI does
+    fn call(&mut self, name: String, args: Vec<Operand>) {
+        let on_stack = args.len().saturating_sub(ARGS.len());
+        self.f.out_args = self.f.out_args.max(on_stack);
+        for (n, &a) in args.iter().enumerate().skip(ARGS.len()) {
+            self.emit(Instr::Mov(Width::Q, a, Operand::OutArg(n - ARGS.len())));
+        }
+        for (&a, &r) in args.iter().zip(ARGS.iter()) {
+            self.emit(Instr::Mov(Width::Q, a, Operand::reg(r)));
+        }
+        let n = args.len().min(ARGS.len());
+        self.emit(Instr::Call(name, n));
+    }
+
+    fn result(&mut self, d: ir::Temp) {
+        let rax = Operand::reg(Reg::Rax);
+        self.emit(match self.temps[d] {
+            // The ABI only defines the low byte of a bool or char
+            Size::I8 => Instr::Movzb(rax, virt(d)),
+            Size::I32 => Instr::Mov(Width::L, rax, Operand::virt(d)),
+            Size::I64 => Instr::Mov(Width::Q, rax, Operand::virt(d)),
+        });
+    }
+
+    fn terminator(&mut self, t: &Terminator) {
+        match *t {
+            Terminator::Jump(l) => self.emit(Instr::Jmp(l)),
+            Terminator::Branch(ir::Operand::Imm(n), yes, no) => self.emit(Instr::Jmp(if n != 0 { yes } else { no })),
+            Terminator::Branch(c, yes, no) => {
+                self.emit(Instr::Cmp(Width::L, Operand::Imm(0), operand(c)));
+                self.emit(Instr::J(Cond::Ne, yes));
+                self.emit(Instr::Jmp(no));
+            }
+            Terminator::Return(None) => self.emit(Instr::Ret(false)),
+            Terminator::Return(Some(a)) => {
+                self.emit(Instr::Mov(Width::Q, operand(a), Operand::reg(Reg::Rax)));
+                self.emit(Instr::Ret(true));
+            }
+            Terminator::Error(a) => {
+                self.emit(Instr::Mov(Width::Q, operand(a), Operand::reg(Reg::Rdi)));
+                self.emit(Instr::Trap("c0_error", 1));
+            }
+        }
+    }
+}
@@ -0,0 +1,53 @@
+use codegen::asm::{Function, Instr, Loc, Operand, Virt, Width};
+use std::collections::HashMap;
+use std::mem;
+
+/*
+Spilling virtual registers which could not be given a register to the
+stack. Each spilled register gets a slot of its own, loaded into a new
+virtual register before each instruction which reads it and stored from
+one after each which writes it. The new registers live only across one
+instruction, so they are never worth spilling themselves; they are
+returned for allocation to know that.
+ */
+pub fn spill(f: &mut Function, spilled: &[Virt]) -> Vec<Virt> {
+    let slots: HashMap<Virt, usize> = spilled.iter().enumerate().map(|(n, &v)| (v, f.slots + n)).collect();
+    f.slots += spilled.len();
+    let mut fresh = Vec::new();
+    let mut instrs = Vec::new();
+    for mut i in mem::take(&mut f.instrs) {
+        let uses = i.uses();
+        let defs = i.defs();
+        // The new register standing for each spilled one the instruction mentions
+        let mut renamed: Vec<(Virt, Virt)> = Vec::new();
+        for l in i.locs_mut() {
+            if let Loc::Virt(v) = *l {
+                if slots.contains_key(&v) {
+                    let t = match renamed.iter().find(|&&(w, _)| w == v) {
+                        Some(&(_, t)) => t,
+                        None => {
+                            let t = f.virt();
+                            renamed.push((v, t));
+                            fresh.push(t);
+                            t
+                        }
+                    };
+                    *l = Loc::Virt(t);
+                }
+            }
+        }
+        for &(v, t) in &renamed {
+            if uses.contains(&Loc::Virt(v)) {
+                instrs.push(Instr::Mov(Width::Q, Operand::Slot(slots[&v]), Operand::virt(t)));
+            }
+        }
+        instrs.push(i);
+        for &(v, t) in &renamed {
+            if defs.contains(&Loc::Virt(v)) {
+                instrs.push(Instr::Mov(Width::Q, Operand::virt(t), Operand::Slot(slots[&v])));
+            }
+        }
+    }
+    f.instrs = instrs;
+    fresh
+}
@@ -0,0 +1,39 @@
+use codegen::link::link;
+use codegen::{c, emit};
+use driver::options::Backend;
+use elab::tests::elaborated;
+use ir::{lower, ssa};
+use std::env;
+use std::fs;
+use std::os::unix::process::ExitStatusExt;
+use std::process::{self, Command};
+use std::sync::atomic::{AtomicUsize, Ordering};
+
+/*
+Compiling test programs with either backend and running them, for the tests
+of both
+ */
+
+// The exit code or signal of the compiled program, and what it printed
+pub fn run(backend: Backend, text: &str, dyn_check: bool) -> (Option<i32>, Option<i32>, String) {
+    let core = elaborated(text, dyn_check);
+    let (name, source) = match backend {
+        Backend::Asm => {
+            let mut lowered = lower::lower(&core);
+            for f in &mut lowered.functions {
+                ssa::construct(f);
+                ssa::destruct(f);
+            }
+            ("program.s", emit::program(&lowered).0)
+        }
+        Backend::C => ("program.c", c::program(&core)),
+    };
+    static RUNS: AtomicUsize = AtomicUsize::new(0);
+    let n = RUNS.fetch_add(1, Ordering::SeqCst);
+    let executable = env::temp_dir().join(format!("c0c-test-{}-{}", process::id(), n));
+    let executable = executable.to_str().unwrap();
+    link(&[(name, &source)], executable).unwrap();
+    let out = Command::new(executable).output().unwrap();
+    let _ = fs::remove_file(executable);
+    (out.status.code(), out.status.signal(), String::from_utf8(out.stdout).unwrap())
+}
@@ -0,0 +1,249 @@
+use ast::dump::{self, Format};
+use driver::options::{Backend, Emit, Options, Stage, USAGE};
+use ast::ast::Program;
+use driver::session::{Analysis, Session};
+use codegen;
+use elab;
+use ir;
+use interp::error::RuntimeError;
+use interp::interp::{self, Interpreter};
+use interp::natives;
+use lexer::lexer::Lexeme;
+use source::diagnostic::Diagnostic;
+use source::source_map::SourceMap;
+use std::fmt::Write;
+use std::{fs, io};
+
+/*
+The driver runs the stages of the compiler over the files named on the command
+line and turns the outcome into an exit code
+
+    0  success
+    1  the program has errors, or could not be read
+    2  the command line was malformed
+
+A program run with `-x` exits with the result of its `main` instead, or if it
+fails as a shell reports a process killed by the signal cc0 would stop it
+with, 134 for SIGABRT, 136 for SIGFPE and 139 for SIGSEGV.
+ */
+pub const EXIT_SUCCESS: i32 = 0;
+pub const EXIT_FAILURE: i32 = 1;
+pub const EXIT_USAGE: i32 = 2;
+
+pub fn main(args: &[String]) -> i32 {
+    let options = match Options::parse(args) {
+        Ok(options) => options,
+        Err(e) => {
+            eprintln!("c0c: {}\n{}", e, USAGE);
+            return EXIT_USAGE;
+        }
+    };
+    if options.help {
+        println!("{}", USAGE);
+        return EXIT_SUCCESS;
+    }
+    if options.version {
+        println!("c0c {}", env!("CARGO_PKG_VERSION"));
+        return EXIT_SUCCESS;
+    }
+    let mut session = Session::new();
+    match compile(&options, &mut session) {
+        Ok(code) => code,
+        Err(Failure::Diagnostics(diagnostics)) => {
+            for d in &diagnostics {
+                eprint!("{}", d.render(&session.sources));
+            }
+            EXIT_FAILURE
+        }
+        Err(Failure::Message(message)) => {
+            eprintln!("c0c: {}", message);
+            EXIT_FAILURE
+        }
+        Err(Failure::Runtime(e)) => {
+            eprint!("{}", e.diagnostic().render(&session.sources));
+            e.kind.signal().exit_code()
+        }
+    }
+}
+
+pub enum Failure {
+    Diagnostics(Vec<Diagnostic>),
+    Message(String),
+    // The program ran, and stopped with an error
+    Runtime(RuntimeError),
+}
+
+impl From<Diagnostic> for Failure {
+    fn from(d: Diagnostic) -> Failure {
+        Failure::Diagnostics(vec![d])
+    }
+}
+
+impl From<RuntimeError> for Failure {
+    fn from(e: RuntimeError) -> Failure {
+        Failure::Runtime(e)
+    }
+}
+
+// The exit code of the compiler, or of the program when it is run
+pub fn compile(options: &Options, session: &mut Session) -> Result<i32, Failure> {
+    session.load(&options.files)?;
+    let tokens = session.lex()?;
+    if options.dump_tokens || options.emit == Some(Emit::Tokens) {
+        let mut out = String::new();
+        for lexemes in &tokens {
+            out.push_str(&dump_tokens(&session.sources, lexemes));
+        }
+        if options.emit == Some(Emit::Tokens) {
+            return output(options, &out);
+        }
+        print!("{}", out);
+    }
+
+    let program = session.parse(tokens, &options.libs, &options.lib_paths)?;
+    if let Some(format) = options.dump_ast {
+        print!("{}", dump::render(&dump::program(&program), format, &session.sources));
+    }
+    if options.emit == Some(Emit::Ast) {
+        return output(options, &dump::render(&dump::program(&program), Format::Tree, &session.sources));
+    }
+    if options.stop_after == Some(Stage::Parse) {
+        return Ok(EXIT_SUCCESS);
+    }
+
+    let analysis = session.check(&program)?;
+    if options.stop_after == Some(Stage::Typecheck) {
+        return Ok(EXIT_SUCCESS);
+    }
+    if options.exec {
+        return run(program, analysis, options.dyn_check);
+    }
+
+    let Analysis { resolution, typing, layouts } = analysis;
+    let core = elab::elab::elaborate(&program, &resolution, &typing, &layouts, options.dyn_check);
+    if options.emit == Some(Emit::Core) {
+        return output(options, &elab::print::program(&core));
+    }
+    let missing = codegen::link::missing(&core);
+    if !missing.is_empty() && !matches!(options.emit, Some(Emit::Ir) | Some(Emit::Ssa)) {
+        return Err(Failure::Message(format!(
+            "the library functions `{}` are not available in compiled programs",
+            missing.join("`, `")
+        )));
+    }
+    let executable = options.output.as_deref().unwrap_or("a.out");
+    if options.emit == Some(Emit::C) || (options.emit.is_none() && options.backend == Backend::C) {
+        let c = codegen::c::program(&core);
+        if options.emit == Some(Emit::C) {
+            return output(options, &c);
+        }
+        codegen::link::link(&[("program.c", &c)], executable).map_err(Failure::Message)?;
+        return Ok(EXIT_SUCCESS);
+    }
+    let mut lowered = ir::lower::lower(&core);
+    if options.emit == Some(Emit::Ir) {
+        return output(options, &ir::print::program(&lowered));
+    }
+    for f in &mut lowered.functions {
+        ir::ssa::construct(f);
+        ir::verify::ssa(f).map_err(|e| internal(&f.name, "SSA construction", e))?;
+    }
+    if options.emit == Some(Emit::Ssa) {
+        return output(options, &ir::print::program(&lowered));
+    }
+    for f in &mut lowered.functions {
+        ir::ssa::destruct(f);
+        ir::verify::function(f).map_err(|e| internal(&f.name, "SSA destruction", e))?;
+    }
+
+    let (asm, stats) = codegen::emit::program(&lowered);
+    if options.regalloc_stats {
+        for (function, stats) in stats {
+            eprintln!("{}: {}", function, stats);
+        }
+    }
+    if options.emit == Some(Emit::Asm) {
+        return output(options, &asm);
+    }
+    codegen::link::link(&[("program.s", &asm)], executable).map_err(Failure::Message)?;
+    Ok(EXIT_SUCCESS)
+}
+
+fn run(program: Program, analysis: Analysis, dyn_check: bool) -> Result<i32, Failure> {
+    // Found before the program starts, rather than when it gets to them
+    let missing = natives::missing(&program, &analysis.resolution, dyn_check);
+    if !missing.is_empty() {
+        return Err(Failure::Message(format!(
+            "the library functions `{}` are not available in the interpreter",
+            missing.join("`, `")
+        )));
+    }
+    let result = interp::with_stack(move || {
+        let (stdin, stdout) = (io::stdin(), io::stdout());
+        let (mut input, mut out) = (stdin.lock(), stdout.lock());
+        let mut interpreter = Interpreter::new(analysis.resolution, analysis.typing, &mut out, &mut input);
+        interpreter.dyn_check = dyn_check;
+        interpreter.define(&program);
+        interpreter.run_main()
+    });
+    match result {
+        Ok(Ok(code)) => Ok(code),
+        Ok(Err(e)) => Err(Failure::Runtime(e)),
+        Err(e) => Err(Failure::Message(format!("cannot start the interpreter: {}", e))),
+    }
+}
+
+// A pass which broke the IR of a function
+fn internal(function: &str, pass: &str, error: String) -> Failure {
+    Failure::Message(format!("internal error: {} left `{}` malformed: {}", pass, function, error))
+}
+
+// Writes the requested output to the `-o` file, or standard output
+fn output(options: &Options, text: &str) -> Result<i32, Failure> {
+    match options.output {
+        Some(ref path) => match fs::write(path, text) {
+            Ok(()) => Ok(EXIT_SUCCESS),
+            Err(e) => Err(Failure::Message(format!("cannot write `{}`: {}", path, e))),
+        },
+        None => {
+            print!("{}", text);
+            Ok(EXIT_SUCCESS)
+        }
+    }
+}
+
+// One token per line, `file:line:col-line:col  token`
+pub fn dump_tokens(sources: &SourceMap, lexemes: &[Lexeme]) -> String {
+    let mut out = String::new();
+    for l in lexemes {
+        let file = sources.file(l.span.file);
+        let (l1, c1) = file.position(l.span.lo);
+        let (l2, c2) = file.position(l.span.hi);
+        let _ = writeln!(out, "{}:{}:{}-{}:{}\t{:?}", file.name, l1, c1, l2, c2, l.token);
+    }
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use lexer::lexer::Lexer;
+
+    #[test]
+    fn dumping_tokens() {
+        let mut sources = SourceMap::new();
+        let file = sources.add("t.c0", String::from("int x;\n//@assert x;\n"));
+        let tokens = Lexer::new(file, &sources.file(file).text).tokens().unwrap();
+        assert_eq!(
+            dump_tokens(&sources, &tokens),
+            "t.c0:1:1-1:4\tInt\n\
+             t.c0:1:5-1:6\tIdent(\"x\")\n\
+             t.c0:1:6-1:7\tSemiColon\n\
+             t.c0:2:1-2:4\tAnnoStart\n\
+             t.c0:2:4-2:10\tAssert\n\
+             t.c0:2:11-2:12\tIdent(\"x\")\n\
+             t.c0:2:12-2:13\tSemiColon\n\
+             t.c0:2:13-3:1\tAnnoEnd\n"
+        );
+    }
+}
@@ -0,0 +1,270 @@
+use ast::pretty;
+use driver::driver::{EXIT_FAILURE, EXIT_SUCCESS, EXIT_USAGE};
+use lexer::lexer::{Comment, Lexer};
+use parser::parser::Parser;
+use source::diagnostic::Diagnostic;
+use source::source_map::{FileId, SourceMap, Span};
+use std::fs;
+use std::io::{self, Read};
+
+/*
+c0fmt rewrites C0 files in the canonical style of `ast::pretty`, keeping
+their comments
+
+    c0fmt [options] <file>...
+
+Files are formatted in place, `-` formats standard input to standard output.
+A file which does not lex or parse is left alone.
+ */
+pub const USAGE: &str = "\
+usage: c0fmt [options] <file>...
+
+Formats the given C0 files in place, or standard input given `-`.
+
+options:
+  --check          change nothing, list the files which are not formatted
+                   and fail if there are any
+  --width=<n>      break lines longer than <n> columns (default 80)
+  -h, --help       print this message
+  -V, --version    print the formatter version";
+
+pub const DEFAULT_WIDTH: usize = 80;
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct FmtOptions {
+    pub files: Vec<String>,
+    pub check: bool,
+    pub width: usize,
+    pub help: bool,
+    pub version: bool,
+}
+
+impl FmtOptions {
+    pub fn parse(args: &[String]) -> Result<FmtOptions, String> {
+        let mut options = FmtOptions {
+            files: Vec::new(),
+            check: false,
+            width: DEFAULT_WIDTH,
+            help: false,
+            version: false,
+        };
+        let mut args = args.iter();
+        while let Some(arg) = args.next() {
+            match &arg[..] {
+                "--check" => options.check = true,
+                "-h" | "--help" => options.help = true,
+                "-V" | "--version" => options.version = true,
+                "--width" => match args.next() {
+                    Some(width) => options.width = width_value(width)?,
+                    None => return Err(String::from("option `--width` requires an argument")),
+                },
+                _ if arg.starts_with("--width=") => options.width = width_value(&arg["--width=".len()..])?,
+                _ if arg.starts_with('-') && arg != "-" => {
+                    return Err(format!("unknown option `{}`", arg))
+                }
+                _ => options.files.push(arg.clone()),
+            }
+        }
+        if options.files.is_empty() && !options.help && !options.version {
+            return Err(String::from("no input files"));
+        }
+        Ok(options)
+    }
+}
+
+fn width_value(s: &str) -> Result<usize, String> {
+    match s.parse::<usize>() {
+        Ok(width) if width > 0 => Ok(width),
+        _ => Err(format!("invalid width `{}`", s)),
+    }
+}
+
+pub fn main(args: &[String]) -> i32 {
+    let options = match FmtOptions::parse(args) {
+        Ok(options) => options,
+        Err(e) => {
+            eprintln!("c0fmt: {}\n{}", e, USAGE);
+            return EXIT_USAGE;
+        }
+    };
+    if options.help {
+        println!("{}", USAGE);
+        return EXIT_SUCCESS;
+    }
+    if options.version {
+        println!("c0fmt {}", env!("CARGO_PKG_VERSION"));
+        return EXIT_SUCCESS;
+    }
+    let mut sources = SourceMap::new();
+    let mut status = EXIT_SUCCESS;
+    for path in &options.files {
+        match format_path(&options, &mut sources, path) {
+            Ok(true) if options.check => status = EXIT_FAILURE,
+            Ok(_) => (),
+            Err(message) => {
+                eprint!("{}", message);
+                status = EXIT_FAILURE;
+            }
+        }
+    }
+    status
+}
+
+// Formats one file, returning whether it changed, or the message to print
+fn format_path(options: &FmtOptions, sources: &mut SourceMap, path: &str) -> Result<bool, String> {
+    let stdin = path == "-";
+    let text = if stdin {
+        let mut text = String::new();
+        match io::stdin().read_to_string(&mut text) {
+            Ok(_) => text,
+            Err(e) => return Err(format!("c0fmt: cannot read standard input: {}\n", e)),
+        }
+    } else {
+        match fs::read_to_string(path) {
+            Ok(text) => text,
+            Err(e) => return Err(format!("c0fmt: cannot read `{}`: {}\n", path, e)),
+        }
+    };
+    let name = if stdin { "<stdin>" } else { path };
+    let file = sources.add(name, text);
+    let formatted = match format_file(sources, file, options.width) {
+        Ok(formatted) => formatted,
+        Err(d) => return Err(d.render(sources)),
+    };
+    let changed = formatted != sources.file(file).text;
+    if options.check {
+        if changed {
+            println!("{}: not formatted", name);
+        }
+    } else if stdin {
+        print!("{}", formatted);
+    } else if changed {
+        if let Err(e) = fs::write(path, formatted) {
+            return Err(format!("c0fmt: cannot write `{}`: {}\n", path, e));
+        }
+    }
+    Ok(changed)
+}
+
+/*
+The formatted source of a file. The result is parsed again and must give
+the same tree and the same comments, anything else is a bug in the
+formatter and the file is left alone.
+ */
+pub fn format_file(sources: &SourceMap, file: FileId, width: usize) -> Result<String, Diagnostic> {
+    let source = sources.file(file);
+    let (lexemes, comments) = Lexer::new(file, &source.text).tokens_and_comments()?;
+    let program = Parser::new(lexemes).parseProgram()?;
+    let formatted = pretty::format(&program, source, &comments, width);
+
+    let same = match Lexer::new(file, &formatted).tokens_and_comments() {
+        Ok((lexemes, reformatted)) => {
+            Parser::new(lexemes).parseProgram().ok() == Some(program)
+                && texts(&comments) == texts(&reformatted)
+        }
+        Err(_) => false,
+    };
+    if !same {
+        return Err(Diagnostic::error(
+            Span::new(file, 0, 0),
+            "formatting would change the meaning of this file, it has been left as is",
+        ));
+    }
+    Ok(formatted)
+}
+
+// Comments as they are printed, block comments lose trailing spaces on each line
+fn texts(comments: &[Comment]) -> Vec<String> {
+    comments
+        .iter()
+        .map(|c| c.text.lines().map(str::trim_end).collect::<Vec<_>>().join("\n"))
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn format(text: &str, width: usize) -> Result<String, Diagnostic> {
+        let mut sources = SourceMap::new();
+        let file = sources.add("t.c0", String::from(text));
+        format_file(&sources, file, width)
+    }
+
+    #[test]
+    fn formatting_with_comments() {
+        let source = fs::read_to_string("./src/driver/tests/comments.c0").unwrap();
+        let expected = fs::read_to_string("./src/driver/tests/comments.fmt.c0").unwrap();
+        let formatted = format(&source, DEFAULT_WIDTH).unwrap();
+        assert_eq!(formatted, expected);
+        // Formatted files stay as they are
+        assert_eq!(format(&formatted, DEFAULT_WIDTH).unwrap(), formatted);
+    }
+
+    #[test]
+    fn formatting_idempotently() {
+        let cases = vec![
+            (
+                "int f(int x) {\n  //@assert x == 1; // check\n  /*@assert x == 2;\n     @assert x == 3; @*/\n  \
+                 return x;\n}\n",
+                "int f(int x) {\n  //@assert x == 1; // check\n  //@assert x == 2;\n  //@assert x == 3;\n  \
+                 return x;\n}\n",
+            ),
+            (
+                "int g(int a, /* the count */ int b) // sum\n//@requires a > 0; // positive\n\
+                 { // body\n  return a + b; }",
+                "int g(int a, /* the count */ int b) // sum\n//@requires a > 0; // positive\n\
+                 { // body\n  return a + b;\n}\n",
+            ),
+            (
+                "int h(int a, // first\n      int b /* last */);\nint k(/* none */) // proto\n;\n\
+                 int m() // sum\n{ while (true) // forever\n { } return 0; }",
+                "int h(int a, // first\n    int b /* last */);\n\nint k(/* none */); // proto\n\n\
+                 int m() { // sum\n  while (true) { // forever\n  }\n  return 0;\n}\n",
+            ),
+        ];
+        for (source, expected) in cases {
+            let formatted = format(source, DEFAULT_WIDTH).unwrap();
+            assert_eq!(formatted, expected);
+            assert_eq!(format(&formatted, DEFAULT_WIDTH).unwrap(), formatted);
+        }
+    }
+
+    #[test]
+    fn breaking_long_lines() {
+        let source = "int f(int a, int b) { return g(a + b, a * b) && a < b || longer_name(b, a); }";
+        assert_eq!(
+            format(source, 30).unwrap(),
+            "int f(int a, int b) {\n  \
+               return g(a + b, a * b) &&\n          \
+                   a < b ||\n      \
+               longer_name(b, a);\n\
+             }\n"
+        );
+        assert_eq!(
+            format(source, 80).unwrap(),
+            "int f(int a, int b) {\n  return g(a + b, a * b) && a < b || longer_name(b, a);\n}\n"
+        );
+    }
+
+    #[test]
+    fn refusing_syntax_errors() {
+        let error = format("int f() { return 1 }", DEFAULT_WIDTH).unwrap_err();
+        assert_eq!(error.message, "expected `;`, found `}`");
+        assert!(format("int f() { return 'ab'; }", DEFAULT_WIDTH).is_err());
+    }
+
+    #[test]
+    fn parsing_fmt_options() {
+        let args: Vec<String> = vec!["--check", "--width=100", "a.c0", "-"]
+            .into_iter()
+            .map(String::from)
+            .collect();
+        let options = FmtOptions::parse(&args).unwrap();
+        assert!(options.check);
+        assert_eq!(options.width, 100);
+        assert_eq!(options.files, vec!["a.c0", "-"]);
+        let args = vec![String::from("--width=0"), String::from("a.c0")];
+        assert_eq!(FmtOptions::parse(&args), Err(String::from("invalid width `0`")));
+    }
+}
@@ -0,0 +1,216 @@
+use ast::ast::*;
+use lexer::lexer::{Lexeme, Lexer};
+use parser::parser::{ParseContext, ParseResult, Parser};
+use source::diagnostic::Diagnostic;
+use source::source_map::{FileId, SourceMap, Span};
+use std::collections::HashSet;
+use std::path::{Path, PathBuf};
+use std::{fs, mem};
+
+/*
+Loading what programs `#use`. The declarations of a library or file are put
+where the `#use` is, before those of the file using it, and its typedefs are
+known while parsing the rest of that file.
+
+`#use <lib>` looks for `lib.h0` in each `-L` directory in order, then among the
+standard libraries bundled here. `#use "file.c0"` is relative to the directory
+of the file using it. Either is loaded at most once, however many files use
+it, and a file also named on the command line is not loaded again.
+ */
+pub const LIBRARIES: &[(&str, &str)] = &[
+    ("args", include_str!("libs/args.h0")),
+    ("conio", include_str!("libs/conio.h0")),
+    ("file", include_str!("libs/file.h0")),
+    ("img", include_str!("libs/img.h0")),
+    ("parse", include_str!("libs/parse.h0")),
+    ("rand", include_str!("libs/rand.h0")),
+    ("string", include_str!("libs/string.h0")),
+    ("util", include_str!("libs/util.h0")),
+];
+
+pub fn bundled(name: &str) -> Option<&'static str> {
+    LIBRARIES.iter().find(|&&(lib, _)| lib == name).map(|&(_, header)| header)
+}
+
+pub struct Loader<'a> {
+    sources: &'a mut SourceMap,
+    lib_paths: &'a [String],
+    pub loaded: Loaded,
+}
+
+// What a loader has parsed so far, to carry on parsing the same program later
+#[derive(Clone, Debug, Default)]
+pub struct Loaded {
+    pub ctx: ParseContext,
+    libraries: HashSet<String>,
+    files: HashSet<PathBuf>,
+    // The files holding library headers, rather than programs
+    pub library_files: Vec<FileId>,
+}
+
+impl<'a> Loader<'a> {
+    pub fn new(sources: &'a mut SourceMap, lib_paths: &'a [String]) -> Loader<'a> {
+        Loader::resume(sources, lib_paths, Loaded::default())
+    }
+
+    pub fn resume(sources: &'a mut SourceMap, lib_paths: &'a [String], loaded: Loaded) -> Loader<'a> {
+        Loader {
+            sources,
+            lib_paths,
+            loaded,
+        }
+    }
+
+    // Parses a file named on the command line, unless it has been used already
+    pub fn file(&mut self, file: FileId, lexemes: Vec<Lexeme>, decls: &mut Vec<GDecl>) -> ParseResult<()> {
+        let path = canonical(&self.sources.file(file).name);
+        if self.loaded.files.insert(path) {
+            self.parse(file, lexemes, decls)?;
+        }
+        Ok(())
+    }
+
+    // Loads a library, as if by `#use <name>`, false if there is none such
+    pub fn library(&mut self, name: &str, decls: &mut Vec<GDecl>) -> ParseResult<bool> {
+        if self.loaded.libraries.contains(name) {
+            return Ok(true);
+        }
+        let file = match self.find_library(name) {
+            Some(file) => file,
+            None => return Ok(false),
+        };
+        self.loaded.libraries.insert(String::from(name));
+        self.loaded.library_files.push(file);
+        let lexemes = self.lex(file)?;
+        self.parse(file, lexemes, decls)?;
+        Ok(true)
+    }
+
+    fn find_library(&mut self, name: &str) -> Option<FileId> {
+        for dir in self.lib_paths {
+            let path = Path::new(dir).join(format!("{}.h0", name));
+            if let Ok(text) = fs::read_to_string(&path) {
+                return Some(self.sources.add(&path.to_string_lossy(), text));
+            }
+        }
+        bundled(name).map(|header| self.sources.add(&format!("<{}>", name), String::from(header)))
+    }
+
+    pub fn lex(&self, file: FileId) -> ParseResult<Vec<Lexeme>> {
+        Lexer::new(file, &self.sources.file(file).text).tokens()
+    }
+
+    // Parses the declarations of a file, loading those it uses as they come
+    pub fn parse(&mut self, file: FileId, lexemes: Vec<Lexeme>, decls: &mut Vec<GDecl>) -> ParseResult<()> {
+        let mut parser = Parser::with_context(lexemes, mem::take(&mut self.loaded.ctx));
+        while !parser.atEnd() {
+            let d = parser.parseGDecl()?;
+            let used = match d.kind {
+                GDeclKind::Use(ref u) => Some((u.clone(), d.span)),
+                _ => None,
+            };
+            decls.push(d);
+            if let Some((u, span)) = used {
+                // What is used is parsed with the typedefs and ids so far
+                mem::swap(&mut self.loaded.ctx, parser.context_mut());
+                let loaded = self.load(file, u, span, decls);
+                mem::swap(&mut self.loaded.ctx, parser.context_mut());
+                loaded?;
+            }
+        }
+        self.loaded.ctx = parser.context();
+        Ok(())
+    }
+
+    fn load(&mut self, from: FileId, u: Use, span: Span, decls: &mut Vec<GDecl>) -> ParseResult<()> {
+        match u {
+            Use::Library(name) => {
+                if self.library(&name, decls)? {
+                    Ok(())
+                } else {
+                    Err(Diagnostic::error(span, format!("cannot find library <{}>", name))
+                        .help("add the directory of its header to the search path with -L"))
+                }
+            }
+            Use::File(name) => {
+                let dir = Path::new(&self.sources.file(from).name)
+                    .parent()
+                    .map(Path::to_path_buf)
+                    .unwrap_or_default();
+                let path = dir.join(&name);
+                if !self.loaded.files.insert(canonical(&path.to_string_lossy())) {
+                    return Ok(());
+                }
+                let text = fs::read_to_string(&path)
+                    .map_err(|e| Diagnostic::error(span, format!("cannot read \"{}\": {}", name, e)))?;
+                let file = self.sources.add(&path.to_string_lossy(), text);
+                let lexemes = self.lex(file)?;
+                self.parse(file, lexemes, decls)
+            }
+        }
+    }
+}
+
+// Names a file the same however it was reached
+fn canonical(path: &str) -> PathBuf {
+    fs::canonicalize(path).unwrap_or_else(|_| PathBuf::from(path))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn load(path: &str, lib_paths: &[String]) -> Result<(Program, SourceMap), Diagnostic> {
+        let mut sources = SourceMap::new();
+        let file = sources.load(path).unwrap();
+        let mut decls = Vec::new();
+        {
+            let mut loader = Loader::new(&mut sources, lib_paths);
+            let lexemes = loader.lex(file)?;
+            loader.file(file, lexemes, &mut decls)?;
+        }
+        Ok((Program { decls }, sources))
+    }
+
+    fn functions(p: &Program) -> Vec<&str> {
+        p.decls
+            .iter()
+            .filter_map(|d| match d.kind {
+                GDeclKind::Function(ref f) => Some(&f.name.name[..]),
+                _ => None,
+            })
+            .collect()
+    }
+
+    #[test]
+    fn parsing_bundled_libraries() {
+        for &(name, header) in LIBRARIES {
+            let p = Parser::new(Lexer::new(0, header).tokens().unwrap()).parseProgram();
+            assert!(p.is_ok(), "<{}>: {:?}", name, p.err());
+        }
+    }
+
+    #[test]
+    fn using_libraries_and_files() {
+        let (p, sources) = load("./src/driver/tests/uses/main.c0", &[]).unwrap();
+        let functions = functions(&p);
+        // <string> and "helper.c0" are loaded once, though both files use them
+        assert_eq!(functions.iter().filter(|&&f| f == "string_join").count(), 1);
+        assert_eq!(functions.iter().filter(|&&f| f == "helper").count(), 1);
+        assert!(functions.contains(&"file_read"));
+        let main = functions.iter().position(|&f| f == "main").unwrap();
+        assert!(functions.iter().position(|&f| f == "helper").unwrap() < main);
+        assert!(sources.files().iter().any(|f| f.name == "<string>"));
+    }
+
+    #[test]
+    fn searching_library_paths() {
+        match load("./src/driver/tests/uses/local.c0", &[]) {
+            Err(d) => assert_eq!(d.message, "cannot find library <local>"),
+            Ok(_) => panic!("<local> is not on the search path"),
+        }
+        let lib_paths = [String::from("./src/driver/tests/uses/lib")];
+        let (p, _) = load("./src/driver/tests/uses/local.c0", &lib_paths).unwrap();
+        assert_eq!(functions(&p), vec!["local_answer", "main"]);
+    }
+}
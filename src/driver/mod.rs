@@ -0,0 +1,6 @@
+pub mod options;
+pub mod driver;
+pub mod fmt;
+pub mod libraries;
+pub mod session;
+pub mod repl;
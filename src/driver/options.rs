@@ -0,0 +1,319 @@
+use ast::dump::Format;
+use std::fmt;
+
+/*
+Command line options, modeled on cc0
+
+    c0c [options] <file>...
+
+Files are compiled in the order given as a single program, `-` naming
+standard input.
+ */
+pub const USAGE: &str = "\
+usage: c0c [options] <file>...
+
+Compiles the given C0 files, in order, as a single program. The file `-` is
+read from standard input.
+
+options:
+  -o <file>              write output to <file>
+  -d, --dyn-check        check contracts dynamically
+  -x, --exec             run the program, exiting with the result of main
+  -l <lib>               use library <lib>, as if by `#use <lib>`
+  -L <dir>               add <dir> to the library search path
+  --emit=<what>          output tokens, ast, core, ir, ssa, asm or c instead of an executable
+  --backend=<name>       build executables from asm (default) or c
+  --stop-after=<stage>   stop after the parse or typecheck stage
+  --dump-tokens          print every token with its span
+  --dump-ast[=<format>]  print the syntax tree as a tree (default), json or sexp
+  --regalloc-stats       report the spills and moves removed by register allocation
+  -h, --help             print this message
+  -V, --version          print the compiler version";
+
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum Emit {
+    Tokens,
+    Ast,
+    Core,
+    Ir,
+    Ssa,
+    Asm,
+    C,
+}
+
+const EMITS: [(&str, Emit); 7] = [
+    ("tokens", Emit::Tokens),
+    ("ast", Emit::Ast),
+    ("core", Emit::Core),
+    ("ir", Emit::Ir),
+    ("ssa", Emit::Ssa),
+    ("asm", Emit::Asm),
+    ("c", Emit::C),
+];
+
+impl Emit {
+    fn parse(s: &str) -> Option<Emit> {
+        EMITS.iter().find(|&&(name, _)| name == s).map(|&(_, emit)| emit)
+    }
+
+    fn name(self) -> &'static str {
+        EMITS.iter().find(|&&(_, emit)| emit == self).map(|&(name, _)| name).unwrap()
+    }
+}
+
+// How executables are built, through assembly or through C
+#[derive(Copy, Clone, Debug, PartialEq, Default)]
+pub enum Backend {
+    #[default]
+    Asm,
+    C,
+}
+
+impl Backend {
+    fn parse(s: &str) -> Option<Backend> {
+        match s {
+            "asm" => Some(Backend::Asm),
+            "c" => Some(Backend::C),
+            _ => None,
+        }
+    }
+}
+
+#[derive(Copy, Clone, Debug, PartialEq, PartialOrd)]
+pub enum Stage {
+    Parse,
+    Typecheck,
+}
+
+impl Stage {
+    fn parse(s: &str) -> Option<Stage> {
+        match s {
+            "parse" => Some(Stage::Parse),
+            "typecheck" => Some(Stage::Typecheck),
+            _ => None,
+        }
+    }
+
+    fn name(self) -> &'static str {
+        match self {
+            Stage::Parse => "parse",
+            Stage::Typecheck => "typecheck",
+        }
+    }
+}
+
+#[derive(Clone, Debug, PartialEq, Default)]
+pub struct Options {
+    pub files: Vec<String>,
+    pub output: Option<String>,
+    pub dyn_check: bool,
+    pub exec: bool,
+    pub libs: Vec<String>,
+    pub lib_paths: Vec<String>,
+    pub emit: Option<Emit>,
+    pub backend: Backend,
+    pub stop_after: Option<Stage>,
+    pub dump_tokens: bool,
+    pub dump_ast: Option<Format>,
+    pub regalloc_stats: bool,
+    pub help: bool,
+    pub version: bool,
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct UsageError(pub String);
+
+impl fmt::Display for UsageError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}", self.0)
+    }
+}
+
+// The value of an option given either as `-o file`, `-ofile` or `--output=file`
+fn value<'a, I>(name: &str, attached: &str, rest: &mut I) -> Result<String, UsageError>
+where
+    I: Iterator<Item = &'a String>,
+{
+    if !attached.is_empty() {
+        return Ok(String::from(attached));
+    }
+    match rest.next() {
+        Some(v) => Ok(v.clone()),
+        None => Err(UsageError(format!("option `{}` requires an argument", name))),
+    }
+}
+
+impl Options {
+    pub fn parse(args: &[String]) -> Result<Options, UsageError> {
+        let mut options = Options::default();
+        let mut args = args.iter();
+        let mut only_files = false;
+        while let Some(arg) = args.next() {
+            if only_files || !arg.starts_with('-') || arg == "-" {
+                options.files.push(arg.clone());
+                continue;
+            }
+            if arg.starts_with("--") {
+                let (name, attached) = match arg.find('=') {
+                    Some(i) => (&arg[..i], Some(&arg[i + 1..])),
+                    None => (&arg[..], None),
+                };
+                match (name, attached) {
+                    ("--", None) => only_files = true,
+                    ("--help", None) => options.help = true,
+                    ("--version", None) => options.version = true,
+                    ("--dyn-check", None) => options.dyn_check = true,
+                    ("--exec", None) => options.exec = true,
+                    ("--dump-tokens", None) => options.dump_tokens = true,
+                    ("--dump-ast", None) => options.dump_ast = Some(Format::Tree),
+                    ("--regalloc-stats", None) => options.regalloc_stats = true,
+                    ("--dump-ast", Some(format)) => match Format::parse(format) {
+                        Some(format) => options.dump_ast = Some(format),
+                        None => return Err(UsageError(format!("unknown ast format `{}`", format))),
+                    },
+                    ("--output", _) => {
+                        options.output = Some(value(name, attached.unwrap_or(""), &mut args)?)
+                    }
+                    ("--emit", Some(what)) => match Emit::parse(what) {
+                        Some(emit) => options.emit = Some(emit),
+                        None => return Err(UsageError(format!("unknown emit kind `{}`", what))),
+                    },
+                    ("--backend", Some(backend)) => match Backend::parse(backend) {
+                        Some(backend) => options.backend = backend,
+                        None => return Err(UsageError(format!("unknown backend `{}`", backend))),
+                    },
+                    ("--stop-after", Some(stage)) => match Stage::parse(stage) {
+                        Some(stage) => options.stop_after = Some(stage),
+                        None => return Err(UsageError(format!("unknown stage `{}`", stage))),
+                    },
+                    _ => return Err(UsageError(format!("unknown option `{}`", arg))),
+                }
+                continue;
+            }
+            // The flag is the dash and the character after it, which need not be ASCII
+            let split = arg.char_indices().nth(2).map_or(arg.len(), |(i, _)| i);
+            let (flag, attached) = arg.split_at(split);
+            match flag {
+                "-h" if attached.is_empty() => options.help = true,
+                "-V" if attached.is_empty() => options.version = true,
+                "-d" if attached.is_empty() => options.dyn_check = true,
+                "-x" if attached.is_empty() => options.exec = true,
+                "-o" => options.output = Some(value(flag, attached, &mut args)?),
+                "-l" => options.libs.push(value(flag, attached, &mut args)?),
+                "-L" => options.lib_paths.push(value(flag, attached, &mut args)?),
+                _ => return Err(UsageError(format!("unknown option `{}`", arg))),
+            }
+        }
+        if options.files.is_empty() && !options.help && !options.version {
+            return Err(UsageError(String::from("no input files")));
+        }
+        options.check_conflicts()?;
+        Ok(options)
+    }
+
+    // Options asking for outcomes the driver cannot both deliver
+    fn check_conflicts(&self) -> Result<(), UsageError> {
+        let conflict = |a: &str, b: &str| Err(UsageError(format!("options `{}` and `{}` conflict", a, b)));
+        let emit = self.emit.map(|emit| format!("--emit={}", emit.name()));
+        let stop = self.stop_after.map(|stage| format!("--stop-after={}", stage.name()));
+        if self.exec {
+            if let Some(ref emit) = emit {
+                return conflict("-x", emit);
+            }
+            if let Some(ref stop) = stop {
+                return conflict("-x", stop);
+            }
+        }
+        // Tokens and the syntax tree are output before any stage stops the compiler
+        if let (Some(ref emit), Some(ref stop)) = (emit, stop) {
+            if !matches!(self.emit, Some(Emit::Tokens) | Some(Emit::Ast)) {
+                return conflict(emit, stop);
+            }
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn parse(args: &[&str]) -> Result<Options, UsageError> {
+        let args: Vec<String> = args.iter().map(|s| s.to_string()).collect();
+        Options::parse(&args)
+    }
+
+    #[test]
+    fn parsing_options() {
+        let options = parse(&[
+            "a.c0", "-d", "-o", "out", "-lconio", "-l", "string", "-L", "libs", "b.c0",
+            "--emit=ast", "--stop-after=parse",
+        ])
+        .unwrap();
+        assert_eq!(options.files, vec!["a.c0", "b.c0"]);
+        assert_eq!(options.output, Some(String::from("out")));
+        assert!(options.dyn_check);
+        assert!(!options.exec);
+        assert_eq!(options.libs, vec!["conio", "string"]);
+        assert_eq!(options.lib_paths, vec!["libs"]);
+        assert_eq!(options.emit, Some(Emit::Ast));
+        assert_eq!(options.stop_after, Some(Stage::Parse));
+        assert_eq!(options.dump_ast, None);
+        let options = parse(&["--dump-tokens", "--dump-ast=sexp", "a.c0"]).unwrap();
+        assert!(options.dump_tokens);
+        assert_eq!(options.dump_ast, Some(Format::Sexp));
+        assert_eq!(parse(&["--dump-ast", "a.c0"]).unwrap().dump_ast, Some(Format::Tree));
+        assert!(parse(&["-x", "a.c0"]).unwrap().exec);
+        assert!(parse(&["--regalloc-stats", "a.c0"]).unwrap().regalloc_stats);
+        assert_eq!(parse(&["a.c0"]).unwrap().backend, Backend::Asm);
+        assert_eq!(parse(&["--backend=c", "a.c0"]).unwrap().backend, Backend::C);
+    }
+
+    #[test]
+    fn usage_errors() {
+        assert_eq!(parse(&[]), Err(UsageError(String::from("no input files"))));
+        assert_eq!(
+            parse(&["a.c0", "-o"]),
+            Err(UsageError(String::from("option `-o` requires an argument")))
+        );
+        assert_eq!(
+            parse(&["a.c0", "--emit=exe"]),
+            Err(UsageError(String::from("unknown emit kind `exe`")))
+        );
+        assert_eq!(
+            parse(&["a.c0", "--backend=llvm"]),
+            Err(UsageError(String::from("unknown backend `llvm`")))
+        );
+        assert_eq!(
+            parse(&["a.c0", "-q"]),
+            Err(UsageError(String::from("unknown option `-q`")))
+        );
+        assert_eq!(
+            parse(&["a.c0", "--dump-ast=xml"]),
+            Err(UsageError(String::from("unknown ast format `xml`")))
+        );
+        assert_eq!(
+            parse(&["a.c0", "-é"]),
+            Err(UsageError(String::from("unknown option `-é`")))
+        );
+        assert!(parse(&["--help"]).unwrap().help);
+    }
+
+    #[test]
+    fn conflicting_options() {
+        let cases = vec![
+            (vec!["--emit=asm", "-x"], "options `-x` and `--emit=asm` conflict"),
+            (vec!["-x", "--stop-after=typecheck"], "options `-x` and `--stop-after=typecheck` conflict"),
+            (vec!["--emit=asm", "--stop-after=parse"], "options `--emit=asm` and `--stop-after=parse` conflict"),
+            (
+                vec!["--emit=core", "--stop-after=typecheck"],
+                "options `--emit=core` and `--stop-after=typecheck` conflict",
+            ),
+        ];
+        for (mut args, error) in cases {
+            args.push("a.c0");
+            assert_eq!(parse(&args), Err(UsageError(String::from(error))));
+        }
+        assert!(parse(&["--emit=tokens", "--stop-after=typecheck", "a.c0"]).is_ok());
+    }
+}
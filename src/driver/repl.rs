@@ -0,0 +1,572 @@
+use ast::ast::*;
+use ast::pretty;
+use driver::driver::{Failure, EXIT_FAILURE, EXIT_SUCCESS, EXIT_USAGE};
+use driver::libraries::{Loaded, Loader};
+use interp::interp::{self, Interpreter};
+use interp::value::Value;
+use lexer::lexer::{Lexeme, Lexer, Token};
+use parser::parser::{ParseResult, Parser};
+use semant::resolve::{self, DeclId, Resolution};
+use semant::typeck::{self, Typing};
+use semant::{contracts, init, returns, structs};
+use source::source_map::{FileId, SourceMap, Span};
+use std::collections::HashSet;
+use std::io::{self, BufRead, IsTerminal, Write};
+use std::slice;
+
+/*
+An interactive toplevel in the style of coin
+
+    c0repl [-d] [-l <lib>] [-L <dir>] [<file>...]
+
+Each input is either declarations, which are added to the program, or
+statements, which are run as the body of a function whose variables persist
+from one input to the next. An expression statement prints its value and
+type, and the semicolon after it may be left out. Input with unclosed braces
+continues on the next line.
+
+Every input is checked with all that came before, as one program, and is
+forgotten if it has errors, so a mistake never loses the state. A variable
+declared without a value cannot be read until an input assigns it.
+ */
+pub const USAGE: &str = "\
+usage: c0repl [options] [<file>...]
+
+Runs C0 interactively, after loading the given files.
+
+options:
+  -d, --dyn-check  check contracts dynamically
+  -l <lib>         use library <lib>, as if by `#use <lib>`
+  -L <dir>         add <dir> to the library search path
+  -h, --help       print this message";
+
+pub const HELP: &str = "\
+Enter declarations, statements or expressions. The value and type of each
+expression is printed, and variables keep their values from one input to the
+next.
+
+  #use <lib>    load a library
+  #use \"file\"   load the declarations of a file
+  #functions    list the functions declared
+  #help         print this message
+  #quit         leave, as does the end of the input";
+
+// The name of the function the statements of an input are checked as the body of
+const TOPLEVEL: &str = "<input>";
+const TOPLEVEL_ID: NodeId = NodeId::MAX;
+
+pub fn main(args: &[String]) -> i32 {
+    let (mut dyn_check, mut lib_paths) = (false, Vec::new());
+    let mut startup = Vec::new();
+    let mut args = args.iter();
+    while let Some(arg) = args.next() {
+        match &arg[..] {
+            "-h" | "--help" => {
+                println!("{}", USAGE);
+                return EXIT_SUCCESS;
+            }
+            "-d" | "--dyn-check" => dyn_check = true,
+            "-l" | "-L" => match args.next() {
+                Some(value) if arg == "-l" => startup.push(format!("#use <{}>", value)),
+                Some(dir) => lib_paths.push(dir.clone()),
+                None => {
+                    eprintln!("c0repl: option `{}` requires an argument\n{}", arg, USAGE);
+                    return EXIT_USAGE;
+                }
+            },
+            _ if arg.starts_with('-') => {
+                eprintln!("c0repl: unknown option `{}`\n{}", arg, USAGE);
+                return EXIT_USAGE;
+            }
+            file => startup.push(format!("#use \"{}\"", file)),
+        }
+    }
+    // Values live on the thread of the interpreter, so the toplevel does too
+    let session = move || {
+        let repl = Repl {
+            dyn_check,
+            lib_paths,
+            ..Repl::default()
+        };
+        repl.session(startup)
+    };
+    match interp::with_stack(session) {
+        Ok(code) => code,
+        Err(e) => {
+            eprintln!("c0repl: cannot start the interpreter: {}", e);
+            EXIT_FAILURE
+        }
+    }
+}
+
+#[derive(Debug, PartialEq)]
+pub enum Reply {
+    Continue,
+    Quit,
+}
+
+// A variable of the toplevel
+struct Binding {
+    tp: Tp,
+    name: Ident,
+    value: Value,
+    // Whether an input has assigned it, otherwise `value` is only a default
+    assigned: bool,
+}
+
+#[derive(Default)]
+pub struct Repl {
+    pub sources: SourceMap,
+    pub lib_paths: Vec<String>,
+    pub dyn_check: bool,
+    loaded: Loaded,
+    // The declarations of the program so far
+    decls: Vec<GDecl>,
+    vars: Vec<Binding>,
+}
+
+impl Repl {
+    // Reads and runs inputs until the end, or `#quit`
+    fn session(mut self, startup: Vec<String>) -> i32 {
+        let (stdin, stdout) = (io::stdin(), io::stdout());
+        let interactive = stdin.is_terminal();
+        let (mut input, mut out) = (stdin.lock(), stdout.lock());
+        for text in startup {
+            if let Err(failure) = self.input(&text, &mut out, &mut input) {
+                self.report(failure);
+                return EXIT_FAILURE;
+            }
+        }
+        if interactive {
+            println!("c0repl {}, #help for help", env!("CARGO_PKG_VERSION"));
+        }
+        loop {
+            let text = match self.read(interactive, &mut input) {
+                Some(text) => text,
+                None => return EXIT_SUCCESS,
+            };
+            match self.input(&text, &mut out, &mut input) {
+                Ok(Reply::Continue) => (),
+                Ok(Reply::Quit) => return EXIT_SUCCESS,
+                Err(failure) => self.report(failure),
+            }
+        }
+    }
+
+    // The next input, which goes on while it has unclosed braces
+    fn read(&self, interactive: bool, input: &mut dyn BufRead) -> Option<String> {
+        let mut text = String::new();
+        loop {
+            if interactive {
+                print!("{}", if text.is_empty() { "--> " } else { "... " });
+                let _ = io::stdout().flush();
+            }
+            match input.read_line(&mut text) {
+                Ok(0) | Err(_) if text.is_empty() => return None,
+                Ok(0) | Err(_) => return Some(text),
+                Ok(_) => (),
+            }
+            if !unclosed(&text) {
+                return Some(text);
+            }
+        }
+    }
+
+    fn report(&self, failure: Failure) {
+        match failure {
+            Failure::Diagnostics(diagnostics) => {
+                for d in &diagnostics {
+                    eprint!("{}", d.render(&self.sources));
+                }
+            }
+            Failure::Message(message) => eprintln!("{}", message),
+            Failure::Runtime(e) => eprint!("{}", e.diagnostic().render(&self.sources)),
+        }
+    }
+
+    // Runs one input, what it prints written to `out`
+    pub fn input(&mut self, text: &str, out: &mut dyn Write, input: &mut dyn BufRead) -> Result<Reply, Failure> {
+        let command = text.trim();
+        if command.is_empty() {
+            return Ok(Reply::Continue);
+        }
+        if command.starts_with('#') && !command.starts_with("#use") {
+            return self.command(command, out);
+        }
+        let file = self.sources.add(TOPLEVEL, String::from(text));
+        let lexemes = Lexer::new(file, text).tokens()?;
+        let mut decls = Vec::new();
+        let mut loader = Loader::resume(&mut self.sources, &self.lib_paths, self.loaded.clone());
+        match loader.parse(file, lexemes.clone(), &mut decls) {
+            Ok(()) => {
+                let loaded = loader.loaded;
+                self.check(&decls, Vec::new())?;
+                self.decls.extend(decls);
+                self.loaded = loaded;
+                Ok(Reply::Continue)
+            }
+            // The error of whichever reading of the input got further
+            Err(not_decls) => match self.statements(file, lexemes) {
+                Ok(stmts) => self.run(stmts, out, input).map(|()| Reply::Continue),
+                Err(not_stmts) if not_stmts.span.lo > not_decls.span.lo => Err(Failure::from(not_stmts)),
+                Err(_) => Err(Failure::from(not_decls)),
+            },
+        }
+    }
+
+    fn command(&mut self, command: &str, out: &mut dyn Write) -> Result<Reply, Failure> {
+        match command {
+            "#quit" => return Ok(Reply::Quit),
+            "#help" => say(out, HELP)?,
+            "#functions" => {
+                let mut seen = HashSet::new();
+                for d in &self.decls {
+                    if let GDeclKind::Function(ref f) = d.kind {
+                        if seen.insert(&f.name.name) {
+                            say(out, &prototype(f))?;
+                        }
+                    }
+                }
+            }
+            _ => return Err(Failure::Message(format!("unknown command `{}`, #help lists them", command))),
+        }
+        Ok(Reply::Continue)
+    }
+
+    // The input as statements, the semicolon after a last expression optional
+    fn statements(&mut self, file: FileId, mut lexemes: Vec<Lexeme>) -> ParseResult<Vec<Stmt>> {
+        let parse = |lexemes: Vec<Lexeme>, ctx| {
+            let mut parser = Parser::with_context(lexemes, ctx);
+            let mut stmts = Vec::new();
+            while !parser.atEnd() {
+                stmts.push(parser.parseStmt()?);
+            }
+            Ok((stmts, parser.context()))
+        };
+        let parsed = match parse(lexemes.clone(), self.loaded.ctx.clone()) {
+            Err(e) if !matches!(lexemes.last().map(|l| &l.token), Some(&Token::SemiColon) | Some(&Token::RCurly)) => {
+                let end = self.sources.file(file).text.trim_end().len();
+                lexemes.push(Lexeme {
+                    token: Token::SemiColon,
+                    span: Span::new(file, end, end),
+                });
+                parse(lexemes, self.loaded.ctx.clone()).map_err(|_| e)
+            }
+            parsed => parsed,
+        };
+        let (stmts, ctx) = parsed?;
+        self.loaded.ctx = ctx;
+        Ok(stmts)
+    }
+
+    /*
+    Checks the program with `decls` added, and the statements `stmts` as the
+    body of the toplevel function after the declarations of its variables.
+    Those variables start out assigned if earlier inputs assigned them.
+     */
+    fn check(&self, decls: &[GDecl], stmts: Vec<Stmt>) -> Result<(Program, Resolution, Typing), Failure> {
+        let mut program = Program {
+            decls: self.decls.iter().chain(decls).cloned().collect(),
+        };
+        init::check(&program, &resolve::resolve(&program).map_err(Failure::Diagnostics)?)
+            .map_err(Failure::Diagnostics)?;
+        let mut body: Vec<Stmt> = self.vars.iter().map(|b| declaration(&b.tp, &b.name)).collect();
+        body.extend(stmts);
+        program.decls.push(toplevel(body));
+        let resolution = resolve::resolve(&program).map_err(Failure::Diagnostics)?;
+        contracts::check(&program, &resolution).map_err(Failure::Diagnostics)?;
+        let typing = typeck::check(&program, &resolution).map_err(Failure::Diagnostics)?;
+        structs::check(&program, &typing).map_err(Failure::Diagnostics)?;
+        returns::check(&program).map_err(Failure::Diagnostics)?;
+        if let Some(&GDecl {
+            kind: GDeclKind::Function(Function { body: Some(ref body), .. }),
+            ..
+        }) = program.decls.last()
+        {
+            init::check_stmts(&body.stmts, &resolution, self.assigned(&resolution)).map_err(Failure::Diagnostics)?;
+        }
+        Ok((program, resolution, typing))
+    }
+
+    // The variables of the toplevel which earlier inputs assigned
+    fn assigned(&self, resolution: &Resolution) -> HashSet<DeclId> {
+        self.vars
+            .iter()
+            .filter(|b| b.assigned)
+            .filter_map(|b| resolution.decl_id(&b.name))
+            .collect()
+    }
+
+    fn run(&mut self, stmts: Vec<Stmt>, out: &mut dyn Write, input: &mut dyn BufRead) -> Result<(), Failure> {
+        let (program, resolution, typing) = self.check(&[], stmts.clone())?;
+        let mut interpreter = Interpreter::new(resolution, typing, out, input);
+        interpreter.dyn_check = self.dyn_check;
+        interpreter.define(&program);
+        for b in &self.vars {
+            if let Some(decl) = interpreter.resolution.decl_id(&b.name) {
+                interpreter.bind(decl, b.value.clone());
+            }
+        }
+        let mut assigned = self.assigned(&interpreter.resolution);
+        let mut result = Ok(());
+        for s in &stmts {
+            result = toplevel_stmt(&mut interpreter, s);
+            if result.is_err() {
+                break;
+            }
+            // Unless it left the toplevel, what the statement assigned
+            if let Ok(Some(after)) = init::check_stmts(slice::from_ref(s), &interpreter.resolution, assigned.clone()) {
+                assigned = after;
+            }
+        }
+        // What the variables hold now is kept, even after an error
+        for b in &mut self.vars {
+            if let Some(decl) = interpreter.resolution.decl_id(&b.name) {
+                if let Some(v) = interpreter.value(decl) {
+                    b.value = v.clone();
+                }
+                b.assigned = assigned.contains(&decl);
+            }
+        }
+        for s in &stmts {
+            if let StmtKind::Simple(Simple {
+                kind: SimpleKind::Decl(ref tp, ref name, _),
+                ..
+            }) = s.kind
+            {
+                if let Some(decl) = interpreter.resolution.decl_id(name) {
+                    if let Some(v) = interpreter.value(decl) {
+                        self.vars.push(Binding {
+                            tp: tp.clone(),
+                            name: name.clone(),
+                            value: v.clone(),
+                            assigned: assigned.contains(&decl),
+                        });
+                    }
+                }
+            }
+        }
+        result
+    }
+}
+
+// Runs a statement of the toplevel, printing the values of expressions and new variables
+fn toplevel_stmt(interpreter: &mut Interpreter, s: &Stmt) -> Result<(), Failure> {
+    let shown = match s.kind {
+        StmtKind::Simple(Simple {
+            kind: SimpleKind::Exp(ref e),
+            ..
+        }) => {
+            let v = interpreter.exp(e)?;
+            Some((String::new(), v, interpreter.typing.exp(e).clone()))
+        }
+        StmtKind::Simple(Simple {
+            kind: SimpleKind::Decl(_, ref x, Some(_)),
+            ..
+        }) => {
+            interpreter.stmt(s)?;
+            interpreter.resolution.decl_id(x).and_then(|decl| {
+                let v = interpreter.value(decl)?.clone();
+                Some((format!("{} is ", x.name), v, interpreter.typing.vars[&decl].clone()))
+            })
+        }
+        _ => {
+            interpreter.stmt(s)?;
+            None
+        }
+    };
+    match shown {
+        Some((_, Value::Void, _)) | None => Ok(()),
+        Some((prefix, v, ty)) => say(interpreter.out(), &format!("{}{} ({})", prefix, v, ty)),
+    }
+}
+
+fn say(out: &mut dyn Write, text: &str) -> Result<(), Failure> {
+    writeln!(out, "{}", text).map_err(|e| Failure::Message(format!("cannot write the output: {}", e)))
+}
+
+// Whether the input has more `{` than `}`, and so goes on
+fn unclosed(text: &str) -> bool {
+    match Lexer::new(0, text).tokens() {
+        Ok(lexemes) => {
+            let depth = lexemes.iter().fold(0, |depth, l| match l.token {
+                Token::LCurly => depth + 1,
+                Token::RCurly => depth - 1,
+                _ => depth,
+            });
+            depth > 0
+        }
+        Err(_) => false,
+    }
+}
+
+fn prototype(f: &Function) -> String {
+    let params: Vec<String> = f
+        .params
+        .iter()
+        .map(|p| format!("{} {}", pretty::tp(&p.tp), p.name.name))
+        .collect();
+    format!("{} {}({})", pretty::tp(&f.ret), f.name.name, params.join(", "))
+}
+
+// `tp x;`, a variable of the toplevel declared again for the next input
+fn declaration(tp: &Tp, x: &Ident) -> Stmt {
+    let span = x.span;
+    Stmt {
+        kind: StmtKind::Simple(Simple {
+            kind: SimpleKind::Decl(tp.clone(), x.clone(), None),
+            span,
+        }),
+        span,
+    }
+}
+
+fn toplevel(body: Vec<Stmt>) -> GDecl {
+    let span = Span::default();
+    GDecl {
+        kind: GDeclKind::Function(Function {
+            ret: Tp {
+                kind: TpKind::Void,
+                span,
+            },
+            name: Ident {
+                id: TOPLEVEL_ID,
+                name: String::from(TOPLEVEL),
+                span,
+            },
+            params: Vec::new(),
+            specs: Vec::new(),
+            body: Some(Block { stmts: body, span }),
+        }),
+        span,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // Runs each input in turn, giving what each printed or its error
+    fn transcript(inputs: &[&str]) -> Vec<String> {
+        let mut repl = Repl::default();
+        inputs
+            .iter()
+            .map(|text| {
+                let mut out = Vec::new();
+                let mut input: &[u8] = b"";
+                match repl.input(text, &mut out, &mut input) {
+                    Ok(_) => String::from_utf8(out).unwrap(),
+                    Err(Failure::Diagnostics(ds)) => format!("error: {}", ds[0].message),
+                    Err(Failure::Message(m)) => m,
+                    Err(Failure::Runtime(e)) => format!("error: {}", e.message),
+                }
+            })
+            .collect()
+    }
+
+    #[test]
+    fn keeping_state() {
+        let inputs = [
+            "int x = 3;",
+            "x * 2",
+            "int square(int n) { return n * n; }",
+            "x = square(x); x;",
+            "bool b = x > 5",
+            "int[] A;\nA = alloc_array(int, 2);\nA[1] = 7;",
+            "A[1] + x",
+            "\"s\"",
+            "#functions",
+        ];
+        assert_eq!(
+            transcript(&inputs),
+            vec![
+                "x is 3 (int)\n",
+                "6 (int)\n",
+                "",
+                "9 (int)\n",
+                "b is true (bool)\n",
+                "",
+                "16 (int)\n",
+                "\"s\" (string)\n",
+                "int square(int n)\n",
+            ]
+        );
+    }
+
+    #[test]
+    fn recovering_from_errors() {
+        let inputs = [
+            "int x = 1;",
+            "x = x + true;",
+            "y + 1",
+            "int f(int n) { return n / 0; }",
+            "x++; f(x); x++;",
+            "x",
+            "#what",
+            "typedef int num;",
+            "num y = x;",
+            "#quit",
+        ];
+        assert_eq!(
+            transcript(&inputs),
+            vec![
+                "x is 1 (int)\n",
+                "error: expected `int`, found `bool`",
+                "error: undeclared variable `y`",
+                "",
+                "error: division by zero",
+                "2 (int)\n",
+                "unknown command `#what`, #help lists them",
+                "",
+                "y is 2 (int)\n",
+                "",
+            ]
+        );
+    }
+
+    #[test]
+    fn reading_unassigned_variables() {
+        let inputs = [
+            "int z;",
+            "z",
+            "z = 4;",
+            "z",
+            "int w; if (z > 5) w = 1;",
+            "w + 1",
+            "int v = 1 / 0;",
+            "v",
+            "int u; u = 2; 1 / 0;",
+            "u",
+        ];
+        assert_eq!(
+            transcript(&inputs),
+            vec![
+                "",
+                "error: `z` may be used before it is initialized",
+                "",
+                "4 (int)\n",
+                "",
+                "error: `w` may be used before it is initialized",
+                "error: division by zero",
+                "error: undeclared variable `v`",
+                "error: division by zero",
+                "2 (int)\n",
+            ]
+        );
+    }
+
+    #[test]
+    fn using_libraries() {
+        let inputs = ["#use <string>", "string_join(\"a\", string_fromint(1))", "#use <nothing>"];
+        assert_eq!(
+            transcript(&inputs),
+            vec!["", "\"a1\" (string)\n", "error: cannot find library <nothing>"]
+        );
+    }
+
+    #[test]
+    fn continuing_inputs() {
+        assert!(unclosed("int f(int x) {"));
+        assert!(!unclosed("int f(int x) { return x; }"));
+    }
+}
@@ -0,0 +1,147 @@
+use ast::ast::Program;
+use driver::driver::Failure;
+use driver::libraries::Loader;
+use lexer::lexer::{Lexeme, Lexer};
+use semant::resolve::{self, Resolution};
+use semant::structs::{self, Layouts};
+use semant::typeck::{self, Typing};
+use semant::{contracts, init, linkage, returns};
+use source::source_map::{FileId, SourceMap};
+use std::io::{self, Read};
+
+/*
+A compilation session: the files making up one program, those named on the
+command line and everything they use, in one source map. Files are compiled
+in order as a single program, so declarations in one are visible in the
+files after it, and functions may be defined in any of them.
+ */
+#[derive(Default)]
+pub struct Session {
+    pub sources: SourceMap,
+    // The files named on the command line, in order
+    pub files: Vec<FileId>,
+    // The files holding library headers, whose functions are defined elsewhere
+    pub libraries: Vec<FileId>,
+}
+
+// What the checks learned about a correct program
+pub struct Analysis {
+    pub resolution: Resolution,
+    pub typing: Typing,
+    pub layouts: Layouts,
+}
+
+impl Session {
+    pub fn new() -> Session {
+        Session::default()
+    }
+
+    // Reads the files, `-` being standard input
+    pub fn load(&mut self, paths: &[String]) -> Result<(), Failure> {
+        self.load_from(paths, &mut io::stdin())
+    }
+
+    fn load_from(&mut self, paths: &[String], stdin: &mut dyn Read) -> Result<(), Failure> {
+        for path in paths {
+            let loaded = if path == "-" {
+                let mut text = String::new();
+                stdin.read_to_string(&mut text).map(|_| self.sources.add("<stdin>", text))
+            } else {
+                self.sources.load(path)
+            };
+            match loaded {
+                Ok(id) => self.files.push(id),
+                Err(e) => return Err(Failure::Message(format!("cannot read `{}`: {}", path, e))),
+            }
+        }
+        Ok(())
+    }
+
+    pub fn lex(&self) -> Result<Vec<Vec<Lexeme>>, Failure> {
+        let mut tokens = Vec::new();
+        for &file in &self.files {
+            tokens.push(Lexer::new(file, &self.sources.file(file).text).tokens()?);
+        }
+        Ok(tokens)
+    }
+
+    // Parses the files, after the libraries `libs`, into one program
+    pub fn parse(&mut self, tokens: Vec<Vec<Lexeme>>, libs: &[String], lib_paths: &[String]) -> Result<Program, Failure> {
+        let mut decls = Vec::new();
+        let mut loader = Loader::new(&mut self.sources, lib_paths);
+        for lib in libs {
+            if !loader.library(lib, &mut decls)? {
+                return Err(Failure::Message(format!("cannot find library <{}>", lib)));
+            }
+        }
+        for (&file, lexemes) in self.files.iter().zip(tokens) {
+            loader.file(file, lexemes, &mut decls)?;
+        }
+        self.libraries = loader.loaded.library_files;
+        Ok(Program { decls })
+    }
+
+    // Every static check, in an order where each can rely on those before
+    pub fn check(&self, program: &Program) -> Result<Analysis, Failure> {
+        let resolution = resolve::resolve(program).map_err(Failure::Diagnostics)?;
+        contracts::check(program, &resolution).map_err(Failure::Diagnostics)?;
+        let typing = typeck::check(program, &resolution).map_err(Failure::Diagnostics)?;
+        let layouts = structs::check(program, &typing).map_err(Failure::Diagnostics)?;
+        returns::check(program).map_err(Failure::Diagnostics)?;
+        init::check(program, &resolution).map_err(Failure::Diagnostics)?;
+        linkage::check(program, &resolution, &typing, &self.libraries).map_err(Failure::Diagnostics)?;
+        Ok(Analysis {
+            resolution,
+            typing,
+            layouts,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn check(paths: &[&str]) -> Result<(), Vec<String>> {
+        let paths: Vec<String> = paths.iter().map(|p| format!("./src/driver/tests/session/{}", p)).collect();
+        let mut session = Session::new();
+        let result = session.load(&paths).and_then(|()| {
+            let tokens = session.lex()?;
+            let program = session.parse(tokens, &[], &[])?;
+            session.check(&program).map(|_| ())
+        });
+        result.map_err(|failure| match failure {
+            Failure::Diagnostics(ds) => ds.iter().map(|d| d.render(&session.sources)).collect(),
+            Failure::Message(m) => vec![m],
+            Failure::Runtime(e) => vec![e.message],
+        })
+    }
+
+    #[test]
+    fn compiling_files_together() {
+        assert_eq!(check(&["stack.c0", "main.c0"]), Ok(()));
+        let errors = check(&["main.c0"]).unwrap_err();
+        assert!(errors[0].contains("undeclared type `stack`"), "{}", errors[0]);
+        let errors = check(&["stack.c0", "conflicting.c0"]).unwrap_err();
+        assert!(errors[0].contains("conflicting.c0:3:5: error: conflicting declarations of `stack_size`"));
+        assert!(errors[0].contains("first declared as `int stack_size(struct stack*)`"));
+        let errors = check(&["stack.c0", "duplicate.c0"]).unwrap_err();
+        assert!(errors[0].contains("duplicate.c0:1:6: error: redefinition of function `stack_push`"));
+        let errors = check(&["stack.c0", "uses_undefined.c0"]).unwrap_err();
+        assert!(errors[0].contains("uses_undefined.c0:4:10: error: function `stack_peek` is used but never defined"));
+    }
+
+    #[test]
+    fn reading_standard_input() {
+        let mut session = Session::new();
+        let paths = [String::from("./src/driver/tests/session/stack.c0"), String::from("-")];
+        let mut stdin: &[u8] = b"int main() { return stack_size(stack_new()); }";
+        let result = session.load_from(&paths, &mut stdin).and_then(|()| {
+            let tokens = session.lex()?;
+            let program = session.parse(tokens, &[], &[])?;
+            session.check(&program).map(|_| ())
+        });
+        assert!(result.is_ok());
+        assert_eq!(session.sources.file(session.files[1]).name, "<stdin>");
+    }
+}
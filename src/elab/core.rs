@@ -0,0 +1,149 @@
+use ast::ast::{BinOp, SpecKind, UnOp};
+use semant::structs::Layouts;
+use semant::types::{Signature, Type};
+use source::source_map::Span;
+
+/*
+The core language C0 programs are elaborated to, a small subset of C0 which
+backends and analyses work on instead of the surface syntax
+
+- loops are `while` loops; `for` loops, their step included, are desugared
+- assignment is to a variable, or a store through an explicit address;
+  compound assignment, `++` and `--` are spelled out
+- `&&`, `||` and `?:` are control flow, an `if` assigning a temporary
+- memory is reached through addresses, `*p`, `A[i]` and `a.f`, which are
+  computed once when an assignment needs them
+- contracts, when checked, are assertions at the points they are checked
+- blocks do not introduce scopes, every variable of a function is listed in
+  `Function::vars`
+
+Expressions are evaluated left to right, as in C0: when a later operand needs
+statements of its own, the earlier ones are computed into temporaries first.
+Every node keeps the span of the source it comes from, and every expression
+and address its type.
+ */
+pub type VarId = usize;
+
+#[derive(Clone, Debug)]
+pub struct Program {
+    pub functions: Vec<Function>,
+    // Functions called but not defined, from libraries
+    pub externs: Vec<Extern>,
+    pub layouts: Layouts,
+}
+
+#[derive(Clone, Debug)]
+pub struct Extern {
+    pub name: String,
+    pub sig: Signature,
+}
+
+#[derive(Clone, Debug)]
+pub struct Function {
+    pub name: String,
+    pub params: Vec<VarId>,
+    pub ret: Type,
+    pub vars: Vec<Var>,
+    pub body: Block,
+    pub span: Span,
+}
+
+#[derive(Clone, Debug)]
+pub struct Var {
+    pub name: String,
+    pub ty: Type,
+    // Introduced by elaboration
+    pub temp: bool,
+}
+
+pub type Block = Vec<Stmt>;
+
+#[derive(Clone, Debug)]
+pub struct Stmt {
+    pub kind: StmtKind,
+    pub span: Span,
+}
+
+#[derive(Clone, Debug)]
+pub enum StmtKind {
+    Assign(VarId, Exp),
+    Store(Addr, Exp),
+    // An expression evaluated for its effects, or the errors it may raise
+    Eval(Exp),
+    If(Exp, Block, Block),
+    While(Exp, Block),
+    Break,
+    Continue,
+    Return(Option<Exp>),
+    // `assert` when the kind is `None`, otherwise a contract, with the message to fail with
+    Assert(Exp, Option<SpecKind>, String),
+    Error(Exp),
+}
+
+#[derive(Clone, Debug)]
+pub struct Exp {
+    pub kind: ExpKind,
+    pub ty: Type,
+    pub span: Span,
+}
+
+/*
+Operators are those of C0 except `*` on pointers, which is a `Load`, and
+`&&` and `||`, which are control flow.
+ */
+#[derive(Clone, Debug)]
+pub enum ExpKind {
+    Int(i32),
+    Bool(bool),
+    Char(char),
+    Str(String),
+    Null,
+    Var(VarId),
+    Unop(UnOp, Box<Exp>),
+    Binop(BinOp, Box<Exp>, Box<Exp>),
+    Call(String, Vec<Exp>),
+    Load(Addr),
+    // The address itself, a pointer to the location
+    AddrOf(Addr),
+    Alloc(Type),
+    AllocArray(Type, Box<Exp>),
+    Length(Box<Exp>),
+}
+
+// A location in memory, of type `ty`
+#[derive(Clone, Debug)]
+pub struct Addr {
+    pub kind: AddrKind,
+    pub ty: Type,
+    pub span: Span,
+}
+
+#[derive(Clone, Debug)]
+pub enum AddrKind {
+    // `*p`, which must not be NULL
+    Deref(Box<Exp>),
+    // `A[i]`, where `i` must be within the bounds of `A`
+    Element(Box<Exp>, Box<Exp>),
+    // `a.f`, the field `f` at `offset` bytes into the struct at `a`
+    Field(Box<Addr>, String, usize),
+}
+
+impl Exp {
+    pub fn new(kind: ExpKind, ty: Type, span: Span) -> Exp {
+        Exp { kind, ty, span }
+    }
+
+    // Constants and variables, which can be evaluated again with the same result
+    pub fn is_atom(&self) -> bool {
+        matches!(
+            self.kind,
+            ExpKind::Int(_) | ExpKind::Bool(_) | ExpKind::Char(_) | ExpKind::Str(_) | ExpKind::Null | ExpKind::Var(_)
+        )
+    }
+}
+
+impl Stmt {
+    pub fn new(kind: StmtKind, span: Span) -> Stmt {
+        Stmt { kind, span }
+    }
+}
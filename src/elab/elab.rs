@@ -0,0 +1,679 @@
+use ast::ast::{self, GDeclKind, Ident, SimpleKind, Spec, SpecKind};
+use ast::fold::Fold;
+use ast::pretty;
+use elab::core::*;
+use semant::contracts::{self, Rebind};
+use semant::resolve::{DeclId, Resolution};
+use semant::structs::Layouts;
+use semant::typeck::Typing;
+use semant::types::Type;
+use source::source_map::Span;
+use std::collections::HashMap;
+use std::mem;
+
+/*
+Elaborates a checked program to the core language, see `core`. Contracts
+become assertions only with `dyn_check`
+
+- `@requires` at the start of the function, from every declaration of it
+- `@ensures` before each `return`, with `\result` in a variable
+- `@loop_invariant` before each test of the loop condition
+- `@assert` where it is written
+- the contracts of library functions around each call to them
+
+A `for` loop runs its step at the end of the body and before each
+`continue`. Void functions end with an explicit `return`.
+ */
+pub fn elaborate(
+    p: &ast::Program,
+    resolution: &Resolution,
+    typing: &Typing,
+    layouts: &Layouts,
+    dyn_check: bool,
+) -> Program {
+    let mut declarations: HashMap<DeclId, Vec<&ast::Function>> = HashMap::new();
+    let mut specs: HashMap<DeclId, Vec<Spec>> = HashMap::new();
+    let mut rebind = Rebind::new(p, resolution);
+    for d in &p.decls {
+        if let GDeclKind::Function(ref f) = d.kind {
+            if let Some(decl) = resolution.decl_id(&f.name) {
+                declarations.entry(decl).or_default().push(f);
+                let rebound = f.specs.iter().map(|s| rebind.fold_spec(s.clone()));
+                specs.entry(decl).or_default().extend(rebound);
+            }
+        }
+    }
+    let mut elaborator = Elaborator {
+        resolution,
+        typing,
+        layouts,
+        dyn_check,
+        declarations,
+        specs: &specs,
+        externs: Vec::new(),
+        vars: Vec::new(),
+        locals: HashMap::new(),
+        ensures: Vec::new(),
+        result: None,
+        steps: Vec::new(),
+    };
+    let mut functions = Vec::new();
+    for d in &p.decls {
+        if let GDeclKind::Function(ref f) = d.kind {
+            if let Some(ref body) = f.body {
+                functions.push(elaborator.function(f, body, d.span));
+            }
+        }
+    }
+    Program {
+        functions,
+        externs: elaborator.externs,
+        layouts: layouts.clone(),
+    }
+}
+
+struct Elaborator<'a> {
+    resolution: &'a Resolution,
+    typing: &'a Typing,
+    layouts: &'a Layouts,
+    dyn_check: bool,
+    declarations: HashMap<DeclId, Vec<&'a ast::Function>>,
+    // The contracts of every declaration of each function, rebound to the same parameters
+    specs: &'a HashMap<DeclId, Vec<Spec>>,
+    externs: Vec<Extern>,
+    // Of the function being elaborated
+    vars: Vec<Var>,
+    locals: HashMap<DeclId, VarId>,
+    ensures: Vec<&'a Spec>,
+    // The variable `\result` stands for
+    result: Option<VarId>,
+    // The steps of the enclosing loops, run before `continue`
+    steps: Vec<Block>,
+}
+
+impl<'a> Elaborator<'a> {
+    fn function(&mut self, f: &'a ast::Function, body: &'a ast::Block, span: Span) -> Function {
+        let decl = self.decl(&f.name);
+        let sig = self.typing.functions[&decl].clone();
+        self.vars.clear();
+        self.locals.clear();
+        self.result = None;
+        let params: Vec<VarId> =
+            f.params.iter().zip(&sig.params).map(|(p, ty)| self.var(&p.name, ty.clone())).collect();
+        let specs: Vec<&'a Spec> = self.specs[&decl].iter().collect();
+        self.ensures = specs.iter().cloned().filter(|s| s.kind == SpecKind::Ensures).collect();
+        if self.dyn_check && !self.ensures.is_empty() && sig.ret != Type::Void {
+            self.result = Some(self.temp("\\result", sig.ret.clone()));
+        }
+
+        let mut out = Vec::new();
+        self.check(&specs, SpecKind::Requires, &mut out);
+        for s in &body.stmts {
+            self.stmt(s, &mut out);
+        }
+        let returns = matches!(out.last(), Some(&Stmt { kind: StmtKind::Return(_), .. }));
+        if sig.ret == Type::Void && !returns {
+            let end = Span { lo: body.span.hi - 1, ..body.span };
+            self.ret(None, end, &mut out);
+        }
+        Function {
+            name: f.name.name.clone(),
+            params,
+            ret: sig.ret,
+            vars: mem::take(&mut self.vars),
+            body: out,
+            span,
+        }
+    }
+
+    fn decl(&self, x: &Ident) -> DeclId {
+        self.resolution.decl_id(x).expect("names are resolved")
+    }
+
+    fn var(&mut self, x: &Ident, ty: Type) -> VarId {
+        let v = self.vars.len();
+        self.vars.push(Var {
+            name: x.name.clone(),
+            ty,
+            temp: false,
+        });
+        self.locals.insert(self.decl(x), v);
+        v
+    }
+
+    fn temp(&mut self, name: &str, ty: Type) -> VarId {
+        self.vars.push(Var {
+            name: String::from(name),
+            ty,
+            temp: true,
+        });
+        self.vars.len() - 1
+    }
+
+    fn fresh(&mut self, ty: Type) -> VarId {
+        let n = self.vars.iter().filter(|v| v.temp).count();
+        self.temp(&format!("_t{}", n), ty)
+    }
+
+    // `e` in a temporary, unless it is an atom
+    fn settle(&mut self, e: Exp, out: &mut Block) -> Exp {
+        if e.is_atom() {
+            return e;
+        }
+        let t = self.fresh(e.ty.clone());
+        let (ty, span) = (e.ty.clone(), e.span);
+        out.push(Stmt::new(StmtKind::Assign(t, e), span));
+        Exp::new(ExpKind::Var(t), ty, span)
+    }
+
+    fn check(&mut self, specs: &[&'a Spec], kind: SpecKind, out: &mut Block) {
+        if !self.dyn_check {
+            return;
+        }
+        for spec in specs.iter().filter(|s| s.kind == kind) {
+            let c = self.exp(&spec.exp, out);
+            let message = format!("@{} `{}` failed", kind, pretty::exp(&spec.exp));
+            out.push(Stmt::new(StmtKind::Assert(c, Some(kind), message), spec.span));
+        }
+    }
+
+    fn stmt(&mut self, s: &'a ast::Stmt, out: &mut Block) {
+        let kind = match s.kind {
+            ast::StmtKind::Simple(ref simple) => return self.simple(simple, out),
+            ast::StmtKind::If(ref c, ref then, ref otherwise) => {
+                let c = self.exp(c, out);
+                let mut yes = Vec::new();
+                self.stmt(then, &mut yes);
+                let mut no = Vec::new();
+                if let Some(ref otherwise) = *otherwise {
+                    self.stmt(otherwise, &mut no);
+                }
+                StmtKind::If(c, yes, no)
+            }
+            ast::StmtKind::While(ref c, ref invariants, ref body) => {
+                return self.repeat(c, invariants, None, body, s.span, out);
+            }
+            ast::StmtKind::For(ref init, ref c, ref step, ref invariants, ref body) => {
+                if let Some(ref init) = *init {
+                    self.simple(init, out);
+                }
+                return self.repeat(c, invariants, step.as_ref().map(|s| &**s), body, s.span, out);
+            }
+            ast::StmtKind::Return(ref e) => {
+                let e = e.as_ref().map(|e| self.exp(e, out));
+                return self.ret(e, s.span, out);
+            }
+            ast::StmtKind::Break => StmtKind::Break,
+            ast::StmtKind::Continue => {
+                let step = self.steps.last().cloned().unwrap_or_default();
+                out.extend(step);
+                StmtKind::Continue
+            }
+            ast::StmtKind::Block(ref b) => {
+                for s in &b.stmts {
+                    self.stmt(s, out);
+                }
+                return;
+            }
+            ast::StmtKind::Assert(ref c) => {
+                let c = self.exp(c, out);
+                StmtKind::Assert(c, None, String::from("assertion failed"))
+            }
+            ast::StmtKind::Error(ref e) => StmtKind::Error(self.exp(e, out)),
+            ast::StmtKind::Annotation(ref specs) => {
+                let specs: Vec<&Spec> = specs.iter().collect();
+                return self.check(&specs, SpecKind::Assert, out);
+            }
+        };
+        out.push(Stmt::new(kind, s.span));
+    }
+
+    /*
+    A loop whose condition needs statements of its own, or invariants, tests
+    it inside the body
+
+        while (true) { <invariants> <condition> if (!c) break; <body> <step> }
+     */
+    fn repeat(
+        &mut self,
+        c: &'a ast::Exp,
+        invariants: &'a [Spec],
+        step: Option<&'a ast::Simple>,
+        body: &'a ast::Stmt,
+        span: Span,
+        out: &mut Block,
+    ) {
+        let mut head = Vec::new();
+        let invariants: Vec<&Spec> = invariants.iter().collect();
+        self.check(&invariants, SpecKind::LoopInvariant, &mut head);
+        let c = self.exp(c, &mut head);
+        let mut step_block = Vec::new();
+        if let Some(step) = step {
+            self.simple(step, &mut step_block);
+        }
+        self.steps.push(step_block);
+        let mut inner = Vec::new();
+        self.stmt(body, &mut inner);
+        inner.extend(self.steps.pop().unwrap_or_default());
+
+        if head.is_empty() {
+            out.push(Stmt::new(StmtKind::While(c, inner), span));
+        } else {
+            let exit = Stmt::new(StmtKind::Break, c.span);
+            let not = Exp::new(ExpKind::Unop(ast::UnOp::Not, Box::new(c.clone())), Type::Bool, c.span);
+            head.push(Stmt::new(StmtKind::If(not, vec![exit], Vec::new()), c.span));
+            head.extend(inner);
+            let forever = Exp::new(ExpKind::Bool(true), Type::Bool, c.span);
+            out.push(Stmt::new(StmtKind::While(forever, head), span));
+        }
+    }
+
+    fn ret(&mut self, e: Option<Exp>, span: Span, out: &mut Block) {
+        let mut e = e;
+        if self.dyn_check && !self.ensures.is_empty() {
+            if let (Some(v), Some(r)) = (e.take(), self.result) {
+                let ty = v.ty.clone();
+                out.push(Stmt::new(StmtKind::Assign(r, v), span));
+                e = Some(Exp::new(ExpKind::Var(r), ty, span));
+            }
+            let ensures = self.ensures.clone();
+            self.check(&ensures, SpecKind::Ensures, out);
+        }
+        out.push(Stmt::new(StmtKind::Return(e), span));
+    }
+
+    fn simple(&mut self, s: &'a ast::Simple, out: &mut Block) {
+        match s.kind {
+            SimpleKind::Assign(op, ref lv, ref e) => {
+                let op = match op {
+                    ast::AsnOp::Assign => None,
+                    ast::AsnOp::Compound(op) => Some(op),
+                };
+                self.assign(lv, op, Some(e), s.span, out);
+            }
+            SimpleKind::Post(op, ref lv) => {
+                let op = match op {
+                    ast::PostOp::Incr => ast::BinOp::Add,
+                    ast::PostOp::Decr => ast::BinOp::Sub,
+                };
+                self.assign(lv, Some(op), None, s.span, out);
+            }
+            SimpleKind::Exp(ref e) => {
+                let e = self.exp(e, out);
+                if !e.is_atom() {
+                    out.push(Stmt::new(StmtKind::Eval(e), s.span));
+                }
+            }
+            SimpleKind::Decl(_, ref x, ref init) => {
+                let ty = self.typing.vars[&self.decl(x)].clone();
+                let v = self.var(x, ty);
+                if let Some(ref init) = *init {
+                    let e = self.exp(init, out);
+                    out.push(Stmt::new(StmtKind::Assign(v, e), s.span));
+                }
+            }
+        }
+    }
+
+    /*
+    `lv = e`, `lv op= e` or `lv++` when `value` is `None`. A location in
+    memory is computed, and checked, before the value, then written through
+    its address
+
+        _t0 = &A[i]; *_t0 = *_t0 + 1;
+     */
+    fn assign(
+        &mut self,
+        lv: &'a ast::Exp,
+        op: Option<ast::BinOp>,
+        value: Option<&'a ast::Exp>,
+        span: Span,
+        out: &mut Block,
+    ) {
+        let ty = self.typing.exp(lv).clone();
+        if let ast::ExpKind::Var(ref x) = lv.kind {
+            let v = self.locals[&self.decl(x)];
+            let old = Exp::new(ExpKind::Var(v), ty, lv.span);
+            let e = self.update(old, op, value, span, out);
+            return out.push(Stmt::new(StmtKind::Assign(v, e), span));
+        }
+        let addr = self.addr(lv, out);
+        let pointer = match addr.kind {
+            // `&*p` is `p`
+            AddrKind::Deref(p) => self.settle(*p, out),
+            _ => {
+                let ptr = Type::Pointer(Box::new(ty.clone()));
+                let e = Exp::new(ExpKind::AddrOf(addr), ptr, lv.span);
+                self.settle(e, out)
+            }
+        };
+        let place = Addr {
+            kind: AddrKind::Deref(Box::new(pointer)),
+            ty: ty.clone(),
+            span: lv.span,
+        };
+        let old = Exp::new(ExpKind::Load(place.clone()), ty, lv.span);
+        let e = self.update(old, op, value, span, out);
+        out.push(Stmt::new(StmtKind::Store(place, e), span));
+    }
+
+    // The value assigned, combined with the `old` one by a compound assignment
+    fn update(
+        &mut self,
+        old: Exp,
+        op: Option<ast::BinOp>,
+        value: Option<&'a ast::Exp>,
+        span: Span,
+        out: &mut Block,
+    ) -> Exp {
+        let e = match value {
+            Some(e) => self.exp(e, out),
+            None => Exp::new(ExpKind::Int(1), Type::Int, span),
+        };
+        match op {
+            Some(op) => Exp::new(ExpKind::Binop(op, Box::new(old), Box::new(e)), Type::Int, span),
+            None => e,
+        }
+    }
+
+    // Elaborates the operands left to right, keeping that order when a later one needs statements
+    fn exps(&mut self, es: &[&'a ast::Exp], out: &mut Block) -> Vec<Exp> {
+        let parts: Vec<(Block, Exp)> = es
+            .iter()
+            .map(|e| {
+                let mut pre = Vec::new();
+                let e = self.exp(e, &mut pre);
+                (pre, e)
+            })
+            .collect();
+        let last = parts.iter().rposition(|(pre, _)| !pre.is_empty());
+        let mut result = Vec::new();
+        for (i, (pre, e)) in parts.into_iter().enumerate() {
+            out.extend(pre);
+            result.push(match last {
+                Some(last) if i < last => self.settle(e, out),
+                _ => e,
+            });
+        }
+        result
+    }
+
+    fn exp(&mut self, e: &'a ast::Exp, out: &mut Block) -> Exp {
+        let ty = self.typing.exp(e).clone();
+        let kind = match e.kind {
+            ast::ExpKind::Num(n, _) => ExpKind::Int(n),
+            ast::ExpKind::Bool(b) => ExpKind::Bool(b),
+            ast::ExpKind::Null => ExpKind::Null,
+            ast::ExpKind::Char(c) => ExpKind::Char(c),
+            ast::ExpKind::Str(ref s) => ExpKind::Str(s.clone()),
+            ast::ExpKind::Var(ref x) => ExpKind::Var(self.locals[&self.decl(x)]),
+            ast::ExpKind::Result => ExpKind::Var(self.result.expect("`\\result` is only checked with a result")),
+            ast::ExpKind::Unop(ast::UnOp::Deref, _)
+            | ast::ExpKind::Field(..)
+            | ast::ExpKind::Arrow(..)
+            | ast::ExpKind::Index(..) => ExpKind::Load(self.addr(e, out)),
+            ast::ExpKind::Unop(op, ref a) => ExpKind::Unop(op, Box::new(self.exp(a, out))),
+            ast::ExpKind::Binop(op @ ast::BinOp::And, ref a, ref b)
+            | ast::ExpKind::Binop(op @ ast::BinOp::Or, ref a, ref b) => {
+                // `a && b` is `a ? b : false`, `a || b` is `a ? true : b`
+                let stop = Exp::new(ExpKind::Bool(op == ast::BinOp::Or), Type::Bool, e.span);
+                let c = self.exp(a, out);
+                let mut go = Vec::new();
+                let b = self.exp(b, &mut go);
+                let t = self.fresh(Type::Bool);
+                go.push(Stmt::new(StmtKind::Assign(t, b), e.span));
+                let stop = vec![Stmt::new(StmtKind::Assign(t, stop), e.span)];
+                let (yes, no) = if op == ast::BinOp::And { (go, stop) } else { (stop, go) };
+                out.push(Stmt::new(StmtKind::If(c, yes, no), e.span));
+                ExpKind::Var(t)
+            }
+            ast::ExpKind::Binop(op, ref a, ref b) => {
+                let mut es = self.exps(&[a, b], out).into_iter();
+                let (a, b) = (es.next().unwrap(), es.next().unwrap());
+                ExpKind::Binop(op, Box::new(a), Box::new(b))
+            }
+            ast::ExpKind::Ternary(ref c, ref a, ref b) => {
+                let c = self.exp(c, out);
+                let mut yes = Vec::new();
+                let a = self.exp(a, &mut yes);
+                let mut no = Vec::new();
+                let b = self.exp(b, &mut no);
+                let t = self.fresh(ty.clone());
+                yes.push(Stmt::new(StmtKind::Assign(t, a), e.span));
+                no.push(Stmt::new(StmtKind::Assign(t, b), e.span));
+                out.push(Stmt::new(StmtKind::If(c, yes, no), e.span));
+                ExpKind::Var(t)
+            }
+            ast::ExpKind::Call(ref f, ref args) => return self.call(f, args, ty, e.span, out),
+            ast::ExpKind::Alloc(_) => match ty {
+                Type::Pointer(ref inner) => ExpKind::Alloc((**inner).clone()),
+                _ => unreachable!("`alloc` has a pointer type"),
+            },
+            ast::ExpKind::AllocArray(_, ref n) => match ty {
+                Type::Array(ref inner) => ExpKind::AllocArray((**inner).clone(), Box::new(self.exp(n, out))),
+                _ => unreachable!("`alloc_array` has an array type"),
+            },
+            ast::ExpKind::Length(ref a) => ExpKind::Length(Box::new(self.exp(a, out))),
+        };
+        Exp::new(kind, ty, e.span)
+    }
+
+    // The location an l-value, or a selection from memory, denotes
+    fn addr(&mut self, e: &'a ast::Exp, out: &mut Block) -> Addr {
+        let ty = self.typing.exp(e).clone();
+        let kind = match e.kind {
+            ast::ExpKind::Unop(ast::UnOp::Deref, ref p) => AddrKind::Deref(Box::new(self.exp(p, out))),
+            ast::ExpKind::Arrow(ref p, ref f) => {
+                let p = self.exp(p, out);
+                let s = match p.ty {
+                    Type::Pointer(ref s) => (**s).clone(),
+                    _ => unreachable!("`->` selects from a pointer"),
+                };
+                let base = Addr {
+                    kind: AddrKind::Deref(Box::new(p)),
+                    ty: s,
+                    span: e.span,
+                };
+                self.field(base, f)
+            }
+            ast::ExpKind::Field(ref a, ref f) => {
+                let base = self.addr(a, out);
+                self.field(base, f)
+            }
+            ast::ExpKind::Index(ref a, ref i) => {
+                let mut es = self.exps(&[a, i], out).into_iter();
+                let (a, i) = (es.next().unwrap(), es.next().unwrap());
+                AddrKind::Element(Box::new(a), Box::new(i))
+            }
+            _ => unreachable!("`{}` is not a location", pretty::exp(e)),
+        };
+        Addr { kind, ty, span: e.span }
+    }
+
+    fn field(&self, base: Addr, f: &Ident) -> AddrKind {
+        let offset = match base.ty {
+            Type::Struct(ref s) => self.layouts.structs[s].field(&f.name).map_or(0, |field| field.offset),
+            _ => unreachable!("fields are selected from structs"),
+        };
+        AddrKind::Field(Box::new(base), f.name.clone(), offset)
+    }
+
+    fn call(&mut self, f: &'a Ident, args: &'a [ast::Exp], ty: Type, span: Span, out: &mut Block) -> Exp {
+        let decl = self.decl(f);
+        let declarations = self.declarations.get(&decl).cloned().unwrap_or_default();
+        let args: Vec<&ast::Exp> = args.iter().collect();
+        let mut args = self.exps(&args, out);
+        let defined = declarations.iter().any(|g| g.body.is_some());
+        if !defined && !self.externs.iter().any(|x| x.name == f.name) {
+            self.externs.push(Extern {
+                name: f.name.clone(),
+                sig: self.typing.functions[&decl].clone(),
+            });
+        }
+        let specs: Vec<&'a Spec> = self.specs.get(&decl).into_iter().flatten().collect();
+        if defined || !self.dyn_check || specs.is_empty() {
+            return Exp::new(ExpKind::Call(f.name.clone(), args), ty, span);
+        }
+
+        // The contracts of a library function, with its parameters bound to the arguments
+        args = args.into_iter().map(|a| self.bind(a, out)).collect();
+        if let Some(g) = contracts::bound(declarations.iter().cloned()) {
+            for (p, a) in g.params.iter().zip(&args) {
+                if let ExpKind::Var(v) = a.kind {
+                    self.locals.insert(self.decl(&p.name), v);
+                }
+            }
+        }
+        self.check(&specs, SpecKind::Requires, out);
+        let call = Exp::new(ExpKind::Call(f.name.clone(), args), ty.clone(), span);
+        // A void function has no result for its `@ensures` to be about, they are not checked
+        if ty == Type::Void || !specs.iter().any(|s| s.kind == SpecKind::Ensures) {
+            return call;
+        }
+        let r = self.fresh(ty.clone());
+        out.push(Stmt::new(StmtKind::Assign(r, call), span));
+        let outer = self.result.replace(r);
+        self.check(&specs, SpecKind::Ensures, out);
+        self.result = outer;
+        Exp::new(ExpKind::Var(r), ty, span)
+    }
+
+    // `e` in a variable
+    fn bind(&mut self, e: Exp, out: &mut Block) -> Exp {
+        if let ExpKind::Var(_) = e.kind {
+            return e;
+        }
+        let t = self.fresh(e.ty.clone());
+        let (ty, span) = (e.ty.clone(), e.span);
+        out.push(Stmt::new(StmtKind::Assign(t, e), span));
+        Exp::new(ExpKind::Var(t), ty, span)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use elab::print;
+    use elab::tests::elaborated;
+
+    fn core(text: &str, dyn_check: bool) -> String {
+        print::program(&elaborated(text, dyn_check))
+    }
+
+    #[test]
+    fn desugaring() {
+        let cases = vec![
+            (
+                "int f(int n) { int s = 0; for (int i = 0; i < n; i++) { if (i == 2) continue; s += i; } return s; }",
+                "int f(int n) {\n  int s;\n  int i;\n  s = 0;\n  i = 0;\n  while (i < n) {\n    if (i == 2) {\n      \
+                 i = i + 1;\n      continue;\n    }\n    s = s + i;\n    i = i + 1;\n  }\n  return s;\n}\n",
+            ),
+            (
+                "int f(int x) { return x > 0 ? x : -x; }",
+                "int f(int x) {\n  int _t0;\n  if (x > 0) {\n    _t0 = x;\n  } else {\n    _t0 = -x;\n  }\n  \
+                 return _t0;\n}\n",
+            ),
+            (
+                "bool f(bool a, bool b) { return a && b || !a; }",
+                "bool f(bool a, bool b) {\n  bool _t0;\n  bool _t1;\n  if (a) {\n    _t0 = b;\n  } else {\n    \
+                 _t0 = false;\n  }\n  if (_t0) {\n    _t1 = true;\n  } else {\n    _t1 = !a;\n  }\n  return _t1;\n}\n",
+            ),
+            (
+                "void f(int x) { x--; x <<= 2; { int y = x; } { int y = 1; } }",
+                "void f(int x) {\n  int y#1;\n  int y#2;\n  x = x - 1;\n  x = x << 2;\n  y#1 = x;\n  y#2 = 1;\n  \
+                 return;\n}\n",
+            ),
+            (
+                "int f(int x) { while (x > 0 || x < -10) { x /= 2; } return x; }",
+                "int f(int x) {\n  bool _t0;\n  while (true) {\n    if (x > 0) {\n      _t0 = true;\n    \
+                 } else {\n      \
+                 _t0 = x < -10;\n    }\n    if (!_t0) {\n      break;\n    }\n    x = x / 2;\n  }\n  return x;\n}\n",
+            ),
+        ];
+        for (source, expected) in cases {
+            assert_eq!(core(source, false), expected, "{}", source);
+        }
+    }
+
+    #[test]
+    fn computing_addresses() {
+        let structs = "struct p { int x; int y; }; struct s { bool b; struct p at; struct s* next; };\n";
+        let cases = vec![
+            ("int f(int* p) { *p = 1; return *p; }", "  *p = 1;\n  return *p;\n"),
+            ("int f(int[] A) { A[1] += 2; return A[0]; }", "  _t0 = &A[1];\n  *_t0 = *_t0 + 2;\n  return A[0];\n"),
+            (
+                "int f(struct s* s) { s->next->at.y++; return s->at.y; }",
+                "  _t0 = &(*(*s).next).at.y;\n  *_t0 = *_t0 + 1;\n  return (*s).at.y;\n",
+            ),
+            (
+                "int f(struct p*[] P, int** q) { **q = P[0]->x; return (*P[1]).y; }",
+                "  _t0 = *q;\n  *_t0 = (*P[0]).x;\n  return (*P[1]).y;\n",
+            ),
+        ];
+        for (source, expected) in cases {
+            let program = elaborated(&format!("{}{}", structs, source), false);
+            let printed = print::function(&program.functions[0]);
+            // Without the declarations of locals
+            let body: String = printed
+                .lines()
+                .filter(|l| l.contains(" = ") || l.contains("return"))
+                .map(|l| format!("{}\n", l))
+                .collect();
+            assert_eq!(body, expected, "{}", source);
+        }
+        let program = elaborated(&format!("{}{}", structs, "int f(struct s* s) { s->at.y = 1; return 0; }"), false);
+        match program.functions[0].body[0].kind {
+            StmtKind::Assign(_, Exp { kind: ExpKind::AddrOf(ref a), .. }) => match a.kind {
+                AddrKind::Field(ref at, _, 4) => assert!(matches!(at.kind, AddrKind::Field(_, _, 4))),
+                ref kind => panic!("expected a field at offset 4, found {:?}", kind),
+            },
+            ref kind => panic!("expected an address, found {:?}", kind),
+        }
+    }
+
+    #[test]
+    fn keeping_evaluation_order() {
+        let source = "int g(int x) { return x; }\n\
+                      int f(bool b) { return g(1) + (b && g(2) > 0 ? 1 : 0); }";
+        let program = elaborated(source, false);
+        assert_eq!(
+            print::function(&program.functions[1]),
+            "int f(bool b) {\n  bool _t0;\n  int _t1;\n  int _t2;\n  _t2 = g(1);\n  if (b) {\n    _t0 = g(2) > 0;\n  \
+             } else {\n    _t0 = false;\n  }\n  if (_t0) {\n    _t1 = 1;\n  } else {\n    _t1 = 0;\n  }\n  \
+             return _t2 + _t1;\n}\n"
+        );
+    }
+
+    #[test]
+    fn checking_contracts() {
+        let source = "int abs(int x) /*@ensures \\result >= 0; @*/;\n\
+                      int f(int n)\n//@requires n >= 0;\n//@ensures \\result == n;\n\
+                      { int i = 0; while (i < n) //@loop_invariant i <= n;\n { i++; }\n\
+                      //@assert i == n;\n return abs(i); }";
+        assert_eq!(
+            core(source, false),
+            "int abs(int);\n\nint f(int n) {\n  int i;\n  i = 0;\n  while (i < n) {\n    i = i + 1;\n  }\n  \
+             return abs(i);\n}\n"
+        );
+        assert_eq!(
+            core(source, true),
+            "int abs(int);\n\nint f(int n) {\n  int \\result;\n  int i;\n  int _t1;\n  \
+             assert(n >= 0); // @requires\n  i = 0;\n  while (true) {\n    assert(i <= n); // @loop_invariant\n    \
+             if (!(i < n)) {\n      break;\n    }\n    i = i + 1;\n  }\n  assert(i == n); // @assert\n  \
+             _t1 = abs(i);\n  assert(_t1 >= 0); // @ensures\n  \\result = _t1;\n  \
+             assert(\\result == n); // @ensures\n  return \\result;\n}\n"
+        );
+    }
+
+    #[test]
+    fn keeping_spans() {
+        let source = "int f(int[] A) {\n  A[0]++;\n  return A[0];\n}";
+        let program = elaborated(source, false);
+        let spans: Vec<(usize, usize)> = program.functions[0].body.iter().map(|s| (s.span.lo, s.span.hi)).collect();
+        assert_eq!(spans, vec![(19, 23), (19, 25), (29, 41)]);
+        match program.functions[0].body[2].kind {
+            StmtKind::Return(Some(ref e)) => assert_eq!((e.span.lo, e.span.hi), (36, 40)),
+            ref kind => panic!("expected a return, found {:?}", kind),
+        }
+    }
+}
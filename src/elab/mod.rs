@@ -0,0 +1,21 @@
+pub mod core;
+pub mod elab;
+pub mod print;
+
+#[cfg(test)]
+pub mod tests {
+    use elab::core::Program;
+    use elab::elab::elaborate;
+    use semant::resolve::resolve;
+    use semant::tests::parse;
+    use semant::{structs, typeck};
+
+    // The program in the core language, its contracts checked with `dyn_check`
+    pub fn elaborated(text: &str, dyn_check: bool) -> Program {
+        let p = parse(text);
+        let resolution = resolve(&p).unwrap();
+        let typing = typeck::check(&p, &resolution).unwrap();
+        let layouts = structs::check(&p, &typing).unwrap();
+        elaborate(&p, &resolution, &typing, &layouts, dyn_check)
+    }
+}
@@ -0,0 +1,186 @@
+use elab::core::*;
+use lexer::lexer::{escape_char, escape_str};
+use std::collections::HashMap;
+
+/*
+Prints the core language in the syntax of C0, where it has one
+
+    int sum(int[] A, int n) {
+      int s;
+      int i;
+      s = 0;
+      i = 0;
+      while (i < n) {
+        s = s + A[i];
+        i = i + 1;
+      }
+      return s;
+    }
+
+Locals are declared at the top of their function. Loads are written as the
+location they read, `*p`, `A[i]` or `(*p).f`, and `&` takes its address.
+Operands of binary operators are parenthesized unless they are atoms,
+calls, loads or unary operations, so `a + b + c` is `(a + b) + c`. A
+variable which shares its name with another one of the function is told
+apart by its number, `x#3`.
+ */
+pub fn program(p: &Program) -> String {
+    let mut out = String::new();
+    for x in &p.externs {
+        out.push_str(&format!("{};\n", x.sig.show(&x.name)));
+    }
+    for (i, f) in p.functions.iter().enumerate() {
+        if i > 0 || !p.externs.is_empty() {
+            out.push('\n');
+        }
+        out.push_str(&function(f));
+    }
+    out
+}
+
+pub fn function(f: &Function) -> String {
+    let mut printer = Printer::new(f);
+    let params: Vec<String> = f.params.iter().map(|&v| format!("{} {}", f.vars[v].ty, printer.names[v])).collect();
+    printer.line(&format!("{} {}({}) {{", f.ret, f.name, params.join(", ")));
+    printer.indent += 1;
+    for v in (0..f.vars.len()).filter(|v| !f.params.contains(v)) {
+        let decl = format!("{} {};", f.vars[v].ty, printer.names[v]);
+        printer.line(&decl);
+    }
+    printer.block(&f.body);
+    printer.indent -= 1;
+    printer.line("}");
+    printer.out
+}
+
+// An expression of `f`
+pub fn exp(f: &Function, e: &Exp) -> String {
+    Printer::new(f).exp(e)
+}
+
+struct Printer {
+    names: Vec<String>,
+    out: String,
+    indent: usize,
+}
+
+impl Printer {
+    fn new(f: &Function) -> Printer {
+        let mut count = HashMap::new();
+        for v in &f.vars {
+            *count.entry(&v.name).or_insert(0) += 1;
+        }
+        let names = f
+            .vars
+            .iter()
+            .enumerate()
+            .map(|(i, v)| if count[&v.name] > 1 { format!("{}#{}", v.name, i) } else { v.name.clone() })
+            .collect();
+        Printer {
+            names,
+            out: String::new(),
+            indent: 0,
+        }
+    }
+
+    fn line(&mut self, text: &str) {
+        for _ in 0..self.indent {
+            self.out.push_str("  ");
+        }
+        self.out.push_str(text);
+        self.out.push('\n');
+    }
+
+    fn block(&mut self, b: &Block) {
+        for s in b {
+            self.stmt(s);
+        }
+    }
+
+    fn nested(&mut self, b: &Block) {
+        self.indent += 1;
+        self.block(b);
+        self.indent -= 1;
+    }
+
+    fn stmt(&mut self, s: &Stmt) {
+        let text = match s.kind {
+            StmtKind::Assign(v, ref e) => format!("{} = {};", self.names[v], self.exp(e)),
+            StmtKind::Store(ref a, ref e) => format!("{} = {};", self.addr(a), self.exp(e)),
+            StmtKind::Eval(ref e) => format!("{};", self.exp(e)),
+            StmtKind::If(ref c, ref yes, ref no) => {
+                let header = format!("if ({}) {{", self.exp(c));
+                self.line(&header);
+                self.nested(yes);
+                if !no.is_empty() {
+                    self.line("} else {");
+                    self.nested(no);
+                }
+                return self.line("}");
+            }
+            StmtKind::While(ref c, ref body) => {
+                let header = format!("while ({}) {{", self.exp(c));
+                self.line(&header);
+                self.nested(body);
+                return self.line("}");
+            }
+            StmtKind::Break => String::from("break;"),
+            StmtKind::Continue => String::from("continue;"),
+            StmtKind::Return(None) => String::from("return;"),
+            StmtKind::Return(Some(ref e)) => format!("return {};", self.exp(e)),
+            StmtKind::Assert(ref c, None, _) => format!("assert({});", self.exp(c)),
+            StmtKind::Assert(ref c, Some(kind), _) => format!("assert({}); // @{}", self.exp(c), kind),
+            StmtKind::Error(ref e) => format!("error({});", self.exp(e)),
+        };
+        self.line(&text);
+    }
+
+    fn exp(&self, e: &Exp) -> String {
+        match e.kind {
+            ExpKind::Int(n) => n.to_string(),
+            ExpKind::Bool(b) => b.to_string(),
+            ExpKind::Char(c) => format!("'{}'", escape_char(c)),
+            ExpKind::Str(ref s) => format!("\"{}\"", escape_str(s)),
+            ExpKind::Null => String::from("NULL"),
+            ExpKind::Var(v) => self.names[v].clone(),
+            ExpKind::Unop(op, ref a) => match a.kind {
+                ExpKind::Unop(..) | ExpKind::Int(i32::MIN..=-1) => format!("{}({})", op, self.exp(a)),
+                _ => format!("{}{}", op, self.operand(a)),
+            },
+            ExpKind::Binop(op, ref a, ref b) => format!("{} {} {}", self.operand(a), op, self.operand(b)),
+            ExpKind::Call(ref f, ref args) => {
+                let args: Vec<String> = args.iter().map(|a| self.exp(a)).collect();
+                format!("{}({})", f, args.join(", "))
+            }
+            ExpKind::Load(ref a) => self.addr(a),
+            ExpKind::AddrOf(ref a) => format!("&{}", self.addr(a)),
+            ExpKind::Alloc(ref ty) => format!("alloc({})", ty),
+            ExpKind::AllocArray(ref ty, ref n) => format!("alloc_array({}, {})", ty, self.exp(n)),
+            ExpKind::Length(ref a) => format!("\\length({})", self.exp(a)),
+        }
+    }
+
+    fn operand(&self, e: &Exp) -> String {
+        match e.kind {
+            ExpKind::Binop(..) | ExpKind::AddrOf(_) => format!("({})", self.exp(e)),
+            _ => self.exp(e),
+        }
+    }
+
+    fn addr(&self, a: &Addr) -> String {
+        match a.kind {
+            AddrKind::Deref(ref p) => format!("*{}", self.operand(p)),
+            AddrKind::Element(ref array, ref i) => {
+                let array = match array.kind {
+                    ExpKind::Load(Addr { kind: AddrKind::Deref(_), .. }) => format!("({})", self.exp(array)),
+                    _ => self.operand(array),
+                };
+                format!("{}[{}]", array, self.exp(i))
+            }
+            AddrKind::Field(ref base, ref f, _) => match base.kind {
+                AddrKind::Deref(_) => format!("({}).{}", self.addr(base), f),
+                _ => format!("{}.{}", self.addr(base), f),
+            },
+        }
+    }
+}
@@ -0,0 +1,155 @@
+use ast::ast::SpecKind;
+use source::diagnostic::Diagnostic;
+use source::source_map::Span;
+use std::fmt;
+
+/*
+Why a program stopped, where, and the calls it was in. Each kind of error is
+classified by the signal the cc0 runtime stops a compiled program with
+
+    SIGABRT  failed assertions and contracts, `error`, and library functions
+             given arguments they do not accept
+    SIGFPE   division by zero, `INT_MIN / -1`, shifts outside 0 to 31
+    SIGSEGV  NULL dereferences, indices out of bounds, negative array sizes
+             and running out of stack
+ */
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum ErrorKind {
+    Assertion,
+    Contract(SpecKind),
+    Error,
+    Library,
+    Arithmetic,
+    Memory,
+}
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum Signal {
+    Abort,
+    FloatingPoint,
+    Segmentation,
+}
+
+impl ErrorKind {
+    pub fn signal(self) -> Signal {
+        match self {
+            ErrorKind::Assertion | ErrorKind::Contract(_) | ErrorKind::Error | ErrorKind::Library => Signal::Abort,
+            ErrorKind::Arithmetic => Signal::FloatingPoint,
+            ErrorKind::Memory => Signal::Segmentation,
+        }
+    }
+}
+
+impl fmt::Display for ErrorKind {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            ErrorKind::Assertion => write!(f, "assertion failure"),
+            ErrorKind::Contract(kind) => write!(f, "@{} failure", kind),
+            ErrorKind::Error => write!(f, "error"),
+            ErrorKind::Library => write!(f, "library error"),
+            ErrorKind::Arithmetic => write!(f, "arithmetic error"),
+            ErrorKind::Memory => write!(f, "memory error"),
+        }
+    }
+}
+
+impl Signal {
+    pub fn number(self) -> i32 {
+        match self {
+            Signal::Abort => 6,
+            Signal::FloatingPoint => 8,
+            Signal::Segmentation => 11,
+        }
+    }
+
+    // As a shell reports a process killed by the signal
+    pub fn exit_code(self) -> i32 {
+        128 + self.number()
+    }
+}
+
+impl fmt::Display for Signal {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            Signal::Abort => write!(f, "SIGABRT"),
+            Signal::FloatingPoint => write!(f, "SIGFPE"),
+            Signal::Segmentation => write!(f, "SIGSEGV"),
+        }
+    }
+}
+
+// A call the error happened in, where it was called from unless it is `main`
+#[derive(Clone, Debug)]
+pub struct Frame {
+    pub function: String,
+    // The arguments as C0 would write them
+    pub args: Vec<String>,
+    pub call: Option<Span>,
+}
+
+// `f(1, "a")`
+impl fmt::Display for Frame {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}({})", self.function, self.args.join(", "))
+    }
+}
+
+// The calls a diagnostic shows, the innermost, so a stack overflow is not thousands of notes
+pub const TRACE_LIMIT: usize = 16;
+
+#[derive(Clone, Debug)]
+pub struct RuntimeError {
+    pub kind: ErrorKind,
+    pub message: String,
+    pub span: Span,
+    // The innermost call first
+    pub trace: Vec<Frame>,
+}
+
+impl RuntimeError {
+    pub fn new(kind: ErrorKind, span: Span, message: impl Into<String>) -> RuntimeError {
+        RuntimeError {
+            kind,
+            message: message.into(),
+            span,
+            trace: Vec::new(),
+        }
+    }
+
+    /*
+    The error with the calls leading to it
+
+    t.c0:2:10: error: index 3 is out of bounds for an array of length 3
+        return A[i];
+               ^^^^
+    t.c0:5:10: note: in `get(0x55d0c4a0, 3)`, called here
+        return get(A, 3);
+               ^^^^^^^^^
+    note: in `main()`
+    note: memory error, stopped by SIGSEGV
+
+    Calls past the first `TRACE_LIMIT` are only counted, `... 12 more frames`.
+    The innermost call is left out when the error already underlines it, as
+    for a library function failing.
+     */
+    pub fn diagnostic(&self) -> Diagnostic {
+        let mut d = Diagnostic::error(self.span, self.message.clone());
+        let mut trace = &self.trace[..];
+        if let Some(call) = trace.first().and_then(|f| f.call) {
+            // `==` on spans is always true
+            if (call.file, call.lo, call.hi) == (self.span.file, self.span.lo, self.span.hi) {
+                trace = &trace[1..];
+            }
+        }
+        for frame in trace.iter().take(TRACE_LIMIT) {
+            d = match frame.call {
+                Some(call) => d.note(call, format!("in `{}`, called here", frame)),
+                None => d.help(format!("in `{}`", frame)),
+            };
+        }
+        if trace.len() > TRACE_LIMIT {
+            d = d.help(format!("... {} more frames", trace.len() - TRACE_LIMIT));
+        }
+        d.help(format!("{}, stopped by {}", self.kind, self.kind.signal()))
+    }
+}
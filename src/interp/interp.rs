@@ -0,0 +1,751 @@
+use ast::ast::*;
+use ast::pretty;
+use ast::visit::VisitorMut;
+use interp::error::{ErrorKind, Frame, RuntimeError};
+use interp::natives::{self, Io};
+use interp::value::Value;
+use semant::contracts::{self, Rebind};
+use semant::resolve::{DeclId, DeclKind, Resolution};
+use semant::typeck::Typing;
+use semant::types::Type;
+use source::source_map::Span;
+use std::cell::RefCell;
+use std::collections::HashMap;
+use std::io::{self, BufRead, Write};
+use std::rc::Rc;
+use std::{mem, panic, thread};
+
+/*
+A tree-walking interpreter for checked programs, following the dynamic
+semantics of C0
+
+- ints are 32 bit two's complement and wrap around, except that division and
+  modulus by zero, and `INT_MIN / -1` or `INT_MIN % -1`, are errors; shifts
+  are by 0 to 31 only, `>>` is arithmetic
+- `&&`, `||` and `?:` evaluate only what they need, everything else left to
+  right, the location of an assignment before its value
+- reading or writing through NULL, or an array out of bounds, is an error, as
+  is allocating an array of negative size
+- `assert` and `error` stop the program, with their message
+
+Library functions are run by `natives`.
+
+With `-d` contracts are checked as well: `@requires` when a function is called,
+`@ensures` when it returns, `@loop_invariant` before each test of the loop
+condition and `@assert` where it is written. The contracts of every
+declaration of a function apply, those of library headers included. Without
+`-d` they are not evaluated at all.
+ */
+pub type Run<T> = Result<T, RuntimeError>;
+
+// How a statement finished
+pub enum Flow {
+    Next,
+    Break,
+    Continue,
+    Return(Value),
+}
+
+// Deep enough for any reasonable recursion, on the stack of `with_stack`
+pub const MAX_DEPTH: usize = 30_000;
+const STACK_SIZE: usize = 1 << 30;
+
+// Runs `f`, an interpreter, on a thread with a stack for `MAX_DEPTH` calls
+pub fn with_stack<T, F>(f: F) -> io::Result<T>
+where
+    F: FnOnce() -> T + Send + 'static,
+    T: Send + 'static,
+{
+    let thread = thread::Builder::new().stack_size(STACK_SIZE).spawn(f)?;
+    Ok(thread.join().unwrap_or_else(|e| panic::resume_unwind(e)))
+}
+
+// Where an l-value is, the fields selected from a struct after `path`
+enum Place {
+    Var(DeclId),
+    Cell(Rc<RefCell<Value>>),
+    Element(Rc<RefCell<Vec<Value>>>, usize),
+}
+
+pub struct Interpreter<'io> {
+    pub resolution: Resolution,
+    pub typing: Typing,
+    // Whether contracts are checked, as with `-d`
+    pub dyn_check: bool,
+    // Every declaration of each function, one of them its definition
+    functions: HashMap<DeclId, Vec<Rc<Function>>>,
+    // The variables of the function running
+    vars: HashMap<DeclId, Value>,
+    // The value of `\result` while checking `@ensures`
+    result: Option<Value>,
+    depth: usize,
+    io: Io<'io>,
+}
+
+impl<'io> Interpreter<'io> {
+    pub fn new(
+        resolution: Resolution,
+        typing: Typing,
+        out: &'io mut dyn Write,
+        input: &'io mut dyn BufRead,
+    ) -> Interpreter<'io> {
+        Interpreter {
+            resolution,
+            typing,
+            dyn_check: false,
+            functions: HashMap::new(),
+            vars: HashMap::new(),
+            result: None,
+            depth: 0,
+            io: Io { out, input },
+        }
+    }
+
+    // Makes the functions declared by `p` those which are called, their contracts rebound
+    pub fn define(&mut self, p: &Program) {
+        let mut rebind = Rebind::new(p, &self.resolution);
+        let mut functions: HashMap<DeclId, Vec<Rc<Function>>> = HashMap::new();
+        for d in &p.decls {
+            if let GDeclKind::Function(ref f) = d.kind {
+                if let Some(decl) = self.resolution.decl_id(&f.name) {
+                    let mut f = f.clone();
+                    for s in &mut f.specs {
+                        rebind.visit_spec_mut(s);
+                    }
+                    functions.entry(decl).or_default().push(Rc::new(f));
+                }
+            }
+        }
+        self.functions = functions;
+    }
+
+    // Gives a variable of the running function a value
+    pub fn bind(&mut self, decl: DeclId, v: Value) {
+        self.vars.insert(decl, v);
+    }
+
+    pub fn value(&self, decl: DeclId) -> Option<&Value> {
+        self.vars.get(&decl)
+    }
+
+    // Where the program prints
+    pub fn out(&mut self) -> &mut dyn Write {
+        &mut *self.io.out
+    }
+
+    // Runs `main`, whose result is that of the program
+    pub fn run_main(&mut self) -> Run<i32> {
+        let main = self
+            .resolution
+            .decls
+            .iter()
+            .position(|d| d.kind == DeclKind::Function && d.name == "main");
+        match main.filter(|&decl| self.resolution.decls[decl].defined.is_some()) {
+            Some(decl) => Ok(self.call(decl, "main", Vec::new(), None)?.int()),
+            None => Err(RuntimeError::new(ErrorKind::Error, Span::default(), "the program has no `main` function")),
+        }
+    }
+
+    // Calls a function from `call`, or `main` from nowhere
+    fn call(&mut self, decl: DeclId, name: &str, args: Vec<Value>, call: Option<Span>) -> Run<Value> {
+        let span = call.unwrap_or_default();
+        if self.depth == MAX_DEPTH {
+            return Err(RuntimeError::new(ErrorKind::Memory, span, "stack overflow, too many nested calls"));
+        }
+        let declarations = self.functions.get(&decl).cloned().unwrap_or_default();
+        // The contracts of every declaration use the parameters of this one
+        let mut vars = HashMap::new();
+        if let Some(f) = contracts::bound(declarations.iter().map(|f| &**f)) {
+            for (param, arg) in f.params.iter().zip(&args) {
+                if let Some(param) = self.resolution.decl_id(&param.name) {
+                    vars.insert(param, arg.clone());
+                }
+            }
+        }
+        let caller = mem::replace(&mut self.vars, vars);
+        self.depth += 1;
+        let result = self.run_function(&declarations, name, &args, span);
+        self.depth -= 1;
+        self.vars = caller;
+        result.map_err(|mut e| {
+            e.trace.push(Frame {
+                function: String::from(name),
+                args: args.iter().map(Value::to_string).collect(),
+                call,
+            });
+            e
+        })
+    }
+
+    fn run_function(&mut self, declarations: &[Rc<Function>], name: &str, args: &[Value], span: Span) -> Run<Value> {
+        for f in declarations {
+            self.check(&f.specs, SpecKind::Requires)?;
+        }
+        let result = match declarations.iter().find_map(|f| f.body.as_ref()) {
+            Some(body) => match self.block(body)? {
+                Flow::Return(v) => v,
+                _ => Value::Void,
+            },
+            None => self.native(name, args, span)?,
+        };
+        if self.dyn_check {
+            let outer = self.result.replace(result.clone());
+            let checked = declarations.iter().try_for_each(|f| self.check(&f.specs, SpecKind::Ensures));
+            self.result = outer;
+            checked?;
+        }
+        Ok(result)
+    }
+
+    fn native(&mut self, name: &str, args: &[Value], span: Span) -> Run<Value> {
+        match natives::call(name, args, &mut self.io) {
+            Some(result) => result.map_err(|message| {
+                RuntimeError::new(ErrorKind::Library, span, format!("{}: {}", name, message))
+            }),
+            None => Err(RuntimeError::new(
+                ErrorKind::Library,
+                span,
+                format!("`{}` is not defined, or not available in the interpreter", name),
+            )),
+        }
+    }
+
+    // Checks the contracts of one kind among `specs`, only with `-d`
+    fn check(&mut self, specs: &[Spec], kind: SpecKind) -> Run<()> {
+        if !self.dyn_check {
+            return Ok(());
+        }
+        for spec in specs.iter().filter(|s| s.kind == kind) {
+            if !self.exp(&spec.exp)?.bool() {
+                let message = format!("@{} `{}` failed", kind, pretty::exp(&spec.exp));
+                return Err(RuntimeError::new(ErrorKind::Contract(kind), spec.span, message));
+            }
+        }
+        Ok(())
+    }
+
+    pub fn block(&mut self, b: &Block) -> Run<Flow> {
+        for s in &b.stmts {
+            match self.stmt(s)? {
+                Flow::Next => (),
+                flow => return Ok(flow),
+            }
+        }
+        Ok(Flow::Next)
+    }
+
+    pub fn stmt(&mut self, s: &Stmt) -> Run<Flow> {
+        match s.kind {
+            StmtKind::Simple(ref simple) => self.simple(simple)?,
+            StmtKind::If(ref cond, ref then, ref otherwise) => {
+                if self.exp(cond)?.bool() {
+                    return self.stmt(then);
+                } else if let Some(ref otherwise) = *otherwise {
+                    return self.stmt(otherwise);
+                }
+            }
+            StmtKind::While(ref cond, ref invariants, ref body) => return self.repeat(cond, None, invariants, body),
+            StmtKind::For(ref init, ref cond, ref step, ref invariants, ref body) => {
+                if let Some(ref init) = *init {
+                    self.simple(init)?;
+                }
+                return self.repeat(cond, step.as_ref().map(|s| &**s), invariants, body);
+            }
+            StmtKind::Return(ref e) => {
+                let v = match *e {
+                    Some(ref e) => self.exp(e)?,
+                    None => Value::Void,
+                };
+                return Ok(Flow::Return(v));
+            }
+            StmtKind::Break => return Ok(Flow::Break),
+            StmtKind::Continue => return Ok(Flow::Continue),
+            StmtKind::Block(ref b) => return self.block(b),
+            StmtKind::Assert(ref e) => {
+                if !self.exp(e)?.bool() {
+                    return Err(RuntimeError::new(ErrorKind::Assertion, s.span, "assertion failed"));
+                }
+            }
+            StmtKind::Error(ref e) => {
+                let message = self.exp(e)?;
+                return Err(RuntimeError::new(ErrorKind::Error, s.span, message.str()));
+            }
+            StmtKind::Annotation(ref specs) => self.check(specs, SpecKind::Assert)?,
+        }
+        Ok(Flow::Next)
+    }
+
+    /*
+    A loop, with the step of a `for` run after the body and on `continue`. The
+    invariants hold before the condition is tested, so on entry and after every
+    iteration.
+     */
+    fn repeat(&mut self, cond: &Exp, step: Option<&Simple>, invariants: &[Spec], body: &Stmt) -> Run<Flow> {
+        loop {
+            self.check(invariants, SpecKind::LoopInvariant)?;
+            if !self.exp(cond)?.bool() {
+                break;
+            }
+            match self.stmt(body)? {
+                Flow::Break => break,
+                flow @ Flow::Return(_) => return Ok(flow),
+                Flow::Next | Flow::Continue => (),
+            }
+            if let Some(step) = step {
+                self.simple(step)?;
+            }
+        }
+        Ok(Flow::Next)
+    }
+
+    pub fn simple(&mut self, s: &Simple) -> Run<()> {
+        match s.kind {
+            SimpleKind::Assign(op, ref lhs, ref rhs) => {
+                let (place, path) = self.place(lhs)?;
+                let v = self.exp(rhs)?;
+                let v = match op {
+                    AsnOp::Assign => v,
+                    AsnOp::Compound(op) => {
+                        let old = self.read(&place, &path);
+                        Value::Int(arith(op, old.int(), v.int(), s.span)?)
+                    }
+                };
+                self.write(&place, &path, v);
+            }
+            SimpleKind::Post(op, ref lhs) => {
+                let (place, path) = self.place(lhs)?;
+                let old = self.read(&place, &path).int();
+                let new = match op {
+                    PostOp::Incr => old.wrapping_add(1),
+                    PostOp::Decr => old.wrapping_sub(1),
+                };
+                self.write(&place, &path, Value::Int(new));
+            }
+            SimpleKind::Exp(ref e) => {
+                self.exp(e)?;
+            }
+            SimpleKind::Decl(_, ref x, ref init) => {
+                if let Some(decl) = self.resolution.decl_id(x) {
+                    let v = match *init {
+                        Some(ref e) => self.exp(e)?,
+                        None => Value::default(&self.typing.vars[&decl], &self.typing),
+                    };
+                    self.vars.insert(decl, v);
+                }
+            }
+        }
+        Ok(())
+    }
+
+    pub fn exp(&mut self, e: &Exp) -> Run<Value> {
+        let v = match e.kind {
+            ExpKind::Num(n, _) => Value::Int(n),
+            ExpKind::Bool(b) => Value::Bool(b),
+            ExpKind::Null => Value::Null,
+            ExpKind::Char(c) => Value::Char(c),
+            ExpKind::Str(ref s) => Value::Str(Rc::from(&s[..])),
+            ExpKind::Var(_) | ExpKind::Field(..) | ExpKind::Arrow(..) | ExpKind::Index(..) => {
+                let (place, path) = self.place(e)?;
+                self.read(&place, &path)
+            }
+            ExpKind::Unop(op, ref inner) => {
+                let v = self.exp(inner)?;
+                match op {
+                    UnOp::Not => Value::Bool(!v.bool()),
+                    UnOp::BitNot => Value::Int(!v.int()),
+                    UnOp::Neg => Value::Int(v.int().wrapping_neg()),
+                    UnOp::Deref => match v {
+                        Value::Pointer(p) => p.borrow().clone(),
+                        _ => return Err(RuntimeError::new(ErrorKind::Memory, e.span, "NULL pointer dereference")),
+                    },
+                }
+            }
+            ExpKind::Binop(BinOp::And, ref a, ref b) => Value::Bool(self.exp(a)?.bool() && self.exp(b)?.bool()),
+            ExpKind::Binop(BinOp::Or, ref a, ref b) => Value::Bool(self.exp(a)?.bool() || self.exp(b)?.bool()),
+            ExpKind::Binop(op, ref a, ref b) => {
+                let (a, b) = (self.exp(a)?, self.exp(b)?);
+                match op {
+                    BinOp::Eq => Value::Bool(a.equals(&b)),
+                    BinOp::Ne => Value::Bool(!a.equals(&b)),
+                    BinOp::Lt | BinOp::Le | BinOp::Ge | BinOp::Gt => Value::Bool(compare(op, &a, &b)),
+                    _ => Value::Int(arith(op, a.int(), b.int(), e.span)?),
+                }
+            }
+            ExpKind::Ternary(ref cond, ref a, ref b) => {
+                if self.exp(cond)?.bool() {
+                    self.exp(a)?
+                } else {
+                    self.exp(b)?
+                }
+            }
+            ExpKind::Call(ref f, ref args) => {
+                let mut values = Vec::new();
+                for arg in args {
+                    values.push(self.exp(arg)?);
+                }
+                self.call_named(f, values, e.span)?
+            }
+            ExpKind::Alloc(_) => match *self.typing.exp(e) {
+                Type::Pointer(ref ty) => Value::Pointer(Rc::new(RefCell::new(Value::default(ty, &self.typing)))),
+                ref ty => panic!("alloc of type {}", ty),
+            },
+            ExpKind::AllocArray(_, ref n) => {
+                let n = self.exp(n)?.int();
+                if n < 0 {
+                    return Err(RuntimeError::new(ErrorKind::Memory, e.span, format!("array size {} is negative", n)));
+                }
+                match *self.typing.exp(e) {
+                    Type::Array(ref ty) => {
+                        let v = Value::default(ty, &self.typing);
+                        Value::Array(Rc::new(RefCell::new(vec![v; n as usize])))
+                    }
+                    ref ty => panic!("alloc_array of type {}", ty),
+                }
+            }
+            ExpKind::Length(ref a) => match self.exp(a)? {
+                Value::Array(a) => Value::Int(a.borrow().len() as i32),
+                v => panic!("length of {:?}", v),
+            },
+            ExpKind::Result => self.result.clone().expect("`\\result` outside of @ensures"),
+        };
+        Ok(v)
+    }
+
+    fn call_named(&mut self, f: &Ident, args: Vec<Value>, span: Span) -> Run<Value> {
+        match self.resolution.decl_id(f) {
+            Some(decl) => self.call(decl, &f.name, args, Some(span)),
+            None => panic!("unresolved function `{}`", f.name),
+        }
+    }
+
+    // Evaluates what an l-value refers to, which must be allocated
+    fn place(&mut self, e: &Exp) -> Run<(Place, Vec<usize>)> {
+        match e.kind {
+            ExpKind::Var(ref x) => match self.resolution.decl_id(x) {
+                Some(decl) => Ok((Place::Var(decl), Vec::new())),
+                None => panic!("unresolved variable `{}`", x.name),
+            },
+            ExpKind::Unop(UnOp::Deref, ref p) => Ok((Place::Cell(self.pointer(p, e.span)?), Vec::new())),
+            ExpKind::Arrow(ref p, ref field) => {
+                let index = self.field(self.typing.exp(p), field);
+                Ok((Place::Cell(self.pointer(p, e.span)?), vec![index]))
+            }
+            ExpKind::Field(ref s, ref field) => {
+                let index = self.field(self.typing.exp(s), field);
+                let (place, mut path) = self.place(s)?;
+                path.push(index);
+                Ok((place, path))
+            }
+            ExpKind::Index(ref a, ref i) => {
+                let a = self.exp(a)?;
+                let i = self.exp(i)?.int();
+                match a {
+                    Value::Array(a) => {
+                        let len = a.borrow().len();
+                        if i < 0 || i as usize >= len {
+                            let message = format!("index {} is out of bounds for an array of length {}", i, len);
+                            return Err(RuntimeError::new(ErrorKind::Memory, e.span, message));
+                        }
+                        Ok((Place::Element(a, i as usize), Vec::new()))
+                    }
+                    v => panic!("indexing {:?}", v),
+                }
+            }
+            // An expression which is not an l-value, only selected from by `.`
+            _ => {
+                let v = self.exp(e)?;
+                Ok((Place::Cell(Rc::new(RefCell::new(v))), Vec::new()))
+            }
+        }
+    }
+
+    fn pointer(&mut self, p: &Exp, span: Span) -> Run<Rc<RefCell<Value>>> {
+        match self.exp(p)? {
+            Value::Pointer(p) => Ok(p),
+            _ => Err(RuntimeError::new(ErrorKind::Memory, span, "NULL pointer dereference")),
+        }
+    }
+
+    // The position of a field in the struct `ty` or pointed to by `ty`
+    fn field(&self, ty: &Type, field: &Ident) -> usize {
+        match *ty {
+            Type::Struct(ref s) => self.typing.structs[s]
+                .iter()
+                .position(|(f, _)| *f == field.name)
+                .expect("field checked by the type checker"),
+            Type::Pointer(ref ty) => self.field(ty, field),
+            ref ty => panic!("field of {}", ty),
+        }
+    }
+
+    fn read(&self, place: &Place, path: &[usize]) -> Value {
+        fn select(v: &Value, path: &[usize]) -> Value {
+            match (v, path.split_first()) {
+                (Value::Struct(fields), Some((&i, rest))) => select(&fields[i], rest),
+                (v, _) => v.clone(),
+            }
+        }
+        match *place {
+            Place::Var(decl) => select(&self.vars[&decl], path),
+            Place::Cell(ref cell) => select(&cell.borrow(), path),
+            Place::Element(ref a, i) => select(&a.borrow()[i], path),
+        }
+    }
+
+    fn write(&mut self, place: &Place, path: &[usize], v: Value) {
+        fn select<'v>(mut target: &'v mut Value, path: &[usize]) -> &'v mut Value {
+            for &i in path {
+                target = match *target {
+                    Value::Struct(ref mut fields) => &mut fields[i],
+                    ref v => panic!("field of {:?}", v),
+                };
+            }
+            target
+        }
+        match *place {
+            Place::Var(decl) => {
+                self.vars.insert(decl, v);
+            }
+            Place::Cell(ref cell) => *select(&mut cell.borrow_mut(), path) = v,
+            Place::Element(ref a, i) => *select(&mut a.borrow_mut()[i], path) = v,
+        }
+    }
+}
+
+fn compare(op: BinOp, a: &Value, b: &Value) -> bool {
+    let (a, b) = match (a, b) {
+        (Value::Char(a), Value::Char(b)) => (*a as i32, *b as i32),
+        (a, b) => (a.int(), b.int()),
+    };
+    match op {
+        BinOp::Lt => a < b,
+        BinOp::Le => a <= b,
+        BinOp::Ge => a >= b,
+        BinOp::Gt => a > b,
+        _ => unreachable!(),
+    }
+}
+
+// The arithmetic operators, as C0 defines them on 32 bit ints
+pub fn arith(op: BinOp, a: i32, b: i32, span: Span) -> Run<i32> {
+    let n = match op {
+        BinOp::Add => a.wrapping_add(b),
+        BinOp::Sub => a.wrapping_sub(b),
+        BinOp::Mul => a.wrapping_mul(b),
+        BinOp::Div | BinOp::Mod if b == 0 => return Err(RuntimeError::new(ErrorKind::Arithmetic, span, "division by zero")),
+        BinOp::Div | BinOp::Mod if a == i32::MIN && b == -1 => {
+            return Err(RuntimeError::new(ErrorKind::Arithmetic, span, format!("overflow, {} {} {} is not an int", a, op, b)))
+        }
+        BinOp::Div => a / b,
+        BinOp::Mod => a % b,
+        BinOp::Shl | BinOp::Shr if !(0..32).contains(&b) => {
+            return Err(RuntimeError::new(ErrorKind::Arithmetic, span, format!("cannot shift by {}, only by 0 to 31", b)))
+        }
+        BinOp::Shl => a << b,
+        BinOp::Shr => a >> b,
+        BinOp::BitAnd => a & b,
+        BinOp::BitXor => a ^ b,
+        BinOp::BitOr => a | b,
+        _ => panic!("`{}` is not arithmetic", op),
+    };
+    Ok(n)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use interp::error::{Signal, TRACE_LIMIT};
+    use semant::tests::parse;
+    use semant::{resolve, typeck};
+    use source::source_map::SourceMap;
+
+    // The result of main and what the program printed, or the runtime error
+    fn run(text: &str) -> Result<(i32, String), String> {
+        run_checked(text, false)
+    }
+
+    fn run_checked(text: &str, dyn_check: bool) -> Result<(i32, String), String> {
+        let text = String::from(text);
+        with_stack(move || run_here(&text, dyn_check)).unwrap().map_err(|e| e.message)
+    }
+
+    fn fail(text: &str, dyn_check: bool) -> RuntimeError {
+        let text = String::from(text);
+        match with_stack(move || run_here(&text, dyn_check)).unwrap() {
+            Ok(_) => panic!("the program succeeds"),
+            Err(e) => e,
+        }
+    }
+
+    fn run_here(text: &str, dyn_check: bool) -> Result<(i32, String), RuntimeError> {
+        let p = parse(text);
+        let resolution = resolve::resolve(&p).unwrap();
+        let typing = typeck::check(&p, &resolution).unwrap();
+        let mut out = Vec::new();
+        let result = {
+            let mut input: &[u8] = b"";
+            let mut interpreter = Interpreter::new(resolution, typing, &mut out, &mut input);
+            interpreter.dyn_check = dyn_check;
+            interpreter.define(&p);
+            interpreter.run_main()
+        };
+        match result {
+            Ok(n) => Ok((n, String::from_utf8(out).unwrap())),
+            Err(e) => Err(e),
+        }
+    }
+
+    #[test]
+    fn running_programs() {
+        let cases = vec![
+            ("int main() { return 2147483647 + 1; }", -2147483648),
+            ("int main() { return -2147483648 * -1 + (-7 / 2) * 10 + -7 % 2; }", 2147483647 - 30),
+            ("int main() { return (-16 >> 2) + (1 << 31 >> 31) + (5 & 3 | 8 ^ 1) + ~0; }", -4 - 1 + 9 - 1),
+            ("int main() { int x = 3; x *= 4; x -= 2; x <<= 1; x++; return x; }", 21),
+            ("int main() { int s = 0;\nfor (int i = 0; i < 10; i++) { if (i == 7) break; if (i % 2 == 0) continue; s += i; }\n\
+              return s; }", 1 + 3 + 5),
+            ("int f(int n) { return n < 2 ? n : f(n - 1) + f(n - 2); }\nint main() { return f(15); }", 610),
+            ("bool t(int* p) { (*p)++; return true; }\n\
+              int main() { int* p = alloc(int); bool b = false && t(p); b = true || t(p); return *p; }", 0),
+            ("struct pt { int x; bool b; struct pt* next; };\n\
+              int main() { struct pt* p = alloc(struct pt); p->next = alloc(struct pt); p->next->x = 4;\n\
+              (*p).x = 3; struct pt[] A = alloc_array(struct pt, 2); A[1].x = p->x + p->next->x;\n\
+              return A[1].x * 10 + A[0].x + (p->next->next == NULL ? 100 : 0); }", 170),
+            ("int main() { int[] A = alloc_array(int, 2); int[] B = A; B[1] = 5; char[] C = alloc_array(char, 1);\n\
+              return A[1] + (A == B ? 1 : 0) + (C[0] == '\\0' ? 10 : 0); }", 16),
+        ];
+        for (source, result) in cases {
+            assert_eq!(run(source), Ok((result, String::new())), "{}", source);
+        }
+    }
+
+    #[test]
+    fn calling_libraries() {
+        let program = "void printint(int n);\nvoid println(string s);\n\
+                       string string_join(string a, string b);\nstring string_fromint(int n);\n\
+                       int main() { printint(-3); println(string_join(\", \", string_fromint(7))); return 0; }";
+        assert_eq!(run(program), Ok((0, String::from("-3, 7\n"))));
+    }
+
+    #[test]
+    fn finding_missing_libraries() {
+        let program = "void println(string s);\nint image_width(int i);\nint args_count();\n\
+                       bool file_closed(int f);\nvoid file_close(int f)\n//@requires !file_closed(f);\n;\n\
+                       int helper() { return image_width(0) + image_width(1); }\n\
+                       int main() { println(\"x\"); file_close(0); return helper() + args_count(); }";
+        let p = parse(program);
+        let resolution = resolve::resolve(&p).unwrap();
+        assert_eq!(natives::missing(&p, &resolution, false), vec!["image_width", "file_close", "args_count"]);
+        let checked = vec!["file_closed", "image_width", "file_close", "args_count"];
+        assert_eq!(natives::missing(&p, &resolution, true), checked);
+    }
+
+    #[test]
+    fn runtime_errors() {
+        let cases = vec![
+            ("int main() { int x = 0; return 1 / x; }", "division by zero"),
+            ("int main() { int x = 0; return 1 % x; }", "division by zero"),
+            ("int main() { int x = -1; return -2147483648 / x; }", "overflow, -2147483648 / -1 is not an int"),
+            ("int main() { int x = -1; x %= -1; x = -2147483648; x %= -1; return x; }",
+             "overflow, -2147483648 % -1 is not an int"),
+            ("int main() { return 1 << 32; }", "cannot shift by 32, only by 0 to 31"),
+            ("int main() { int x = -1; return 1 >> x; }", "cannot shift by -1, only by 0 to 31"),
+            ("int main() { int[] A = alloc_array(int, 3); return A[3]; }",
+             "index 3 is out of bounds for an array of length 3"),
+            ("int main() { int[] A = alloc_array(int, 3); A[-1] = 0; return 0; }",
+             "index -1 is out of bounds for an array of length 3"),
+            ("int main() { int[] A = alloc_array(int, -1); return 0; }", "array size -1 is negative"),
+            ("int main() { int* p = NULL; return *p; }", "NULL pointer dereference"),
+            ("struct s { int x; };\nint main() { struct s* p = NULL; p->x = 1; return 0; }", "NULL pointer dereference"),
+            ("int main() { assert(1 > 2); return 0; }", "assertion failed"),
+            ("int main() { error(\"the end\"); }", "the end"),
+            ("int f(int n) { return f(n + 1); }\nint main() { return f(0); }", "stack overflow, too many nested calls"),
+        ];
+        for (source, message) in cases {
+            assert_eq!(run(source), Err(String::from(message)), "{}", source);
+        }
+    }
+
+    #[test]
+    fn checking_contracts() {
+        let ok = "int sum(int[] A, int n)\n//@requires 0 <= n && n <= \\length(A);\n//@ensures \\result >= 0;\n\
+                  { int s = 0;\nfor (int i = 0; i < n; i++)\n//@loop_invariant 0 <= i && i <= n;\n{ s += A[i]; }\n\
+                  //@assert s >= 0;\nreturn s; }\n\
+                  int main() { int[] A = alloc_array(int, 3); A[2] = 5; return sum(A, 3); }";
+        assert_eq!(run_checked(ok, true), Ok((5, String::new())));
+        let cases = vec![
+            ("int f(int x)\n//@requires x > 0;\n{ return x; }\nint main() { return f(0); }", "@requires `x > 0` failed"),
+            ("int f(int x)\n//@ensures \\result > x;\n{ return x; }\nint main() { return f(1); }",
+             "@ensures `\\result > x` failed"),
+            ("int main() { int i = 0; while (i < 3)\n//@loop_invariant i < 3;\n{ i++; } return i; }",
+             "@loop_invariant `i < 3` failed"),
+            ("int main() { int i = 5;\n//@assert i == 4;\nreturn i; }", "@assert `i == 4` failed"),
+            // The contracts of a prototype apply, with its own parameter names
+            ("int f(int y)\n//@requires y != 2;\n;\nint f(int x) { return x; }\nint main() { return f(2); }",
+             "@requires `y != 2` failed"),
+        ];
+        for (source, message) in cases {
+            assert_eq!(run_checked(source, true), Err(String::from(message)), "{}", source);
+        }
+        // Without -d contracts are not even evaluated
+        let skipped = "bool boom() { error(\"evaluated\"); }\n\
+                       int main()\n//@ensures boom();\n{ int i = 0;\n//@assert boom();\nreturn 3; }";
+        assert_eq!(run(skipped), Ok((3, String::new())));
+        assert_eq!(run_checked(skipped, true), Err(String::from("evaluated")));
+    }
+
+    #[test]
+    fn classifying_errors() {
+        let cases = vec![
+            ("int main() { int x = 0; return 3 / x; }", ErrorKind::Arithmetic, Signal::FloatingPoint),
+            ("int main() { return 1 << -1; }", ErrorKind::Arithmetic, Signal::FloatingPoint),
+            ("int main() { int* p = NULL; return *p; }", ErrorKind::Memory, Signal::Segmentation),
+            ("int main() { int[] A = alloc_array(int, 0); return A[0]; }", ErrorKind::Memory, Signal::Segmentation),
+            ("int main() { assert(false); return 0; }", ErrorKind::Assertion, Signal::Abort),
+            ("int main() { error(\"no\"); }", ErrorKind::Error, Signal::Abort),
+            ("int main()\n//@ensures \\result > 0;\n{ return 0; }", ErrorKind::Contract(SpecKind::Ensures), Signal::Abort),
+            ("char char_chr(int n);\nint main() { char_chr(200); return 0; }", ErrorKind::Library, Signal::Abort),
+        ];
+        for (source, kind, signal) in cases {
+            let e = fail(source, true);
+            assert_eq!((e.kind, e.kind.signal()), (kind, signal), "{}", source);
+        }
+        assert_eq!(Signal::Segmentation.exit_code(), 139);
+    }
+
+    #[test]
+    fn tracing_calls() {
+        let program = "int get(int[] A, int i) { return A[i]; }\n\
+                       int twice(string s, int n) { int[] A = alloc_array(int, n); return get(A, n) * 2; }\n\
+                       int main() { return twice(\"x\", 3); }";
+        let e = fail(program, false);
+        let trace: Vec<(String, bool)> = e.trace.iter().map(|f| (f.to_string(), f.call.is_some())).collect();
+        assert_eq!(trace[1..], [(String::from("twice(\"x\", 3)"), true), (String::from("main()"), false)]);
+        assert!(trace[0].0.starts_with("get(0x") && trace[0].0.ends_with(", 3)"), "{}", trace[0].0);
+    }
+
+    #[test]
+    fn tracing_library_errors() {
+        let program = "char char_chr(int n);\nchar f(int n) { return char_chr(n); }\nint main() { f(200); return 0; }";
+        let e = fail(program, false);
+        assert_eq!(e.trace.len(), 3);
+        let mut sources = SourceMap::new();
+        sources.add("t.c0", String::from(program));
+        let text = e.diagnostic().render(&sources);
+        assert_eq!(text.matches("called here").count(), 1, "{}", text);
+        assert!(text.contains("note: in `f(200)`, called here"), "{}", text);
+    }
+
+    #[test]
+    fn tracing_deep_recursion() {
+        let program = "int f(int n) { return f(n + 1); }\nint main() { return f(0); }";
+        let e = fail(program, false);
+        assert_eq!(e.trace.len(), MAX_DEPTH);
+        let mut sources = SourceMap::new();
+        sources.add("t.c0", String::from(program));
+        let text = e.diagnostic().render(&sources);
+        assert_eq!(text.matches("called here").count(), TRACE_LIMIT);
+        assert!(text.contains(&format!("note: ... {} more frames\n", MAX_DEPTH - 1 - TRACE_LIMIT)), "{}", text);
+        assert!(text.len() < 4096, "{}", text);
+    }
+}
@@ -0,0 +1,4 @@
+pub mod error;
+pub mod interp;
+pub mod natives;
+pub mod value;
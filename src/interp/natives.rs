@@ -0,0 +1,241 @@
+use ast::ast::*;
+use ast::visit::{self, Visitor};
+use interp::value::Value;
+use semant::resolve::Resolution;
+use std::cell::RefCell;
+use std::cmp::Ordering;
+use std::io::{BufRead, Write};
+use std::rc::Rc;
+
+/*
+The bundled libraries which the interpreter implements itself: conio, string,
+util, parse and rand. Their preconditions which would be undefined behaviour
+in C are checked always, as the C0 runtime does, not only with `-d`.
+
+Files, images and command line arguments need a compiled program.
+ */
+// The library functions implemented here
+pub const LIBRARY: &[&str] = &[
+    "print", "println", "printint", "printbool", "printchar", "flush", "eof", "readline",
+    "string_length", "string_charat", "string_join", "string_sub", "string_equal", "string_compare",
+    "string_fromint", "string_frombool", "string_fromchar", "string_tolower", "string_terminated",
+    "string_to_chararray", "string_from_chararray", "char_ord", "char_chr",
+    "int_size", "int_max", "int_min", "abs", "max", "min", "int2hex",
+    "parse_bool", "parse_int", "num_tokens", "int_tokens", "parse_tokens", "parse_ints",
+    "init_rand", "rand",
+];
+
+// The library functions the program calls which are not implemented here, in the order the calls
+// are written, counting calls in contracts only if they are checked
+pub fn missing(p: &Program, resolution: &Resolution, dyn_check: bool) -> Vec<String> {
+    let mut calls = Calls {
+        resolution,
+        dyn_check,
+        missing: Vec::new(),
+    };
+    calls.visit_program(p);
+    calls.missing
+}
+
+struct Calls<'a> {
+    resolution: &'a Resolution,
+    dyn_check: bool,
+    missing: Vec<String>,
+}
+
+impl<'a> Visitor for Calls<'a> {
+    fn visit_spec(&mut self, s: &Spec) {
+        if self.dyn_check {
+            visit::walk_spec(self, s)
+        }
+    }
+
+    fn visit_exp(&mut self, e: &Exp) {
+        if let ExpKind::Call(ref f, _) = e.kind {
+            let library = self.resolution.decl_id(f).is_some_and(|decl| self.resolution.decls[decl].defined.is_none());
+            if library && !LIBRARY.contains(&&f.name[..]) && !self.missing.contains(&f.name) {
+                self.missing.push(f.name.clone());
+            }
+        }
+        visit::walk_exp(self, e)
+    }
+}
+
+pub struct Io<'a> {
+    pub out: &'a mut dyn Write,
+    pub input: &'a mut dyn BufRead,
+}
+
+pub type NativeResult = Result<Value, String>;
+
+// Calls the library function `name`, `None` if it is not implemented here
+pub fn call(name: &str, args: &[Value], io: &mut Io) -> Option<NativeResult> {
+    run(name, args, io).transpose()
+}
+
+fn run(name: &str, args: &[Value], io: &mut Io) -> Result<Option<Value>, String> {
+    let result = match (name, args) {
+        // <conio>
+        ("print", [s]) => write(io, s.str()),
+        ("println", [s]) => write(io, &format!("{}\n", s.str())),
+        ("printint", [n]) => write(io, &n.int().to_string()),
+        ("printbool", [b]) => write(io, &b.bool().to_string()),
+        ("printchar", [c]) => write(io, &c.char().to_string()),
+        ("flush", []) => io.out.flush().map(|()| Value::Void).map_err(|e| e.to_string()),
+        ("eof", []) => Ok(Value::Bool(io.input.fill_buf().map(|b| b.is_empty()).unwrap_or(true))),
+        ("readline", []) => readline(io),
+
+        // <string>
+        ("string_length", [s]) => Ok(Value::Int(s.str().len() as i32)),
+        ("string_charat", [s, i]) => {
+            let (s, i) = (s.str(), i.int());
+            if i < 0 || i as usize >= s.len() {
+                Err(format!("index {} is out of bounds for a string of length {}", i, s.len()))
+            } else {
+                Ok(Value::Char(s.as_bytes()[i as usize] as char))
+            }
+        }
+        ("string_join", [a, b]) => Ok(string(format!("{}{}", a.str(), b.str()))),
+        ("string_sub", [s, start, end]) => {
+            let (s, start, end) = (s.str(), start.int(), end.int());
+            if 0 <= start && start <= end && end as usize <= s.len() {
+                Ok(string(String::from(&s[start as usize..end as usize])))
+            } else {
+                Err(format!("substring {}..{} is out of bounds for a string of length {}", start, end, s.len()))
+            }
+        }
+        ("string_equal", [a, b]) => Ok(Value::Bool(a.str() == b.str())),
+        ("string_compare", [a, b]) => Ok(Value::Int(match a.str().cmp(b.str()) {
+            Ordering::Less => -1,
+            Ordering::Equal => 0,
+            Ordering::Greater => 1,
+        })),
+        ("string_fromint", [n]) => Ok(string(n.int().to_string())),
+        ("string_frombool", [b]) => Ok(string(b.bool().to_string())),
+        ("string_fromchar", [c]) => Ok(string(c.char().to_string())),
+        ("string_tolower", [s]) => Ok(string(s.str().to_ascii_lowercase())),
+        ("string_terminated", [a, n]) => {
+            let a = elements(a);
+            let n = n.int().max(0) as usize;
+            Ok(Value::Bool(a.iter().take(n).any(|c| c.char() == '\0')))
+        }
+        ("string_to_chararray", [s]) => {
+            let chars = s.str().chars().chain(Some('\0')).map(Value::Char).collect();
+            Ok(Value::Array(Rc::new(RefCell::new(chars))))
+        }
+        ("string_from_chararray", [a]) => {
+            Ok(string(elements(a).iter().map(Value::char).take_while(|&c| c != '\0').collect()))
+        }
+        ("char_ord", [c]) => Ok(Value::Int(c.char() as i32)),
+        ("char_chr", [n]) => match n.int() {
+            n @ 0..=127 => Ok(Value::Char(n as u8 as char)),
+            n => Err(format!("{} is not an ASCII character", n)),
+        },
+
+        // <util>
+        ("int_size", []) => Ok(Value::Int(4)),
+        ("int_max", []) => Ok(Value::Int(i32::MAX)),
+        ("int_min", []) => Ok(Value::Int(i32::MIN)),
+        ("abs", [x]) => Ok(Value::Int(x.int().wrapping_abs())),
+        ("max", [x, y]) => Ok(Value::Int(x.int().max(y.int()))),
+        ("min", [x, y]) => Ok(Value::Int(x.int().min(y.int()))),
+        ("int2hex", [x]) => Ok(string(format!("{:08X}", x.int() as u32))),
+
+        // <parse>
+        ("parse_bool", [s]) => Ok(match s.str() {
+            "true" => pointer(Value::Bool(true)),
+            "false" => pointer(Value::Bool(false)),
+            _ => Value::Null,
+        }),
+        ("parse_int", [s, base]) => {
+            let base = radix(base)?;
+            Ok(parse_int(s.str(), base).map(|n| pointer(Value::Int(n))).unwrap_or(Value::Null))
+        }
+        ("num_tokens", [s]) => Ok(Value::Int(s.str().split_whitespace().count() as i32)),
+        ("int_tokens", [s, base]) => {
+            let base = radix(base)?;
+            Ok(Value::Bool(s.str().split_whitespace().all(|t| parse_int(t, base).is_some())))
+        }
+        ("parse_tokens", [s]) => {
+            let tokens = s.str().split_whitespace().map(|t| string(String::from(t))).collect();
+            Ok(Value::Array(Rc::new(RefCell::new(tokens))))
+        }
+        ("parse_ints", [s, base]) => {
+            let base = radix(base)?;
+            let mut ints = Vec::new();
+            for t in s.str().split_whitespace() {
+                match parse_int(t, base) {
+                    Some(n) => ints.push(Value::Int(n)),
+                    None => return Err(format!("`{}` is not an int", t)),
+                }
+            }
+            Ok(Value::Array(Rc::new(RefCell::new(ints))))
+        }
+
+        // <rand>, a generator is a pointer to its state
+        ("init_rand", [seed]) => Ok(pointer(Value::Int(seed.int()))),
+        ("rand", [Value::Pointer(state)]) => {
+            let mut state = state.borrow_mut();
+            let next = state.int().wrapping_mul(1_664_525).wrapping_add(1_013_904_223);
+            *state = Value::Int(next);
+            Ok(Value::Int(next))
+        }
+        ("rand", [Value::Null]) => Err(String::from("the generator is NULL")),
+
+        _ => return Ok(None),
+    };
+    result.map(Some)
+}
+
+fn write(io: &mut Io, s: &str) -> NativeResult {
+    match io.out.write_all(s.as_bytes()) {
+        Ok(()) => Ok(Value::Void),
+        Err(e) => Err(e.to_string()),
+    }
+}
+
+fn readline(io: &mut Io) -> NativeResult {
+    let mut line = String::new();
+    match io.input.read_line(&mut line) {
+        Ok(0) => Err(String::from("end of input")),
+        Ok(_) => {
+            if line.ends_with('\n') {
+                line.pop();
+            }
+            Ok(string(line))
+        }
+        Err(e) => Err(e.to_string()),
+    }
+}
+
+fn string(s: String) -> Value {
+    Value::Str(Rc::from(s))
+}
+
+fn pointer(v: Value) -> Value {
+    Value::Pointer(Rc::new(RefCell::new(v)))
+}
+
+fn elements(a: &Value) -> Vec<Value> {
+    match *a {
+        Value::Array(ref a) => a.borrow().clone(),
+        _ => Vec::new(),
+    }
+}
+
+fn radix(base: &Value) -> Result<u32, String> {
+    match base.int() {
+        base @ 2..=36 => Ok(base as u32),
+        base => Err(format!("base {} is not between 2 and 36", base)),
+    }
+}
+
+// An int, with an optional sign, which must be in range
+fn parse_int(s: &str, base: u32) -> Option<i32> {
+    let n = i64::from_str_radix(s, base).ok()?;
+    if n < i64::from(i32::MIN) || n > i64::from(i32::MAX) {
+        None
+    } else {
+        Some(n as i32)
+    }
+}
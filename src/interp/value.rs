@@ -0,0 +1,105 @@
+use lexer::lexer::{escape_char, escape_str};
+use semant::types::Type;
+use semant::typeck::Typing;
+use std::cell::RefCell;
+use std::fmt;
+use std::rc::Rc;
+
+/*
+Values of C0 programs while they run. Memory is only reached through pointers
+and arrays, which share what they point to; two are equal when they are the
+same allocation. A struct is stored inline, in the cell of its pointer or the
+element of its array, with its fields in the order they are declared.
+ */
+#[derive(Clone, Debug)]
+pub enum Value {
+    Int(i32),
+    Bool(bool),
+    Char(char),
+    Str(Rc<str>),
+    Null,
+    Pointer(Rc<RefCell<Value>>),
+    Array(Rc<RefCell<Vec<Value>>>),
+    Struct(Vec<Value>),
+    // The result of a void function
+    Void,
+}
+
+impl Value {
+    // The value of a variable or allocation of type `ty` before anything is written to it
+    pub fn default(ty: &Type, typing: &Typing) -> Value {
+        match *ty {
+            Type::Int => Value::Int(0),
+            Type::Bool => Value::Bool(false),
+            Type::Char => Value::Char('\0'),
+            Type::String => Value::Str(Rc::from("")),
+            Type::Pointer(_) | Type::Null => Value::Null,
+            Type::Array(_) => Value::Array(Rc::new(RefCell::new(Vec::new()))),
+            Type::Struct(ref s) => Value::Struct(
+                typing.structs[s]
+                    .iter()
+                    .map(|(_, ty)| Value::default(ty, typing))
+                    .collect(),
+            ),
+            Type::Void => Value::Void,
+        }
+    }
+
+    pub fn int(&self) -> i32 {
+        match *self {
+            Value::Int(n) => n,
+            _ => panic!("expected an int, found {:?}", self),
+        }
+    }
+
+    pub fn bool(&self) -> bool {
+        match *self {
+            Value::Bool(b) => b,
+            _ => panic!("expected a bool, found {:?}", self),
+        }
+    }
+
+    pub fn char(&self) -> char {
+        match *self {
+            Value::Char(c) => c,
+            _ => panic!("expected a char, found {:?}", self),
+        }
+    }
+
+    pub fn str(&self) -> &str {
+        match *self {
+            Value::Str(ref s) => s,
+            _ => panic!("expected a string, found {:?}", self),
+        }
+    }
+
+    // `==` on the types which have it, memory is compared by identity
+    pub fn equals(&self, other: &Value) -> bool {
+        match (self, other) {
+            (Value::Int(a), Value::Int(b)) => a == b,
+            (Value::Bool(a), Value::Bool(b)) => a == b,
+            (Value::Char(a), Value::Char(b)) => a == b,
+            (Value::Null, Value::Null) => true,
+            (Value::Pointer(a), Value::Pointer(b)) => Rc::ptr_eq(a, b),
+            (Value::Array(a), Value::Array(b)) => Rc::ptr_eq(a, b),
+            _ => false,
+        }
+    }
+}
+
+// As C0 would write the value, memory by its address
+impl fmt::Display for Value {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            Value::Int(n) => write!(f, "{}", n),
+            Value::Bool(b) => write!(f, "{}", b),
+            Value::Char(c) => write!(f, "'{}'", escape_char(c)),
+            Value::Str(ref s) => write!(f, "\"{}\"", escape_str(s)),
+            Value::Null => write!(f, "NULL"),
+            Value::Pointer(ref p) => write!(f, "{:p}", Rc::as_ptr(p)),
+            Value::Array(ref a) => write!(f, "{:p}", Rc::as_ptr(a)),
+            Value::Struct(_) => write!(f, "{{...}}"),
+            Value::Void => write!(f, "(void)"),
+        }
+    }
+}
@@ -0,0 +1,182 @@
+use ir::ir::*;
+use std::mem;
+
+/*
+The control-flow graph of a function, its blocks joined by the edges their
+terminators take. The entry is block 0. A branch with both targets the same
+block is a single edge.
+ */
+#[derive(Clone, Debug, PartialEq)]
+pub struct Cfg {
+    succs: Vec<Vec<Label>>,
+    preds: Vec<Vec<Label>>,
+}
+
+impl Cfg {
+    pub fn new(f: &Function) -> Cfg {
+        let succs: Vec<Vec<Label>> = f.blocks.iter().map(|b| b.term.successors()).collect();
+        let mut preds = vec![Vec::new(); f.blocks.len()];
+        for (l, targets) in succs.iter().enumerate() {
+            for &s in targets {
+                preds[s].push(l);
+            }
+        }
+        Cfg { succs, preds }
+    }
+
+    pub fn len(&self) -> usize {
+        self.succs.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.succs.is_empty()
+    }
+
+    pub fn succs(&self, l: Label) -> &[Label] {
+        &self.succs[l]
+    }
+
+    pub fn preds(&self, l: Label) -> &[Label] {
+        &self.preds[l]
+    }
+
+    // The blocks reachable from the entry, each after all its successors but those closing loops
+    pub fn postorder(&self) -> Vec<Label> {
+        let mut order = Vec::new();
+        if self.is_empty() {
+            return order;
+        }
+        let mut visited = vec![false; self.len()];
+        // Blocks with the index of the next successor to visit
+        let mut stack = vec![(0, 0)];
+        visited[0] = true;
+        while let Some(&mut (l, ref mut next)) = stack.last_mut() {
+            match self.succs[l].get(*next) {
+                Some(&s) => {
+                    *next += 1;
+                    if !visited[s] {
+                        visited[s] = true;
+                        stack.push((s, 0));
+                    }
+                }
+                None => {
+                    order.push(l);
+                    stack.pop();
+                }
+            }
+        }
+        order
+    }
+
+    // The reachable blocks, each before its successors but those closing loops
+    pub fn reverse_postorder(&self) -> Vec<Label> {
+        let mut order = self.postorder();
+        order.reverse();
+        order
+    }
+}
+
+/*
+Edges to blocks which do nothing but jump go straight to where the jumps
+lead, then the blocks no longer reached are removed.
+ */
+pub fn simplify(f: &mut Function) {
+    let n = f.blocks.len();
+    let target: Vec<Label> = (0..n)
+        .map(|l| {
+            let mut t = l;
+            // A loop of empty blocks is left as it is
+            for _ in 0..n {
+                match f.blocks[t] {
+                    Block {
+                        ref instrs,
+                        term: Terminator::Jump(next),
+                    } if instrs.is_empty() => t = next,
+                    _ => break,
+                }
+            }
+            t
+        })
+        .collect();
+    for b in &mut f.blocks {
+        for s in b.term.successors_mut() {
+            *s = target[*s];
+        }
+    }
+    remove_unreachable(f);
+}
+
+// Removes the blocks which cannot be reached from the entry, renumbering the others in order
+pub fn remove_unreachable(f: &mut Function) {
+    let mut reachable = vec![false; f.blocks.len()];
+    for l in Cfg::new(f).postorder() {
+        reachable[l] = true;
+    }
+    let mut renamed = vec![0; f.blocks.len()];
+    let mut next = 0;
+    for (l, &r) in reachable.iter().enumerate() {
+        renamed[l] = next;
+        if r {
+            next += 1;
+        }
+    }
+    let blocks = mem::take(&mut f.blocks);
+    for (l, mut b) in blocks.into_iter().enumerate() {
+        if reachable[l] {
+            for target in b.term.successors_mut() {
+                *target = renamed[*target];
+            }
+            f.blocks.push(b);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use ir::parse::parse;
+    use ir::print;
+
+    fn function(text: &str) -> Function {
+        parse(0, text).unwrap().functions.remove(0)
+    }
+
+    const LOOP: &str = "function f(%0 i8) {\n\
+                        L0:\n  jump L1\n\
+                        L1:\n  branch %0, L2, L3\n\
+                        L2:\n  branch %0, L1, L1\n\
+                        L3:\n  return\n\
+                        L4:\n  jump L3\n\
+                        }";
+
+    #[test]
+    fn querying_edges() {
+        let cfg = Cfg::new(&function(LOOP));
+        assert_eq!(cfg.len(), 5);
+        assert_eq!(cfg.succs(1), &[2, 3]);
+        assert_eq!(cfg.succs(2), &[1]);
+        assert_eq!(cfg.preds(1), &[0, 2]);
+        assert_eq!(cfg.preds(3), &[1, 4]);
+        assert!(cfg.preds(4).is_empty());
+        assert_eq!(cfg.postorder(), vec![2, 3, 1, 0]);
+        assert_eq!(cfg.reverse_postorder(), vec![0, 1, 3, 2]);
+    }
+
+    #[test]
+    fn simplifying() {
+        let mut f = function(LOOP);
+        simplify(&mut f);
+        assert_eq!(
+            print::function(&f),
+            "function f(%0 i8) {\nL0:\n  jump L1\nL1:\n  branch %0, L2, L3\n\
+             L2:\n  branch %0, L1, L1\nL3:\n  return\n}\n"
+        );
+        let mut f = function("function f(%0 i8) {\nL0:\n  branch %0, L1, L2\nL1:\n  jump L2\nL2:\n  return\n}");
+        simplify(&mut f);
+        assert_eq!(f.blocks[0].term, Terminator::Branch(Operand::Temp(0), 1, 1));
+        assert_eq!(f.blocks.len(), 2);
+        let mut forever = function("function f() {\nL0:\n  jump L1\nL1:\n  jump L2\nL2:\n  jump L1\n}");
+        simplify(&mut forever);
+        assert_eq!(forever.blocks.len(), 2);
+    }
+}
@@ -0,0 +1,193 @@
+use ir::cfg::Cfg;
+use ir::ir::Label;
+
+/*
+The dominator tree of a control-flow graph and its dominance frontiers.
+
+A block dominates another when every path from the entry to the other goes
+through it; its immediate dominator is the closest of those. The dominance
+frontier of a block is where its dominance ends, the blocks it does not
+strictly dominate with a predecessor it does dominate, which is where SSA
+construction places phis.
+
+Immediate dominators are computed with the iterative algorithm of Cooper,
+Harvey and Kennedy, "A Simple, Fast Dominance Algorithm". Unreachable blocks
+have no dominator and dominate nothing.
+ */
+#[derive(Clone, Debug, PartialEq)]
+pub struct Dominators {
+    // `None` for the entry and unreachable blocks
+    idom: Vec<Option<Label>>,
+    children: Vec<Vec<Label>>,
+    frontiers: Vec<Vec<Label>>,
+}
+
+impl Dominators {
+    pub fn new(cfg: &Cfg) -> Dominators {
+        let n = cfg.len();
+        let order = cfg.reverse_postorder();
+        // The position of each reachable block in reverse postorder
+        let mut index = vec![usize::MAX; n];
+        for (i, &l) in order.iter().enumerate() {
+            index[l] = i;
+        }
+        let mut idom: Vec<Option<Label>> = vec![None; n];
+        if n == 0 {
+            return Dominators {
+                idom,
+                children: Vec::new(),
+                frontiers: Vec::new(),
+            };
+        }
+        idom[0] = Some(0);
+        let mut changed = true;
+        while changed {
+            changed = false;
+            for &l in &order[1..] {
+                let mut new = None;
+                for &p in cfg.preds(l) {
+                    if idom[p].is_none() {
+                        continue;
+                    }
+                    new = Some(match new {
+                        None => p,
+                        Some(mut other) => {
+                            let mut p = p;
+                            while p != other {
+                                while index[p] > index[other] {
+                                    p = idom[p].unwrap();
+                                }
+                                while index[other] > index[p] {
+                                    other = idom[other].unwrap();
+                                }
+                            }
+                            p
+                        }
+                    });
+                }
+                if new != idom[l] {
+                    idom[l] = new;
+                    changed = true;
+                }
+            }
+        }
+        idom[0] = None;
+
+        let mut children = vec![Vec::new(); n];
+        for (l, d) in idom.iter().enumerate() {
+            if let Some(d) = *d {
+                children[d].push(l);
+            }
+        }
+        let mut frontiers = vec![Vec::new(); n];
+        for &l in &order {
+            let preds = cfg.preds(l);
+            if preds.len() < 2 {
+                continue;
+            }
+            for &p in preds.iter().filter(|&&p| index[p] != usize::MAX) {
+                // Walk up from the predecessor to the block's dominator
+                let mut runner = Some(p);
+                while let Some(r) = runner {
+                    if Some(r) == idom[l] {
+                        break;
+                    }
+                    if !frontiers[r].contains(&l) {
+                        frontiers[r].push(l);
+                    }
+                    runner = idom[r];
+                }
+            }
+        }
+        Dominators {
+            idom,
+            children,
+            frontiers,
+        }
+    }
+
+    pub fn idom(&self, l: Label) -> Option<Label> {
+        self.idom[l]
+    }
+
+    // The blocks immediately dominated by `l`
+    pub fn children(&self, l: Label) -> &[Label] {
+        &self.children[l]
+    }
+
+    pub fn frontier(&self, l: Label) -> &[Label] {
+        &self.frontiers[l]
+    }
+
+    // Whether every path from the entry to `b` goes through `a`, as it does when they are the same
+    pub fn dominates(&self, a: Label, b: Label) -> bool {
+        if b != 0 && self.idom[b].is_none() {
+            return false;
+        }
+        let mut l = Some(b);
+        while let Some(x) = l {
+            if x == a {
+                return true;
+            }
+            l = self.idom[x];
+        }
+        false
+    }
+
+    // The reachable blocks, each before the blocks it dominates
+    pub fn preorder(&self) -> Vec<Label> {
+        let mut order = Vec::new();
+        if self.idom.is_empty() {
+            return order;
+        }
+        let mut stack = vec![0];
+        while let Some(l) = stack.pop() {
+            order.push(l);
+            stack.extend(self.children[l].iter().rev());
+        }
+        order
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use ir::parse::parse;
+
+    fn dominators(text: &str) -> Dominators {
+        let f = parse(0, text).unwrap().functions.remove(0);
+        Dominators::new(&Cfg::new(&f))
+    }
+
+    // An if inside a loop, then an unreachable block
+    const NESTED: &str = "function f(%0 i8) {\n\
+                          L0:\n  jump L1\n\
+                          L1:\n  branch %0, L2, L5\n\
+                          L2:\n  branch %0, L3, L4\n\
+                          L3:\n  jump L4\n\
+                          L4:\n  jump L1\n\
+                          L5:\n  return\n\
+                          L6:\n  jump L4\n\
+                          }";
+
+    #[test]
+    fn building_the_tree() {
+        let d = dominators(NESTED);
+        let idoms: Vec<Option<Label>> = (0..7).map(|l| d.idom(l)).collect();
+        assert_eq!(idoms, vec![None, Some(0), Some(1), Some(2), Some(2), Some(1), None]);
+        assert_eq!(d.children(1), &[2, 5]);
+        assert_eq!(d.preorder(), vec![0, 1, 2, 3, 4, 5]);
+        assert!(d.dominates(1, 4));
+        assert!(d.dominates(4, 4));
+        assert!(!d.dominates(3, 4));
+        assert!(!d.dominates(0, 6));
+    }
+
+    #[test]
+    fn finding_frontiers() {
+        let d = dominators(NESTED);
+        let frontiers: Vec<&[Label]> = (0..7).map(|l| d.frontier(l)).collect();
+        let expected: Vec<&[Label]> = vec![&[], &[1], &[1], &[4], &[1], &[], &[]];
+        assert_eq!(frontiers, expected);
+    }
+}
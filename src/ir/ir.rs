@@ -0,0 +1,367 @@
+use std::fmt;
+
+/*
+A three-address intermediate representation, lowered from the core language.
+
+A function is a list of basic blocks, the first its entry, each a sequence of
+instructions ending in a terminator which says where control goes next.
+Values live in temporaries, `%n`, of which the parameters are the first;
+every temporary has the size of the values it holds. Immediate operands are
+integers, `NULL` is 0, `true` 1.
+
+Memory is only touched by `load` and `store`, of a size, at an address
+computed by `elem` for array elements and pointer arithmetic for fields. The
+errors C0 defines are explicit instructions placed before the operation they
+guard
+
+    check_null p         SIGSEGV unless p is not NULL
+    check_bounds A, i    SIGSEGV unless 0 <= i < \length(A)
+    check_div a, b       SIGFPE if b is 0, or a is INT_MIN and b is -1
+    check_shift b        SIGFPE unless 0 <= b < 32
+    assert c, "message"  SIGABRT with the message unless c
+
+so that operations themselves never fail, apart from running out of memory.
+Arithmetic on `i32` wraps, comparisons produce an `i8` bool.
+
+In SSA form, see `ssa`, blocks begin with `phi` instructions choosing a value
+by the predecessor control came from; they appear nowhere else.
+ */
+pub type Temp = usize;
+
+// A block, by its index in `Function::blocks`
+pub type Label = usize;
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Hash)]
+pub enum Size {
+    // bool, char
+    I8,
+    // int
+    I32,
+    // pointers, arrays, strings
+    I64,
+}
+
+impl Size {
+    pub fn bytes(self) -> usize {
+        match self {
+            Size::I8 => 1,
+            Size::I32 => 4,
+            Size::I64 => 8,
+        }
+    }
+}
+
+impl fmt::Display for Size {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            Size::I8 => write!(f, "i8"),
+            Size::I32 => write!(f, "i32"),
+            Size::I64 => write!(f, "i64"),
+        }
+    }
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct Program {
+    pub externs: Vec<Extern>,
+    pub functions: Vec<Function>,
+}
+
+// A function of a library, its parameter and result sizes
+#[derive(Clone, Debug, PartialEq)]
+pub struct Extern {
+    pub name: String,
+    pub params: Vec<Size>,
+    pub ret: Option<Size>,
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct Function {
+    pub name: String,
+    pub params: Vec<Temp>,
+    // `None` for void functions
+    pub ret: Option<Size>,
+    pub temps: Vec<Size>,
+    pub blocks: Vec<Block>,
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct Block {
+    pub instrs: Vec<Instr>,
+    pub term: Terminator,
+}
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Hash)]
+pub enum Operand {
+    Temp(Temp),
+    Imm(i64),
+}
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Hash)]
+pub enum Unop {
+    // on i32
+    Neg,
+    BitNot,
+    // on i8 bools
+    Not,
+}
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Hash)]
+pub enum Binop {
+    Add,
+    Sub,
+    Mul,
+    Div,
+    Mod,
+    Shl,
+    Shr,
+    And,
+    Or,
+    Xor,
+    Lt,
+    Le,
+    Gt,
+    Ge,
+    Eq,
+    Ne,
+}
+
+impl Binop {
+    pub fn is_comparison(self) -> bool {
+        matches!(self, Binop::Lt | Binop::Le | Binop::Gt | Binop::Ge | Binop::Eq | Binop::Ne)
+    }
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub enum Instr {
+    // %d = a
+    Move(Temp, Operand),
+    // %d = neg a
+    Unop(Temp, Unop, Operand),
+    // %d = add i32 a, b, the size of the operands
+    Binop(Temp, Binop, Size, Operand, Operand),
+    // %d = call f(a, b), or call f(a, b) without a result
+    Call(Option<Temp>, String, Vec<Operand>),
+    // %d = load i32 a
+    Load(Temp, Size, Operand),
+    // store i32 a, v
+    Store(Size, Operand, Operand),
+    // %d = alloc 16, zeroed bytes
+    Alloc(Temp, usize),
+    // %d = alloc_array 4, n, of n zeroed elements of 4 bytes
+    AllocArray(Temp, usize, Operand),
+    // %d = length A
+    Length(Temp, Operand),
+    // %d = elem 4 A, i, the address of element i of elements of 4 bytes
+    Elem(Temp, usize, Operand, Operand),
+    // %d = string "text"
+    Str(Temp, String),
+    CheckNull(Operand),
+    CheckBounds(Operand, Operand),
+    CheckDiv(Operand, Operand),
+    CheckShift(Operand),
+    Assert(Operand, String),
+    // %d = phi L0 a, L2 b, the operand of the predecessor control came from
+    Phi(Temp, Vec<(Label, Operand)>),
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub enum Terminator {
+    Jump(Label),
+    // branch c, L1, L2 goes to L1 when c is true
+    Branch(Operand, Label, Label),
+    Return(Option<Operand>),
+    // Stops the program with the string `error` was called with
+    Error(Operand),
+}
+
+impl Terminator {
+    pub fn successors(&self) -> Vec<Label> {
+        match *self {
+            Terminator::Jump(l) => vec![l],
+            Terminator::Branch(_, yes, no) if yes == no => vec![yes],
+            Terminator::Branch(_, yes, no) => vec![yes, no],
+            Terminator::Return(_) | Terminator::Error(_) => Vec::new(),
+        }
+    }
+
+    pub fn successors_mut(&mut self) -> Vec<&mut Label> {
+        match *self {
+            Terminator::Jump(ref mut l) => vec![l],
+            Terminator::Branch(_, ref mut yes, ref mut no) => vec![yes, no],
+            Terminator::Return(_) | Terminator::Error(_) => Vec::new(),
+        }
+    }
+
+    pub fn uses(&self) -> Vec<Operand> {
+        match *self {
+            Terminator::Branch(c, _, _) | Terminator::Error(c) => vec![c],
+            Terminator::Return(Some(a)) => vec![a],
+            Terminator::Jump(_) | Terminator::Return(None) => Vec::new(),
+        }
+    }
+
+    pub fn uses_mut(&mut self) -> Vec<&mut Operand> {
+        match *self {
+            Terminator::Branch(ref mut c, _, _) | Terminator::Error(ref mut c) => vec![c],
+            Terminator::Return(Some(ref mut a)) => vec![a],
+            Terminator::Jump(_) | Terminator::Return(None) => Vec::new(),
+        }
+    }
+}
+
+impl Instr {
+    pub fn is_phi(&self) -> bool {
+        matches!(*self, Instr::Phi(..))
+    }
+
+    // The temporary written
+    pub fn def(&self) -> Option<Temp> {
+        match *self {
+            Instr::Move(d, _)
+            | Instr::Unop(d, _, _)
+            | Instr::Binop(d, _, _, _, _)
+            | Instr::Load(d, _, _)
+            | Instr::Alloc(d, _)
+            | Instr::AllocArray(d, _, _)
+            | Instr::Length(d, _)
+            | Instr::Elem(d, _, _, _)
+            | Instr::Str(d, _)
+            | Instr::Phi(d, _) => Some(d),
+            Instr::Call(d, _, _) => d,
+            Instr::Store(..)
+            | Instr::CheckNull(_)
+            | Instr::CheckBounds(..)
+            | Instr::CheckDiv(..)
+            | Instr::CheckShift(_)
+            | Instr::Assert(..) => None,
+        }
+    }
+
+    pub fn def_mut(&mut self) -> Option<&mut Temp> {
+        match *self {
+            Instr::Move(ref mut d, _)
+            | Instr::Unop(ref mut d, _, _)
+            | Instr::Binop(ref mut d, _, _, _, _)
+            | Instr::Load(ref mut d, _, _)
+            | Instr::Alloc(ref mut d, _)
+            | Instr::AllocArray(ref mut d, _, _)
+            | Instr::Length(ref mut d, _)
+            | Instr::Elem(ref mut d, _, _, _)
+            | Instr::Str(ref mut d, _)
+            | Instr::Phi(ref mut d, _) => Some(d),
+            Instr::Call(ref mut d, _, _) => d.as_mut(),
+            Instr::Store(..)
+            | Instr::CheckNull(_)
+            | Instr::CheckBounds(..)
+            | Instr::CheckDiv(..)
+            | Instr::CheckShift(_)
+            | Instr::Assert(..) => None,
+        }
+    }
+
+    // The operands read, in order
+    pub fn uses(&self) -> Vec<Operand> {
+        match *self {
+            Instr::Move(_, a)
+            | Instr::Unop(_, _, a)
+            | Instr::Load(_, _, a)
+            | Instr::AllocArray(_, _, a)
+            | Instr::Length(_, a)
+            | Instr::CheckNull(a)
+            | Instr::CheckShift(a)
+            | Instr::Assert(a, _) => vec![a],
+            Instr::Binop(_, _, _, a, b)
+            | Instr::Store(_, a, b)
+            | Instr::Elem(_, _, a, b)
+            | Instr::CheckBounds(a, b)
+            | Instr::CheckDiv(a, b) => vec![a, b],
+            Instr::Call(_, _, ref args) => args.clone(),
+            Instr::Phi(_, ref args) => args.iter().map(|&(_, a)| a).collect(),
+            Instr::Alloc(..) | Instr::Str(..) => Vec::new(),
+        }
+    }
+
+    pub fn uses_mut(&mut self) -> Vec<&mut Operand> {
+        match *self {
+            Instr::Move(_, ref mut a)
+            | Instr::Unop(_, _, ref mut a)
+            | Instr::Load(_, _, ref mut a)
+            | Instr::AllocArray(_, _, ref mut a)
+            | Instr::Length(_, ref mut a)
+            | Instr::CheckNull(ref mut a)
+            | Instr::CheckShift(ref mut a)
+            | Instr::Assert(ref mut a, _) => vec![a],
+            Instr::Binop(_, _, _, ref mut a, ref mut b)
+            | Instr::Store(_, ref mut a, ref mut b)
+            | Instr::Elem(_, _, ref mut a, ref mut b)
+            | Instr::CheckBounds(ref mut a, ref mut b)
+            | Instr::CheckDiv(ref mut a, ref mut b) => vec![a, b],
+            Instr::Call(_, _, ref mut args) => args.iter_mut().collect(),
+            Instr::Phi(_, ref mut args) => args.iter_mut().map(|&mut (_, ref mut a)| a).collect(),
+            Instr::Alloc(..) | Instr::Str(..) => Vec::new(),
+        }
+    }
+}
+
+impl Function {
+    pub fn temp(&mut self, size: Size) -> Temp {
+        self.temps.push(size);
+        self.temps.len() - 1
+    }
+
+    // Drops the temporaries which are never mentioned, renumbering the others in order
+    pub fn compact(&mut self) {
+        let mut used = vec![false; self.temps.len()];
+        for &p in &self.params {
+            used[p] = true;
+        }
+        for b in &self.blocks {
+            for i in &b.instrs {
+                if let Some(d) = i.def() {
+                    used[d] = true;
+                }
+            }
+            let uses = b.instrs.iter().flat_map(Instr::uses).chain(b.term.uses());
+            for a in uses {
+                if let Operand::Temp(t) = a {
+                    used[t] = true;
+                }
+            }
+        }
+        let mut renamed = vec![0; self.temps.len()];
+        let mut temps = Vec::new();
+        for (t, &size) in self.temps.iter().enumerate() {
+            if used[t] {
+                renamed[t] = temps.len();
+                temps.push(size);
+            }
+        }
+        self.temps = temps;
+        self.rename(|t| renamed[t]);
+    }
+
+    // Renames every temporary, parameters included
+    pub fn rename(&mut self, mut rename: impl FnMut(Temp) -> Temp) {
+        for p in &mut self.params {
+            *p = rename(*p);
+        }
+        for b in &mut self.blocks {
+            for i in &mut b.instrs {
+                if let Some(d) = i.def_mut() {
+                    *d = rename(*d);
+                }
+                for a in i.uses_mut() {
+                    if let Operand::Temp(ref mut t) = *a {
+                        *t = rename(*t);
+                    }
+                }
+            }
+            for a in b.term.uses_mut() {
+                if let Operand::Temp(ref mut t) = *a {
+                    *t = rename(*t);
+                }
+            }
+        }
+    }
+}
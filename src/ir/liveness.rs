@@ -0,0 +1,88 @@
+use ir::cfg::Cfg;
+use ir::ir::*;
+use std::collections::BTreeSet;
+
+/*
+The temporaries live on entry to and exit from each block, those which may
+be read before they are next written.
+
+A phi reads its operand at the end of the predecessor it names, not in its
+own block, and writes its temporary on entry, so an operand is live out of
+its predecessor and the phi's temporary is not live in.
+ */
+#[derive(Clone, Debug, PartialEq)]
+pub struct Liveness {
+    live_in: Vec<BTreeSet<Temp>>,
+    live_out: Vec<BTreeSet<Temp>>,
+}
+
+impl Liveness {
+    pub fn new(f: &Function, cfg: &Cfg) -> Liveness {
+        let n = f.blocks.len();
+        // What each block reads before writing, and writes
+        let mut uses = vec![BTreeSet::new(); n];
+        let mut defs = vec![BTreeSet::new(); n];
+        // What each block's phis read at the end of each predecessor
+        let mut phi_uses = vec![Vec::new(); n];
+        for (l, b) in f.blocks.iter().enumerate() {
+            for i in &b.instrs {
+                if let Instr::Phi(_, ref args) = *i {
+                    for &(p, a) in args {
+                        if let Operand::Temp(t) = a {
+                            phi_uses[l].push((p, t));
+                        }
+                    }
+                } else {
+                    for a in i.uses() {
+                        if let Operand::Temp(t) = a {
+                            if !defs[l].contains(&t) {
+                                uses[l].insert(t);
+                            }
+                        }
+                    }
+                }
+                if let Some(d) = i.def() {
+                    defs[l].insert(d);
+                }
+            }
+            for a in b.term.uses() {
+                if let Operand::Temp(t) = a {
+                    if !defs[l].contains(&t) {
+                        uses[l].insert(t);
+                    }
+                }
+            }
+        }
+
+        let mut live_in = vec![BTreeSet::new(); n];
+        let mut live_out = vec![BTreeSet::new(); n];
+        let order = cfg.postorder();
+        let mut changed = true;
+        while changed {
+            changed = false;
+            for &l in &order {
+                let mut out = BTreeSet::new();
+                for &s in cfg.succs(l) {
+                    out.extend(live_in[s].iter().cloned());
+                    out.extend(phi_uses[s].iter().filter(|&&(p, _)| p == l).map(|&(_, t)| t));
+                }
+                let mut live: BTreeSet<Temp> = out.difference(&defs[l]).cloned().collect();
+                live.extend(uses[l].iter().cloned());
+                if live != live_in[l] || out != live_out[l] {
+                    live_in[l] = live;
+                    live_out[l] = out;
+                    changed = true;
+                }
+            }
+        }
+        Liveness { live_in, live_out }
+    }
+
+    pub fn live_in(&self, l: Label) -> &BTreeSet<Temp> {
+        &self.live_in[l]
+    }
+
+    pub fn live_out(&self, l: Label) -> &BTreeSet<Temp> {
+        &self.live_out[l]
+    }
+}
@@ -0,0 +1,435 @@
+use ast::ast::{BinOp, UnOp};
+use elab::core::{self, Addr, AddrKind, Exp, ExpKind, Stmt, StmtKind};
+use ir::cfg;
+use ir::ir::*;
+use semant::structs::Layouts;
+use semant::types::Type;
+
+/*
+Lowers the core language to the IR. The variables of a function are its
+first temporaries, numbered as in the core, and every operation gets a
+fresh temporary for its result. Loops are laid out as
+
+    jump Lhead
+    Lhead: <condition> branch c, Lbody, Lexit
+    Lbody: <body> jump Lhead
+    Lexit:
+
+`break` jumps to the exit and `continue` to the head. Branches on constants
+become jumps, and code which cannot be reached is dropped, as are blocks
+which only jump to another.
+ */
+pub fn lower(p: &core::Program) -> Program {
+    let externs = p
+        .externs
+        .iter()
+        .map(|x| Extern {
+            name: x.name.clone(),
+            params: x.sig.params.iter().map(size).collect(),
+            ret: ret_size(&x.sig.ret),
+        })
+        .collect();
+    let functions = p.functions.iter().map(|f| function(f, &p.layouts)).collect();
+    Program { externs, functions }
+}
+
+// The size of a value of a small type
+pub fn size(ty: &Type) -> Size {
+    match *ty {
+        Type::Bool | Type::Char => Size::I8,
+        Type::Int => Size::I32,
+        _ => Size::I64,
+    }
+}
+
+pub fn ret_size(ty: &Type) -> Option<Size> {
+    match *ty {
+        Type::Void => None,
+        ref ty => Some(size(ty)),
+    }
+}
+
+fn function(f: &core::Function, layouts: &Layouts) -> Function {
+    let mut lowerer = Lowerer {
+        layouts,
+        vars: f.vars.len(),
+        temps: f.vars.iter().map(|v| size(&v.ty)).collect(),
+        blocks: Vec::new(),
+        current: None,
+        loops: Vec::new(),
+    };
+    let entry = lowerer.block();
+    lowerer.current = Some(entry);
+    lowerer.stmts(&f.body);
+    if lowerer.current.is_some() {
+        lowerer.end(Terminator::Return(None));
+    }
+    let blocks = lowerer
+        .blocks
+        .into_iter()
+        .map(|(instrs, term)| Block {
+            instrs,
+            term: term.unwrap_or(Terminator::Return(None)),
+        })
+        .collect();
+    let mut lowered = Function {
+        name: f.name.clone(),
+        params: f.params.clone(),
+        ret: ret_size(&f.ret),
+        temps: lowerer.temps,
+        blocks,
+    };
+    cfg::simplify(&mut lowered);
+    lowered.compact();
+    lowered
+}
+
+struct Lowerer<'a> {
+    layouts: &'a Layouts,
+    // The number of variables, the temporaries after them are fresh
+    vars: usize,
+    temps: Vec<Size>,
+    blocks: Vec<(Vec<Instr>, Option<Terminator>)>,
+    // The block being filled, `None` after a terminator
+    current: Option<Label>,
+    // The head and exit of each enclosing loop
+    loops: Vec<(Label, Label)>,
+}
+
+impl<'a> Lowerer<'a> {
+    fn block(&mut self) -> Label {
+        self.blocks.push((Vec::new(), None));
+        self.blocks.len() - 1
+    }
+
+    fn temp(&mut self, size: Size) -> Temp {
+        self.temps.push(size);
+        self.temps.len() - 1
+    }
+
+    // The block being filled, a new unreachable one after a terminator
+    fn current(&mut self) -> Label {
+        match self.current {
+            Some(l) => l,
+            None => {
+                let l = self.block();
+                self.current = Some(l);
+                l
+            }
+        }
+    }
+
+    fn emit(&mut self, i: Instr) {
+        let l = self.current();
+        self.blocks[l].0.push(i);
+    }
+
+    fn end(&mut self, term: Terminator) {
+        let l = self.current();
+        self.blocks[l].1 = Some(term);
+        self.current = None;
+    }
+
+    fn branch(&mut self, c: Operand, yes: Label, no: Label) {
+        match c {
+            Operand::Imm(0) => self.end(Terminator::Jump(no)),
+            Operand::Imm(_) => self.end(Terminator::Jump(yes)),
+            c => self.end(Terminator::Branch(c, yes, no)),
+        }
+    }
+
+    // Ends the current block, if it is reachable, with a jump to `l`
+    fn jump(&mut self, l: Label) {
+        if self.current.is_some() {
+            self.end(Terminator::Jump(l));
+        }
+    }
+
+    // Writes `a` to variable `v`, directly from the instruction computing it if it is fresh
+    fn assign(&mut self, v: Temp, a: Operand) {
+        if let (Operand::Temp(t), Some(l)) = (a, self.current) {
+            if t >= self.vars {
+                if let Some(last) = self.blocks[l].0.last_mut() {
+                    if last.def() == Some(t) {
+                        *last.def_mut().unwrap() = v;
+                        return;
+                    }
+                }
+            }
+        }
+        self.emit(Instr::Move(v, a));
+    }
+
+    fn stmts(&mut self, stmts: &[Stmt]) {
+        for s in stmts {
+            self.stmt(s);
+        }
+    }
+
+    fn stmt(&mut self, s: &Stmt) {
+        match s.kind {
+            StmtKind::Assign(v, ref e) => {
+                let a = self.exp(e);
+                self.assign(v, a);
+            }
+            StmtKind::Store(ref addr, ref e) => {
+                let a = self.addr(addr);
+                let v = self.exp(e);
+                self.emit(Instr::Store(size(&addr.ty), a, v));
+            }
+            StmtKind::Eval(ref e) => {
+                self.exp(e);
+            }
+            StmtKind::If(ref c, ref yes, ref no) => {
+                let c = self.exp(c);
+                let (then, join) = (self.block(), self.block());
+                let otherwise = if no.is_empty() { join } else { self.block() };
+                self.branch(c, then, otherwise);
+                self.current = Some(then);
+                self.stmts(yes);
+                self.jump(join);
+                if !no.is_empty() {
+                    self.current = Some(otherwise);
+                    self.stmts(no);
+                    self.jump(join);
+                }
+                self.current = Some(join);
+            }
+            StmtKind::While(ref c, ref body) => {
+                let head = self.block();
+                self.jump(head);
+                self.current = Some(head);
+                let c = self.exp(c);
+                let (inner, exit) = (self.block(), self.block());
+                self.branch(c, inner, exit);
+                self.loops.push((head, exit));
+                self.current = Some(inner);
+                self.stmts(body);
+                self.loops.pop();
+                self.jump(head);
+                self.current = Some(exit);
+            }
+            StmtKind::Break => {
+                let (_, exit) = *self.loops.last().expect("`break` is inside a loop");
+                self.end(Terminator::Jump(exit));
+            }
+            StmtKind::Continue => {
+                let (head, _) = *self.loops.last().expect("`continue` is inside a loop");
+                self.end(Terminator::Jump(head));
+            }
+            StmtKind::Return(ref e) => {
+                let a = e.as_ref().map(|e| self.exp(e));
+                self.end(Terminator::Return(a));
+            }
+            StmtKind::Assert(ref c, _, ref message) => {
+                let c = self.exp(c);
+                self.emit(Instr::Assert(c, message.clone()));
+            }
+            StmtKind::Error(ref e) => {
+                let a = self.exp(e);
+                self.end(Terminator::Error(a));
+            }
+        }
+    }
+
+    fn exp(&mut self, e: &Exp) -> Operand {
+        match e.kind {
+            ExpKind::Int(n) => Operand::Imm(i64::from(n)),
+            ExpKind::Bool(b) => Operand::Imm(i64::from(b)),
+            ExpKind::Char(c) => Operand::Imm(i64::from(c as u32)),
+            ExpKind::Null => Operand::Imm(0),
+            ExpKind::Str(ref s) => {
+                let t = self.temp(Size::I64);
+                self.emit(Instr::Str(t, s.clone()));
+                Operand::Temp(t)
+            }
+            ExpKind::Var(v) => Operand::Temp(v),
+            ExpKind::Unop(op, ref a) => {
+                let a = self.exp(a);
+                let op = match op {
+                    UnOp::Neg => Unop::Neg,
+                    UnOp::BitNot => Unop::BitNot,
+                    UnOp::Not => Unop::Not,
+                    UnOp::Deref => unreachable!("dereferences are loads"),
+                };
+                if let Operand::Imm(n) = a {
+                    return Operand::Imm(fold(op, n));
+                }
+                let t = self.temp(size(&e.ty));
+                self.emit(Instr::Unop(t, op, a));
+                Operand::Temp(t)
+            }
+            ExpKind::Binop(op, ref a, ref b) => {
+                let operands = size(&a.ty);
+                let (a, b) = (self.exp(a), self.exp(b));
+                let op = binop(op);
+                match op {
+                    Binop::Div | Binop::Mod => self.emit(Instr::CheckDiv(a, b)),
+                    Binop::Shl | Binop::Shr => self.emit(Instr::CheckShift(b)),
+                    _ => (),
+                }
+                let t = self.temp(size(&e.ty));
+                self.emit(Instr::Binop(t, op, operands, a, b));
+                Operand::Temp(t)
+            }
+            ExpKind::Call(ref f, ref args) => {
+                let args = args.iter().map(|a| self.exp(a)).collect();
+                if e.ty == Type::Void {
+                    self.emit(Instr::Call(None, f.clone(), args));
+                    return Operand::Imm(0);
+                }
+                let t = self.temp(size(&e.ty));
+                self.emit(Instr::Call(Some(t), f.clone(), args));
+                Operand::Temp(t)
+            }
+            ExpKind::Load(ref addr) => {
+                let a = self.addr(addr);
+                let t = self.temp(size(&addr.ty));
+                self.emit(Instr::Load(t, size(&addr.ty), a));
+                Operand::Temp(t)
+            }
+            ExpKind::AddrOf(ref addr) => self.addr(addr),
+            ExpKind::Alloc(ref ty) => {
+                let t = self.temp(Size::I64);
+                self.emit(Instr::Alloc(t, self.layouts.size_of(ty)));
+                Operand::Temp(t)
+            }
+            ExpKind::AllocArray(ref ty, ref n) => {
+                let n = self.exp(n);
+                let t = self.temp(Size::I64);
+                self.emit(Instr::AllocArray(t, self.layouts.size_of(ty), n));
+                Operand::Temp(t)
+            }
+            ExpKind::Length(ref a) => {
+                let a = self.exp(a);
+                let t = self.temp(Size::I32);
+                self.emit(Instr::Length(t, a));
+                Operand::Temp(t)
+            }
+        }
+    }
+
+    // The address of a location, checked
+    fn addr(&mut self, addr: &Addr) -> Operand {
+        match addr.kind {
+            AddrKind::Deref(ref p) => {
+                let p = self.exp(p);
+                self.emit(Instr::CheckNull(p));
+                p
+            }
+            AddrKind::Element(ref array, ref i) => {
+                let (array, i) = (self.exp(array), self.exp(i));
+                self.emit(Instr::CheckBounds(array, i));
+                let t = self.temp(Size::I64);
+                self.emit(Instr::Elem(t, self.layouts.size_of(&addr.ty), array, i));
+                Operand::Temp(t)
+            }
+            AddrKind::Field(ref base, _, offset) => {
+                let base = self.addr(base);
+                if offset == 0 {
+                    return base;
+                }
+                let t = self.temp(Size::I64);
+                self.emit(Instr::Binop(t, Binop::Add, Size::I64, base, Operand::Imm(offset as i64)));
+                Operand::Temp(t)
+            }
+        }
+    }
+}
+
+fn binop(op: BinOp) -> Binop {
+    match op {
+        BinOp::Mul => Binop::Mul,
+        BinOp::Div => Binop::Div,
+        BinOp::Mod => Binop::Mod,
+        BinOp::Add => Binop::Add,
+        BinOp::Sub => Binop::Sub,
+        BinOp::Shl => Binop::Shl,
+        BinOp::Shr => Binop::Shr,
+        BinOp::Lt => Binop::Lt,
+        BinOp::Le => Binop::Le,
+        BinOp::Ge => Binop::Ge,
+        BinOp::Gt => Binop::Gt,
+        BinOp::Eq => Binop::Eq,
+        BinOp::Ne => Binop::Ne,
+        BinOp::BitAnd => Binop::And,
+        BinOp::BitXor => Binop::Xor,
+        BinOp::BitOr => Binop::Or,
+        BinOp::And | BinOp::Or => unreachable!("`&&` and `||` are control flow in the core"),
+    }
+}
+
+// A unary operator applied to a constant, as 32 bit arithmetic
+fn fold(op: Unop, n: i64) -> i64 {
+    match op {
+        Unop::Neg => i64::from((n as i32).wrapping_neg()),
+        Unop::BitNot => i64::from(!(n as i32)),
+        Unop::Not => i64::from(n == 0),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use elab::tests::elaborated;
+    use ir::{parse, print};
+
+    fn lowered(text: &str) -> Program {
+        lower(&elaborated(text, true))
+    }
+
+    #[test]
+    fn lowering_functions() {
+        let cases = vec![
+            (
+                "int f(int x, int y) { return x / y + (x << 2); }",
+                "function f(%0 i32, %1 i32) -> i32 {\n  temps %2 i32, %3 i32, %4 i32\nL0:\n  check_div %0, %1\n  \
+                 %2 = div i32 %0, %1\n  check_shift 2\n  %3 = shl i32 %0, 2\n  %4 = add i32 %2, %3\n  return %4\n}\n",
+            ),
+            (
+                "int f(int n) { int i = 0; while (true) { if (i >= n) break; i++; } return i; }",
+                "function f(%0 i32) -> i32 {\n  temps %1 i32, %2 i8\nL0:\n  %1 = 0\n  jump L1\nL1:\n  \
+                 %2 = ge i32 %1, %0\n  branch %2, L2, L3\nL2:\n  return %1\nL3:\n  %1 = add i32 %1, 1\n  jump L1\n}\n",
+            ),
+            (
+                "struct p { int x; char c; struct p* next; };\n\
+                 char f(struct p* p) { p->next->c = 'a'; return p->c; }",
+                "function f(%0 i64) -> i8 {\n  temps %1 i64, %2 i64, %3 i64, %4 i64, %5 i8\nL0:\n  check_null %0\n  \
+                 %2 = add i64 %0, 8\n  %3 = load i64 %2\n  check_null %3\n  %1 = add i64 %3, 4\n  check_null %1\n  \
+                 store i8 %1, 97\n  check_null %0\n  %4 = add i64 %0, 4\n  %5 = load i8 %4\n  return %5\n}\n",
+            ),
+            (
+                "bool f(int[] A, int i) //@requires i < \\length(A);\n{ return i >= 0 && A[i] == 0; }",
+                "function f(%0 i64, %1 i32) -> i8 {\n  temps %2 i8, %3 i32, %4 i8, %5 i8, %6 i64, %7 i32\nL0:\n  \
+                 %3 = length %0\n  %4 = lt i32 %1, %3\n  assert %4, \"@requires `i < \\\\length(A)` failed\"\n  \
+                 %5 = ge i32 %1, 0\n  branch %5, L1, L3\nL1:\n  check_bounds %0, %1\n  %6 = elem 4 %0, %1\n  \
+                 %7 = load i32 %6\n  %2 = eq i32 %7, 0\n  jump L2\nL2:\n  return %2\nL3:\n  %2 = 0\n  jump L2\n}\n",
+            ),
+            (
+                "void f(string s) { error(s); }",
+                "function f(%0 i64) {\nL0:\n  error %0\n}\n",
+            ),
+        ];
+        for (source, expected) in cases {
+            let p = lowered(source);
+            assert_eq!(print::function(p.functions.last().unwrap()), expected, "{}", source);
+        }
+    }
+
+    #[test]
+    fn printing_and_parsing() {
+        let source = "int g(int x);\n\
+                      struct s { bool b; int[] A; };\n\
+                      int f(struct s* s, string t) {\n\
+                        int[] A = alloc_array(int, 4);\n\
+                        s->A = A;\n\
+                        for (int i = 0; i < 4; i++) { A[i] = g(-i) % 3; }\n\
+                        if (!s->b) { t = \"quote \\\" and\\n\"; }\n\
+                        return A[~2 + 5];\n\
+                      }";
+        let p = lowered(source);
+        let text = print::program(&p);
+        assert!(text.starts_with("extern g(i32) -> i32\n\nfunction f(%0 i64, %1 i64) -> i32 {\n"), "{}", text);
+        assert_eq!(parse::parse(0, &text), Ok(p), "{}", text);
+    }
+}
@@ -0,0 +1,9 @@
+pub mod ir;
+pub mod cfg;
+pub mod dom;
+pub mod liveness;
+pub mod lower;
+pub mod parse;
+pub mod print;
+pub mod ssa;
+pub mod verify;
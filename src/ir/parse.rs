@@ -0,0 +1,611 @@
+use ir::ir::*;
+use source::diagnostic::Diagnostic;
+use source::source_map::{FileId, Span};
+
+/*
+Reads the textual form of the IR written by `print`. Blocks must be labelled
+`L0`, `L1`, ... in order, and every temporary must be declared, as a
+parameter or in the `temps` line, before the first block.
+ */
+pub fn parse(file: FileId, text: &str) -> Result<Program, Diagnostic> {
+    let tokens = tokenize(file, text)?;
+    let mut parser = Parser {
+        file,
+        tokens,
+        pos: 0,
+        end: text.len(),
+        labels: Vec::new(),
+    };
+    parser.program()
+}
+
+#[derive(Clone, Debug, PartialEq)]
+enum Token {
+    Word(String),
+    Temp(Temp),
+    Int(i64),
+    Str(String),
+    Punct(&'static str),
+}
+
+fn tokenize(file: FileId, text: &str) -> Result<Vec<(Token, Span)>, Diagnostic> {
+    let chars: Vec<(usize, char)> = text.char_indices().collect();
+    let at = |i: usize| chars.get(i).map_or(text.len(), |&(pos, _)| pos);
+    let mut tokens = Vec::new();
+    let mut i = 0;
+    while i < chars.len() {
+        let (lo, c) = chars[i];
+        let start = i;
+        let token = match c {
+            _ if c.is_whitespace() => {
+                i += 1;
+                continue;
+            }
+            '%' => {
+                i += 1;
+                while i < chars.len() && chars[i].1.is_ascii_digit() {
+                    i += 1;
+                }
+                match text[at(start + 1)..at(i)].parse() {
+                    Ok(t) => Token::Temp(t),
+                    Err(_) => return Err(Diagnostic::error(Span::new(file, lo, at(i)), "expected a temporary number")),
+                }
+            }
+            '-' if chars.get(i + 1).map(|&(_, c)| c) == Some('>') => {
+                i += 2;
+                Token::Punct("->")
+            }
+            '-' | '0'..='9' => {
+                i += 1;
+                while i < chars.len() && chars[i].1.is_ascii_digit() {
+                    i += 1;
+                }
+                match text[lo..at(i)].parse() {
+                    Ok(n) => Token::Int(n),
+                    Err(_) => return Err(Diagnostic::error(Span::new(file, lo, at(i)), "expected a number")),
+                }
+            }
+            '(' | ')' | ',' | '=' | '{' | '}' | ':' => {
+                i += 1;
+                Token::Punct(match c {
+                    '(' => "(",
+                    ')' => ")",
+                    ',' => ",",
+                    '=' => "=",
+                    '{' => "{",
+                    '}' => "}",
+                    _ => ":",
+                })
+            }
+            '"' => {
+                i += 1;
+                let mut s = String::new();
+                loop {
+                    let c = match chars.get(i) {
+                        Some(&(_, c)) => c,
+                        None => return Err(Diagnostic::error(Span::new(file, lo, text.len()), "unterminated string")),
+                    };
+                    i += 1;
+                    match c {
+                        '"' => break,
+                        '\\' => {
+                            let escaped = chars.get(i).map(|&(_, c)| c);
+                            i += 1;
+                            s.push(match escaped {
+                                Some('n') => '\n',
+                                Some('t') => '\t',
+                                Some('v') => '\x0B',
+                                Some('b') => '\x08',
+                                Some('r') => '\r',
+                                Some('f') => '\x0C',
+                                Some('a') => '\x07',
+                                Some('0') => '\0',
+                                Some(c @ '\\') | Some(c @ '"') | Some(c @ '\'') => c,
+                                _ => {
+                                    let span = Span::new(file, at(i - 2), at(i));
+                                    return Err(Diagnostic::error(span, "unknown escape sequence"));
+                                }
+                            });
+                        }
+                        c => s.push(c),
+                    }
+                }
+                Token::Str(s)
+            }
+            _ if c.is_ascii_alphabetic() || c == '_' => {
+                while i < chars.len() && (chars[i].1.is_ascii_alphanumeric() || chars[i].1 == '_') {
+                    i += 1;
+                }
+                Token::Word(String::from(&text[lo..at(i)]))
+            }
+            _ => return Err(Diagnostic::error(Span::new(file, lo, at(i + 1)), format!("unexpected `{}`", c))),
+        };
+        tokens.push((token, Span::new(file, lo, at(i))));
+    }
+    Ok(tokens)
+}
+
+struct Parser {
+    file: FileId,
+    tokens: Vec<(Token, Span)>,
+    pos: usize,
+    end: usize,
+    // The labels branched to in the function being parsed
+    labels: Vec<(Label, Span)>,
+}
+
+type ParseResult<T> = Result<T, Diagnostic>;
+
+impl Parser {
+    fn peek(&self) -> Option<&Token> {
+        self.tokens.get(self.pos).map(|(t, _)| t)
+    }
+
+    fn span(&self) -> Span {
+        self.tokens.get(self.pos).map_or(Span::new(self.file, self.end, self.end), |&(_, span)| span)
+    }
+
+    fn error<T>(&self, message: impl Into<String>) -> ParseResult<T> {
+        Err(Diagnostic::error(self.span(), message))
+    }
+
+    fn eat(&mut self, punct: &str) -> bool {
+        match self.peek() {
+            Some(&Token::Punct(p)) if p == punct => {
+                self.pos += 1;
+                true
+            }
+            _ => false,
+        }
+    }
+
+    fn expect(&mut self, punct: &str) -> ParseResult<()> {
+        if self.eat(punct) {
+            Ok(())
+        } else {
+            self.error(format!("expected `{}`", punct))
+        }
+    }
+
+    fn word(&mut self) -> ParseResult<String> {
+        match self.peek() {
+            Some(Token::Word(w)) => {
+                let w = w.clone();
+                self.pos += 1;
+                Ok(w)
+            }
+            _ => self.error("expected a name"),
+        }
+    }
+
+    fn is_word(&self, w: &str) -> bool {
+        matches!(self.peek(), Some(Token::Word(ref x)) if x == w)
+    }
+
+    fn size(&mut self) -> ParseResult<Size> {
+        let size = match self.peek() {
+            Some(Token::Word(w)) if w == "i8" => Size::I8,
+            Some(Token::Word(w)) if w == "i32" => Size::I32,
+            Some(Token::Word(w)) if w == "i64" => Size::I64,
+            _ => return self.error("expected a size, `i8`, `i32` or `i64`"),
+        };
+        self.pos += 1;
+        Ok(size)
+    }
+
+    fn number(&mut self) -> ParseResult<i64> {
+        match self.peek() {
+            Some(&Token::Int(n)) => {
+                self.pos += 1;
+                Ok(n)
+            }
+            _ => self.error("expected a number"),
+        }
+    }
+
+    fn bytes(&mut self) -> ParseResult<usize> {
+        match self.number()? {
+            n if n >= 0 => Ok(n as usize),
+            _ => {
+                self.pos -= 1;
+                self.error("expected a number of bytes")
+            }
+        }
+    }
+
+    fn temp(&mut self) -> ParseResult<Temp> {
+        match self.peek() {
+            Some(&Token::Temp(t)) => {
+                self.pos += 1;
+                Ok(t)
+            }
+            _ => self.error("expected a temporary"),
+        }
+    }
+
+    fn operand(&mut self) -> ParseResult<Operand> {
+        match self.peek() {
+            Some(&Token::Temp(t)) => {
+                self.pos += 1;
+                Ok(Operand::Temp(t))
+            }
+            Some(&Token::Int(n)) => {
+                self.pos += 1;
+                Ok(Operand::Imm(n))
+            }
+            _ => self.error("expected an operand"),
+        }
+    }
+
+    fn at_operand(&self) -> bool {
+        matches!(self.peek(), Some(Token::Temp(_)) | Some(Token::Int(_)))
+    }
+
+    fn string(&mut self) -> ParseResult<String> {
+        match self.peek() {
+            Some(Token::Str(s)) => {
+                let s = s.clone();
+                self.pos += 1;
+                Ok(s)
+            }
+            _ => self.error("expected a string"),
+        }
+    }
+
+    // `L3`, remembered to check the block exists
+    fn label(&mut self) -> ParseResult<Label> {
+        let span = self.span();
+        let word = self.word()?;
+        match word.strip_prefix('L') {
+            Some(n) if !n.is_empty() && n.bytes().all(|b| b.is_ascii_digit()) => {
+                let l = n.parse().map_err(|_| Diagnostic::error(span, "expected a label"))?;
+                self.labels.push((l, span));
+                Ok(l)
+            }
+            _ => Err(Diagnostic::error(span, "expected a label")),
+        }
+    }
+
+    fn program(&mut self) -> ParseResult<Program> {
+        let mut program = Program {
+            externs: Vec::new(),
+            functions: Vec::new(),
+        };
+        while self.peek().is_some() {
+            if self.is_word("extern") {
+                self.pos += 1;
+                program.externs.push(self.extern_fn()?);
+            } else if self.is_word("function") {
+                self.pos += 1;
+                program.functions.push(self.function()?);
+            } else {
+                return self.error("expected `extern` or `function`");
+            }
+        }
+        Ok(program)
+    }
+
+    fn ret(&mut self) -> ParseResult<Option<Size>> {
+        if self.eat("->") {
+            self.size().map(Some)
+        } else {
+            Ok(None)
+        }
+    }
+
+    fn extern_fn(&mut self) -> ParseResult<Extern> {
+        let name = self.word()?;
+        self.expect("(")?;
+        let mut params = Vec::new();
+        while !self.eat(")") {
+            if !params.is_empty() {
+                self.expect(",")?;
+            }
+            params.push(self.size()?);
+        }
+        Ok(Extern {
+            name,
+            params,
+            ret: self.ret()?,
+        })
+    }
+
+    // `%3 i32`, which must not have been declared before
+    fn declare(&mut self, temps: &mut Vec<Option<Size>>) -> ParseResult<Temp> {
+        let span = self.span();
+        let t = self.temp()?;
+        let size = self.size()?;
+        if temps.len() <= t {
+            temps.resize(t + 1, None);
+        }
+        if temps[t].is_some() {
+            return Err(Diagnostic::error(span, format!("%{} is declared twice", t)));
+        }
+        temps[t] = Some(size);
+        Ok(t)
+    }
+
+    fn function(&mut self) -> ParseResult<Function> {
+        let name = self.word()?;
+        let mut temps = Vec::new();
+        self.expect("(")?;
+        let mut params = Vec::new();
+        while !self.eat(")") {
+            if !params.is_empty() {
+                self.expect(",")?;
+            }
+            params.push(self.declare(&mut temps)?);
+        }
+        let ret = self.ret()?;
+        self.expect("{")?;
+        if self.is_word("temps") {
+            self.pos += 1;
+            loop {
+                self.declare(&mut temps)?;
+                if !self.eat(",") {
+                    break;
+                }
+            }
+        }
+        let start = self.pos;
+        self.labels.clear();
+        let mut blocks = Vec::new();
+        while !self.eat("}") {
+            if self.peek().is_none() {
+                return self.error("expected `}`");
+            }
+            let span = self.span();
+            let l = self.label()?;
+            self.labels.pop();
+            if l != blocks.len() {
+                return Err(Diagnostic::error(span, format!("expected label L{}", blocks.len())));
+            }
+            self.expect(":")?;
+            blocks.push(self.block()?);
+        }
+        if blocks.is_empty() {
+            return self.error("a function needs a block");
+        }
+        let end = self.pos;
+
+        // Every temporary and label used must exist
+        for (token, span) in &self.tokens[start..end] {
+            match *token {
+                Token::Temp(t) if temps.get(t).cloned().flatten().is_none() => {
+                    return Err(Diagnostic::error(*span, format!("%{} is not declared", t)));
+                }
+                _ => (),
+            }
+        }
+        if let Some(&(l, span)) = self.labels.iter().find(|&&(l, _)| l >= blocks.len()) {
+            return Err(Diagnostic::error(span, format!("there is no block L{}", l)));
+        }
+        let temps = temps.into_iter().map(|size| size.unwrap_or(Size::I64)).collect();
+        Ok(Function {
+            name,
+            params,
+            ret,
+            temps,
+            blocks,
+        })
+    }
+
+    fn block(&mut self) -> ParseResult<Block> {
+        let mut instrs = Vec::new();
+        loop {
+            match self.peek() {
+                Some(Token::Word(w)) if ["jump", "branch", "return", "error"].contains(&w.as_str()) => {
+                    let term = self.terminator()?;
+                    return Ok(Block { instrs, term });
+                }
+                None | Some(Token::Punct(_)) => return self.error("expected a terminator"),
+                _ if self.tokens.get(self.pos + 1).map(|(t, _)| t) == Some(&Token::Punct(":")) => {
+                    return self.error("expected a terminator");
+                }
+                _ => instrs.push(self.instr()?),
+            }
+        }
+    }
+
+    fn terminator(&mut self) -> ParseResult<Terminator> {
+        let term = match self.word()?.as_str() {
+            "jump" => Terminator::Jump(self.label()?),
+            "branch" => {
+                let c = self.operand()?;
+                self.expect(",")?;
+                let yes = self.label()?;
+                self.expect(",")?;
+                Terminator::Branch(c, yes, self.label()?)
+            }
+            "return" if self.at_operand() => Terminator::Return(Some(self.operand()?)),
+            "return" => Terminator::Return(None),
+            _ => Terminator::Error(self.operand()?),
+        };
+        Ok(term)
+    }
+
+    fn call(&mut self, d: Option<Temp>) -> ParseResult<Instr> {
+        let name = self.word()?;
+        self.expect("(")?;
+        let mut args = Vec::new();
+        while !self.eat(")") {
+            if !args.is_empty() {
+                self.expect(",")?;
+            }
+            args.push(self.operand()?);
+        }
+        Ok(Instr::Call(d, name, args))
+    }
+
+    // `a, b`
+    fn pair(&mut self) -> ParseResult<(Operand, Operand)> {
+        let a = self.operand()?;
+        self.expect(",")?;
+        Ok((a, self.operand()?))
+    }
+
+    fn instr(&mut self) -> ParseResult<Instr> {
+        if let Some(&Token::Temp(d)) = self.peek() {
+            self.pos += 1;
+            self.expect("=")?;
+            return self.definition(d);
+        }
+        let span = self.span();
+        let instr = match self.word()?.as_str() {
+            "call" => self.call(None)?,
+            "store" => {
+                let size = self.size()?;
+                let (a, v) = self.pair()?;
+                Instr::Store(size, a, v)
+            }
+            "check_null" => Instr::CheckNull(self.operand()?),
+            "check_bounds" => {
+                let (a, i) = self.pair()?;
+                Instr::CheckBounds(a, i)
+            }
+            "check_div" => {
+                let (a, b) = self.pair()?;
+                Instr::CheckDiv(a, b)
+            }
+            "check_shift" => Instr::CheckShift(self.operand()?),
+            "assert" => {
+                let c = self.operand()?;
+                self.expect(",")?;
+                Instr::Assert(c, self.string()?)
+            }
+            w => return Err(Diagnostic::error(span, format!("unknown instruction `{}`", w))),
+        };
+        Ok(instr)
+    }
+
+    // What follows `%d =`
+    fn definition(&mut self, d: Temp) -> ParseResult<Instr> {
+        if self.at_operand() {
+            return Ok(Instr::Move(d, self.operand()?));
+        }
+        let span = self.span();
+        let word = self.word()?;
+        let unop = match word.as_str() {
+            "neg" => Some(Unop::Neg),
+            "bitnot" => Some(Unop::BitNot),
+            "not" => Some(Unop::Not),
+            _ => None,
+        };
+        if let Some(op) = unop {
+            return Ok(Instr::Unop(d, op, self.operand()?));
+        }
+        if let Some(op) = binop(&word) {
+            let size = self.size()?;
+            let (a, b) = self.pair()?;
+            return Ok(Instr::Binop(d, op, size, a, b));
+        }
+        let instr = match word.as_str() {
+            "call" => self.call(Some(d))?,
+            "load" => {
+                let size = self.size()?;
+                Instr::Load(d, size, self.operand()?)
+            }
+            "alloc" => Instr::Alloc(d, self.bytes()?),
+            "alloc_array" => {
+                let bytes = self.bytes()?;
+                self.expect(",")?;
+                Instr::AllocArray(d, bytes, self.operand()?)
+            }
+            "length" => Instr::Length(d, self.operand()?),
+            "elem" => {
+                let bytes = self.bytes()?;
+                let (a, i) = self.pair()?;
+                Instr::Elem(d, bytes, a, i)
+            }
+            "string" => Instr::Str(d, self.string()?),
+            "phi" => {
+                let mut args = Vec::new();
+                loop {
+                    let l = self.label()?;
+                    args.push((l, self.operand()?));
+                    if !self.eat(",") {
+                        break;
+                    }
+                }
+                Instr::Phi(d, args)
+            }
+            w => return Err(Diagnostic::error(span, format!("unknown instruction `{}`", w))),
+        };
+        Ok(instr)
+    }
+}
+
+fn binop(word: &str) -> Option<Binop> {
+    let op = match word {
+        "add" => Binop::Add,
+        "sub" => Binop::Sub,
+        "mul" => Binop::Mul,
+        "div" => Binop::Div,
+        "mod" => Binop::Mod,
+        "shl" => Binop::Shl,
+        "shr" => Binop::Shr,
+        "and" => Binop::And,
+        "or" => Binop::Or,
+        "xor" => Binop::Xor,
+        "lt" => Binop::Lt,
+        "le" => Binop::Le,
+        "gt" => Binop::Gt,
+        "ge" => Binop::Ge,
+        "eq" => Binop::Eq,
+        "ne" => Binop::Ne,
+        _ => return None,
+    };
+    Some(op)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn error(text: &str) -> (String, usize) {
+        let d = parse(0, text).unwrap_err();
+        (d.message, d.span.lo)
+    }
+
+    #[test]
+    fn parsing_instructions() {
+        let text = "extern print(i64)\n\
+                    function f(%0 i64, %1 i32) -> i8 {\n  temps %2 i8, %3 i64\n\
+                    L0:\n  %2 = not 1\n  %3 = string \"a\\tb\"\n\
+                    call print(%3)\n  store i32 %0, -5\n  branch %2, L1, L1\n\
+                    L1:\n  return %2\n}\n";
+        let p = parse(0, text).unwrap();
+        assert_eq!(p.externs, vec![Extern { name: String::from("print"), params: vec![Size::I64], ret: None }]);
+        let f = &p.functions[0];
+        assert_eq!(f.temps, vec![Size::I64, Size::I32, Size::I8, Size::I64]);
+        assert_eq!(
+            f.blocks[0].instrs,
+            vec![
+                Instr::Unop(2, Unop::Not, Operand::Imm(1)),
+                Instr::Str(3, String::from("a\tb")),
+                Instr::Call(None, String::from("print"), vec![Operand::Temp(3)]),
+                Instr::Store(Size::I32, Operand::Temp(0), Operand::Imm(-5)),
+            ]
+        );
+        assert_eq!(f.blocks[1].term, Terminator::Return(Some(Operand::Temp(2))));
+    }
+
+    #[test]
+    fn reporting_errors() {
+        let cases = vec![
+            ("function f() {\nL0:\n  return %1\n}", ("%1 is not declared", 28)),
+            ("function f() {\nL0:\n  jump L2\n}", ("there is no block L2", 26)),
+            ("function f() {\nL1:\n  return\n}", ("expected label L0", 15)),
+            ("function f(%0 i32, %0 i8) {\nL0:\n  return\n}", ("%0 is declared twice", 19)),
+            ("function f(%0 i16) {", ("expected a size, `i8`, `i32` or `i64`", 14)),
+            ("function f() {\nL0:\n  %0 = frob 1\n}", ("unknown instruction `frob`", 26)),
+            ("function f() {\nL0:\n  return\n", ("expected `}`", 28)),
+            ("function f() {\nL0:\n  %0 = 1\n}", ("expected a terminator", 28)),
+            ("function f() {\nL0:\n  %0 = 1\nL1:\n  return\n}", ("expected a terminator", 28)),
+            ("func f()", ("expected `extern` or `function`", 0)),
+        ];
+        for (text, (message, lo)) in cases {
+            assert_eq!(error(text), (String::from(message), lo), "{}", text);
+        }
+    }
+}
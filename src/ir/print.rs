@@ -0,0 +1,160 @@
+use ir::ir::*;
+use lexer::lexer::escape_str;
+use std::fmt::{self, Write};
+
+/*
+The textual form of the IR, which `parse` reads back
+
+    extern printint(i32)
+
+    function sum(%0 i64, %1 i32) -> i32 {
+      temps %2 i32, %3 i32, %4 i8, %5 i64, %6 i32
+    L0:
+      %2 = 0
+      %3 = 0
+      jump L1
+    L1:
+      %4 = lt i32 %3, %1
+      branch %4, L2, L3
+    L2:
+      check_bounds %0, %3
+      %5 = elem 4 %0, %3
+      %6 = load i32 %5
+      %2 = add i32 %2, %6
+      %3 = add i32 %3, 1
+      jump L1
+    L3:
+      return %2
+    }
+
+Blocks are labelled by their index, in order.
+ */
+pub fn program(p: &Program) -> String {
+    let mut out = String::new();
+    for x in &p.externs {
+        let params: Vec<String> = x.params.iter().map(Size::to_string).collect();
+        let _ = writeln!(out, "extern {}({}){}", x.name, params.join(", "), ret(x.ret));
+    }
+    for (i, f) in p.functions.iter().enumerate() {
+        if i > 0 || !p.externs.is_empty() {
+            out.push('\n');
+        }
+        out.push_str(&function(f));
+    }
+    out
+}
+
+pub fn function(f: &Function) -> String {
+    let mut out = String::new();
+    let params: Vec<String> = f.params.iter().map(|&t| format!("%{} {}", t, f.temps[t])).collect();
+    let _ = writeln!(out, "function {}({}){} {{", f.name, params.join(", "), ret(f.ret));
+    let temps: Vec<String> = (0..f.temps.len())
+        .filter(|t| !f.params.contains(t))
+        .map(|t| format!("%{} {}", t, f.temps[t]))
+        .collect();
+    if !temps.is_empty() {
+        let _ = writeln!(out, "  temps {}", temps.join(", "));
+    }
+    for (l, b) in f.blocks.iter().enumerate() {
+        let _ = writeln!(out, "L{}:", l);
+        for i in &b.instrs {
+            let _ = writeln!(out, "  {}", i);
+        }
+        let _ = writeln!(out, "  {}", b.term);
+    }
+    out.push_str("}\n");
+    out
+}
+
+fn ret(size: Option<Size>) -> String {
+    size.map(|s| format!(" -> {}", s)).unwrap_or_default()
+}
+
+impl fmt::Display for Operand {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            Operand::Temp(t) => write!(f, "%{}", t),
+            Operand::Imm(n) => write!(f, "{}", n),
+        }
+    }
+}
+
+impl fmt::Display for Unop {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let s = match *self {
+            Unop::Neg => "neg",
+            Unop::BitNot => "bitnot",
+            Unop::Not => "not",
+        };
+        write!(f, "{}", s)
+    }
+}
+
+impl fmt::Display for Binop {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let s = match *self {
+            Binop::Add => "add",
+            Binop::Sub => "sub",
+            Binop::Mul => "mul",
+            Binop::Div => "div",
+            Binop::Mod => "mod",
+            Binop::Shl => "shl",
+            Binop::Shr => "shr",
+            Binop::And => "and",
+            Binop::Or => "or",
+            Binop::Xor => "xor",
+            Binop::Lt => "lt",
+            Binop::Le => "le",
+            Binop::Gt => "gt",
+            Binop::Ge => "ge",
+            Binop::Eq => "eq",
+            Binop::Ne => "ne",
+        };
+        write!(f, "{}", s)
+    }
+}
+
+fn operands(args: &[Operand]) -> String {
+    let args: Vec<String> = args.iter().map(Operand::to_string).collect();
+    args.join(", ")
+}
+
+impl fmt::Display for Instr {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            Instr::Move(d, a) => write!(f, "%{} = {}", d, a),
+            Instr::Unop(d, op, a) => write!(f, "%{} = {} {}", d, op, a),
+            Instr::Binop(d, op, size, a, b) => write!(f, "%{} = {} {} {}, {}", d, op, size, a, b),
+            Instr::Call(Some(d), ref name, ref args) => write!(f, "%{} = call {}({})", d, name, operands(args)),
+            Instr::Call(None, ref name, ref args) => write!(f, "call {}({})", name, operands(args)),
+            Instr::Load(d, size, a) => write!(f, "%{} = load {} {}", d, size, a),
+            Instr::Store(size, a, v) => write!(f, "store {} {}, {}", size, a, v),
+            Instr::Alloc(d, bytes) => write!(f, "%{} = alloc {}", d, bytes),
+            Instr::AllocArray(d, bytes, n) => write!(f, "%{} = alloc_array {}, {}", d, bytes, n),
+            Instr::Length(d, a) => write!(f, "%{} = length {}", d, a),
+            Instr::Elem(d, bytes, a, i) => write!(f, "%{} = elem {} {}, {}", d, bytes, a, i),
+            Instr::Str(d, ref s) => write!(f, "%{} = string \"{}\"", d, escape_str(s)),
+            Instr::CheckNull(a) => write!(f, "check_null {}", a),
+            Instr::CheckBounds(a, i) => write!(f, "check_bounds {}, {}", a, i),
+            Instr::CheckDiv(a, b) => write!(f, "check_div {}, {}", a, b),
+            Instr::CheckShift(b) => write!(f, "check_shift {}", b),
+            Instr::Assert(c, ref message) => write!(f, "assert {}, \"{}\"", c, escape_str(message)),
+            Instr::Phi(d, ref args) => {
+                let args: Vec<String> = args.iter().map(|&(l, a)| format!("L{} {}", l, a)).collect();
+                write!(f, "%{} = phi {}", d, args.join(", "))
+            }
+        }
+    }
+}
+
+impl fmt::Display for Terminator {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            Terminator::Jump(l) => write!(f, "jump L{}", l),
+            Terminator::Branch(c, yes, no) => write!(f, "branch {}, L{}, L{}", c, yes, no),
+            Terminator::Return(None) => write!(f, "return"),
+            Terminator::Return(Some(a)) => write!(f, "return {}", a),
+            Terminator::Error(a) => write!(f, "error {}", a),
+        }
+    }
+}
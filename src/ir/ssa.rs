@@ -0,0 +1,385 @@
+use ir::cfg::{self, Cfg};
+use ir::dom::Dominators;
+use ir::ir::*;
+use ir::liveness::Liveness;
+use std::mem;
+
+/*
+Conversion to and from static single assignment form, where every temporary
+is written exactly once and joins of values are explicit phis.
+
+Construction follows Cytron et al., "Efficiently Computing Static Single
+Assignment Form and the Control Dependence Graph": a temporary written in a
+block needs a phi in each block of that block's iterated dominance frontier,
+then a walk of the dominator tree renames each write to a fresh temporary
+and each read to the write reaching it. Phis are only placed where their
+temporary is live, so the form is pruned. The first write of a temporary
+keeps its number, the others get new ones.
+
+Destruction replaces the phis of a block with copies at the end of its
+predecessors. An edge from a block with several successors to one with
+several predecessors is split first, so the copies run on that edge only.
+The copies of an edge happen at once, in parallel, so they are ordered with
+a temporary to break cycles like `%1, %2 = %2, %1`.
+ */
+pub fn construct(f: &mut Function) {
+    cfg::remove_unreachable(f);
+    separate_entry(f);
+    let cfg = Cfg::new(f);
+    let dom = Dominators::new(&cfg);
+    insert_phis(f, &cfg, &dom);
+    rename(f, &cfg, &dom);
+}
+
+// Gives the function an entry no block jumps back to, for parameters to be defined in
+fn separate_entry(f: &mut Function) {
+    if f.blocks.iter().all(|b| !b.term.successors().contains(&0)) {
+        return;
+    }
+    let moved = f.blocks.len();
+    for b in &mut f.blocks {
+        for s in b.term.successors_mut() {
+            if *s == 0 {
+                *s = moved;
+            }
+        }
+    }
+    let entry = Block {
+        instrs: Vec::new(),
+        term: Terminator::Jump(moved),
+    };
+    let old = mem::replace(&mut f.blocks[0], entry);
+    f.blocks.push(old);
+}
+
+fn insert_phis(f: &mut Function, cfg: &Cfg, dom: &Dominators) {
+    let live = Liveness::new(f, cfg);
+    let mut sites = vec![Vec::new(); f.temps.len()];
+    for (l, b) in f.blocks.iter().enumerate() {
+        for d in b.instrs.iter().filter_map(Instr::def) {
+            if !sites[d].contains(&l) {
+                sites[d].push(l);
+            }
+        }
+    }
+    // The temporaries given phis in each block, in order
+    let mut phis = vec![Vec::new(); f.blocks.len()];
+    for (t, mut work) in sites.into_iter().enumerate() {
+        let mut defined: Vec<Label> = work.clone();
+        while let Some(l) = work.pop() {
+            for &d in dom.frontier(l) {
+                if phis[d].contains(&t) || !live.live_in(d).contains(&t) {
+                    continue;
+                }
+                phis[d].push(t);
+                if !defined.contains(&d) {
+                    defined.push(d);
+                    work.push(d);
+                }
+            }
+        }
+    }
+    for (l, temps) in phis.into_iter().enumerate() {
+        let mut instrs: Vec<Instr> = temps
+            .into_iter()
+            .map(|t| Instr::Phi(t, cfg.preds(l).iter().map(|&p| (p, Operand::Temp(t))).collect()))
+            .collect();
+        instrs.append(&mut f.blocks[l].instrs);
+        f.blocks[l].instrs = instrs;
+    }
+}
+
+enum Visit {
+    Enter(Label),
+    // Undoes the writes of a block once those it dominates are renamed
+    Leave(Vec<Temp>),
+}
+
+fn rename(f: &mut Function, cfg: &Cfg, dom: &Dominators) {
+    let originals = f.temps.len();
+    // The current name of each original temporary, innermost last
+    let mut names: Vec<Vec<Temp>> = vec![Vec::new(); originals];
+    // Whether the original number has been given to a write
+    let mut taken = vec![false; originals];
+    for &p in &f.params {
+        names[p].push(p);
+        taken[p] = true;
+    }
+    let current = |names: &Vec<Vec<Temp>>, a: &mut Operand| {
+        if let Operand::Temp(t) = *a {
+            // A temporary read before any write, which C0 rules out on paths that happen
+            *a = names[t].last().map_or(Operand::Imm(0), |&n| Operand::Temp(n));
+        }
+    };
+
+    let mut stack = vec![Visit::Enter(0)];
+    while let Some(visit) = stack.pop() {
+        let l = match visit {
+            Visit::Enter(l) => l,
+            Visit::Leave(written) => {
+                for t in written {
+                    names[t].pop();
+                }
+                continue;
+            }
+        };
+        let mut written = Vec::new();
+        let mut instrs = mem::take(&mut f.blocks[l].instrs);
+        for i in &mut instrs {
+            if !i.is_phi() {
+                for a in i.uses_mut() {
+                    current(&names, a);
+                }
+            }
+            if let Some(d) = i.def_mut() {
+                let t = *d;
+                let n = if taken[t] { f.temp(f.temps[t]) } else { t };
+                taken[t] = true;
+                names[t].push(n);
+                written.push(t);
+                *d = n;
+            }
+        }
+        f.blocks[l].instrs = instrs;
+        for a in f.blocks[l].term.uses_mut() {
+            current(&names, a);
+        }
+        for &s in cfg.succs(l) {
+            for i in &mut f.blocks[s].instrs {
+                if let Instr::Phi(_, ref mut args) = *i {
+                    for &mut (p, ref mut a) in args.iter_mut() {
+                        if p == l {
+                            current(&names, a);
+                        }
+                    }
+                }
+            }
+        }
+        stack.push(Visit::Leave(written));
+        stack.extend(dom.children(l).iter().rev().map(|&c| Visit::Enter(c)));
+    }
+}
+
+pub fn destruct(f: &mut Function) {
+    let cfg = Cfg::new(f);
+    for l in 0..cfg.len() {
+        let phis: Vec<(Temp, Vec<(Label, Operand)>)> = f.blocks[l]
+            .instrs
+            .iter()
+            .filter_map(|i| match *i {
+                Instr::Phi(d, ref args) => Some((d, args.clone())),
+                _ => None,
+            })
+            .collect();
+        if phis.is_empty() {
+            continue;
+        }
+        f.blocks[l].instrs.retain(|i| !i.is_phi());
+        for &p in cfg.preds(l) {
+            let copies: Vec<(Temp, Operand)> = phis
+                .iter()
+                .filter_map(|&(d, ref args)| args.iter().find(|&&(q, _)| q == p).map(|&(_, a)| (d, a)))
+                .collect();
+            let moves = sequentialize(&copies, &mut f.temps);
+            if cfg.succs(p).len() == 1 {
+                let b = &mut f.blocks[p];
+                b.term = Terminator::Jump(l);
+                b.instrs.extend(moves);
+            } else {
+                let split = f.blocks.len();
+                for s in f.blocks[p].term.successors_mut() {
+                    if *s == l {
+                        *s = split;
+                    }
+                }
+                f.blocks.push(Block {
+                    instrs: moves,
+                    term: Terminator::Jump(l),
+                });
+            }
+        }
+    }
+}
+
+/*
+Orders copies which happen at once, `%d = a` for distinct `%d`, into moves.
+A copy is made once nothing left reads its destination. When only cycles
+remain, the destination of one is saved to a new temporary, which the
+others then read instead.
+ */
+pub fn sequentialize(copies: &[(Temp, Operand)], temps: &mut Vec<Size>) -> Vec<Instr> {
+    let mut pending: Vec<(Temp, Operand)> = copies.iter().cloned().filter(|&(d, a)| a != Operand::Temp(d)).collect();
+    let mut moves = Vec::new();
+    while !pending.is_empty() {
+        let free = pending.iter().position(|&(d, _)| pending.iter().all(|&(_, a)| a != Operand::Temp(d)));
+        match free {
+            Some(i) => {
+                let (d, a) = pending.remove(i);
+                moves.push(Instr::Move(d, a));
+            }
+            None => {
+                let d = pending[0].0;
+                temps.push(temps[d]);
+                let saved = temps.len() - 1;
+                moves.push(Instr::Move(saved, Operand::Temp(d)));
+                for &mut (_, ref mut a) in &mut pending {
+                    if *a == Operand::Temp(d) {
+                        *a = Operand::Temp(saved);
+                    }
+                }
+            }
+        }
+    }
+    moves
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use elab::tests::elaborated;
+    use ir::lower::lower;
+    use ir::parse::parse;
+    use ir::print;
+    use ir::verify;
+    use std::collections::HashMap;
+
+    fn function(text: &str) -> Function {
+        parse(0, text).unwrap().functions.remove(0)
+    }
+
+    const SUM: &str = "function sum(%0 i32) -> i32 {\n  temps %1 i32, %2 i32, %3 i8\n\
+                       L0:\n  %1 = 0\n  %2 = 0\n  jump L1\n\
+                       L1:\n  %3 = lt i32 %2, %0\n  branch %3, L2, L3\n\
+                       L2:\n  %1 = add i32 %1, %2\n  %2 = add i32 %2, 1\n  jump L1\n\
+                       L3:\n  return %1\n\
+                       }";
+
+    #[test]
+    fn constructing() {
+        let mut f = function(SUM);
+        construct(&mut f);
+        assert_eq!(verify::ssa(&f), Ok(()));
+        let expected = "function sum(%0 i32) -> i32 {\n  temps %1 i32, %2 i32, %3 i8, %4 i32, %5 i32, %6 i32, %7 i32\n\
+                        L0:\n  %1 = 0\n  %2 = 0\n  jump L1\n\
+                        L1:\n  %4 = phi L0 %1, L2 %6\n  %5 = phi L0 %2, L2 %7\n  %3 = lt i32 %5, %0\n\
+                        \x20 branch %3, L2, L3\n\
+                        L2:\n  %6 = add i32 %4, %5\n  %7 = add i32 %5, 1\n  jump L1\n\
+                        L3:\n  return %4\n\
+                        }\n";
+        assert_eq!(print::function(&f), expected);
+    }
+
+    #[test]
+    fn pruning_phis() {
+        // %1 is dead after the join, so it needs no phi there
+        let mut f = function(
+            "function f(%0 i8) -> i8 {\n  temps %1 i32\n\
+             L0:\n  branch %0, L1, L2\n\
+             L1:\n  %1 = 1\n  jump L3\n\
+             L2:\n  %1 = 2\n  jump L3\n\
+             L3:\n  return %0\n\
+             }",
+        );
+        construct(&mut f);
+        assert_eq!(verify::ssa(&f), Ok(()));
+        assert!(f.blocks[3].instrs.is_empty());
+    }
+
+    #[test]
+    fn destructing() {
+        let mut f = function(SUM);
+        construct(&mut f);
+        destruct(&mut f);
+        assert_eq!(verify::function(&f), Ok(()));
+        // The edge L1 -> L2 is not split, L2 has one predecessor
+        assert_eq!(f.blocks.len(), 4);
+        assert_eq!(f.blocks[0].instrs[2..], [Instr::Move(4, Operand::Temp(1)), Instr::Move(5, Operand::Temp(2))]);
+        assert_eq!(f.blocks[2].instrs[2..], [Instr::Move(4, Operand::Temp(6)), Instr::Move(5, Operand::Temp(7))]);
+    }
+
+    #[test]
+    fn splitting_critical_edges() {
+        let mut f = function(
+            "function f(%0 i8) -> i32 {\n  temps %1 i32, %2 i32\n\
+             L0:\n  %1 = 1\n  branch %0, L1, L2\n\
+             L1:\n  jump L2\n\
+             L2:\n  %2 = phi L0 %1, L1 7\n  return %2\n\
+             }",
+        );
+        destruct(&mut f);
+        assert_eq!(f.blocks[0].term, Terminator::Branch(Operand::Temp(0), 1, 3));
+        assert_eq!(f.blocks[1].instrs, [Instr::Move(2, Operand::Imm(7))]);
+        assert_eq!(f.blocks[3].instrs, [Instr::Move(2, Operand::Temp(1))]);
+        assert_eq!(f.blocks[3].term, Terminator::Jump(2));
+    }
+
+    #[test]
+    fn converting_lowered_programs() {
+        let sources = vec![
+            "int gcd(int a, int b) { while (b != 0) { int t = b; b = a % b; a = t; } return a; }",
+            "int f(int n) {\n\
+             int s = 0;\n\
+             for (int i = 0; i < n; i++) {\n\
+               if (i % 3 == 0) continue;\n\
+               for (int j = i; j > 0 && s < 100; j--) s += (j & 1) == 0 ? j : -j;\n\
+               if (s > 50) break;\n\
+             }\n\
+             return s;\n\
+             }",
+            "struct p { int x; int[] ys; };\n\
+             int f(struct p* q, bool b) //@requires q != NULL;\n\
+             { int r; if (b) { r = q->x; } else { r = q->ys[0]; } q->x++; return r; }",
+        ];
+        for text in sources {
+            for mut f in lower(&elaborated(text, true)).functions {
+                construct(&mut f);
+                assert_eq!(verify::ssa(&f), Ok(()), "{}", print::function(&f));
+                destruct(&mut f);
+                assert_eq!(verify::function(&f), Ok(()), "{}", print::function(&f));
+            }
+        }
+    }
+
+    // Runs moves one after another on an environment of temporaries
+    fn run(moves: &[Instr], mut env: HashMap<Temp, i64>) -> HashMap<Temp, i64> {
+        for m in moves {
+            match *m {
+                Instr::Move(d, Operand::Temp(t)) => {
+                    let v = env[&t];
+                    env.insert(d, v);
+                }
+                Instr::Move(d, Operand::Imm(n)) => {
+                    env.insert(d, n);
+                }
+                _ => panic!("not a move: {}", m),
+            }
+        }
+        env
+    }
+
+    #[test]
+    fn sequentializing_copies() {
+        let t = Operand::Temp;
+        let cases: Vec<(Vec<(Temp, Operand)>, usize)> = vec![
+            (vec![(0, t(1)), (1, t(0))], 3),
+            (vec![(0, t(1)), (1, t(2)), (2, t(0))], 4),
+            (vec![(1, t(0)), (2, t(1)), (3, t(2))], 3),
+            (vec![(0, t(1)), (1, t(0)), (2, t(0)), (3, Operand::Imm(9))], 5),
+            (vec![(0, t(0)), (1, t(1))], 0),
+        ];
+        for (copies, count) in cases {
+            let mut temps = vec![Size::I32; 4];
+            let moves = sequentialize(&copies, &mut temps);
+            assert_eq!(moves.len(), count, "{:?}", copies);
+            let before: HashMap<Temp, i64> = (0..temps.len()).map(|t| (t, t as i64 * 10)).collect();
+            let after = run(&moves, before.clone());
+            for &(d, a) in &copies {
+                let expected = match a {
+                    Operand::Temp(s) => before[&s],
+                    Operand::Imm(n) => n,
+                };
+                assert_eq!(after[&d], expected, "{:?}", copies);
+            }
+        }
+    }
+}
@@ -0,0 +1,215 @@
+use ir::cfg::Cfg;
+use ir::dom::Dominators;
+use ir::ir::*;
+
+/*
+Checks that a function is well formed, to be run after each pass over the
+IR so that a pass breaking it is caught where it happens. A function has a
+block; its parameters are distinct; every temporary and label it mentions
+exists; and outside SSA form it has no phis.
+
+In SSA form, additionally:
+
+    - the entry has no predecessors and every block is reachable
+    - each temporary is written once, parameters by the call
+    - phis come first in their block, with one operand per predecessor
+    - every read is dominated by the write, which for an operand of a phi
+      means the write reaches the end of the predecessor it names
+
+The error says what is wrong and where.
+ */
+pub fn function(f: &Function) -> Result<(), String> {
+    structure(f)?;
+    for (l, b) in f.blocks.iter().enumerate() {
+        if let Some(i) = b.instrs.iter().find(|i| i.is_phi()) {
+            return Err(format!("L{}: `{}` outside SSA form", l, i));
+        }
+    }
+    Ok(())
+}
+
+pub fn ssa(f: &Function) -> Result<(), String> {
+    structure(f)?;
+    let cfg = Cfg::new(f);
+    if !cfg.preds(0).is_empty() {
+        return Err(String::from("L0: the entry has predecessors"));
+    }
+    let reachable = cfg.postorder();
+    if let Some(l) = (0..cfg.len()).find(|l| !reachable.contains(l)) {
+        return Err(format!("L{} is unreachable", l));
+    }
+
+    // Where each temporary is written, a block and the position of the instruction in it
+    let mut defs: Vec<Option<(Label, usize)>> = vec![None; f.temps.len()];
+    for &p in &f.params {
+        defs[p] = Some((0, 0));
+    }
+    for (l, b) in f.blocks.iter().enumerate() {
+        for (n, i) in b.instrs.iter().enumerate() {
+            if let Some(d) = i.def() {
+                if defs[d].is_some() {
+                    return Err(format!("L{}: %{} is written more than once", l, d));
+                }
+                // After the parameters, which are at position 0 of the entry
+                defs[d] = Some((l, n + 1));
+            }
+        }
+    }
+
+    let dom = Dominators::new(&cfg);
+    let available = |t: Temp, l: Label, n: usize| match defs[t] {
+        Some((dl, dn)) => dl != l && dom.dominates(dl, l) || dl == l && dn <= n,
+        None => false,
+    };
+    for (l, b) in f.blocks.iter().enumerate() {
+        let mut phis = true;
+        for (n, i) in b.instrs.iter().enumerate() {
+            if let Instr::Phi(_, ref args) = *i {
+                if !phis {
+                    return Err(format!("L{}: `{}` follows other instructions", l, i));
+                }
+                let mut labels: Vec<Label> = args.iter().map(|&(p, _)| p).collect();
+                labels.sort_unstable();
+                let mut preds = cfg.preds(l).to_vec();
+                preds.sort_unstable();
+                if labels != preds {
+                    return Err(format!("L{}: `{}` does not have one operand per predecessor", l, i));
+                }
+                for &(p, a) in args {
+                    if let Operand::Temp(t) = a {
+                        if !available(t, p, f.blocks[p].instrs.len()) {
+                            return Err(format!("L{}: %{} is not written at the end of L{}", l, t, p));
+                        }
+                    }
+                }
+                continue;
+            }
+            phis = false;
+            for a in i.uses() {
+                if let Operand::Temp(t) = a {
+                    if !available(t, l, n) {
+                        return Err(format!("L{}: `{}` reads %{} before it is written", l, i, t));
+                    }
+                }
+            }
+        }
+        for a in b.term.uses() {
+            if let Operand::Temp(t) = a {
+                if !available(t, l, b.instrs.len()) {
+                    return Err(format!("L{}: `{}` reads %{} before it is written", l, b.term, t));
+                }
+            }
+        }
+    }
+    Ok(())
+}
+
+// What holds in and out of SSA form
+fn structure(f: &Function) -> Result<(), String> {
+    if f.blocks.is_empty() {
+        return Err(String::from("there are no blocks"));
+    }
+    for (n, &p) in f.params.iter().enumerate() {
+        if p >= f.temps.len() {
+            return Err(format!("parameter %{} does not exist", p));
+        }
+        if f.params[..n].contains(&p) {
+            return Err(format!("parameter %{} is repeated", p));
+        }
+    }
+    let temp = |l: Label, a: Operand| match a {
+        Operand::Temp(t) if t >= f.temps.len() => Err(format!("L{}: %{} does not exist", l, t)),
+        _ => Ok(()),
+    };
+    let label = |l: Label, target: Label| {
+        if target >= f.blocks.len() {
+            Err(format!("L{}: there is no block L{}", l, target))
+        } else {
+            Ok(())
+        }
+    };
+    for (l, b) in f.blocks.iter().enumerate() {
+        for i in &b.instrs {
+            if let Some(d) = i.def() {
+                temp(l, Operand::Temp(d))?;
+            }
+            for a in i.uses() {
+                temp(l, a)?;
+            }
+            if let Instr::Phi(_, ref args) = *i {
+                for &(p, _) in args {
+                    label(l, p)?;
+                }
+            }
+        }
+        for a in b.term.uses() {
+            temp(l, a)?;
+        }
+        for s in b.term.successors() {
+            label(l, s)?;
+        }
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use ir::parse::parse;
+
+    fn parsed(text: &str) -> Function {
+        parse(0, text).unwrap().functions.remove(0)
+    }
+
+    #[test]
+    fn checking_ssa() {
+        let header = "function f(%0 i8) -> i32 {\n  temps %1 i32, %2 i32\n";
+        let cases = vec![
+            (
+                "L0:\n  %1 = 1\n  branch %0, L1, L2\nL1:\n  jump L2\nL2:\n  %2 = phi L0 %1, L1 2\n  return %2\n}",
+                Ok(()),
+            ),
+            ("L0:\n  %1 = 1\n  %1 = 2\n  return %1\n}", Err("L0: %1 is written more than once")),
+            ("L0:\n  %0 = 1\n  return 0\n}", Err("L0: %0 is written more than once")),
+            (
+                "L0:\n  %2 = add i32 %1, 1\n  %1 = 1\n  return %2\n}",
+                Err("L0: `%2 = add i32 %1, 1` reads %1 before it is written"),
+            ),
+            (
+                "L0:\n  branch %0, L1, L2\nL1:\n  %1 = 1\n  jump L2\nL2:\n  return %1\n}",
+                Err("L2: `return %1` reads %1 before it is written"),
+            ),
+            (
+                "L0:\n  branch %0, L1, L2\nL1:\n  %1 = 1\n  jump L2\nL2:\n  %2 = phi L1 %1\n  return %2\n}",
+                Err("L2: `%2 = phi L1 %1` does not have one operand per predecessor"),
+            ),
+            (
+                "L0:\n  branch %0, L1, L2\nL1:\n  %1 = 1\n  jump L2\nL2:\n  %2 = phi L0 %1, L1 %1\n  return %2\n}",
+                Err("L2: %1 is not written at the end of L0"),
+            ),
+            (
+                "L0:\n  jump L1\nL1:\n  %1 = 1\n  %2 = phi L0 3\n  return %2\n}",
+                Err("L1: `%2 = phi L0 3` follows other instructions"),
+            ),
+            ("L0:\n  jump L0\n}", Err("L0: the entry has predecessors")),
+            ("L0:\n  return 1\nL1:\n  return 2\n}", Err("L1 is unreachable")),
+        ];
+        for (body, expected) in cases {
+            let f = parsed(&format!("{}{}", header, body));
+            assert_eq!(ssa(&f), expected.map_err(String::from), "{}", body);
+        }
+    }
+
+    #[test]
+    fn checking_structure() {
+        let mut f = parsed("function f(%0 i8) {\nL0:\n  %0 = phi L0 1\n  jump L0\n}");
+        assert_eq!(function(&f), Err(String::from("L0: `%0 = phi L0 1` outside SSA form")));
+        f.blocks[0].instrs.clear();
+        f.blocks[0].term = Terminator::Jump(3);
+        assert_eq!(function(&f), Err(String::from("L0: there is no block L3")));
+        f.blocks[0].term = Terminator::Return(Some(Operand::Temp(4)));
+        assert_eq!(function(&f), Err(String::from("L0: %4 does not exist")));
+        f.params.push(0);
+        assert_eq!(function(&f), Err(String::from("parameter %0 is repeated")));
+    }
+}
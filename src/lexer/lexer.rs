@@ -1,475 +1,936 @@
-    #![allow(dead_code)]
-    use std::fs::File;
-    use std::io::{ BufRead, BufReader };
-    use std::collections::{VecDeque, HashMap};
-
-    #[derive(Copy, Clone, Debug, PartialEq, Eq, Hash)]
-    pub enum Token {
-        Empty,
-        // Character and operators
-        Undefined(Option<char>),
-        Comma, 
-        LCurly,
-        RCurly,
-        Equal,
-        Equality,
-        And,
-        AndEq,
-        Xor,
-        XorEq,
-        Or,
-        OrEq,
-        Mult,
-        MultEq,
-        Not,
-        BooleanNot,
-        BitNot,
-        BitNotEq,
-        Mod,
-        ModEq,
-        Div,
-        DivEq,
-        Plus,
-        PlusEq, 
-        PostPlusEq,
-        Minus,
-        MinusEq,
-        PostMinusEq,
-        Gt, 
-        Lt,
-        Gte,
-        Lte,
-        NotEq,
-        LShift,
-        LShiftEq,
-        RShift,
-        RShiftEq,
-        LBracket,
-        RBracket,
-        LParen,
-        RParen,
-        BooleanAnd, 
-        BooleanOr, 
-        Num(u32),
-        FieldSelect, 
-        FieldDeref,
-        TernIf,
-        TernElse, 
-        QuoteMark,
-        DQuoteMark,
-        PointerDeref,
-        // Types
-        Int, 
-        Bool, 
-        Char,
-        String,
-        Void,
-        Struct,
-        // TODO: how to represent structs
-        // TODO: How to represent collection of arguments for function types 
-        // Keywords
-        If, 
-        Else,
-        While, 
-        For, 
-        Return, 
-        Assert, 
-        Error, 
-        Alloc, 
-        AllocArray,
-        Typedef, 
-        Break, 
-        Continue, 
-        SemiColon, 
-        Use,
-        True, 
-        False,
-        Null,
-    }
-
-    pub fn open_file(path:&mut String) -> BufReader<File>{
-        let f = File::open(path).expect( "Unable to open file" );
-        let buffered_reader = BufReader::new(f);
-        buffered_reader
-    }
-
-    pub fn print_lines(path: &mut String){
-        let file_reader = open_file(path);
-
-        for line in file_reader.lines(){
-        let line = line.expect( "Unable to read line" );
-        println!( "Line: {}", line )
-        }
-    }
-
-    #[derive(Clone)]
-    pub struct Lexer {
-        tokens: VecDeque<Token>,
-        chars: VecDeque<char>
-    }
-
-    // TODO: Grammar extensions for annotations
-    impl Lexer {
-        pub fn new(mut file_path: &mut String) -> Lexer {
-            let mut chars = chars(file_path);
-            let tokens = VecDeque::new();
-            Lexer {
-                tokens,
-                chars,
-            }
+use source::diagnostic::Diagnostic;
+use source::source_map::{FileId, Span};
+use std::fmt;
+
+#[derive(Clone, Debug, PartialEq, Eq, Hash)]
+pub enum Token {
+    // Character and operators
+    Comma,
+    LCurly,
+    RCurly,
+    Equal,
+    Equality,
+    And,
+    AndEq,
+    Xor,
+    XorEq,
+    Or,
+    OrEq,
+    Mult,
+    MultEq,
+    Not,
+    BitNot,
+    Mod,
+    ModEq,
+    Div,
+    DivEq,
+    Plus,
+    PlusEq,
+    PostPlusEq,
+    Minus,
+    MinusEq,
+    PostMinusEq,
+    Gt,
+    Lt,
+    Gte,
+    Lte,
+    NotEq,
+    LShift,
+    LShiftEq,
+    RShift,
+    RShiftEq,
+    LBracket,
+    RBracket,
+    LParen,
+    RParen,
+    BooleanAnd,
+    BooleanOr,
+    FieldSelect,
+    FieldDeref,
+    TernIf,
+    TernElse,
+    SemiColon,
+    // Literals
+    Ident(String),
+    Num(u32),
+    HexNum(u32),
+    StrLit(String),
+    ChrLit(char),
+    LibLit(String),
+    // Types
+    Int,
+    Bool,
+    Char,
+    String,
+    Void,
+    Struct,
+    // Keywords
+    If,
+    Else,
+    While,
+    For,
+    Return,
+    Assert,
+    Error,
+    Alloc,
+    AllocArray,
+    Typedef,
+    Break,
+    Continue,
+    Use,
+    True,
+    False,
+    Null,
+    // Annotations, `//@` or `/*@` open one and the end of the line or `@*/`
+    // closes it. The remaining keywords only exist inside annotations.
+    AnnoStart,
+    AnnoEnd,
+    Requires,
+    Ensures,
+    LoopInvariant,
+    Result,
+    Length,
+}
+
+impl fmt::Display for Token {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let s = match *self {
+            Token::Comma => ",",
+            Token::LCurly => "{",
+            Token::RCurly => "}",
+            Token::Equal => "=",
+            Token::Equality => "==",
+            Token::And => "&",
+            Token::AndEq => "&=",
+            Token::Xor => "^",
+            Token::XorEq => "^=",
+            Token::Or => "|",
+            Token::OrEq => "|=",
+            Token::Mult => "*",
+            Token::MultEq => "*=",
+            Token::Not => "!",
+            Token::BitNot => "~",
+            Token::Mod => "%",
+            Token::ModEq => "%=",
+            Token::Div => "/",
+            Token::DivEq => "/=",
+            Token::Plus => "+",
+            Token::PlusEq => "+=",
+            Token::PostPlusEq => "++",
+            Token::Minus => "-",
+            Token::MinusEq => "-=",
+            Token::PostMinusEq => "--",
+            Token::Gt => ">",
+            Token::Lt => "<",
+            Token::Gte => ">=",
+            Token::Lte => "<=",
+            Token::NotEq => "!=",
+            Token::LShift => "<<",
+            Token::LShiftEq => "<<=",
+            Token::RShift => ">>",
+            Token::RShiftEq => ">>=",
+            Token::LBracket => "[",
+            Token::RBracket => "]",
+            Token::LParen => "(",
+            Token::RParen => ")",
+            Token::BooleanAnd => "&&",
+            Token::BooleanOr => "||",
+            Token::FieldSelect => ".",
+            Token::FieldDeref => "->",
+            Token::TernIf => "?",
+            Token::TernElse => ":",
+            Token::SemiColon => ";",
+            Token::Ident(ref s) => return write!(f, "{}", s),
+            Token::Num(n) => return write!(f, "{}", n),
+            Token::HexNum(n) => return write!(f, "0x{:X}", n),
+            Token::StrLit(ref s) => return write!(f, "\"{}\"", escape_str(s)),
+            Token::ChrLit(c) => return write!(f, "'{}'", escape_char(c)),
+            Token::LibLit(ref s) => return write!(f, "<{}>", s),
+            Token::Int => "int",
+            Token::Bool => "bool",
+            Token::Char => "char",
+            Token::String => "string",
+            Token::Void => "void",
+            Token::Struct => "struct",
+            Token::If => "if",
+            Token::Else => "else",
+            Token::While => "while",
+            Token::For => "for",
+            Token::Return => "return",
+            Token::Assert => "assert",
+            Token::Error => "error",
+            Token::Alloc => "alloc",
+            Token::AllocArray => "alloc_array",
+            Token::Typedef => "typedef",
+            Token::Break => "break",
+            Token::Continue => "continue",
+            Token::Use => "#use",
+            Token::True => "true",
+            Token::False => "false",
+            Token::Null => "NULL",
+            Token::AnnoStart => "//@",
+            Token::AnnoEnd => "end of annotation",
+            Token::Requires => "requires",
+            Token::Ensures => "ensures",
+            Token::LoopInvariant => "loop_invariant",
+            Token::Result => "\\result",
+            Token::Length => "\\length",
+        };
+        write!(f, "{}", s)
+    }
+}
+
+// Escapes a string literal body so that it lexes back to the same string
+pub fn escape_str(s: &str) -> String {
+    let mut out = String::new();
+    for c in s.chars() {
+        match c {
+            '"' => out.push_str("\\\""),
+            _ => out.push_str(&escape(c)),
+        }
+    }
+    out
+}
+
+pub fn escape_char(c: char) -> String {
+    match c {
+        '\'' => String::from("\\'"),
+        '\0' => String::from("\\0"),
+        _ => escape(c),
+    }
+}
+
+fn escape(c: char) -> String {
+    match c {
+        '\n' => String::from("\\n"),
+        '\t' => String::from("\\t"),
+        '\x0B' => String::from("\\v"),
+        '\x08' => String::from("\\b"),
+        '\r' => String::from("\\r"),
+        '\x0C' => String::from("\\f"),
+        '\x07' => String::from("\\a"),
+        '\\' => String::from("\\\\"),
+        _ => c.to_string(),
+    }
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct Lexeme {
+    pub token: Token,
+    pub span: Span,
+}
+
+// A comment, kept as trivia so tools like the formatter can put it back
+#[derive(Clone, Debug, PartialEq)]
+pub struct Comment {
+    pub text: String,
+    pub span: Span,
+}
+
+#[derive(Copy, Clone, Debug, PartialEq)]
+enum Annotation {
+    None,
+    Line,
+    Block,
+}
+
+pub type LexResult<T> = Result<T, Diagnostic>;
+
+/*
+The lexer walks the characters of a single file producing lexemes on demand.
+
+<id> ::= [A-Za-z_][A-Za-z0-9_]*
+<num> ::= <decnum> | <hexnum>
+<decnum> ::= 0 | [1-9][0-9]*
+<hexnum> ::= 0[xX][0-9a-fA-F]+
+<strlit> ::= "<schar>*"
+<chrlit> ::= ’<cchar>’
+<liblit> ::= <<lchar>*>
+
+Comments and whitespace are skipped, except inside `//@` annotations where the
+end of the line is significant and produces an `AnnoEnd` token. Skipped
+comments are remembered in `comments`.
+ */
+#[derive(Clone)]
+pub struct Lexer {
+    file: FileId,
+    chars: Vec<(usize, char)>,
+    len: usize,
+    pos: usize,
+    annotation: Annotation,
+    // A library literal `<lib>` may only follow `#use`
+    after_use: bool,
+    comments: Vec<Comment>,
+}
+
+impl Lexer {
+    pub fn new(file: FileId, text: &str) -> Lexer {
+        Lexer {
+            file,
+            chars: text.char_indices().collect(),
+            len: text.len(),
+            pos: 0,
+            annotation: Annotation::None,
+            after_use: false,
+            comments: Vec::new(),
         }
+    }
 
-        // Unintialised lexer object returned, for testing
-        pub fn empty() -> Lexer {
-            Lexer{
-                tokens: VecDeque::new(),
-                chars: VecDeque::new(),
-            }
+    // Lexes the remainder of the file
+    pub fn tokens(mut self) -> LexResult<Vec<Lexeme>> {
+        let mut lexemes = Vec::new();
+        while let Some(lexeme) = self.next_lexeme()? {
+            lexemes.push(lexeme);
         }
+        Ok(lexemes)
+    }
 
-        pub fn tokens(self) -> VecDeque<Token> {
-            self.tokens
-        }
-
-        pub fn next(&mut self) -> Option<Token> {
-            println!("{:?}", self.chars);
-            match self.chars.pop_front() {
-                Some(c) => {
-                    match c {
-                        ' '|'\n'|'\t' => self.next(),
-                        ';' => Some(Token::SemiColon),
-                        '(' => Some(Token::LParen),
-                        ')' => Some(Token::RParen),
-                        '~' => Some(ops(Token::BitNot,   &mut self.chars)),
-                        '=' => Some(ops(Token::Equal,    &mut self.chars)),
-                        '!' => Some(ops(Token::Not  ,    &mut self.chars)),
-                        '+' => Some(ops(Token::Plus ,    &mut self.chars)),
-                        '-' => Some(ops(Token::Minus,    &mut self.chars)),
-                        '&' => Some(ops(Token::And  ,    &mut self.chars)),
-                        '%' => Some(ops(Token::Mod  ,    &mut self.chars)),
-                        '/' => Some(ops(Token::Div  ,    &mut self.chars)),
-                        '*' => Some(ops(Token::Mult ,    &mut self.chars)),
-                        '<' => Some(ops(Token::Lt   ,    &mut self.chars)),
-                        '>' => Some(ops(Token::Gt   ,    &mut self.chars)),
-                        '^' => Some(ops(Token::Xor  ,    &mut self.chars)),
-                        '|' => Some(ops(Token::Or   ,    &mut self.chars)),
-                        '[' => Some(Token::LBracket),
-                        ']' => Some(Token::RBracket),
-                        '{' => Some(Token::LCurly),
-                        '}' => Some(Token::RCurly),
-                        ',' => Some(Token::Comma),
-                        '.' => Some(Token::FieldSelect),
-                        '?' => Some(Token::TernIf),
-                        ':' => Some(Token::TernElse),
-                        '"' => Some(Token::DQuoteMark),
-                        '\''=> Some(Token::QuoteMark),
-                        '0'|'1'|'2'|
-                        '3'|'4'|'5'|
-                        '6'|'7'|'8'|
-                        '9' => Some(numeric(c, &mut self.chars)),
-
-                        'i' | 'b' | 'c' | 
-                        's' | 'v' | 'a' |
-                        'e' | 'r' | 'w' |
-                        'f' | '_' | 't' |
-                        '#' | 'N' => Some(keyword(c, &mut self.chars)),
-                        // ' ' => continue,
-                        _   => Some(Token::Undefined(Some(c))),
-                    }
-                }
-                _ => None,
-            }
+    // Lexes the remainder of the file, also returning the comments in it
+    pub fn tokens_and_comments(mut self) -> LexResult<(Vec<Lexeme>, Vec<Comment>)> {
+        let mut lexemes = Vec::new();
+        while let Some(lexeme) = self.next_lexeme()? {
+            lexemes.push(lexeme);
         }
+        Ok((lexemes, self.comments))
     }
 
-    // TODO: Lexing strings
-    // TODO: spotting syntax errors
-    fn lex_tokens(chars: &mut VecDeque<char>) -> VecDeque<Token> {
-        VecDeque::new()
+    fn peek(&self) -> Option<char> {
+        self.peek_at(0)
     }
 
-    fn chars(file_path: &mut String) -> VecDeque<char>{
-        let mut chars: VecDeque<char> = VecDeque::new();
-        for line in open_file(file_path).lines() {
-            match line {
-                Ok(value) => for c in value.chars() {
-                    chars.push_back(c);
-                },
-                Err(_) => break,
-            }
+    fn peek_at(&self, n: usize) -> Option<char> {
+        self.chars.get(self.pos + n).map(|&(_, c)| c)
+    }
+
+    fn offset(&self) -> usize {
+        match self.chars.get(self.pos) {
+            Some(&(i, _)) => i,
+            None => self.len,
         }
-        println!("{:?}", chars);
-        chars
     }
 
-    fn re_insert(t1: char, t2: char, cs: &mut VecDeque<char>, ret: Token) -> Token {
-        cs.push_front(t2); 
-        cs.push_front(t1);
-        ret
+    fn bump(&mut self) -> Option<char> {
+        let c = self.peek();
+        if c.is_some() {
+            self.pos += 1;
+        }
+        c
     }
 
-    fn re_insert2 (t1: char, cs: &mut VecDeque<char>, ret: Token) -> Token {
-        cs.push_front(t1);
-        ret
+    fn eat(&mut self, c: char) -> bool {
+        if self.peek() == Some(c) {
+            self.pos += 1;
+            true
+        } else {
+            false
+        }
     }
 
-    fn ops(head: Token, chars: &mut VecDeque<char>) -> Token {
-        let tail  = chars.pop_front().unwrap_or(' ');
-        let tail2 = chars.pop_front().unwrap_or(' ');
-        match head {
-            Token::Equal => {
-                match tail {
-                    '=' => Token::Equality,
-                    _   => re_insert(tail, tail2, chars, head),
-                }
-            }
-            Token::Lt => {
-                match tail {
-                    '=' => Token::Lte,
-                    '<' => match tail2 { 
-                        '=' => Token::LShiftEq,
-                        _  => re_insert2(tail2, chars, Token::LShift),
-                        },
-                    _ => re_insert(tail, tail2, chars, head),
-                }
-            }
-            Token::Gt => {
-                match tail {
-                    '=' => Token::Gte,
-                    '>' => match tail2 {
-                        '=' => Token::RShiftEq,
-                        _   => re_insert2(tail2, chars, Token::RShift), 
-                    }, 
-                    _   => re_insert(tail, tail2, chars, head),
-                }
-            }
-            Token::Not => {
-                match tail {
-                    '=' => Token::NotEq,
-                    '!' => Token::BooleanNot,
-                    _   => re_insert(tail, tail2, chars, head),
+    fn span_from(&self, lo: usize) -> Span {
+        Span::new(self.file, lo, self.offset())
+    }
+
+    fn error(&self, lo: usize, message: impl Into<String>) -> Diagnostic {
+        Diagnostic::error(self.span_from(lo), message)
+    }
+
+    pub fn next_lexeme(&mut self) -> LexResult<Option<Lexeme>> {
+        if let Some(lexeme) = self.skip_trivia()? {
+            return Ok(Some(lexeme));
+        }
+        let lo = self.offset();
+        let c = match self.bump() {
+            Some(c) => c,
+            None => {
+                if self.annotation == Annotation::Line {
+                    self.annotation = Annotation::None;
+                    return Ok(Some(Lexeme {
+                        token: Token::AnnoEnd,
+                        span: self.span_from(lo),
+                    }));
                 }
-            }
-            Token::Plus => {
-                match tail {
-                    '+' => Token::PostPlusEq, 
-                    '=' => Token::PlusEq,
-                    _   => re_insert(tail, tail2, chars, head),
+                if self.annotation == Annotation::Block {
+                    return Err(self.error(lo, "unterminated annotation, expected `@*/`"));
                 }
+                return Ok(None);
             }
-            Token::Minus => {
-                match tail {
-                    '-' => Token::PostMinusEq,
-                    '=' => Token::MinusEq,
-                    '>' => Token::FieldDeref,
-                    _         => re_insert(tail, tail2, chars, head)
+        };
+        let after_use = self.after_use;
+        self.after_use = false;
+        let token = match c {
+            ';' => Token::SemiColon,
+            '(' => Token::LParen,
+            ')' => Token::RParen,
+            '[' => Token::LBracket,
+            ']' => Token::RBracket,
+            '{' => Token::LCurly,
+            '}' => Token::RCurly,
+            ',' => Token::Comma,
+            '.' => Token::FieldSelect,
+            '?' => Token::TernIf,
+            ':' => Token::TernElse,
+            '~' => Token::BitNot,
+            '<' if after_use => self.lib_lit(lo)?,
+            '=' | '!' | '+' | '-' | '&' | '%' | '/' | '*' | '<' | '>' | '^' | '|' => ops(c, self),
+            '"' => self.str_lit(lo)?,
+            '\'' => self.chr_lit(lo)?,
+            '0'..='9' => self.numeric(c, lo)?,
+            'a'..='z' | 'A'..='Z' | '_' => self.word(),
+            '#' => {
+                let word = self.take_word();
+                if word == "use" {
+                    self.after_use = true;
+                    Token::Use
+                } else {
+                    return Err(self.error(lo, format!("unknown directive `#{}`", word)));
                 }
             }
-            Token::Div => {
-                match tail {
-                    '=' => Token::DivEq,
-                    _   => re_insert(tail, tail2, chars, head),
+            '\\' if self.annotation != Annotation::None => {
+                let word = self.take_word();
+                match word.as_ref() {
+                    "result" => Token::Result,
+                    "length" => Token::Length,
+                    _ => return Err(self.error(lo, format!("unknown annotation function `\\{}`", word))),
                 }
             }
-            Token::Mult => {
-                match tail {
-                    '=' => Token::MultEq, 
-                    _   => re_insert(tail, tail2, chars, head),
+            '@' if self.annotation == Annotation::Block && self.eat('*') => {
+                if !self.eat('/') {
+                    return Err(self.error(lo, "expected `@*/` to close the annotation"));
                 }
+                self.annotation = Annotation::None;
+                Token::AnnoEnd
             }
-            Token::Or => {
-                match tail {
-                    '=' => Token::OrEq, 
-                    '|' => Token::BooleanOr,
-                    _   => re_insert(tail, tail2, chars, head),
+            _ => return Err(self.error(lo, format!("unexpected character `{}`", c))),
+        };
+        Ok(Some(Lexeme {
+            token,
+            span: self.span_from(lo),
+        }))
+    }
+
+    /*
+    Skips whitespace and comments. The only "trivia" that produces a token is
+    the opening of an annotation, or the newline which closes a line annotation
+     */
+    fn skip_trivia(&mut self) -> LexResult<Option<Lexeme>> {
+        loop {
+            let lo = self.offset();
+            match self.peek() {
+                Some('\n') if self.annotation == Annotation::Line => {
+                    self.bump();
+                    self.annotation = Annotation::None;
+                    return Ok(Some(Lexeme {
+                        token: Token::AnnoEnd,
+                        span: self.span_from(lo),
+                    }));
                 }
-            }
-            Token::Mod => {
-                match tail {
-                    '=' => Token::ModEq,
-                    _   => re_insert(tail, tail2, chars, head)
+                Some(c) if c.is_whitespace() => {
+                    self.bump();
                 }
-            }
-            Token::Xor => {
-                match tail {
-                    '=' => Token::XorEq,
-                    _   => re_insert(tail, tail2, chars, head)
+                // Continuation lines of block annotations may start with `@`
+                Some('@') if self.annotation != Annotation::None && self.peek_at(1) != Some('*') => {
+                    self.bump();
                 }
-            }
-            Token::And => {
-                match tail {
-                    '=' => Token::AndEq,
-                    '&' => Token::BooleanAnd, 
-                    _   => re_insert(tail, tail2, chars, head),
+                Some('/') if self.peek_at(1) == Some('/') => {
+                    if self.peek_at(2) == Some('@') && self.annotation == Annotation::None {
+                        self.pos += 3;
+                        self.annotation = Annotation::Line;
+                        return Ok(Some(Lexeme {
+                            token: Token::AnnoStart,
+                            span: self.span_from(lo),
+                        }));
+                    }
+                    while let Some(c) = self.peek() {
+                        if c == '\n' {
+                            break;
+                        }
+                        self.bump();
+                    }
+                    self.comment(lo);
                 }
-            }
-            Token::BitNot => {
-                match tail {
-                    '=' => Token::BitNotEq,
-                    _   => re_insert(tail, tail2, chars, head),
+                Some('/') if self.peek_at(1) == Some('*') => {
+                    if self.peek_at(2) == Some('@') && self.annotation == Annotation::None {
+                        self.pos += 3;
+                        self.annotation = Annotation::Block;
+                        return Ok(Some(Lexeme {
+                            token: Token::AnnoStart,
+                            span: self.span_from(lo),
+                        }));
+                    }
+                    self.block_comment(lo)?;
+                    self.comment(lo);
                 }
+                _ => return Ok(None),
             }
-            _ => re_insert(tail, tail2, chars, head)
-            // TODO: Finish integer ops
-            // TODO: Boolean expressions
-            // TODO: Pointers from Token::Mult
         }
     }
 
-    fn numeric(head: char, chars: &mut VecDeque<char>) -> Token {
-        let mut sum: u32 = head.to_digit(10).unwrap_or(0);
-        loop {
-            match chars.pop_front() {
-                Some(c) => {
-                    match c {
-                        '0'|'1'|'2'|'3'|'4'|'5'|'6'|'7'|'8'|'9' =>{
-                            if sum <= (4294967295/10){
-                                sum = 10 * sum + c.to_digit(10).unwrap_or(0);
-                            };
-                        },
-                        _        => {
-                            chars.push_front(c);
-                            return Token::Num(sum);
-                        }
-                    }
-                },
-                _   =>
-                    return Token::Num(sum),
+    fn comment(&mut self, lo: usize) {
+        let span = self.span_from(lo);
+        let start = self.chars.partition_point(|&(i, _)| i < lo);
+        let text: String = self.chars[start..self.pos].iter().map(|&(_, c)| c).collect();
+        self.comments.push(Comment {
+            text: String::from(text.trim_end()),
+            span,
+        });
+    }
+
+    // Block comments nest, `/* /* */ */` is a single comment
+    fn block_comment(&mut self, lo: usize) -> LexResult<()> {
+        self.pos += 2;
+        let mut depth = 1;
+        while depth > 0 {
+            match self.bump() {
+                Some('/') if self.eat('*') => depth += 1,
+                Some('*') if self.eat('/') => depth -= 1,
+                Some(_) => (),
+                None => return Err(self.error(lo, "unterminated comment")),
             }
         }
+        Ok(())
     }
 
-    fn copy_n(n: usize, vec: &mut VecDeque<char>) -> Vec<char>{
-        let mut ret: Vec<char> = Vec::new();
-
-        for i in 0..n {
-            match vec.get(i) {
-                Some(c) => ret.push(*c),
-                None    => return ret,
+    fn take_word(&mut self) -> String {
+        let mut word = String::new();
+        while let Some(c) = self.peek() {
+            if c.is_ascii_alphanumeric() || c == '_' {
+                word.push(c);
+                self.bump();
+            } else {
+                break;
             }
         }
-
-        ret
+        word
     }
 
-    fn pattern_check(p: Vec<char>, chars: &mut VecDeque<char>) -> Option<Token> {
-
-        let next_n: Vec<char> = copy_n(p.len(), chars);
-
-        if next_n == p {
-            return Some(Token::Undefined(None));
+    fn word(&mut self) -> Token {
+        self.pos -= 1;
+        let word = self.take_word();
+        match keyword(&word, self.annotation != Annotation::None) {
+            Some(t) => t,
+            None => Token::Ident(word),
         }
-
-        None
     }
 
-    fn match_keyword(patterns: &HashMap<Token, Vec<char>>, chars: &mut VecDeque<char>) -> Option<Token>{
-
-        for (t, p) in patterns.iter() {
-            match pattern_check(p.to_vec(), chars) {
-                Some(_) => {
-                    chars.drain(0..p.len());
-                    return Some(t.clone());
+    // <decnum> ::= 0 | [1-9][0-9]*
+    // <hexnum> ::= 0[xX][0-9a-fA-F]+
+    fn numeric(&mut self, head: char, lo: usize) -> LexResult<Token> {
+        if head == '0' && (self.eat('x') || self.eat('X')) {
+            let mut digits = String::new();
+            while let Some(c) = self.peek() {
+                if c.is_ascii_hexdigit() {
+                    digits.push(c);
+                    self.bump();
+                } else {
+                    break;
                 }
-                None    => (),
             }
-
+            if digits.is_empty() {
+                return Err(self.error(lo, "hexadecimal literal has no digits"));
+            }
+            if self.peek().is_some_and(|c| c.is_ascii_alphanumeric() || c == '_') {
+                return Err(self.error(lo, "invalid hexadecimal literal"));
+            }
+            return match u32::from_str_radix(&digits, 16) {
+                Ok(n) => Ok(Token::HexNum(n)),
+                Err(_) => Err(self.error(lo, "hexadecimal literal does not fit in 32 bits")),
+            };
         }
+        let mut digits = head.to_string();
+        while let Some(c) = self.peek() {
+            if c.is_ascii_digit() {
+                digits.push(c);
+                self.bump();
+            } else {
+                break;
+            }
+        }
+        if self.peek().is_some_and(|c| c.is_ascii_alphanumeric() || c == '_') {
+            return Err(self.error(lo, "invalid numeric literal"));
+        }
+        if head == '0' && digits.len() > 1 {
+            return Err(self.error(lo, "decimal literals may not have leading zeros"));
+        }
+        // 2^31 is allowed so that the smallest integer can be written -2147483648
+        match digits.parse::<u64>() {
+            Ok(n) if n <= 1 << 31 => Ok(Token::Num(n as u32)),
+            _ => Err(self.error(lo, "decimal literal out of range, must be at most 2147483648")),
+        }
+    }
 
-        return None;
-
+    // <esc> ::= \n | \t | \v | \b | \r | \f | \a | \\ | \’ | \"
+    fn escape(&mut self, lo: usize, allow_nul: bool) -> LexResult<char> {
+        let c = match self.bump() {
+            Some('n') => '\n',
+            Some('t') => '\t',
+            Some('v') => '\x0B',
+            Some('b') => '\x08',
+            Some('r') => '\r',
+            Some('f') => '\x0C',
+            Some('a') => '\x07',
+            Some('\\') => '\\',
+            Some('\'') => '\'',
+            Some('"') => '"',
+            Some('0') if allow_nul => '\0',
+            Some(c) => return Err(self.error(lo, format!("unknown escape sequence `\\{}`", c))),
+            None => return Err(self.error(lo, "unterminated literal")),
+        };
+        Ok(c)
     }
 
-    fn keyword(head: char, chars: &mut VecDeque<char>) -> Token {
+    // <nchar>, a printing ASCII character, others are written as escapes if at all
+    fn nchar(&self, at: usize, c: char) -> LexResult<char> {
+        if c == ' ' || c.is_ascii_graphic() {
+            Ok(c)
+        } else if c.is_ascii() {
+            let message = format!("unprintable character U+{:04X} in a literal, write an escape sequence", c as u32);
+            Err(self.error(at, message))
+        } else {
+            Err(self.error(at, format!("non-ASCII character `{}` in a literal, C0 characters are ASCII", c)))
+        }
+    }
 
-        let mut patterns = HashMap::new();
-        let check_patterns = |patterns: &HashMap<Token, Vec<char>>, chars: &mut VecDeque<char>| -> Token {
-            match match_keyword(patterns, chars) {
-                Some(token) => token, 
-                _           => Token::Undefined(Some(head)),
+    // <strlit> ::= "<schar>*"
+    fn str_lit(&mut self, lo: usize) -> LexResult<Token> {
+        let mut s = String::new();
+        loop {
+            let at = self.offset();
+            match self.bump() {
+                Some('"') => return Ok(Token::StrLit(s)),
+                Some('\\') => {
+                    let c = self.escape(lo, false)?;
+                    s.push(c);
+                }
+                Some('\n') | Some('\r') | None => return Err(self.error(lo, "unterminated string literal")),
+                Some(c) => s.push(self.nchar(at, c)?),
             }
+        }
+    }
+
+    // <chrlit> ::= ’<cchar>’
+    fn chr_lit(&mut self, lo: usize) -> LexResult<Token> {
+        let at = self.offset();
+        let c = match self.bump() {
+            Some('\\') => self.escape(lo, true)?,
+            Some('\'') | Some('\n') | None => return Err(self.error(lo, "empty character literal")),
+            Some(c) => self.nchar(at, c)?,
         };
+        if self.eat('\'') {
+            Ok(Token::ChrLit(c))
+        } else {
+            Err(self.error(lo, "unterminated character literal"))
+        }
+    }
 
-        match head { 
-            'a' => {
-                patterns.insert(Token::Assert, vec!['s', 's', 'e', 'r', 't']);
-                patterns.insert(Token::Alloc, vec!['l', 'l', 'o', 'c']);
-                check_patterns(&patterns, chars)
+    // <liblit> ::= <<lchar>*>
+    fn lib_lit(&mut self, lo: usize) -> LexResult<Token> {
+        let mut s = String::new();
+        loop {
+            match self.bump() {
+                Some('>') => return Ok(Token::LibLit(s)),
+                Some('\n') | None => return Err(self.error(lo, "unterminated library name")),
+                Some(c) => s.push(c),
             }
-
-            'b' => {
-                patterns.insert(Token::Bool, vec!['o', 'o', 'l']);
-                patterns.insert(Token::Break, vec!['r', 'e', 'a', 'k']);
-                check_patterns(&patterns, chars)
+        }
+    }
+}
+
+fn keyword(word: &str, in_annotation: bool) -> Option<Token> {
+    let t = match word {
+        "int" => Token::Int,
+        "bool" => Token::Bool,
+        "char" => Token::Char,
+        "string" => Token::String,
+        "void" => Token::Void,
+        "struct" => Token::Struct,
+        "if" => Token::If,
+        "else" => Token::Else,
+        "while" => Token::While,
+        "for" => Token::For,
+        "return" => Token::Return,
+        "assert" => Token::Assert,
+        "error" => Token::Error,
+        "alloc" => Token::Alloc,
+        "alloc_array" => Token::AllocArray,
+        "typedef" => Token::Typedef,
+        "break" => Token::Break,
+        "continue" => Token::Continue,
+        "true" => Token::True,
+        "false" => Token::False,
+        "NULL" => Token::Null,
+        "requires" if in_annotation => Token::Requires,
+        "ensures" if in_annotation => Token::Ensures,
+        "loop_invariant" if in_annotation => Token::LoopInvariant,
+        _ => return None,
+    };
+    Some(t)
+}
+
+// Operators are lexed greedily, `<<=` is a single token rather than `<` `<=`
+fn ops(head: char, lexer: &mut Lexer) -> Token {
+    match head {
+        '=' => {
+            if lexer.eat('=') {
+                Token::Equality
+            } else {
+                Token::Equal
             }
-
-            'c' => {
-                patterns.insert(Token::Char, vec!['h', 'a', 'r']);
-                patterns.insert(Token::Continue, vec!['o', 'n', 't', 'i', 'n', 'u', 'e']);
-                check_patterns(&patterns, chars)
+        }
+        '!' => {
+            if lexer.eat('=') {
+                Token::NotEq
+            } else {
+                Token::Not
             }
-
-            'e' => {
-                patterns.insert(Token::Error, vec!['r', 'r', 'o', 'r']);
-                patterns.insert(Token::Else, vec!['l', 's', 'e']);
-                check_patterns(&patterns, chars)
+        }
+        '+' => {
+            if lexer.eat('+') {
+                Token::PostPlusEq
+            } else if lexer.eat('=') {
+                Token::PlusEq
+            } else {
+                Token::Plus
             }
-
-            'f' => {
-                patterns.insert(Token::For, vec!['o', 'r']);
-                patterns.insert(Token::False, vec!['a','l','s','e']);
-                check_patterns(&patterns, chars)
+        }
+        '-' => {
+            if lexer.eat('-') {
+                Token::PostMinusEq
+            } else if lexer.eat('=') {
+                Token::MinusEq
+            } else if lexer.eat('>') {
+                Token::FieldDeref
+            } else {
+                Token::Minus
             }
-
-            'i' => {
-                patterns.insert(Token::If, vec!['f']);
-                patterns.insert(Token::Int, vec!['n', 't']);
-                check_patterns(&patterns, chars)
-
+        }
+        '&' => {
+            if lexer.eat('&') {
+                Token::BooleanAnd
+            } else if lexer.eat('=') {
+                Token::AndEq
+            } else {
+                Token::And
             }
-
-            'N' => {
-                patterns.insert(Token::Null, vec!['U', 'L', 'L']);
-                check_patterns(&patterns, chars)
+        }
+        '|' => {
+            if lexer.eat('|') {
+                Token::BooleanOr
+            } else if lexer.eat('=') {
+                Token::OrEq
+            } else {
+                Token::Or
             }
-
-            's' => {
-                patterns.insert(Token::String, vec!['t', 'r', 'i', 'n', 'g']);
-                patterns.insert(Token::Struct, vec!['t', 'r', 'u', 'c', 't']);
-                check_patterns(&patterns, chars)
+        }
+        '%' => {
+            if lexer.eat('=') {
+                Token::ModEq
+            } else {
+                Token::Mod
             }
-
-            't' => {
-                patterns.insert(Token::Typedef, vec!['y', 'p', 'e', 'd', 'e', 'f']);
-                patterns.insert(Token::True, vec!['r', 'u', 'e']);
-                check_patterns(&patterns, chars)
+        }
+        '/' => {
+            if lexer.eat('=') {
+                Token::DivEq
+            } else {
+                Token::Div
             }
-
-            'r' => {
-                patterns.insert(Token::Return, vec!['e', 't', 'u', 'r', 'n']);
-                check_patterns(&patterns, chars) 
+        }
+        '*' => {
+            if lexer.eat('=') {
+                Token::MultEq
+            } else {
+                Token::Mult
             }
-
-            'w' => {
-                patterns.insert(Token::While, vec!['h', 'i', 'l', 'e']);
-                check_patterns(&patterns, chars)
+        }
+        '^' => {
+            if lexer.eat('=') {
+                Token::XorEq
+            } else {
+                Token::Xor
             }
-
-            'v' => {
-                patterns.insert(Token::Void, vec!['o', 'i', 'd']);
-                check_patterns(&patterns, chars)
+        }
+        '<' => {
+            if lexer.eat('<') {
+                if lexer.eat('=') {
+                    Token::LShiftEq
+                } else {
+                    Token::LShift
+                }
+            } else if lexer.eat('=') {
+                Token::Lte
+            } else {
+                Token::Lt
             }
-
-            '_' => {
-                patterns.insert(Token::AllocArray, vec!['a', 'r', 'r', 'a', 'y']);
-                check_patterns(&patterns, chars)
+        }
+        _ => {
+            if lexer.eat('>') {
+                if lexer.eat('=') {
+                    Token::RShiftEq
+                } else {
+                    Token::RShift
+                }
+            } else if lexer.eat('=') {
+                Token::Gte
+            } else {
+                Token::Gt
             }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::fs;
+
+    fn lex(path: &str) -> Vec<Token> {
+        let text = fs::read_to_string(path).unwrap();
+        Lexer::new(0, &text)
+            .tokens()
+            .unwrap()
+            .into_iter()
+            .map(|l| l.token)
+            .collect()
+    }
 
-            '#' => {
-                patterns.insert(Token::Use, vec!['u', 's', 'e']);
-                check_patterns(&patterns, chars)
-            }
+    #[test]
+    fn lexing_operators() {
+        let expected = vec![
+            Token::Mult, Token::Minus, Token::Plus, Token::Div, Token::Equal,
+            Token::Equality, Token::Lt, Token::Lte, Token::Gte, Token::Gt, Token::NotEq,
+            Token::Mod, Token::LShift, Token::RShift, Token::And, Token::Xor, Token::Or,
+            Token::BitNot, Token::LParen, Token::RParen, Token::PlusEq, Token::MinusEq,
+            Token::DivEq, Token::MultEq, Token::OrEq, Token::LShiftEq, Token::RShiftEq,
+            Token::ModEq, Token::BitNot, Token::Equal, Token::AndEq, Token::XorEq,
+            Token::PostPlusEq, Token::PostMinusEq, Token::BooleanAnd, Token::BooleanOr,
+            Token::Not, Token::Not,
+        ];
+        assert_eq!(lex("./src/lexer/tests/expressions.c0"), expected);
+        let expected = vec![
+            Token::Plus, Token::Minus, Token::Mult, Token::Lt, Token::Gt, Token::Or,
+            Token::Xor, Token::BitNot, Token::Div, Token::Equal,
+        ];
+        assert_eq!(lex("./src/lexer/tests/simple_expressions.c0"), expected);
+    }
+
+    #[test]
+    fn lexing_numerics() {
+        let expected = vec![
+            Token::Num(1), Token::Num(12), Token::Num(123), Token::Num(1234),
+            Token::Num(12345), Token::Num(123456), Token::Num(1234567), Token::Num(0),
+            Token::Num(2147483648), Token::HexNum(0), Token::HexNum(0xFF),
+            Token::HexNum(0xDEADBEEF), Token::HexNum(0xFFFFFFFF),
+        ];
+        assert_eq!(lex("./src/lexer/tests/numerics.c0"), expected);
+    }
+
+    #[test]
+    fn lexing_keywords() {
+        let expected = vec![
+            Token::Assert, Token::Alloc, Token::AllocArray, Token::Bool, Token::Break,
+            Token::Char, Token::Continue, Token::Error, Token::For, Token::If, Token::Else,
+            Token::Int, Token::String, Token::Struct, Token::Typedef, Token::Return,
+            Token::While, Token::Void, Token::Use, Token::SemiColon,
+        ];
+        assert_eq!(lex("./src/lexer/tests/types.c0"), expected);
+        // Keywords are whole words, not prefixes
+        let tokens: Vec<Token> = Lexer::new(0, "interval iffy requires")
+            .tokens()
+            .unwrap()
+            .into_iter()
+            .map(|l| l.token)
+            .collect();
+        assert_eq!(
+            tokens,
+            vec![
+                Token::Ident(String::from("interval")),
+                Token::Ident(String::from("iffy")),
+                Token::Ident(String::from("requires")),
+            ]
+        );
+    }
+
+    #[test]
+    fn lexing_annotations() {
+        let tokens = lex("./src/lexer/tests/exp.c0");
+        assert_eq!(
+            &tokens[..10],
+            &[
+                Token::Int,
+                Token::Ident(String::from("exp")),
+                Token::LParen,
+                Token::Int,
+                Token::Ident(String::from("b")),
+                Token::Comma,
+                Token::Int,
+                Token::Ident(String::from("e")),
+                Token::RParen,
+                Token::AnnoStart,
+            ]
+        );
+        assert_eq!(
+            &tokens[10..15],
+            &[
+                Token::Requires,
+                Token::Ident(String::from("e")),
+                Token::Gte,
+                Token::Num(0),
+                Token::SemiColon,
+            ]
+        );
+        assert_eq!(tokens[15], Token::AnnoEnd);
+    }
+
+    #[test]
+    fn lexing_literals() {
+        let tokens = Lexer::new(0, "#use <conio>\n\"a\\n\" '\\0' 'x' /* a /* nested */ comment */ x")
+            .tokens()
+            .unwrap();
+        let tokens: Vec<Token> = tokens.into_iter().map(|l| l.token).collect();
+        assert_eq!(
+            tokens,
+            vec![
+                Token::Use,
+                Token::LibLit(String::from("conio")),
+                Token::StrLit(String::from("a\n")),
+                Token::ChrLit('\0'),
+                Token::ChrLit('x'),
+                Token::Ident(String::from("x")),
+            ]
+        );
+    }
+
+    #[test]
+    fn keeping_comments() {
+        let text = "x; // one\r\n/* two /* nested */ */ y //@assert z; // three\n";
+        let (tokens, comments) = Lexer::new(0, text).tokens_and_comments().unwrap();
+        assert_eq!(tokens.len(), 8);
+        let comments: Vec<(&str, usize)> = comments.iter().map(|c| (&c.text[..], c.span.lo)).collect();
+        assert_eq!(
+            comments,
+            vec![("// one", 3), ("/* two /* nested */ */", 11), ("// three", 49)]
+        );
+    }
 
-            _   => Token::Undefined(Some(head)), 
+    #[test]
+    fn lexing_errors() {
+        assert!(Lexer::new(0, "012").tokens().is_err());
+        assert!(Lexer::new(0, "2147483649").tokens().is_err());
+        assert!(Lexer::new(0, "\"abc").tokens().is_err());
+        assert!(Lexer::new(0, "$").tokens().is_err());
+        let span = Lexer::new(0, "x $").tokens().unwrap_err().span;
+        assert_eq!((span.lo, span.hi), (2, 3));
+        // Only printing ASCII characters are written as they are in literals
+        let cases = [
+            ("'中'", "non-ASCII character `中` in a literal, C0 characters are ASCII", (1, 4)),
+            ("\"a中b\"", "non-ASCII character `中` in a literal, C0 characters are ASCII", (2, 5)),
+            ("'\t'", "unprintable character U+0009 in a literal, write an escape sequence", (1, 2)),
+            ("\"a\x01\"", "unprintable character U+0001 in a literal, write an escape sequence", (2, 3)),
+        ];
+        for &(text, message, (lo, hi)) in &cases {
+            let e = Lexer::new(0, text).tokens().unwrap_err();
+            assert_eq!((&e.message[..], e.span.lo, e.span.hi), (message, lo, hi), "{}", text);
         }
+        assert!(Lexer::new(0, "'~' \" !\\t\"").tokens().is_ok());
     }
+}
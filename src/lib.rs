@@ -0,0 +1,12 @@
+#![allow(clippy::module_inception)]
+
+pub mod source;
+pub mod lexer;
+pub mod ast;
+pub mod parser;
+pub mod semant;
+pub mod elab;
+pub mod ir;
+pub mod codegen;
+pub mod interp;
+pub mod driver;
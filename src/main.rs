@@ -1,7 +1,9 @@
-mod lexer;
-mod parser;
-extern crate either;
+extern crate c0;
+
+use std::env;
+use std::process;
 
 fn main() {
-    ()
+    let args: Vec<String> = env::args().skip(1).collect();
+    process::exit(c0::driver::driver::main(&args))
 }
@@ -1,10 +1,9 @@
 #![allow(non_snake_case)]
-use lexer::lexer::*;
-use std::vec::Vec;
-use std::result;
-use std::collections::VecDeque;
-use std::string::String;
-use either::Either;
+use ast::ast::*;
+use lexer::lexer::{Lexeme, Token};
+use source::diagnostic::Diagnostic;
+use source::source_map::Span;
+use std::collections::HashSet;
 
 /*
 The purpose of the parser is to apply semantic meaning to our language lexemes.
@@ -17,295 +16,600 @@ Our parser must therefore perform the following functions
 Fortunately there is a formal grammar specification
 for the C0 language. The problems with this grammar is that
 it's left recursive which will introduce complications with parsing down the line.
+
+The grammar is also ambiguous without knowing which identifiers name types,
+`a * b;` declares `b` when `a` is a typedef and multiplies otherwise. Like C
+compilers we remember typedef names as they are declared, the set lives in a
+`ParseContext` so it can be shared by all the files of a program.
  */
-#[derive(Clone, Debug, PartialEq)]
-enum Exp{
-    Id(Vec<Token>),
-    Num(u32),
-    Sep(Token),
-    Unop(Token),
-    Binop(Token),
-    Asnop(Token),
-    Postop(Token),
-    Tp(Vec<Either<Exp, Token>>), // TODO: tp changes using either
-    Expr( Vec<Either<Exp,Token>>),
-}
-fn seps() -> Vec<Token> {
-    vec![
-        Token::LParen,
-        Token::RParen,
-        Token::LBracket,
-        Token::RBracket,
-        Token::LCurly,
-        Token::RCurly,
-        Token::Comma,
-        Token::SemiColon
-    ]
-}
+pub type ParseResult<T> = Result<T, Diagnostic>;
 
-fn unops() -> Vec<Token> {
-    vec![
-        Token::Not,
-        Token::BitNot,
-        Token::Minus,
-        Token::Mult,
-    ]
+#[derive(Clone, Debug, Default)]
+pub struct ParseContext {
+    pub typedefs: HashSet<String>,
+    pub next_id: NodeId,
 }
 
-fn binops() -> Vec<Token> {
-    vec![
-        Token::FieldSelect,
-        Token::FieldDeref,
-        Token::Mult,
-        Token::Div,
-        Token::Mod,
-        Token::Mod,
-        Token::Plus,
-        Token::Minus,
-        Token::LShift,
-        Token::RShift,
-        Token::Lt,
-        Token::Lte,
-        Token::Gte,
-        Token::Gt,
-        Token::Equality,
-        Token::NotEq,
-        Token::And,
-        Token::Xor,
-        Token::Or,
-        Token::BooleanAnd,
-        Token::BooleanOr,
-        Token::TernIf,
-        Token::TernElse,
-    ]
+#[derive(Clone)]
+pub struct Parser {
+    lexemes: Vec<Lexeme>,
+    pos: usize,
+    ctx: ParseContext,
+    // `#use` directives must come before any other declaration
+    seen_decl: bool,
 }
 
-fn asnops() -> Vec<Token>{
-    vec![
-        Token::Equal,
-        Token::PlusEq,
-        Token::MinusEq,
-        Token::MultEq,
-        Token::DivEq,
-        Token::ModEq,
-        Token::LShiftEq,
-        Token::RShiftEq,
-        Token::AndEq,
-        Token::XorEq,
-        Token::OrEq,
-    ]
-}
-fn postops() -> Vec<Token>{
-    vec![
-        Token::PostMinusEq,
-        Token::PostPlusEq,
-    ]
-}
-fn exprTokens() -> Vec<Token> {
-    vec![
-        Token::LParen,
-        Token::True,
-        Token::False,
-        Token::Num(1),
-        Token::Undefined(None),
-        Token::Alloc,
-        Token::AllocArray,
-        Token::Null,
-    ]
+fn unexpected(found: Option<&Lexeme>, expected: &str, eof: Span) -> Diagnostic {
+    match found {
+        Some(l) => Diagnostic::error(l.span, format!("expected {}, found `{}`", expected, l.token)),
+        None => Diagnostic::error(eof, format!("expected {}, found end of file", expected)),
+    }
 }
 
-type ParseResult = Result<Exp, ()>;
+fn binop(t: &Token) -> Option<BinOp> {
+    let op = match *t {
+        Token::Mult => BinOp::Mul,
+        Token::Div => BinOp::Div,
+        Token::Mod => BinOp::Mod,
+        Token::Plus => BinOp::Add,
+        Token::Minus => BinOp::Sub,
+        Token::LShift => BinOp::Shl,
+        Token::RShift => BinOp::Shr,
+        Token::Lt => BinOp::Lt,
+        Token::Lte => BinOp::Le,
+        Token::Gte => BinOp::Ge,
+        Token::Gt => BinOp::Gt,
+        Token::Equality => BinOp::Eq,
+        Token::NotEq => BinOp::Ne,
+        Token::And => BinOp::BitAnd,
+        Token::Xor => BinOp::BitXor,
+        Token::Or => BinOp::BitOr,
+        Token::BooleanAnd => BinOp::And,
+        Token::BooleanOr => BinOp::Or,
+        _ => return None,
+    };
+    Some(op)
+}
 
-fn tryParseWithoutFail(
-    f: fn(parser: &mut Parser) -> ParseResult,
-    acc: &mut Vec<Either<Exp, Token>>,
-    parser: &mut Parser)
-{
-    match f(parser) {
-        Ok(e) => acc.push(Either::Left(e)),
-        _ => ()
-    }
+fn unop(t: &Token) -> Option<UnOp> {
+    let op = match *t {
+        Token::Not => UnOp::Not,
+        Token::BitNot => UnOp::BitNot,
+        Token::Minus => UnOp::Neg,
+        Token::Mult => UnOp::Deref,
+        _ => return None,
+    };
+    Some(op)
 }
 
-fn tryParseWithFail(
-    f: fn(parser: &mut Parser) -> ParseResult,
-    acc: &mut Vec<Either<Exp, Token>>,
-    parser: &mut Parser,
-    message: String )
-{
-    match f(parser) {
-        Ok(e) => acc.push(Either::Left(e)),
-        _ => panic!(message),
-    }
+fn asnop(t: &Token) -> Option<AsnOp> {
+    let op = match *t {
+        Token::Equal => AsnOp::Assign,
+        Token::PlusEq => AsnOp::Compound(BinOp::Add),
+        Token::MinusEq => AsnOp::Compound(BinOp::Sub),
+        Token::MultEq => AsnOp::Compound(BinOp::Mul),
+        Token::DivEq => AsnOp::Compound(BinOp::Div),
+        Token::ModEq => AsnOp::Compound(BinOp::Mod),
+        Token::LShiftEq => AsnOp::Compound(BinOp::Shl),
+        Token::RShiftEq => AsnOp::Compound(BinOp::Shr),
+        Token::AndEq => AsnOp::Compound(BinOp::BitAnd),
+        Token::XorEq => AsnOp::Compound(BinOp::BitXor),
+        Token::OrEq => AsnOp::Compound(BinOp::BitOr),
+        _ => return None,
+    };
+    Some(op)
 }
 
-type EatResult = Result<Token, ()>;
-fn tryEatWithFail(
-    f: fn(parser: &mut Parser) -> EatResult,
-    acc: &mut Vec<Either<Exp, Token>>,
-    parser: &mut Parser,
-    message: String
-) {
-    match f(parser) {
-        Ok(t) => acc.push(Either::Right(t)),
-        _ => panic!(message),
+fn postop(t: &Token) -> Option<PostOp> {
+    match *t {
+        Token::PostPlusEq => Some(PostOp::Incr),
+        Token::PostMinusEq => Some(PostOp::Decr),
+        _ => None,
     }
 }
 
-#[derive(Clone)]
-pub struct Parser {
-    lexer: Lexer,
-    head: Option<Token>,
-}
 impl Parser {
-    pub fn new(file_path: &mut String) -> Parser {
-        let mut lexer = Lexer::new(file_path);
-        let head = lexer.next();
+    pub fn new(lexemes: Vec<Lexeme>) -> Parser {
+        Parser::with_context(lexemes, ParseContext::default())
+    }
+
+    pub fn with_context(lexemes: Vec<Lexeme>, ctx: ParseContext) -> Parser {
         Parser {
-            lexer,
-            head,
+            lexemes,
+            pos: 0,
+            ctx,
+            seen_decl: false,
         }
     }
 
+    // Hands back the typedef names and node ids for parsing the next file
+    pub fn context(self) -> ParseContext {
+        self.ctx
+    }
 
+    // The same while parsing, to lend it to a file this one uses
+    pub fn context_mut(&mut self) -> &mut ParseContext {
+        &mut self.ctx
+    }
 
-    fn eat(&mut self, t: Token) -> Result<Token, ()>{
-        match self.head {
-            Some(h) =>{
-                match (h, t) {
-                    (Token::Undefined(_), Token::Undefined(_)) |
-                    (Token::Num(_), Token::Num(_)) => {
-                        self.head = self.lexer.next();
-                        Ok(h)
-                    },
-                    (_, _) => {
-                        if h == t {
-                            self.head = self.lexer.next();
-                            Ok(h)
-                        } else {
-                            Err(())
-                        }
-                    }
-                }
-            },
-            _ => Err(()),
+    pub fn isTypedef(&self, name: &str) -> bool {
+        self.ctx.typedefs.contains(name)
+    }
+
+    fn peek(&self) -> Option<&Token> {
+        self.peekAt(0)
+    }
+
+    fn peekAt(&self, n: usize) -> Option<&Token> {
+        self.lexemes.get(self.pos + n).map(|l| &l.token)
+    }
+
+    fn check(&self, t: &Token) -> bool {
+        self.peek() == Some(t)
+    }
+
+    pub fn atEnd(&self) -> bool {
+        self.pos >= self.lexemes.len()
+    }
+
+    // Span of the next lexeme, or an empty span after the last one at the end of input
+    fn span(&self) -> Span {
+        match self.lexemes.get(self.pos) {
+            Some(l) => l.span,
+            None => self.eof(),
         }
     }
 
-    /* The following parse functions handle the parsing of language rules that produce
-     * tokens
-    <id> ::= [A-Za-z_][A-Za-z0-9_]*
-    <num> ::= <decnum> | <hexnum>
-    <decnum> ::= 0 | [1-9][0-9]*
-    <hexnum> ::= 0[xX][0-9a-fA-F]+
-    <strlit> ::= "<schar>*"
-    <chrlit> ::= ’<cchar>’
-    <liblit> ::= <<lchar>*>
-    <schar> ::= <nchar> | <esc>
-    <cchar> ::= <nchar> | <esc> | " | \0
-    <nchar> ::= (normal printing character except ")
-    <lchar> ::= (normal printing character except >)
-    <esc> ::= \n | \t | \v | \b | \r | \f | \a
-    | \\ | \’ | \"
-    <sep> ::= ( | ) | [ | ] | { | } | , | ;
-    <unop> ::= ! | ~ | - | *
-    <binop> ::= . | -> | * | / | % | + | - | << | >>
-    | < | <= | >= | > | == | !=
-    | & | ^ | | | && | || | ? | :
-    <asnop> ::= = | += | -= | *= | /= | %= | <<= | >>=
-    | &= | ^= | |=
-    <postop> ::= -- | ++
-     */
-    // TODO?: support for stringlit and chrlit
+    fn eof(&self) -> Span {
+        match self.lexemes.last() {
+            Some(l) => Span::new(l.span.file, l.span.hi, l.span.hi),
+            None => Span::default(),
+        }
+    }
 
-    // <id> ::= [A-Za-z_][A-Za-z0-9_]*
-    pub fn parseId(&mut self) -> Result<Exp, ()>{
-        let mut tokens = Vec::new();
-        loop {
-            match self.eat(Token::Undefined(None)){
-                Ok(t) => tokens.push(t),
-                Err(_) => break,
-            }
+    // Span from `lo` up to the end of the last consumed lexeme
+    fn spanFrom(&self, lo: Span) -> Span {
+        if self.pos == 0 {
+            return lo;
         }
-        if tokens.len() > 0 {
-            Ok(Exp::Id(tokens))
+        lo.to(self.lexemes[self.pos - 1].span)
+    }
+
+    fn bump(&mut self) -> Span {
+        let span = self.span();
+        self.pos += 1;
+        span
+    }
+
+    fn accept(&mut self, t: &Token) -> bool {
+        if self.check(t) {
+            self.pos += 1;
+            true
         } else {
-            Err(())
+            false
         }
     }
 
-    // num ::= 0 | [1-9][0-9]* // TODO: lexing hex
-    pub fn parseNum(&mut self) -> Result<Exp, ()>{
-        match self.eat(Token::Num(0)) {
-            Ok(Token::Num(x)) => Ok(Exp::Num(x)),
-            _ => Err(()),
+    fn eat(&mut self, t: Token) -> ParseResult<Span> {
+        if self.check(&t) {
+            Ok(self.bump())
+        } else {
+            Err(self.unexpected(&format!("`{}`", t)))
         }
     }
 
+    fn unexpected(&self, expected: &str) -> Diagnostic {
+        unexpected(self.lexemes.get(self.pos), expected, self.eof())
+    }
 
-    //<sep> ::= ( | ) | [ | ] | { | } | , | ;
-    pub fn parseSep(&mut self) -> Result<Exp, ()> {
-        for t in seps()  {
-            match self.eat(t) {
-                Ok(t) => return Ok(Exp::Sep(t)),
-                _ => (),
-            }
+    fn fresh(&mut self) -> NodeId {
+        let id = self.ctx.next_id;
+        self.ctx.next_id += 1;
+        id
+    }
+
+    fn exp(&mut self, kind: ExpKind, span: Span) -> Exp {
+        Exp {
+            id: self.fresh(),
+            kind,
+            span,
         }
-        Err(())
     }
 
+    // <id> ::= [A-Za-z_][A-Za-z0-9_]*
+    pub fn parseId(&mut self) -> ParseResult<Ident> {
+        let name = match self.peek() {
+            Some(Token::Ident(name)) => name.clone(),
+            _ => return Err(self.unexpected("an identifier")),
+        };
+        let span = self.bump();
+        Ok(Ident {
+            id: self.fresh(),
+            name,
+            span,
+        })
+    }
 
-    // <unop> ::= ! | ~ | - | *
-    pub fn parseUnop(&mut self) -> Result<Exp, ()> {
-        for t in unops() {
-            match self.eat(t) {
-                Ok(Token::Mult) => return Ok(Exp::Unop(Token::PointerDeref)),
-                Ok(t) => return Ok(Exp::Unop(t)),
-                _ => (),
-            }
+    // <prog> ::= (<gdecl> | <gdefn>)*
+    pub fn parseProgram(&mut self) -> ParseResult<Program> {
+        let mut decls = Vec::new();
+        while !self.atEnd() {
+            decls.push(self.parseGDecl()?);
         }
-        Err(())
+        Ok(Program { decls })
     }
 
-    //<binop> ::= . | -> | * | / | % | + | - | << | >>
-    //    | < | <= | >= | > | == | !=
-    //    | & | ^ | | | && | || | ? | :
+    /*
+    <gdecl> ::= struct <sid> ;
+              | <tp> <vid> ( [<tp> <vid> (, <tp> <vid>)*] ) ;
+              | #use <liblit> \n | #use <strlit> \n
+    <gdefn> ::= struct <sid> { (<tp> <fid> ;)* } ;
+              | <tp> <vid> ( [<tp> <vid> (, <tp> <vid>)*] ) { <stmt>* }
+              | typedef <tp> <aid> ;
+     */
+    pub fn parseGDecl(&mut self) -> ParseResult<GDecl> {
+        let lo = self.span();
+        if self.accept(&Token::Use) {
+            if self.seen_decl {
+                return Err(Diagnostic::error(lo, "#use directives must come before all declarations"));
+            }
+            let kind = match self.peek() {
+                Some(Token::LibLit(lib)) => Use::Library(lib.clone()),
+                Some(Token::StrLit(file)) => Use::File(file.clone()),
+                _ => return Err(self.unexpected("a library name or file name after #use")),
+            };
+            self.bump();
+            return Ok(GDecl {
+                kind: GDeclKind::Use(kind),
+                span: self.spanFrom(lo),
+            });
+        }
+        self.seen_decl = true;
 
+        if self.accept(&Token::Typedef) {
+            let tp = self.parseTp()?;
+            let name = self.parseId()?;
+            self.eat(Token::SemiColon)?;
+            self.ctx.typedefs.insert(name.name.clone());
+            return Ok(GDecl {
+                kind: GDeclKind::Typedef(tp, name),
+                span: self.spanFrom(lo),
+            });
+        }
 
-    pub fn parseBinop(&mut self) -> Result<Exp, ()> {
-        for t in binops() {
-            match self.eat(t) {
-                Ok(t) => return Ok(Exp::Binop(t)),
-                _ => (),
+        let isStruct = match (self.peek(), self.peekAt(1), self.peekAt(2)) {
+            (Some(&Token::Struct), Some(&Token::Ident(_)), Some(t)) => {
+                *t == Token::SemiColon || *t == Token::LCurly
+            }
+            _ => false,
+        };
+        if isStruct {
+            self.bump();
+            let name = self.parseId()?;
+            if self.accept(&Token::SemiColon) {
+                return Ok(GDecl {
+                    kind: GDeclKind::StructDecl(name),
+                    span: self.spanFrom(lo),
+                });
+            }
+            self.eat(Token::LCurly)?;
+            let mut fields = Vec::new();
+            while !self.accept(&Token::RCurly) {
+                let fieldLo = self.span();
+                let tp = self.parseTp()?;
+                let fieldName = self.parseId()?;
+                self.eat(Token::SemiColon)?;
+                fields.push(Field {
+                    tp,
+                    name: fieldName,
+                    span: self.spanFrom(fieldLo),
+                });
+            }
+            self.eat(Token::SemiColon)?;
+            return Ok(GDecl {
+                kind: GDeclKind::StructDef(name, fields),
+                span: self.spanFrom(lo),
+            });
+        }
+
+        let ret = self.parseTp()?;
+        let name = self.parseId()?;
+        self.eat(Token::LParen)?;
+        let mut params = Vec::new();
+        if !self.accept(&Token::RParen) {
+            loop {
+                let paramLo = self.span();
+                let tp = self.parseTp()?;
+                let paramName = self.parseId()?;
+                params.push(Param {
+                    tp,
+                    name: paramName,
+                    span: self.spanFrom(paramLo),
+                });
+                if self.accept(&Token::RParen) {
+                    break;
+                }
+                self.eat(Token::Comma)?;
             }
         }
-        Err(())
+        let specs = self.parseAnnotations(&[SpecKind::Requires, SpecKind::Ensures])?;
+        let body = if self.accept(&Token::SemiColon) {
+            None
+        } else if self.check(&Token::LCurly) {
+            Some(self.parseBlock()?)
+        } else {
+            return Err(self.unexpected("`;` or a function body"));
+        };
+        Ok(GDecl {
+            kind: GDeclKind::Function(Function {
+                ret,
+                name,
+                params,
+                specs,
+                body,
+            }),
+            span: self.spanFrom(lo),
+        })
     }
 
+    /*
+    Annotations hold a sequence of contracts
+
+    <anno> ::= //@ (<spec> <exp> ;)* \n | /*@ (<spec> <exp> ;)* @*/
+    <spec> ::= requires | ensures | loop_invariant | assert
 
-    //<asnop> ::= = | += | -= | *= | /= | %= | <<= | >>=
-    //    | &= | ^= | |=
-    pub fn parseAsnop(&mut self) -> Result<Exp, ()> {
-        for t in asnops(){
-            match self.eat(t) {
-                Ok(t) => return Ok(Exp::Asnop(t)),
-                _ => (),
+    which kinds are allowed depends on where the annotation appears.
+     */
+    fn parseAnnotations(&mut self, allowed: &[SpecKind]) -> ParseResult<Vec<Spec>> {
+        let mut specs = Vec::new();
+        while self.accept(&Token::AnnoStart) {
+            while !self.accept(&Token::AnnoEnd) {
+                let lo = self.span();
+                let kind = match self.peek() {
+                    Some(&Token::Requires) => SpecKind::Requires,
+                    Some(&Token::Ensures) => SpecKind::Ensures,
+                    Some(&Token::LoopInvariant) => SpecKind::LoopInvariant,
+                    Some(&Token::Assert) => SpecKind::Assert,
+                    _ => return Err(self.unexpected("a contract")),
+                };
+                self.bump();
+                if !allowed.contains(&kind) {
+                    let place = match kind {
+                        SpecKind::Requires | SpecKind::Ensures => "before a function body",
+                        SpecKind::LoopInvariant => "before a loop body",
+                        SpecKind::Assert => "among statements",
+                    };
+                    return Err(Diagnostic::error(lo, format!("@{} may only appear {}", kind, place)));
+                }
+                let exp = self.parseExp()?;
+                self.eat(Token::SemiColon)?;
+                specs.push(Spec {
+                    kind,
+                    exp,
+                    span: self.spanFrom(lo),
+                });
             }
         }
+        Ok(specs)
+    }
+
+    pub fn parseBlock(&mut self) -> ParseResult<Block> {
+        let lo = self.eat(Token::LCurly)?;
+        let mut stmts = Vec::new();
+        while !self.accept(&Token::RCurly) {
+            if self.atEnd() {
+                return Err(self.unexpected("`}`"));
+            }
+            stmts.push(self.parseStmt()?);
+        }
+        Ok(Block {
+            stmts,
+            span: self.spanFrom(lo),
+        })
+    }
+
+    /*
+    <stmt> ::= <simple> ;
+             | if ( <exp> ) <stmt> [ else <stmt> ]
+             | while ( <exp> ) <stmt>
+             | for ( [<simple>] ; <exp> ; [<simple>] ) <stmt>
+             | return [<exp>] ;
+             | break ;
+             | continue ;
+             | { <stmt>* }
+             | assert ( <exp> ) ;
+             | error ( <exp> ) ;
+    The dangling else binds to the nearest if.
+     */
+    pub fn parseStmt(&mut self) -> ParseResult<Stmt> {
+        let lo = self.span();
+        let kind = match self.peek() {
+            Some(&Token::If) => {
+                self.bump();
+                self.eat(Token::LParen)?;
+                let cond = self.parseExp()?;
+                self.eat(Token::RParen)?;
+                let then = self.parseStmt()?;
+                let otherwise = if self.accept(&Token::Else) {
+                    Some(Box::new(self.parseStmt()?))
+                } else {
+                    None
+                };
+                StmtKind::If(cond, Box::new(then), otherwise)
+            }
+            Some(&Token::While) => {
+                self.bump();
+                self.eat(Token::LParen)?;
+                let cond = self.parseExp()?;
+                self.eat(Token::RParen)?;
+                let invariants = self.parseAnnotations(&[SpecKind::LoopInvariant])?;
+                let body = self.parseStmt()?;
+                StmtKind::While(cond, invariants, Box::new(body))
+            }
+            Some(&Token::For) => {
+                self.bump();
+                self.eat(Token::LParen)?;
+                let init = if self.check(&Token::SemiColon) {
+                    None
+                } else {
+                    Some(self.parseSimple()?)
+                };
+                self.eat(Token::SemiColon)?;
+                let cond = self.parseExp()?;
+                self.eat(Token::SemiColon)?;
+                let step = if self.check(&Token::RParen) {
+                    None
+                } else {
+                    let step = self.parseSimple()?;
+                    if let SimpleKind::Decl(..) = step.kind {
+                        return Err(Diagnostic::error(
+                            step.span,
+                            "the step of a for loop cannot declare a variable",
+                        ));
+                    }
+                    Some(step)
+                };
+                self.eat(Token::RParen)?;
+                let invariants = self.parseAnnotations(&[SpecKind::LoopInvariant])?;
+                let body = self.parseStmt()?;
+                StmtKind::For(init.map(Box::new), cond, step.map(Box::new), invariants, Box::new(body))
+            }
+            Some(&Token::Return) => {
+                self.bump();
+                let exp = if self.check(&Token::SemiColon) {
+                    None
+                } else {
+                    Some(self.parseExp()?)
+                };
+                self.eat(Token::SemiColon)?;
+                StmtKind::Return(exp)
+            }
+            Some(&Token::Break) | Some(&Token::Continue) => {
+                let isBreak = self.check(&Token::Break);
+                self.bump();
+                self.eat(Token::SemiColon)?;
+                if isBreak {
+                    StmtKind::Break
+                } else {
+                    StmtKind::Continue
+                }
+            }
+            Some(&Token::LCurly) => StmtKind::Block(self.parseBlock()?),
+            Some(&Token::Assert) | Some(&Token::Error) => {
+                let isAssert = self.check(&Token::Assert);
+                self.bump();
+                self.eat(Token::LParen)?;
+                let exp = self.parseExp()?;
+                self.eat(Token::RParen)?;
+                self.eat(Token::SemiColon)?;
+                if isAssert {
+                    StmtKind::Assert(exp)
+                } else {
+                    StmtKind::Error(exp)
+                }
+            }
+            Some(&Token::AnnoStart) => StmtKind::Annotation(self.parseAnnotations(&[SpecKind::Assert])?),
+            _ => {
+                let simple = self.parseSimple()?;
+                self.eat(Token::SemiColon)?;
+                StmtKind::Simple(simple)
+            }
+        };
+        Ok(Stmt {
+            kind,
+            span: self.spanFrom(lo),
+        })
+    }
 
-        Err(())
+    fn startsTp(&self) -> bool {
+        match self.peek() {
+            Some(&Token::Int)
+            | Some(&Token::Bool)
+            | Some(&Token::String)
+            | Some(&Token::Char)
+            | Some(&Token::Void)
+            | Some(&Token::Struct) => true,
+            Some(Token::Ident(name)) => self.isTypedef(name) || self.looksLikeDecl(),
+            _ => false,
+        }
     }
 
-    //<postop> ::= -- | ++
-    pub fn parsePostop(&mut self) -> Result<Exp, ()> {
-        for t in postops() {
-            match self.eat(t) {
-                Ok(t) => return Ok(Exp::Postop(t)),
-                _ => (),
+    /*
+    A name we have not seen a typedef for may still be a type, defined in a
+    library header or another file. When what follows can only be read as a
+    declaration, `t x`, `t[] x` or `t* x = e`, we parse one and leave the
+    unknown type name to be reported later. `t * x;` stays a multiplication.
+     */
+    fn looksLikeDecl(&self) -> bool {
+        let mut n = 1;
+        let mut stars = false;
+        let mut brackets = false;
+        loop {
+            match (self.peekAt(n), self.peekAt(n + 1)) {
+                (Some(&Token::Mult), _) => {
+                    stars = true;
+                    n += 1;
+                }
+                (Some(&Token::LBracket), Some(&Token::RBracket)) => {
+                    brackets = true;
+                    n += 2;
+                }
+                _ => break,
             }
         }
+        match self.peekAt(n) {
+            Some(&Token::Ident(_)) => !stars || brackets || self.peekAt(n + 1) == Some(&Token::Equal),
+            _ => false,
+        }
+    }
 
-        Err(())
+    /*
+    <simple> ::= <lv> <asnop> <exp>
+               | <lv> ++
+               | <lv> --
+               | <exp>
+               | <tp> <vid> [= <exp>]
+    Rather than parsing an <lv> up front we parse an expression and check it
+    has the shape of an l-value once we see an assignment or postfix operator.
+     */
+    pub fn parseSimple(&mut self) -> ParseResult<Simple> {
+        let lo = self.span();
+        if self.startsTp() {
+            let tp = self.parseTp()?;
+            let name = self.parseId()?;
+            let init = if self.accept(&Token::Equal) {
+                Some(self.parseExp()?)
+            } else {
+                None
+            };
+            return Ok(Simple {
+                kind: SimpleKind::Decl(tp, name, init),
+                span: self.spanFrom(lo),
+            });
+        }
+        let exp = self.parseExp()?;
+        let asn = self.peek().and_then(asnop);
+        let post = self.peek().and_then(postop);
+        let kind = if asn.is_some() || post.is_some() {
+            if !exp.is_lvalue() {
+                return Err(Diagnostic::error(exp.span, "left-hand side is not assignable"));
+            }
+            self.bump();
+            match (asn, post) {
+                (Some(op), _) => {
+                    let rhs = self.parseExp()?;
+                    SimpleKind::Assign(op, exp, rhs)
+                }
+                (_, Some(op)) => SimpleKind::Post(op, exp),
+                _ => unreachable!(),
+            }
+        } else {
+            SimpleKind::Exp(exp)
+        };
+        Ok(Simple {
+            kind,
+            span: self.spanFrom(lo),
+        })
     }
 
     /*
@@ -339,75 +643,55 @@ impl Parser {
     <tp> ::= B1 | B1<tp'> | ... | Bs | Bs<tp'>
     <tp'> ::= * | *<tp'> | [] | []<tp'>
     */
-    fn _parseTp(&mut self, acc: &mut Vec<Either<Exp,Token>>){
-        for t in vec![
-            Token::Mult,
-            Token::LBracket,
-        ] {
-            match self.eat(t) {
-                Ok(Token::LBracket) => {
-                    match self.eat(Token::RBracket) {
-                        Ok(_) => {
-                            acc.push(Either::Right(Token::LBracket));
-                            acc.push(Either::Right(Token::RBracket));
-                            self._parseTp(acc);
-                        },
-                        _ => panic!("Unmatched left square bracket in source"),
-                    }
-                }
-                Ok(t) => {
-                    acc.push(Either::Right(Token::PointerDeref));
-                    self._parseTp(acc);
-                }
-                _ => (),
-            }
+    fn _parseTp(&mut self, base: Tp) -> ParseResult<Tp> {
+        let lo = base.span;
+        if self.accept(&Token::Mult) {
+            let tp = Tp {
+                kind: TpKind::Pointer(Box::new(base)),
+                span: self.spanFrom(lo),
+            };
+            return self._parseTp(tp);
+        }
+        if self.accept(&Token::LBracket) {
+            self.eat(Token::RBracket)?;
+            let tp = Tp {
+                kind: TpKind::Array(Box::new(base)),
+                span: self.spanFrom(lo),
+            };
+            return self._parseTp(tp);
         }
+        Ok(base)
     }
-    pub fn parseTp(&mut self) -> Result<Exp, ()> {
-        let mut _tpAcc: Vec<Either<Exp, Token>> = vec![];
-        match self.parseId() {
-            Ok(e) => {
-                _tpAcc.push(Either::Left(e));
-                self._parseTp(&mut _tpAcc);
-                return Ok(Exp::Tp(_tpAcc));
+
+    pub fn parseTp(&mut self) -> ParseResult<Tp> {
+        let lo = self.span();
+        let kind = match self.peek() {
+            Some(&Token::Struct) => {
+                self.bump();
+                TpKind::Struct(self.parseId()?)
             }
-            _ => ()
-        };
-        for t in vec![
-            Token::Int,
-            Token::Bool,
-            Token::Char,
-            Token::String,
-            Token::Void,
-            Token::Struct,
-        ] {
-            match self.eat(t) {
-                Ok(Token::Struct) => {
-                    let f = |parser: &mut Parser|{
-                        parser.parseId()
-                    };
-                    _tpAcc.push(Either::Right(Token::Struct));
-                    tryParseWithFail(
-                        f,
-                        &mut _tpAcc,
-                        self,
-                        String::from("failed to parse id for struct, none specified")
-                    );
-                    self._parseTp(&mut _tpAcc);
-                    return Ok(Exp::Tp(_tpAcc));
-                },
-
-                Ok(t) => {
-                    _tpAcc.push(Either::Right(t));
-                    let leftRecursion = self._parseTp(&mut _tpAcc);
-                    return Ok(Exp::Tp(_tpAcc));
-                },
-                _ => (),
+            Some(&Token::Ident(_)) => TpKind::Name(self.parseId()?),
+            Some(t) => {
+                let kind = match *t {
+                    Token::Int => TpKind::Int,
+                    Token::Bool => TpKind::Bool,
+                    Token::String => TpKind::String,
+                    Token::Char => TpKind::Char,
+                    Token::Void => TpKind::Void,
+                    _ => return Err(self.unexpected("a type")),
+                };
+                self.bump();
+                kind
             }
-        }
-
-        Err(())
+            None => return Err(self.unexpected("a type")),
+        };
+        let base = Tp {
+            kind,
+            span: self.spanFrom(lo),
+        };
+        self._parseTp(base)
     }
+
     /*
     Similar to <tp>, <exp> consists of a lot of left recursive generators
     <exp> ::= ( <exp> )
@@ -419,370 +703,385 @@ impl Parser {
     | <exp> [ <exp> ]
     | alloc ( <tp> ) | alloc_array ( <tp> , <exp> )
 
-    Using Paull's algorithm we get the following
+    The grammar says nothing about how tightly operators bind, so the left
+    recursion is removed by stratifying by precedence instead:
 
-    <exp> ::= ( <exp> ) | ( <exp> ) <exp'> | <num> | <num> <exp'>
-              | true | true <exp'>
-              | false | false <exp'> | NULL | NULL <exp'> | <vid> | <vid> <exp'> | <unop><exp> | <unop> <exp> <exp'>
-              | <vid> ( [<exp> (, <exp>)*] )
-              | <vid> ( [<exp> (, <exp>)*] ) <exp'>
-              | alloc (<tp> )
-              | alloc (<tp> ) <exp'>
-              | alloc_array (<tp>, <exp> )
-              | alloc_array (<tp>, <exp> ) <exp'>
+    <exp>     ::= <binary> | <binary> ? <exp> : <exp>
+    <binary>  ::= <unary> (<binop> <unary>)*        (precedence climbing)
+    <unary>   ::= <unop> <unary> | <postfix>
+    <postfix> ::= <primary> <exp'>
+    <exp'>    ::= . <fid> <exp'> | -> <fid> <exp'> | [ <exp> ] <exp'> | empty
+     */
+    pub fn parseExp(&mut self) -> ParseResult<Exp> {
+        let cond = self.parseBinary(1)?;
+        if !self.accept(&Token::TernIf) {
+            return Ok(cond);
+        }
+        let then = self.parseExp()?;
+        self.eat(Token::TernElse)?;
+        let otherwise = self.parseExp()?;
+        let span = cond.span.to(otherwise.span);
+        Ok(self.exp(
+            ExpKind::Ternary(Box::new(cond), Box::new(then), Box::new(otherwise)),
+            span,
+        ))
+    }
 
-    <exp'> ::= <binop> <exp> | <binop> <exp> <exp'> | ? <exp> : <exp> | ? <exp> : <exp> <exp'>
-              | . <fid> | . <fid> <exp'> | -> <fid> | -> <fid> <exp'> | [ <exp> ] | [ <exp> ] <exp'>
+    fn parseBinary(&mut self, minPrecedence: u8) -> ParseResult<Exp> {
+        let mut lhs = self.parseUnary()?;
+        loop {
+            let op = match self.peek().and_then(binop) {
+                Some(op) if op.precedence() >= minPrecedence => op,
+                _ => return Ok(lhs),
+            };
+            self.bump();
+            let rhs = self.parseBinary(op.precedence() + 1)?;
+            let span = lhs.span.to(rhs.span);
+            lhs = self.exp(ExpKind::Binop(op, Box::new(lhs), Box::new(rhs)), span);
+        }
+    }
 
-    */
-    fn _parseExp (&mut self, acc: &mut Vec<Either<Exp, Token>> ){
-        let mut startTokens = binops();
-        startTokens.push(Token::LBracket);
-        for t in startTokens{
-            match self.eat(t) {
-                Ok(Token::LBracket) => {
-                    acc.push(Either::Right(Token::LBracket));
-                    match self.parseExp() {
-                        Ok(e) => acc.push(Either::Left(e)),
-                        _ => (),
-                    }
-                    match self.eat(Token::RBracket) {
-                        Ok(Token::RBracket) => {
-                            acc.push(Either::Right(Token::RBracket));
-                            self._parseExp(acc);
-                        }
-                        _ => panic!("Improper syntax, missing closing bracket")
-                    }
-                }
-                Ok(Token::FieldSelect)
-                | Ok(Token::FieldDeref) => {
-                    acc.push(Either::Right(t));
-                    match self.parseId() {
-                        Ok(e) => acc.push(Either::Left(e)),
-                        _ => panic!("Invalid struct access, no field id"),
-                    }
-                    self._parseExp(acc);
-                }
-                Ok(Token::TernIf) => {
-                    acc.push(Either::Right(Token::TernIf));
-                    match self.parseExp() {
-                        Ok(e) => acc.push(Either::Left(e)),
-                        _ => (),
-                    }
-                    match self.eat(Token::Else) {
-                        Ok(t) => {
-                            acc.push(Either::Right(t));
-                            match self.parseExp() {
-                                Ok(e) => acc.push(Either::Left(e)),
-                                _ => ()
-                            }
-                            self._parseExp(acc);
-                        }
-                        _ => panic!("unmatched ternary else"),
-                    }
-                }
-                Ok(t) => {
-                    acc.push(Either::Right(t));
-                    match self.parseExp() {
-                        Ok(e) => acc.push(Either::Left(e)),
-                        _ => (),
-                    }
-                    self._parseExp(acc);
-                }
-                _ => ()
+    fn parseUnary(&mut self) -> ParseResult<Exp> {
+        match self.peek().and_then(unop) {
+            Some(op) => {
+                let lo = self.bump();
+                let operand = self.parseUnary()?;
+                let span = lo.to(operand.span);
+                Ok(self.exp(ExpKind::Unop(op, Box::new(operand)), span))
+            }
+            None => {
+                let primary = self.parsePrimary()?;
+                self._parseExp(primary)
             }
         }
     }
 
+    fn _parseExp(&mut self, base: Exp) -> ParseResult<Exp> {
+        let lo = base.span;
+        let kind = match self.peek() {
+            Some(&Token::FieldSelect) => {
+                self.bump();
+                ExpKind::Field(Box::new(base), self.parseId()?)
+            }
+            Some(&Token::FieldDeref) => {
+                self.bump();
+                ExpKind::Arrow(Box::new(base), self.parseId()?)
+            }
+            Some(&Token::LBracket) => {
+                self.bump();
+                let index = self.parseExp()?;
+                self.eat(Token::RBracket)?;
+                ExpKind::Index(Box::new(base), Box::new(index))
+            }
+            _ => return Ok(base),
+        };
+        let span = self.spanFrom(lo);
+        let exp = self.exp(kind, span);
+        self._parseExp(exp)
+    }
 
-    pub fn parseExp(&mut self) -> Result<Exp, ()> {
-        let mut acc: Vec<Either<Exp, Token>> = Vec::new();
-        let tokens = exprTokens();
-        // Try parsing none token values
-        match self.parseNum() {
-            Ok(e) => {
-                acc.push(Either::Left(e));
-                self._parseExp(&mut acc);
-                return Ok(Exp::Expr(acc))
-            },
-            _ => (),
+    fn parsePrimary(&mut self) -> ParseResult<Exp> {
+        let lo = self.span();
+        let token = match self.peek() {
+            Some(t) => t.clone(),
+            None => return Err(self.unexpected("an expression")),
         };
-        match self.parseId() {
-            Ok(e) => {
-                acc.push(Either::Left(e));
-                match self.eat(Token::LParen) {
-                    Ok(t) => {
-                        acc.push(Either::Right(Token::LParen));
-                        loop{
-                            match self.parseExp() {
-                                Ok(e) => {
-                                    acc.push(Either::Left(e));
-                                    match self.eat(Token::Comma) {
-                                        Ok(_) => continue,
-                                        _ => break,
-                                    }
-                                },
-                                _ => break,
+        let kind = match token {
+            Token::Num(n) => {
+                self.bump();
+                ExpKind::Num(n as i32, Radix::Dec)
+            }
+            Token::HexNum(n) => {
+                self.bump();
+                ExpKind::Num(n as i32, Radix::Hex)
+            }
+            Token::True | Token::False => {
+                self.bump();
+                ExpKind::Bool(token == Token::True)
+            }
+            Token::Null => {
+                self.bump();
+                ExpKind::Null
+            }
+            Token::StrLit(s) => {
+                self.bump();
+                ExpKind::Str(s)
+            }
+            Token::ChrLit(c) => {
+                self.bump();
+                ExpKind::Char(c)
+            }
+            Token::Result => {
+                self.bump();
+                ExpKind::Result
+            }
+            Token::LParen => {
+                self.bump();
+                let mut exp = self.parseExp()?;
+                self.eat(Token::RParen)?;
+                exp.span = self.spanFrom(lo);
+                return Ok(exp);
+            }
+            Token::Length => {
+                self.bump();
+                self.eat(Token::LParen)?;
+                let exp = self.parseExp()?;
+                self.eat(Token::RParen)?;
+                ExpKind::Length(Box::new(exp))
+            }
+            Token::Alloc => {
+                self.bump();
+                self.eat(Token::LParen)?;
+                let tp = self.parseTp()?;
+                self.eat(Token::RParen)?;
+                ExpKind::Alloc(tp)
+            }
+            Token::AllocArray => {
+                self.bump();
+                self.eat(Token::LParen)?;
+                let tp = self.parseTp()?;
+                self.eat(Token::Comma)?;
+                let len = self.parseExp()?;
+                self.eat(Token::RParen)?;
+                ExpKind::AllocArray(tp, Box::new(len))
+            }
+            Token::Ident(_) => {
+                let name = self.parseId()?;
+                if self.accept(&Token::LParen) {
+                    let mut args = Vec::new();
+                    if !self.accept(&Token::RParen) {
+                        loop {
+                            args.push(self.parseExp()?);
+                            if self.accept(&Token::RParen) {
+                                break;
                             }
+                            self.eat(Token::Comma)?;
                         }
-                        match self.eat(Token::RParen) {
-                            Ok(_) => acc.push(Either::Right(Token::RParen)),
-                            _ => panic!("unmatched opening bracket"),
-                        }
-                        self._parseExp(&mut acc);
-                        return Ok(Exp::Expr(acc));
-                    }
-                    _ =>  {
-                        self._parseExp(&mut acc);
-                        return Ok(Exp::Expr(acc));
                     }
+                    ExpKind::Call(name, args)
+                } else {
+                    ExpKind::Var(name)
                 }
             }
-            _ => (),
-        };
-        match self.parseUnop() {
-            Ok(e) => {
-                acc.push(Either::Left(e));
-                match self.parseExp() {
-                    Ok(e) => acc.push(Either::Left(e)),
-                    _ => ()
-                }
-                let _exp = self._parseExp(&mut acc);
-                return Ok(Exp::Expr(acc));
-            }
-            _ => (),
+            _ => return Err(self.unexpected("an expression")),
         };
-
-        for t in exprTokens() {
-            match self.eat(t) {
-                Ok(Token::Alloc) => {
-                    acc.push(Either::Right(Token::Alloc));
-                    tryEatWithFail(
-                        |parser| parser.eat(Token::LParen),
-                        &mut acc,
-                        self,
-                        String::from("syntax error, alloc requires a declaration")
-                    );
-                    tryParseWithFail(
-                        |parser| parser.parseTp(),
-                        &mut acc,
-                        self,
-                        String::from("must specify argument to alloc")
-                    );
-                    tryEatWithFail(
-                        |parser| parser.eat(Token::RParen),
-                        &mut acc,
-                        self,
-                        String::from("unmatched closing parentheses")
-                    );
-                    self._parseExp(&mut acc);
-                    return Ok(Exp::Expr(acc));
-                }
-                Ok(Token::AllocArray) => {
-                    acc.push(Either::Right(Token::AllocArray));
-                    tryEatWithFail(
-                        |parser|parser.eat(Token::RParen),
-                        &mut acc,
-                        self,
-                        String::from("alloc_array requires opening bracket arg")
-                    );
-                    tryParseWithFail(
-                        |parser|parser.parseTp(),
-                        &mut acc,
-                        self,
-                        String::from("first argument to alloc array must be specified")
-                    );
-                    tryEatWithFail(
-                        |parser| parser.eat(Token::Comma),
-                        &mut acc,
-                        self,
-                        String::from("must specify expression for alloc array")
-                    );
-                    tryParseWithFail(
-                        |parser| parser.parseExp(),
-                        &mut acc,
-                        self,
-                        String::from("failed to parse expr for second arg in alloc array")
-                    );
-                    tryEatWithFail(
-                        |parser| parser.eat(Token::RParen),
-                        &mut acc,
-                        self,
-                        String::from("missing closing bracket in alloc array")
-                    );
-                    self._parseExp(&mut acc);
-                    return Ok(Exp::Expr(acc));
-                },
-                Ok(t) => {
-                    acc.push(Either::Right(t));
-                    self._parseExp(&mut acc);
-                    return Ok(Exp::Expr(acc));
-                },
-                _ => (),
-            }
-        };
-        Err(())
+        let span = self.spanFrom(lo);
+        Ok(self.exp(kind, span))
     }
-
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
-    use std::fmt::Debug;
-    fn vecCheck<T: PartialEq + Debug>(v1: Vec<T>, v2: Vec<T>) {
-        assert_eq!(v1.len(), v2.len());
-        let count = v1
-            .into_iter()
-            .zip(v2)
-            .map(|(a, b)| {
-                assert_eq!(a, b)
-            }).count();
+    use lexer::lexer::Lexer;
+    use std::fs;
+
+    fn parser(path: &str) -> Parser {
+        let text = fs::read_to_string(path).unwrap();
+        Parser::new(Lexer::new(0, &text).tokens().unwrap())
     }
+
+    fn parse(text: &str) -> ParseResult<Program> {
+        Parser::new(Lexer::new(0, text).tokens().unwrap()).parseProgram()
+    }
+
+    fn tp(kind: TpKind) -> Tp {
+        Tp {
+            kind,
+            span: Span::default(),
+        }
+    }
+
+    fn id(name: &str) -> Ident {
+        Ident {
+            id: 0,
+            name: String::from(name),
+            span: Span::default(),
+        }
+    }
+
+    // Prints expressions fully parenthesised to make precedence visible
+    fn shape(e: &Exp) -> String {
+        match e.kind {
+            ExpKind::Num(n, _) => n.to_string(),
+            ExpKind::Var(ref x) => x.name.clone(),
+            ExpKind::Unop(op, ref e) => format!("{}{}", op, shape(e)),
+            ExpKind::Binop(op, ref l, ref r) => format!("({} {} {})", shape(l), op, shape(r)),
+            ExpKind::Ternary(ref c, ref t, ref f) => {
+                format!("({} ? {} : {})", shape(c), shape(t), shape(f))
+            }
+            ExpKind::Arrow(ref e, ref f) => format!("{}->{}", shape(e), f.name),
+            ExpKind::Field(ref e, ref f) => format!("{}.{}", shape(e), f.name),
+            ExpKind::Index(ref a, ref i) => format!("{}[{}]", shape(a), shape(i)),
+            ExpKind::Call(ref f, ref args) => {
+                let args: Vec<String> = args.iter().map(shape).collect();
+                format!("{}({})", f.name, args.join(", "))
+            }
+            _ => String::from("?"),
+        }
+    }
+
     #[test]
     fn parsingLexicalTokens() {
-        let mut parser = Parser::new(&mut String::from("./src/parser/tests/tokens.txt"));
-        let expectedResult: Vec<Result<Exp, ()>> = vec![
-            Ok(Exp::Id(vec![Token::Undefined(Some('v')), Token::Undefined(Some('a'))])),
-            Ok(Exp::Num(1234)),
-            Ok(Exp::Sep(Token::LParen)),
-            Ok(Exp::Sep(Token::RParen)),
-            Ok(Exp::Sep(Token::LBracket)),
-            Ok(Exp::Sep(Token::RBracket)),
-            Ok(Exp::Sep(Token::LCurly)),
-            Ok(Exp::Sep(Token::RCurly)),
-            Ok(Exp::Sep(Token::Comma)),
-            Ok(Exp::Sep(Token::SemiColon)),
-            Ok(Exp::Unop(Token::Not)),
-            Ok(Exp::Unop(Token::BitNot)),
-            Ok(Exp::Unop(Token::PointerDeref)),
-            Ok(Exp::Unop(Token::Minus)),
-            Ok(Exp::Binop(Token::FieldSelect)),
-            Ok(Exp::Binop(Token::FieldDeref)),
-            Ok(Exp::Binop(Token::Div)),
-            Ok(Exp::Binop(Token::Mod)),
-            Ok(Exp::Binop(Token::Plus)),
-            Ok(Exp::Binop(Token::LShift)),
-            Ok(Exp::Binop(Token::RShift)),
-            Ok(Exp::Binop(Token::Lt)),
-            Ok(Exp::Binop(Token::Lte)),
-            Ok(Exp::Binop(Token::Gte)),
-            Ok(Exp::Binop(Token::Gt)),
-            Ok(Exp::Binop(Token::Equality)),
-            Ok(Exp::Binop(Token::NotEq)),
-            Ok(Exp::Binop(Token::And)),
-            Ok(Exp::Binop(Token::Xor)),
-            Ok(Exp::Binop(Token::Or)),
-            Ok(Exp::Binop(Token::BooleanAnd)),
-            Ok(Exp::Binop(Token::BooleanOr)),
-            Ok(Exp::Binop(Token::TernIf)),
-            Ok(Exp::Binop(Token::TernElse)),
-            Ok(Exp::Asnop(Token::Equal)),
-            Ok(Exp::Asnop(Token::PlusEq)),
-            Ok(Exp::Asnop(Token::MinusEq)),
-            Ok(Exp::Asnop(Token::MultEq)),
-            Ok(Exp::Asnop(Token::DivEq)),
-            Ok(Exp::Asnop(Token::ModEq)),
-            Ok(Exp::Asnop(Token::LShiftEq)),
-            Ok(Exp::Asnop(Token::RShiftEq)),
-            Ok(Exp::Asnop(Token::AndEq)),
-            Ok(Exp::Asnop(Token::XorEq)),
-            Ok(Exp::Asnop(Token::OrEq)),
-            Ok(Exp::Postop(Token::PostMinusEq)),
-            Ok(Exp::Postop(Token::PostPlusEq)),
+        let text = fs::read_to_string("./src/parser/tests/tokens.txt").unwrap();
+        // C0 has no octal literals, so the leading zero of the second line is an error
+        let e = Lexer::new(0, &text).tokens().unwrap_err();
+        assert_eq!(e.message, "decimal literals may not have leading zeros");
+        assert_eq!(&text[e.span.lo..e.span.hi], "01234");
+        let lex = |text: &str| -> Vec<Token> {
+            Lexer::new(0, text).tokens().unwrap().into_iter().map(|l| l.token).collect()
+        };
+        assert_eq!(lex(&text[..e.span.lo]), vec![Token::Ident(String::from("va"))]);
+        let expected = vec![
+            Token::LParen, Token::RParen, Token::LBracket, Token::RBracket, Token::LCurly, Token::RCurly,
+            Token::Comma, Token::SemiColon,
+            Token::Not, Token::BitNot, Token::Mult, Token::Minus,
+            Token::FieldSelect, Token::FieldDeref, Token::Div, Token::Mod, Token::Plus, Token::LShift, Token::RShift,
+            Token::Lt, Token::Lte, Token::Gte, Token::Gt, Token::Equality, Token::NotEq,
+            Token::And, Token::Xor, Token::Or, Token::BooleanAnd, Token::BooleanOr, Token::TernIf, Token::TernElse,
+            Token::Equal, Token::PlusEq, Token::MinusEq, Token::MultEq, Token::DivEq, Token::ModEq,
+            Token::LShiftEq, Token::RShiftEq, Token::AndEq, Token::XorEq, Token::OrEq,
+            Token::PostMinusEq, Token::PostPlusEq,
         ];
-        let mut results: Vec<Result<Exp, ()>> = Vec::new();
-        loop {
-            match parser.parseId() {
-                Ok(exp) => {
-                    results.push(Ok(exp));
-                    continue
-                },
-                _ => (),
-            }
-
-            match parser.parseNum() {
-                Ok(exp) => {
-                    results.push(Ok(exp));
-                    continue
-                },
-                _ => (),
-            }
-
-            match parser.parseSep() {
-                Ok(exp) => {
-                    results.push(Ok(exp));
-                    continue
-                },
-                _ => (),
-            }
-
-            match parser.parseUnop(){
-                Ok(exp) => {
-                    results.push(Ok(exp));
-                    continue
-                },
-                _ => (),
-            }
-
-            match parser.parseBinop() {
-                Ok(exp) => {
-                    results.push(Ok(exp));
-                    continue
-                },
-                _ => (),
-            }
-
-            match parser.parseAsnop() {
-                Ok(exp) => {
-                    results.push(Ok(exp));
-                    continue
-                },
-                _ => (),
-            }
-
-            match parser.parsePostop(){
-                Ok(exp) => {
-                    results.push(Ok(exp));
-                    continue
-                }
-                _ => ()
-            }
-            break
-        }
-        vecCheck(expectedResult, results);
+        assert_eq!(lex(&text[e.span.hi..]), expected);
     }
 
     #[test]
     fn parsingTp() {
-        let mut parser = Parser::new(&mut String::from("./src/parser/tests/tp.txt"));
-        let expectedResult: Vec<Result<Exp, ()>> = vec![
-            Ok(Exp::Tp(vec![Either::Right(Token::Int)])),
-            Ok(Exp::Tp(vec![Either::Right(Token::Char)])),
-            Ok(Exp::Tp(vec![Either::Right(Token::Bool)])),
-            Ok(Exp::Tp(vec![Either::Right(Token::String)])),
-            Ok(Exp::Tp(vec![Either::Right(Token::Void)])),
-            Ok(Exp::Tp(vec![
-                Either::Right(Token::Struct),
-                Either::Left(Exp::Id(vec![Token::Undefined(Some('i'))])),
-                Either::Right(Token::PointerDeref)])),
-            Ok(Exp::Tp(vec![
-                Either::Left(Exp::Id(vec![Token::Undefined(Some('i'))])),
-                Either::Right(Token::PointerDeref),
-                Either::Right(Token::PointerDeref)]))
+        let mut parser = parser("./src/parser/tests/tp.txt");
+        let expectedResult = vec![
+            tp(TpKind::Int),
+            tp(TpKind::Char),
+            tp(TpKind::Bool),
+            tp(TpKind::String),
+            tp(TpKind::Void),
+            tp(TpKind::Pointer(Box::new(tp(TpKind::Struct(id("i")))))),
+            tp(TpKind::Pointer(Box::new(tp(TpKind::Pointer(Box::new(tp(TpKind::Name(id("i"))))))))),
+            tp(TpKind::Array(Box::new(tp(TpKind::Pointer(Box::new(tp(TpKind::Int))))))),
         ];
         let mut results = Vec::new();
-        loop {
-            match parser.parseTp() {
-                Ok(exp) =>
-                    results.push(Ok(exp)),
-                _ => break,
-            }
+        while !parser.atEnd() {
+            results.push(parser.parseTp().unwrap());
         }
-        vecCheck(results, expectedResult);
+        // Node ids are not interesting here
+        let results: Vec<Tp> = results
+            .into_iter()
+            .map(|mut t| {
+                fn clear(t: &mut Tp) {
+                    match t.kind {
+                        TpKind::Struct(ref mut x) | TpKind::Name(ref mut x) => x.id = 0,
+                        TpKind::Pointer(ref mut t) | TpKind::Array(ref mut t) => clear(t),
+                        _ => (),
+                    }
+                }
+                clear(&mut t);
+                t
+            })
+            .collect();
+        assert_eq!(results, expectedResult);
     }
 
-}
+    #[test]
+    fn parsingPrecedence() {
+        let mut parser = parser("./src/parser/tests/precedence.txt");
+        let expected = vec![
+            "(1 + (2 * 3))",
+            "((1 - 2) - 3)",
+            "((a < b) == (c >= d))",
+            "((a && b) || (c && d))",
+            "(a ? b : (c ? d : e))",
+            "(a | (b ^ (c & d)))",
+            "(1 << (2 + 3))",
+            "(-a * *p->next)",
+            "!f(x, (y + 1))[2].val",
+            "((a & b) == c)",
+        ];
+        for e in expected {
+            let exp = parser.parseExp().unwrap();
+            parser.eat(Token::SemiColon).unwrap();
+            assert_eq!(shape(&exp), e);
+        }
+        assert!(parser.atEnd());
+    }
 
+    #[test]
+    fn parsingProgram() {
+        let mut parser = parser("./src/parser/tests/program.c0");
+        let program = parser.parseProgram().unwrap();
+        let kinds: Vec<&str> = program
+            .decls
+            .iter()
+            .map(|d| match d.kind {
+                GDeclKind::Use(_) => "use",
+                GDeclKind::StructDecl(_) => "struct decl",
+                GDeclKind::StructDef(..) => "struct def",
+                GDeclKind::Function(ref f) if f.body.is_some() => "function",
+                GDeclKind::Function(_) => "prototype",
+                GDeclKind::Typedef(..) => "typedef",
+            })
+            .collect();
+        assert_eq!(
+            kinds,
+            vec!["use", "struct decl", "typedef", "struct def", "prototype", "function", "function"]
+        );
+        match program.decls[5].kind {
+            GDeclKind::Function(ref f) => {
+                assert_eq!(f.specs.len(), 2);
+                assert_eq!(f.specs[0].kind, SpecKind::Requires);
+                assert_eq!(f.specs[1].kind, SpecKind::Ensures);
+                let body = f.body.as_ref().unwrap();
+                match body.stmts[0].kind {
+                    // `list* l = ...` is a declaration because list is a typedef
+                    StmtKind::Simple(Simple {
+                        kind: SimpleKind::Decl(..),
+                        ..
+                    }) => (),
+                    ref k => panic!("expected a declaration, found {:?}", k),
+                }
+                match body.stmts[2].kind {
+                    StmtKind::While(_, ref invariants, _) => assert_eq!(invariants.len(), 1),
+                    ref k => panic!("expected a loop, found {:?}", k),
+                }
+            }
+            ref k => panic!("expected a function, found {:?}", k),
+        }
+    }
 
+    #[test]
+    fn parsingUnknownTypes() {
+        // Names without a typedef in view, as declared by a library header
+        let cases = vec![
+            ("file_t f;", true),
+            ("rand_t[] gens;", true),
+            ("bitmap_t* b = NULL;", true),
+            ("a * b;", false),
+            ("a * b = c;", true),
+        ];
+        for (source, decl) in cases {
+            let mut parser = Parser::new(Lexer::new(0, source).tokens().unwrap());
+            let simple = parser.parseSimple().unwrap();
+            assert_eq!(matches!(simple.kind, SimpleKind::Decl(..)), decl, "{}", source);
+        }
+    }
 
+    #[test]
+    fn parsingErrors() {
+        let cases = vec![
+            ("int f() { return 1 }", "expected `;`, found `}`"),
+            ("int f() { 3 = x; }", "left-hand side is not assignable"),
+            ("int f() { x + ; }", "expected an expression, found `;`"),
+            ("int x;", "expected `(`, found `;`"),
+            ("int f()\n//@loop_invariant true;\n{}", "@loop_invariant may only appear before a loop body"),
+            ("int f() {", "expected `}`, found end of file"),
+            ("int f();\n#use <conio>", "#use directives must come before all declarations"),
+            ("int f() { for (;true; int i = 0) {} }", "the step of a for loop cannot declare a variable"),
+            ("void f() { while (true) break }", "expected `;`, found `}`"),
+        ];
+        for (source, message) in cases {
+            match parse(source) {
+                Err(d) => assert_eq!(d.message, message, "{}", source),
+                Ok(_) => panic!("`{}` should not parse", source),
+            }
+        }
+    }
+}
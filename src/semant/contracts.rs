@@ -0,0 +1,358 @@
+use ast::ast::*;
+use ast::fold::Fold;
+use ast::visit::{self, Visitor, VisitorMut};
+use semant::resolve::{DeclId, Resolution};
+use source::diagnostic::Diagnostic;
+use source::source_map::Span;
+use std::collections::HashMap;
+use std::ptr;
+
+/*
+Contracts, `@requires`, `@ensures`, `@loop_invariant` and `@assert`, are
+checked only with `-d`, so a program must behave the same with or without
+them. Their expressions must therefore be free of effects: they cannot
+allocate, and may only call pure functions. Assignments are statements and
+cannot be written in a contract at all.
+
+A function is pure if its body writes no memory, assigning only to its own
+variables, and calls only pure functions. Allocating is allowed, new memory
+is not observable by the caller. Functions without a body, from libraries,
+may do anything, input and output among it, so only those known to be pure
+are.
+
+`\result` is only meaningful in the `@ensures` of a function returning a
+value. That contracts are bools, and `\length` is only taken of arrays, is
+checked with the other types.
+
+Each declaration of a function names its parameters itself, and its
+contracts use those names. `Rebind` makes them all refer to the parameters
+of one declaration, for whatever runs the contracts.
+ */
+pub fn check(p: &Program, resolution: &Resolution) -> Result<(), Vec<Diagnostic>> {
+    let mut checker = ContractChecker {
+        resolution,
+        impure: impure_functions(p, resolution),
+        function: String::new(),
+        void: true,
+        spec: None,
+        errors: Vec::new(),
+    };
+    checker.visit_program(p);
+    if checker.errors.is_empty() {
+        Ok(())
+    } else {
+        Err(checker.errors)
+    }
+}
+
+// The library functions which are pure, some of them only looking at state others change
+const PURE_LIBRARY: [&str; 33] = [
+    // string
+    "string_length", "string_charat", "string_join", "string_sub", "string_equal", "string_compare",
+    "string_fromint", "string_frombool", "string_fromchar", "string_tolower", "string_terminated",
+    "string_to_chararray", "string_from_chararray", "char_ord", "char_chr",
+    // util
+    "int_size", "int_max", "int_min", "abs", "max", "min", "int2hex",
+    // parse
+    "parse_bool", "parse_int", "num_tokens", "int_tokens", "parse_tokens", "parse_ints",
+    // conio, file and img
+    "eof", "file_closed", "file_eof", "image_width", "image_height",
+];
+
+// Why a function is not pure
+#[derive(Clone, Debug)]
+struct Impurity {
+    span: Span,
+    reason: String,
+}
+
+// The first direct effect of a function body, and the functions it calls
+struct Effects<'a> {
+    resolution: &'a Resolution,
+    effect: Option<Impurity>,
+    calls: Vec<(DeclId, String, Span)>,
+}
+
+impl<'a> Visitor for Effects<'a> {
+    fn visit_simple(&mut self, s: &Simple) {
+        match s.kind {
+            SimpleKind::Assign(_, ref lhs, _) | SimpleKind::Post(_, ref lhs) => {
+                let local = matches!(lhs.kind, ExpKind::Var(_));
+                if !local && self.effect.is_none() {
+                    self.effect = Some(Impurity {
+                        span: s.span,
+                        reason: String::from("it writes to memory here"),
+                    });
+                }
+            }
+            _ => (),
+        }
+        visit::walk_simple(self, s)
+    }
+
+    fn visit_exp(&mut self, e: &Exp) {
+        if let ExpKind::Call(ref f, _) = e.kind {
+            if let Some(decl) = self.resolution.decl_id(f) {
+                self.calls.push((decl, f.name.clone(), e.span));
+            }
+        }
+        visit::walk_exp(self, e)
+    }
+}
+
+// The functions which are not pure, with why
+fn impure_functions(p: &Program, resolution: &Resolution) -> HashMap<DeclId, Impurity> {
+    let mut effects = HashMap::new();
+    for d in &p.decls {
+        if let GDeclKind::Function(ref f) = d.kind {
+            if let (Some(ref body), Some(decl)) = (&f.body, resolution.decl_id(&f.name)) {
+                let mut v = Effects {
+                    resolution,
+                    effect: None,
+                    calls: Vec::new(),
+                };
+                v.visit_block(body);
+                effects.insert(decl, v);
+            }
+        }
+    }
+    let mut impure: HashMap<DeclId, Impurity> = effects
+        .iter()
+        .filter_map(|(&decl, e)| e.effect.clone().map(|i| (decl, i)))
+        .collect();
+    for d in &p.decls {
+        if let GDeclKind::Function(ref f) = d.kind {
+            if let Some(decl) = resolution.decl_id(&f.name) {
+                if !effects.contains_key(&decl) && !PURE_LIBRARY.contains(&&f.name.name[..]) {
+                    let reason = String::from("it has no body here, and library functions may have effects");
+                    impure.entry(decl).or_insert(Impurity { span: d.span, reason });
+                }
+            }
+        }
+    }
+    // Callers of impure functions are impure, until nothing changes
+    loop {
+        let mut changed = false;
+        for (&decl, e) in &effects {
+            if impure.contains_key(&decl) {
+                continue;
+            }
+            if let Some(&(_, ref name, span)) = e.calls.iter().find(|c| impure.contains_key(&c.0)) {
+                let reason = format!("it calls `{}` here, which is not pure", name);
+                impure.insert(decl, Impurity { span, reason });
+                changed = true;
+            }
+        }
+        if !changed {
+            return impure;
+        }
+    }
+}
+
+/*
+Points the parameters in contracts at those of the declaration `bound` picks,
+changing only ids so that a contract still reads as written. Elaboration
+folds copies of the contracts, the interpreter changes its own in place.
+ */
+pub struct Rebind<'a> {
+    resolution: &'a Resolution,
+    // The id of the bound parameter each other one is replaced by
+    params: HashMap<DeclId, NodeId>,
+}
+
+impl<'a> Rebind<'a> {
+    pub fn new(p: &Program, resolution: &'a Resolution) -> Rebind<'a> {
+        let mut declarations: HashMap<DeclId, Vec<&Function>> = HashMap::new();
+        for d in &p.decls {
+            if let GDeclKind::Function(ref f) = d.kind {
+                if let Some(decl) = resolution.decl_id(&f.name) {
+                    declarations.entry(decl).or_default().push(f);
+                }
+            }
+        }
+        let mut params = HashMap::new();
+        for fs in declarations.values() {
+            let to = match bound(fs.iter().cloned()) {
+                Some(to) => to,
+                None => continue,
+            };
+            for g in fs.iter().filter(|&&g| !ptr::eq(g, to)) {
+                for (from, to) in g.params.iter().zip(&to.params) {
+                    if let Some(decl) = resolution.decl_id(&from.name) {
+                        params.insert(decl, to.name.id);
+                    }
+                }
+            }
+        }
+        Rebind { resolution, params }
+    }
+}
+
+impl<'a> VisitorMut for Rebind<'a> {
+    fn visit_ident_mut(&mut self, x: &mut Ident) {
+        if let Some(&id) = self.resolution.decl_id(x).and_then(|decl| self.params.get(&decl)) {
+            x.id = id;
+        }
+    }
+}
+
+impl<'a> Fold for Rebind<'a> {
+    fn fold_ident(&mut self, mut x: Ident) -> Ident {
+        self.visit_ident_mut(&mut x);
+        x
+    }
+}
+
+// The declaration of a function whose parameters its contracts use, its definition if it has one
+pub fn bound<'f>(mut declarations: impl Iterator<Item = &'f Function> + Clone) -> Option<&'f Function> {
+    declarations.clone().find(|f| f.body.is_some()).or_else(|| declarations.next())
+}
+
+struct ContractChecker<'a> {
+    resolution: &'a Resolution,
+    impure: HashMap<DeclId, Impurity>,
+    // The function being checked, and whether it returns void
+    function: String,
+    void: bool,
+    // The kind of contract being checked, if any
+    spec: Option<SpecKind>,
+    errors: Vec<Diagnostic>,
+}
+
+impl<'a> Visitor for ContractChecker<'a> {
+    fn visit_function(&mut self, f: &Function) {
+        self.function = f.name.name.clone();
+        self.void = f.ret.kind == TpKind::Void;
+        visit::walk_function(self, f)
+    }
+
+    fn visit_spec(&mut self, s: &Spec) {
+        self.spec = Some(s.kind);
+        visit::walk_spec(self, s);
+        self.spec = None;
+    }
+
+    fn visit_exp(&mut self, e: &Exp) {
+        let spec = match self.spec {
+            Some(spec) => spec,
+            None => return visit::walk_exp(self, e),
+        };
+        match e.kind {
+            ExpKind::Result if spec != SpecKind::Ensures => self.errors.push(Diagnostic::error(
+                e.span,
+                format!("`\\result` may only appear in @ensures, not @{}", spec),
+            )),
+            ExpKind::Result if self.void => self.errors.push(Diagnostic::error(
+                e.span,
+                format!("`{}` returns `void`, there is no `\\result`", self.function),
+            )),
+            ExpKind::Alloc(_) | ExpKind::AllocArray(..) => self.errors.push(Diagnostic::error(
+                e.span,
+                format!("@{} contracts cannot allocate memory", spec),
+            )),
+            ExpKind::Call(ref f, _) => {
+                if let Some(impurity) = self.resolution.decl_id(f).and_then(|decl| self.impure.get(&decl)) {
+                    self.errors.push(
+                        Diagnostic::error(
+                            e.span,
+                            format!("@{} contracts may only call pure functions, `{}` is not pure", spec, f.name),
+                        )
+                        .note(impurity.span, impurity.reason.clone()),
+                    );
+                }
+            }
+            _ => (),
+        }
+        visit::walk_exp(self, e)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use ast::pretty;
+    use semant::resolve::resolve;
+    use semant::tests::{errors, parse};
+
+    #[test]
+    fn pure_contracts() {
+        let ok = "bool is_sorted(int[] A, int n)\n\
+                  { for (int i = 1; i < n; i++) if (A[i-1] > A[i]) return false; return true; }\n\
+                  int[] copy(int[] A, int n) { int[] B = alloc_array(int, n); return B; }\n\
+                  int abs(int x);\n\
+                  int sort(int[] A, int n)\n\
+                  //@requires is_sorted(copy(A, n), n) && abs(n) >= 0;\n\
+                  //@ensures \\result >= 0 && is_sorted(A, n);\n\
+                  { for (int i = 0; i < n; i++)\n//@loop_invariant is_sorted(A, i);\n{ A[i] = i; }\n\
+                  //@assert is_sorted(A, n);\n\
+                  return 0; }";
+        assert_eq!(errors(check, ok), Vec::<String>::new());
+    }
+
+    #[test]
+    fn impure_contracts() {
+        let cases = vec![
+            ("void f(int* p)\n//@requires alloc(int) != p;\n{ }", "@requires contracts cannot allocate memory"),
+            ("bool set(int* p) { *p = 1; return true; }\nvoid f(int* p)\n//@requires set(p);\n{ }",
+             "@requires contracts may only call pure functions, `set` is not pure"),
+            ("bool set(int[] A) { A[0]++; return true; }\nbool g(int[] A) { return set(A); }\n\
+              void f(int[] A) { while (true)\n//@loop_invariant g(A);\n{ } }",
+             "@loop_invariant contracts may only call pure functions, `g` is not pure"),
+            ("void println(string s);\nbool p() { println(\"x\"); return true; }\nvoid f()\n//@requires p();\n{ }",
+             "@requires contracts may only call pure functions, `p` is not pure"),
+            ("string readline();\nbool string_equal(string a, string b);\n\
+              void f() {\n//@assert string_equal(readline(), \"\");\n}",
+             "@assert contracts may only call pure functions, `readline` is not pure"),
+            ("int f(int x)\n//@requires \\result > 0;\n{ return x; }",
+             "`\\result` may only appear in @ensures, not @requires"),
+            ("void f(int x)\n//@ensures \\result > 0;\n{ }", "`f` returns `void`, there is no `\\result`"),
+            ("int f(int x) {\n//@assert \\result > 0;\nreturn x; }",
+             "`\\result` may only appear in @ensures, not @assert"),
+        ];
+        for (source, message) in cases {
+            assert_eq!(errors(check, source), vec![String::from(message)], "{}", source);
+        }
+    }
+
+    #[test]
+    fn rebinding_parameters() {
+        let p = parse(
+            "int f(int y)\n//@requires y > 0;\n;\nint f(int x)\n//@ensures \\result == x;\n{ return x; }\n\
+             int g(int a);\nint g(int b)\n//@requires b > 1;\n;",
+        );
+        let resolution = resolve(&p).unwrap();
+        let mut rebind = Rebind::new(&p, &resolution);
+        let decls: Vec<&Function> = p
+            .decls
+            .iter()
+            .filter_map(|d| match d.kind {
+                GDeclKind::Function(ref f) => Some(f),
+                _ => None,
+            })
+            .collect();
+        let param = |f: &Function, i: usize| resolution.decl_id(&f.params[i].name);
+        let cases = [(decls[0], decls[1], "y > 0"), (decls[3], decls[2], "b > 1")];
+        for &(g, bound, text) in &cases {
+            let spec = rebind.fold_spec(g.specs[0].clone());
+            assert_eq!(pretty::exp(&spec.exp), text);
+            let mut vars = Vec::new();
+            visit::walk_exp(&mut Vars(&resolution, &mut vars), &spec.exp);
+            assert!(vars.iter().all(|&v| v == param(bound, 0)), "{}", text);
+        }
+        let mut f = decls[1].clone();
+        rebind.visit_function_mut(&mut f);
+        assert_eq!(f, *decls[1]);
+    }
+
+    // The declarations of the variables read
+    struct Vars<'a>(&'a Resolution, &'a mut Vec<Option<DeclId>>);
+
+    impl<'a> Visitor for Vars<'a> {
+        fn visit_exp(&mut self, e: &Exp) {
+            if let ExpKind::Var(ref x) = e.kind {
+                self.1.push(self.0.decl_id(x));
+            }
+            visit::walk_exp(self, e)
+        }
+    }
+}
@@ -0,0 +1,285 @@
+use ast::ast::*;
+use ast::visit::{self, Visitor};
+use semant::resolve::{DeclId, DeclKind, Resolution};
+use source::diagnostic::Diagnostic;
+use std::collections::HashSet;
+use std::mem;
+
+/*
+Definite initialization: a local variable must be assigned on every path to a
+read of it. Parameters are assigned by the call.
+
+The analysis runs forward over each function body, keeping the set of locals
+definitely assigned so far. Where control flow meets the sets are
+intersected, so
+
+- after `if`, a variable is assigned if both branches assign it
+- after a loop, only what was assigned before it, the body may not run,
+  unless its condition is the literal `true`, then what every `break` has
+- the step of a `for` loop runs after the body and after each `continue`
+- after `return`, `error`, `break` or `continue` nothing is reached, which
+  assigns everything, the identity of intersection, and dead code is not
+  reported
+
+Assigning `x` means `x = e`; `x += e` and `x++` read `x` first.
+ */
+pub fn check(p: &Program, resolution: &Resolution) -> Result<(), Vec<Diagnostic>> {
+    let mut checker = InitChecker::new(resolution);
+    for d in &p.decls {
+        if let GDeclKind::Function(ref f) = d.kind {
+            if let Some(ref body) = f.body {
+                checker.assigned = Some(HashSet::new());
+                checker.block(body);
+            }
+        }
+    }
+    if checker.errors.is_empty() {
+        Ok(())
+    } else {
+        Err(checker.errors)
+    }
+}
+
+/*
+Statements run after others whose effect is known, as the inputs of the REPL:
+`assigned` are the variables already assigned, and the result is those
+assigned after the statements, `None` if their end is not reached.
+ */
+pub fn check_stmts(
+    stmts: &[Stmt],
+    resolution: &Resolution,
+    assigned: HashSet<DeclId>,
+) -> Result<Option<HashSet<DeclId>>, Vec<Diagnostic>> {
+    let mut checker = InitChecker::new(resolution);
+    checker.assigned = Some(assigned);
+    for s in stmts {
+        checker.stmt(s);
+    }
+    if checker.errors.is_empty() {
+        Ok(checker.assigned)
+    } else {
+        Err(checker.errors)
+    }
+}
+
+// The locals definitely assigned, `None` where the code is unreachable
+type Assigned = Option<HashSet<DeclId>>;
+
+fn join(a: Assigned, b: Assigned) -> Assigned {
+    match (a, b) {
+        (Some(a), Some(b)) => Some(a.intersection(&b).cloned().collect()),
+        (a, None) => a,
+        (None, b) => b,
+    }
+}
+
+// What is assigned after a loop
+fn exit(cond: &Exp, before: Assigned, broken: Assigned) -> Assigned {
+    if cond.kind == ExpKind::Bool(true) {
+        broken
+    } else {
+        join(before, broken)
+    }
+}
+
+struct InitChecker<'a> {
+    resolution: &'a Resolution,
+    assigned: Assigned,
+    // Where the innermost loop was continued from
+    continued: Assigned,
+    // Where it was broken out of
+    broken: Assigned,
+    // Each variable is reported once, at its first bad read
+    reported: HashSet<DeclId>,
+    errors: Vec<Diagnostic>,
+}
+
+impl<'a> InitChecker<'a> {
+    fn new(resolution: &'a Resolution) -> InitChecker<'a> {
+        InitChecker {
+            resolution,
+            assigned: Some(HashSet::new()),
+            continued: None,
+            broken: None,
+            reported: HashSet::new(),
+            errors: Vec::new(),
+        }
+    }
+
+    fn assign(&mut self, x: &Ident) {
+        if let (Some(decl), Some(ref mut assigned)) = (self.resolution.decl_id(x), self.assigned.as_mut()) {
+            assigned.insert(decl);
+        }
+    }
+
+    fn block(&mut self, b: &Block) {
+        for s in &b.stmts {
+            self.stmt(s);
+        }
+    }
+
+    fn specs(&mut self, specs: &[Spec]) {
+        for s in specs {
+            self.visit_exp(&s.exp);
+        }
+    }
+
+    fn stmt(&mut self, s: &Stmt) {
+        match s.kind {
+            StmtKind::Simple(ref simple) => self.simple(simple),
+            StmtKind::If(ref cond, ref then, ref otherwise) => {
+                self.visit_exp(cond);
+                let before = self.assigned.clone();
+                self.stmt(then);
+                let after_then = self.assigned.take();
+                self.assigned = before;
+                if let Some(ref otherwise) = *otherwise {
+                    self.stmt(otherwise);
+                }
+                self.assigned = join(after_then, self.assigned.take());
+            }
+            StmtKind::While(ref cond, ref invariants, ref body) => {
+                self.specs(invariants);
+                self.visit_exp(cond);
+                let before = self.assigned.clone();
+                let broken = self.loop_body(body);
+                self.assigned = exit(cond, before, broken);
+            }
+            StmtKind::For(ref init, ref cond, ref step, ref invariants, ref body) => {
+                if let Some(ref init) = *init {
+                    self.simple(init);
+                }
+                self.specs(invariants);
+                self.visit_exp(cond);
+                let before = self.assigned.clone();
+                let broken = self.loop_body(body);
+                if let Some(ref step) = *step {
+                    self.simple(step);
+                }
+                self.assigned = exit(cond, before, broken);
+            }
+            StmtKind::Return(ref e) => {
+                if let Some(ref e) = *e {
+                    self.visit_exp(e);
+                }
+                self.assigned = None;
+            }
+            StmtKind::Error(ref e) => {
+                self.visit_exp(e);
+                self.assigned = None;
+            }
+            StmtKind::Break => self.broken = join(self.broken.take(), self.assigned.take()),
+            StmtKind::Continue => self.continued = join(self.continued.take(), self.assigned.take()),
+            StmtKind::Block(ref b) => self.block(b),
+            StmtKind::Assert(ref e) => self.visit_exp(e),
+            StmtKind::Annotation(ref specs) => self.specs(specs),
+        }
+    }
+
+    // Leaves what reaches the end of the body, or a `continue` in it, and
+    // returns what reaches a `break`
+    fn loop_body(&mut self, body: &Stmt) -> Assigned {
+        let (continued, broken) = (self.continued.take(), self.broken.take());
+        self.stmt(body);
+        self.assigned = join(self.assigned.take(), self.continued.take());
+        self.continued = continued;
+        mem::replace(&mut self.broken, broken)
+    }
+
+    fn simple(&mut self, s: &Simple) {
+        match s.kind {
+            SimpleKind::Assign(AsnOp::Assign, ref lhs, ref rhs) => match lhs.kind {
+                ExpKind::Var(ref x) => {
+                    self.visit_exp(rhs);
+                    self.assign(x);
+                }
+                _ => {
+                    self.visit_exp(lhs);
+                    self.visit_exp(rhs);
+                }
+            },
+            SimpleKind::Assign(AsnOp::Compound(_), ref lhs, ref rhs) => {
+                self.visit_exp(lhs);
+                self.visit_exp(rhs);
+            }
+            SimpleKind::Post(_, ref lhs) => self.visit_exp(lhs),
+            SimpleKind::Exp(ref e) => self.visit_exp(e),
+            SimpleKind::Decl(_, ref x, ref init) => {
+                if let Some(ref init) = *init {
+                    self.visit_exp(init);
+                    self.assign(x);
+                }
+            }
+        }
+    }
+}
+
+// Expressions only read variables
+impl<'a> Visitor for InitChecker<'a> {
+    fn visit_exp(&mut self, e: &Exp) {
+        if let ExpKind::Var(ref x) = e.kind {
+            let decl = match self.resolution.decl_id(x) {
+                Some(decl) => decl,
+                None => return,
+            };
+            let local = &self.resolution.decls[decl];
+            let unassigned = match self.assigned {
+                Some(ref assigned) => !assigned.contains(&decl),
+                None => false,
+            };
+            if local.kind == DeclKind::Local && unassigned && self.reported.insert(decl) {
+                self.errors.push(
+                    Diagnostic::error(x.span, format!("`{}` may be used before it is initialized", x.name))
+                        .note(local.span, "declared here without a value"),
+                );
+            }
+        }
+        visit::walk_exp(self, e)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use semant::tests::errors;
+
+    #[test]
+    fn initialized_variables() {
+        let ok = "int f(int n, bool b) {\n\
+                  int x; int y; int z;\n\
+                  if (b) { x = 1; y = 2; } else { x = 3; }\n\
+                  if (n < 0) { z = 1; } else { error(\"negative\"); }\n\
+                  int s = x + z;\n\
+                  for (int i = 0; i < n; i++) { int t; t = i; s += t; }\n\
+                  int w;\n\
+                  while (true) { w = 1; return w; }\n\
+                  return s; }\n\
+                  int g(int n) { int x; while (true) { if (n > 0) { x = n; break; } n++; continue; }\n\
+                  for (int i = 0; i < n; i += x) { if (i == 1) { continue; } }\n\
+                  return 0; }";
+        assert_eq!(errors(check, ok), Vec::<String>::new());
+    }
+
+    #[test]
+    fn uninitialized_variables() {
+        let cases = vec![
+            ("int f() { int x; return x; }", "`x` may be used before it is initialized"),
+            ("int f(bool b) { int x; if (b) x = 1; return x; }", "`x` may be used before it is initialized"),
+            ("int f(int n) { int x; while (n > 0) { x = n; n--; } return x; }",
+             "`x` may be used before it is initialized"),
+            ("int f(int n) { int x; for (int i = 0; i < n; i++) x = i; return x; }",
+             "`x` may be used before it is initialized"),
+            ("void f() { int x; x++; }", "`x` may be used before it is initialized"),
+            ("void f() { int[] a; a[0] = 1; }", "`a` may be used before it is initialized"),
+            ("int f() { int x;\n//@assert x == 0;\nreturn 0; }", "`x` may be used before it is initialized"),
+            ("int f() { int x; int y = x + x; return x; }", "`x` may be used before it is initialized"),
+            ("int f(int n) { int x; while (n > 0) { if (n == 1) break; x = 1; n--; } return x; }",
+             "`x` may be used before it is initialized"),
+            ("void f(int n) { int x; for (int i = 0; i < n; i += x) { if (i == 1) continue; x = 1; } }",
+             "`x` may be used before it is initialized"),
+        ];
+        for (source, message) in cases {
+            assert_eq!(errors(check, source), vec![String::from(message)], "{}", source);
+        }
+    }
+}
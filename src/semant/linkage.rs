@@ -0,0 +1,152 @@
+use ast::ast::*;
+use ast::visit::{self, Visitor};
+use semant::resolve::{DeclId, DeclKind, Resolution};
+use semant::typeck::Typing;
+use semant::types::{Signature, Type};
+use source::diagnostic::Diagnostic;
+use source::source_map::{FileId, Span};
+use std::collections::HashMap;
+
+/*
+Linking the files of a program together: every function which is used, in
+code or contracts, has exactly one definition among them. Functions declared
+in library headers are defined by the library, so a program cannot define
+them again; a declared function which is never used needs no definition. The program
+runs from its `main`, which must be defined as `int main()`.
+
+Redefinitions within the program are found by name resolution, and prototypes
+disagreeing with each other by the type checker.
+ */
+pub fn check(
+    p: &Program,
+    resolution: &Resolution,
+    typing: &Typing,
+    libraries: &[FileId],
+) -> Result<(), Vec<Diagnostic>> {
+    let mut uses = Uses {
+        resolution,
+        first: HashMap::new(),
+    };
+    uses.visit_program(p);
+    let mut errors = Vec::new();
+    for (decl, d) in resolution.decls.iter().enumerate() {
+        if d.kind != DeclKind::Function {
+            continue;
+        }
+        let library = libraries.contains(&d.span.file);
+        match (d.defined, uses.first.get(&decl)) {
+            (Some(defined), _) if library => errors.push(
+                Diagnostic::error(defined, format!("function `{}` is already defined by a library", d.name))
+                    .note(d.span, "declared in the library here"),
+            ),
+            (None, Some(&used)) if !library => errors.push(
+                Diagnostic::error(used, format!("function `{}` is used but never defined", d.name))
+                    .note(d.span, "declared here"),
+            ),
+            _ => (),
+        }
+    }
+    let main = resolution.decls.iter().enumerate().find(|&(_, d)| d.kind == DeclKind::Function && d.name == "main");
+    let entry = Signature {
+        params: Vec::new(),
+        ret: Type::Int,
+    };
+    match main {
+        Some((decl, d)) if d.defined.is_some() => {
+            let signature = &typing.functions[&decl];
+            if *signature != entry {
+                errors.push(
+                    Diagnostic::error(d.span, format!("function `main` is declared as `{}`", signature.show("main")))
+                        .help(format!("the program runs from `{}`", entry.show("main"))),
+                );
+            }
+        }
+        Some((_, d)) => errors.push(Diagnostic::error(d.span, "function `main` is declared but never defined")),
+        None => {
+            // At the end of the program, where a `main` would go
+            let end = p.decls.iter().rev().map(|g| g.span).find(|s| !libraries.contains(&s.file));
+            let span = end.map_or(Span::default(), |s| Span::new(s.file, s.hi, s.hi));
+            errors.push(Diagnostic::error(span, "the program has no `main` function"));
+        }
+    }
+    // Reported in source order
+    errors.sort_by_key(|d| (d.span.file, d.span.lo));
+    if errors.is_empty() {
+        Ok(())
+    } else {
+        Err(errors)
+    }
+}
+
+// Where each function is first called
+struct Uses<'a> {
+    resolution: &'a Resolution,
+    first: HashMap<DeclId, Span>,
+}
+
+impl<'a> Visitor for Uses<'a> {
+    fn visit_exp(&mut self, e: &Exp) {
+        if let ExpKind::Call(ref f, _) = e.kind {
+            if let Some(decl) = self.resolution.decl_id(f) {
+                self.first.entry(decl).or_insert(f.span);
+            }
+        }
+        visit::walk_exp(self, e)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use lexer::lexer::Lexer;
+    use parser::parser::{ParseContext, Parser};
+    use semant::resolve::resolve;
+    use semant::tests::messages;
+    use semant::typeck;
+
+    // Each text is a file, the first a library header
+    fn errors(files: &[&str]) -> Vec<String> {
+        let mut ctx = ParseContext::default();
+        let mut decls = Vec::new();
+        for (file, text) in files.iter().enumerate() {
+            let mut parser = Parser::with_context(Lexer::new(file, text).tokens().unwrap(), ctx);
+            decls.extend(parser.parseProgram().unwrap().decls);
+            ctx = parser.context();
+        }
+        let p = Program { decls };
+        let resolution = resolve(&p).unwrap();
+        let typing = typeck::check(&p, &resolution).unwrap();
+        messages(check(&p, &resolution, &typing, &[0]))
+    }
+
+    #[test]
+    fn linking_files() {
+        let library = "int lib(int x);";
+        let ok = [
+            library,
+            "int helper(int x);\nint unused(int x);\nint twice(int x) { return helper(helper(x)); }",
+            "int helper(int x) { return lib(x) + 1; }\nint main() { return twice(1); }",
+        ];
+        assert_eq!(errors(&ok), Vec::<String>::new());
+        let undefined = [library, "int helper(int x);\nint main()\n//@ensures \\result == helper(0);\n{ return 0; }"];
+        assert_eq!(errors(&undefined), vec!["function `helper` is used but never defined"]);
+        let redefined = [library, "int lib(int x) { return x; }\nint main() { return 0; }"];
+        assert_eq!(errors(&redefined), vec!["function `lib` is already defined by a library"]);
+    }
+
+    #[test]
+    fn starting_from_main() {
+        assert_eq!(errors(&["", "int main() { return 0; }"]), Vec::<String>::new());
+        let cases = [
+            ("int f() { return 0; }", "the program has no `main` function"),
+            ("", "the program has no `main` function"),
+            ("int main();", "function `main` is declared but never defined"),
+            ("bool main() { return true; }", "function `main` is declared as `bool main()`"),
+            ("int main(int x) { return x; }", "function `main` is declared as `int main(int)`"),
+            ("void main() { }", "function `main` is declared as `void main()`"),
+        ];
+        for &(text, error) in &cases {
+            assert_eq!(errors(&["", text]), vec![error], "{}", text);
+        }
+    }
+}
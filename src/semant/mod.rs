@@ -0,0 +1,41 @@
+pub mod contracts;
+pub mod init;
+pub mod linkage;
+pub mod resolve;
+pub mod returns;
+pub mod structs;
+pub mod typeck;
+pub mod types;
+
+#[cfg(test)]
+pub mod tests {
+    use ast::ast::Program;
+    use lexer::lexer::Lexer;
+    use parser::parser::Parser;
+    use semant::resolve::{resolve, Resolution};
+    use source::diagnostic::Diagnostic;
+
+    pub fn parse(text: &str) -> Program {
+        Parser::new(Lexer::new(0, text).tokens().unwrap())
+            .parseProgram()
+            .unwrap()
+    }
+
+    // The messages of the errors a pass found, none if it succeeded
+    pub fn messages<T>(result: Result<T, Vec<Diagnostic>>) -> Vec<String> {
+        match result {
+            Ok(_) => Vec::new(),
+            Err(errors) => errors.into_iter().map(|d| d.message).collect(),
+        }
+    }
+
+    // The errors `pass` finds in the program `text`, which has to parse and resolve
+    pub fn errors<T, F>(pass: F, text: &str) -> Vec<String>
+    where
+        F: FnOnce(&Program, &Resolution) -> Result<T, Vec<Diagnostic>>,
+    {
+        let p = parse(text);
+        let resolution = resolve(&p).unwrap();
+        messages(pass(&p, &resolution))
+    }
+}
@@ -0,0 +1,465 @@
+use ast::ast::*;
+use ast::visit::{self, Visitor};
+use source::diagnostic::Diagnostic;
+use source::source_map::Span;
+use std::collections::HashMap;
+
+/*
+Name resolution connects every use of a name to its declaration, following
+the scoping rules of C0
+
+- variables are local to the block they are declared in, or to the loop for
+  the initializer of a `for`, and may not shadow a variable of an enclosing
+  scope, parameters included
+- functions must be declared before they are called, by a prototype or their
+  definition, and each is defined at most once
+- typedef names and variables share a namespace, a variable cannot be named
+  like a type
+- struct names live in a namespace of their own, `struct list` and a typedef
+  `list` do not clash; a struct may be mentioned before it is declared
+- field names are looked up by the type checker, they depend on the struct
+
+Each identifier resolved is recorded, by its `NodeId`, in `Resolution::names`
+with the declaration it refers to. Declaring identifiers map to the
+declaration they introduce.
+ */
+pub type DeclId = usize;
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum DeclKind {
+    Local,
+    Param,
+    Function,
+    Typedef,
+    Struct,
+}
+
+// A declared name, a function or struct declared several times has one `Decl`
+#[derive(Clone, Debug)]
+pub struct Decl {
+    pub kind: DeclKind,
+    pub name: String,
+    // The first declaration
+    pub span: Span,
+    // The definition of a function or struct
+    pub defined: Option<Span>,
+}
+
+#[derive(Clone, Debug, Default)]
+pub struct Resolution {
+    pub decls: Vec<Decl>,
+    pub names: HashMap<NodeId, DeclId>,
+}
+
+impl Resolution {
+    pub fn decl(&self, x: &Ident) -> Option<&Decl> {
+        self.names.get(&x.id).map(|&d| &self.decls[d])
+    }
+
+    pub fn decl_id(&self, x: &Ident) -> Option<DeclId> {
+        self.names.get(&x.id).cloned()
+    }
+}
+
+pub fn resolve(p: &Program) -> Result<Resolution, Vec<Diagnostic>> {
+    let mut resolver = Resolver::new();
+    resolver.collect_functions(p);
+    resolver.visit_program(p);
+    if resolver.errors.is_empty() {
+        Ok(resolver.resolution)
+    } else {
+        Err(resolver.errors)
+    }
+}
+
+struct Resolver {
+    resolution: Resolution,
+    errors: Vec<Diagnostic>,
+    // Typedef names, in scope from their declaration on
+    types: HashMap<String, DeclId>,
+    functions: HashMap<String, DeclId>,
+    structs: HashMap<String, DeclId>,
+    // Innermost scope last
+    scopes: Vec<HashMap<String, DeclId>>,
+    // Where each function is first declared, to explain uses before that
+    later: HashMap<String, Span>,
+}
+
+impl Resolver {
+    fn new() -> Resolver {
+        Resolver {
+            resolution: Resolution::default(),
+            errors: Vec::new(),
+            types: HashMap::new(),
+            functions: HashMap::new(),
+            structs: HashMap::new(),
+            scopes: Vec::new(),
+            later: HashMap::new(),
+        }
+    }
+
+    fn collect_functions(&mut self, p: &Program) {
+        for d in &p.decls {
+            if let GDeclKind::Function(ref f) = d.kind {
+                self.later.entry(f.name.name.clone()).or_insert(f.name.span);
+            }
+        }
+    }
+
+    fn error(&mut self, d: Diagnostic) {
+        self.errors.push(d);
+    }
+
+    fn declare(&mut self, kind: DeclKind, x: &Ident) -> DeclId {
+        self.resolution.decls.push(Decl {
+            kind,
+            name: x.name.clone(),
+            span: x.span,
+            defined: None,
+        });
+        let id = self.resolution.decls.len() - 1;
+        self.resolution.names.insert(x.id, id);
+        id
+    }
+
+    fn refer(&mut self, x: &Ident, decl: DeclId) {
+        self.resolution.names.insert(x.id, decl);
+    }
+
+    fn span_of(&self, decl: DeclId) -> Span {
+        self.resolution.decls[decl].span
+    }
+
+    fn local(&self, name: &str) -> Option<DeclId> {
+        self.scopes.iter().rev().filter_map(|scope| scope.get(name)).next().cloned()
+    }
+
+    fn scoped<F: FnOnce(&mut Resolver)>(&mut self, f: F) {
+        self.scopes.push(HashMap::new());
+        f(self);
+        self.scopes.pop();
+    }
+
+    fn declare_variable(&mut self, kind: DeclKind, x: &Ident) {
+        if let Some(previous) = self.local(&x.name) {
+            let span = self.span_of(previous);
+            self.error(
+                Diagnostic::error(x.span, format!("redeclaration of `{}`", x.name))
+                    .note(span, "previously declared here")
+                    .help("C0 does not allow a variable to shadow another"),
+            );
+            return;
+        }
+        if let Some(&t) = self.types.get(&x.name) {
+            let span = self.span_of(t);
+            self.error(
+                Diagnostic::error(x.span, format!("`{}` is already declared as a type", x.name))
+                    .note(span, "the typedef is here"),
+            );
+        }
+        // Declared even when it clashes with a type, so its uses are not reported too
+        let decl = self.declare(kind, x);
+        self.scopes
+            .last_mut()
+            .expect("variables are declared inside a scope")
+            .insert(x.name.clone(), decl);
+    }
+
+    fn use_variable(&mut self, x: &Ident) {
+        if let Some(decl) = self.local(&x.name) {
+            self.refer(x, decl);
+            return;
+        }
+        let d = Diagnostic::error(x.span, format!("undeclared variable `{}`", x.name));
+        let d = match self.types.get(&x.name) {
+            Some(&t) => d.note(self.span_of(t), format!("`{}` names a type", x.name)),
+            None => d,
+        };
+        self.error(d);
+    }
+
+    fn use_function(&mut self, f: &Ident) {
+        if let Some(&decl) = self.functions.get(&f.name) {
+            self.refer(f, decl);
+            return;
+        }
+        let d = Diagnostic::error(f.span, format!("undeclared function `{}`", f.name));
+        let d = match self.later.get(&f.name) {
+            Some(&span) => d
+                .note(span, "it is declared later")
+                .help("functions must be declared before they are used, add a prototype"),
+            None => d,
+        };
+        self.error(d);
+    }
+
+    fn declare_function(&mut self, f: &Function) {
+        let decl = match self.functions.get(&f.name.name) {
+            Some(&decl) => {
+                self.refer(&f.name, decl);
+                decl
+            }
+            None => {
+                let decl = self.declare(DeclKind::Function, &f.name);
+                self.functions.insert(f.name.name.clone(), decl);
+                decl
+            }
+        };
+        if f.body.is_none() {
+            return;
+        }
+        match self.resolution.decls[decl].defined {
+            Some(previous) => self.error(
+                Diagnostic::error(f.name.span, format!("redefinition of function `{}`", f.name.name))
+                    .note(previous, "previously defined here"),
+            ),
+            None => self.resolution.decls[decl].defined = Some(f.name.span),
+        }
+    }
+
+    fn declare_typedef(&mut self, x: &Ident) {
+        if let Some(&previous) = self.types.get(&x.name) {
+            let span = self.span_of(previous);
+            self.error(
+                Diagnostic::error(x.span, format!("redeclaration of type `{}`", x.name))
+                    .note(span, "previously declared here"),
+            );
+            return;
+        }
+        let decl = self.declare(DeclKind::Typedef, x);
+        self.types.insert(x.name.clone(), decl);
+    }
+
+    fn declare_struct(&mut self, x: &Ident, definition: bool) {
+        let decl = match self.structs.get(&x.name) {
+            Some(&decl) => {
+                self.refer(x, decl);
+                decl
+            }
+            None => {
+                let decl = self.declare(DeclKind::Struct, x);
+                self.structs.insert(x.name.clone(), decl);
+                decl
+            }
+        };
+        if !definition {
+            return;
+        }
+        match self.resolution.decls[decl].defined {
+            Some(previous) => self.error(
+                Diagnostic::error(x.span, format!("redefinition of struct `{}`", x.name))
+                    .note(previous, "previously defined here"),
+            ),
+            None => self.resolution.decls[decl].defined = Some(x.span),
+        }
+    }
+
+    // The body of a loop or branch, in a scope of its own even without braces
+    fn nested(&mut self, s: &Stmt) {
+        self.scoped(|r| r.visit_stmt(s));
+    }
+}
+
+impl Visitor for Resolver {
+    fn visit_gdecl(&mut self, d: &GDecl) {
+        match d.kind {
+            GDeclKind::StructDecl(ref name) => self.declare_struct(name, false),
+            GDeclKind::StructDef(ref name, ref fields) => {
+                self.declare_struct(name, true);
+                for f in fields {
+                    self.visit_tp(&f.tp);
+                }
+            }
+            GDeclKind::Typedef(ref t, ref name) => {
+                self.visit_tp(t);
+                self.declare_typedef(name);
+            }
+            _ => visit::walk_gdecl(self, d),
+        }
+    }
+
+    fn visit_function(&mut self, f: &Function) {
+        self.visit_tp(&f.ret);
+        // Declared before the parameters are, so the function may call itself
+        self.declare_function(f);
+        self.scoped(|r| {
+            for p in &f.params {
+                r.visit_tp(&p.tp);
+                r.declare_variable(DeclKind::Param, &p.name);
+            }
+            for s in &f.specs {
+                r.visit_spec(s);
+            }
+            if let Some(ref body) = f.body {
+                r.visit_block(body);
+            }
+        });
+    }
+
+    fn visit_block(&mut self, b: &Block) {
+        self.scoped(|r| visit::walk_block(r, b));
+    }
+
+    fn visit_stmt(&mut self, s: &Stmt) {
+        match s.kind {
+            StmtKind::If(ref cond, ref then, ref otherwise) => {
+                self.visit_exp(cond);
+                self.nested(then);
+                if let Some(ref otherwise) = *otherwise {
+                    self.nested(otherwise);
+                }
+            }
+            StmtKind::While(ref cond, ref invariants, ref body) => {
+                self.visit_exp(cond);
+                for s in invariants {
+                    self.visit_spec(s);
+                }
+                self.nested(body);
+            }
+            // The initializer's variable is in scope for the whole loop
+            StmtKind::For(ref init, ref cond, ref step, ref invariants, ref body) => self.scoped(|r| {
+                if let Some(ref init) = *init {
+                    r.visit_simple(init);
+                }
+                r.visit_exp(cond);
+                if let Some(ref step) = *step {
+                    r.visit_simple(step);
+                }
+                for s in invariants {
+                    r.visit_spec(s);
+                }
+                r.nested(body);
+            }),
+            _ => visit::walk_stmt(self, s),
+        }
+    }
+
+    fn visit_simple(&mut self, s: &Simple) {
+        match s.kind {
+            // `int x = x;` refers to an outer `x`, the new one is not in scope yet
+            SimpleKind::Decl(ref t, ref name, ref init) => {
+                self.visit_tp(t);
+                if let Some(ref init) = *init {
+                    self.visit_exp(init);
+                }
+                self.declare_variable(DeclKind::Local, name);
+            }
+            _ => visit::walk_simple(self, s),
+        }
+    }
+
+    fn visit_tp(&mut self, t: &Tp) {
+        match t.kind {
+            TpKind::Name(ref x) => match self.types.get(&x.name) {
+                Some(&decl) => self.refer(x, decl),
+                None => self.error(Diagnostic::error(x.span, format!("undeclared type `{}`", x.name))),
+            },
+            // Structs may be used before they are declared, as long as only pointers are involved
+            TpKind::Struct(ref x) => {
+                if let Some(&decl) = self.structs.get(&x.name) {
+                    self.refer(x, decl);
+                }
+            }
+            _ => visit::walk_tp(self, t),
+        }
+    }
+
+    fn visit_exp(&mut self, e: &Exp) {
+        match e.kind {
+            ExpKind::Var(ref x) => self.use_variable(x),
+            ExpKind::Call(ref f, _) => {
+                self.use_function(f);
+                visit::walk_exp(self, e);
+            }
+            _ => visit::walk_exp(self, e),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use semant::tests::{messages, parse};
+
+    fn errors(text: &str) -> Vec<String> {
+        messages(resolve(&parse(text)))
+    }
+
+    // The declaration a use refers to, given by the offset of the use
+    struct Uses<'a>(&'a Resolution, Vec<(usize, DeclKind, usize)>);
+
+    impl<'a> Visitor for Uses<'a> {
+        fn visit_exp(&mut self, e: &Exp) {
+            if let ExpKind::Var(ref x) = e.kind {
+                let decl = self.0.decl(x).unwrap();
+                self.1.push((x.span.lo, decl.kind, decl.span.lo));
+            }
+            visit::walk_exp(self, e)
+        }
+    }
+
+    #[test]
+    fn resolving_uses() {
+        let text = "int f(int x) { int y = x; for (int i = 0; i < y; i++) { y += i; } return y; }";
+        let p = parse(text);
+        let resolution = resolve(&p).unwrap();
+        let mut uses = Uses(&resolution, Vec::new());
+        uses.visit_program(&p);
+        let x = text.find("x)").unwrap();
+        let y = text.find("y =").unwrap();
+        let i = text.find("i =").unwrap();
+        let kinds: Vec<(DeclKind, usize)> = uses.1.iter().map(|&(_, kind, decl)| (kind, decl)).collect();
+        assert_eq!(
+            kinds,
+            vec![
+                (DeclKind::Param, x),
+                (DeclKind::Local, i),
+                (DeclKind::Local, y),
+                (DeclKind::Local, i),
+                (DeclKind::Local, y),
+                (DeclKind::Local, i),
+                (DeclKind::Local, y),
+            ]
+        );
+    }
+
+    #[test]
+    fn scoping_rules() {
+        let ok = "typedef struct list* list;\n\
+                  struct list { int head; list tail; };\n\
+                  int g(int n);\n\
+                  int f(list l) { if (l == NULL) { int n = 0; return g(n); } else { int n = 1; return f(l->tail) + n; } }\n\
+                  int g(int n) { for (int i = 0; i < n; i++) {} for (int i = 0; i < n; i++) {} return n; }";
+        assert_eq!(errors(ok), Vec::<String>::new());
+    }
+
+    #[test]
+    fn resolution_errors() {
+        let cases = vec![
+            ("int f() { return x; }", "undeclared variable `x`"),
+            ("int f() { return g(); }\nint g() { return 1; }", "undeclared function `g`"),
+            ("int f(int x) { int x = 1; return x; }", "redeclaration of `x`"),
+            ("int f() { int x = 1; { int x = 2; } return x; }", "redeclaration of `x`"),
+            ("int f(int x, bool x) { return 1; }", "redeclaration of `x`"),
+            ("typedef int t;\nint f() { int t = 1; return t; }", "`t` is already declared as a type"),
+            ("t f() { return 1; }", "undeclared type `t`"),
+            ("typedef int t;\ntypedef bool t;", "redeclaration of type `t`"),
+            ("int f() { return 1; }\nint f() { return 2; }", "redefinition of function `f`"),
+            ("struct s { int x; };\nstruct s { int y; };", "redefinition of struct `s`"),
+            ("int f() { for (int i = 0; i < 3; i++) {} return i; }", "undeclared variable `i`"),
+        ];
+        for (source, message) in cases {
+            assert_eq!(errors(source), vec![String::from(message)], "{}", source);
+        }
+    }
+
+    #[test]
+    fn reporting_every_error() {
+        let errors = resolve(&parse("int f() { x = 1; return y + g(); }")).unwrap_err();
+        let messages: Vec<&str> = errors.iter().map(|d| &d.message[..]).collect();
+        assert_eq!(
+            messages,
+            vec!["undeclared variable `x`", "undeclared variable `y`", "undeclared function `g`"]
+        );
+    }
+}
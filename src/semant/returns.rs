@@ -0,0 +1,166 @@
+use ast::ast::*;
+use ast::pretty;
+use ast::visit::{self, Visitor};
+use source::diagnostic::Diagnostic;
+use source::source_map::Span;
+
+/*
+Returns: a function with a result must end every path through its body with
+`return e` or `error(...)`, a void function must not return a value, and
+`return;` is only for void functions. `break` and `continue` are only allowed
+inside loops.
+
+Whether control can fall through a statement, that is reach the one after it,
+is decided on the syntax
+
+- `return`, `error`, `break` and `continue` never fall through
+- `if` falls through if either branch does, a missing `else` does
+- a block falls through if all its statements do
+- a loop falls through unless its condition is the literal `true` and it has
+  no `break` of its own, there is no other way out of it
+ */
+pub fn check(p: &Program) -> Result<(), Vec<Diagnostic>> {
+    let mut checker = ReturnChecker {
+        function: String::new(),
+        void: true,
+        loops: 0,
+        errors: Vec::new(),
+    };
+    checker.visit_program(p);
+    if checker.errors.is_empty() {
+        Ok(())
+    } else {
+        Err(checker.errors)
+    }
+}
+
+pub fn falls_through(s: &Stmt) -> bool {
+    match s.kind {
+        StmtKind::Return(_) | StmtKind::Error(_) | StmtKind::Break | StmtKind::Continue => false,
+        StmtKind::If(_, ref then, Some(ref otherwise)) => falls_through(then) || falls_through(otherwise),
+        StmtKind::Block(ref b) => block_falls_through(b),
+        StmtKind::While(ref cond, _, ref body) | StmtKind::For(_, ref cond, _, _, ref body) => {
+            cond.kind != ExpKind::Bool(true) || breaks(body)
+        }
+        _ => true,
+    }
+}
+
+// Whether `s` has a `break` leaving the loop around it
+fn breaks(s: &Stmt) -> bool {
+    match s.kind {
+        StmtKind::Break => true,
+        StmtKind::If(_, ref then, ref otherwise) => breaks(then) || otherwise.as_ref().is_some_and(|s| breaks(s)),
+        StmtKind::Block(ref b) => b.stmts.iter().any(breaks),
+        _ => false,
+    }
+}
+
+pub fn block_falls_through(b: &Block) -> bool {
+    b.stmts.iter().all(falls_through)
+}
+
+struct ReturnChecker {
+    function: String,
+    void: bool,
+    // How many loops are around the statement being checked
+    loops: usize,
+    errors: Vec<Diagnostic>,
+}
+
+impl Visitor for ReturnChecker {
+    fn visit_function(&mut self, f: &Function) {
+        let body = match f.body {
+            Some(ref body) => body,
+            None => return,
+        };
+        self.function = f.name.name.clone();
+        self.void = f.ret.kind == TpKind::Void;
+        self.visit_block(body);
+        if !self.void && block_falls_through(body) {
+            let end = Span {
+                lo: body.span.hi - 1,
+                ..body.span
+            };
+            self.errors.push(
+                Diagnostic::error(
+                    end,
+                    format!("control reaches the end of `{}` without returning a value", f.name.name),
+                )
+                .note(f.ret.span, format!("`{}` returns `{}`", f.name.name, pretty::tp(&f.ret)))
+                .help("end every path with `return` or `error`"),
+            );
+        }
+    }
+
+    fn visit_stmt(&mut self, s: &Stmt) {
+        match s.kind {
+            StmtKind::Return(Some(ref e)) if self.void => self.errors.push(Diagnostic::error(
+                e.span,
+                format!("`{}` returns `void`, it cannot return a value", self.function),
+            )),
+            StmtKind::Return(None) if !self.void => self.errors.push(Diagnostic::error(
+                s.span,
+                format!("`{}` must return a value", self.function),
+            )),
+            StmtKind::Break | StmtKind::Continue if self.loops == 0 => {
+                let keyword = if s.kind == StmtKind::Break { "break" } else { "continue" };
+                self.errors.push(Diagnostic::error(s.span, format!("`{}` outside of a loop", keyword)));
+            }
+            StmtKind::While(..) | StmtKind::For(..) => {
+                self.loops += 1;
+                visit::walk_stmt(self, s);
+                self.loops -= 1;
+            }
+            _ => visit::walk_stmt(self, s),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use semant::tests;
+
+    fn errors(text: &str) -> Vec<String> {
+        tests::errors(|p, _| check(p), text)
+    }
+
+    #[test]
+    fn returning_on_every_path() {
+        let ok = "int f(int x) { if (x > 0) return 1; else { error(\"no\"); } }\n\
+                  int g(int x) { while (true) { x++; } }\n\
+                  int h(int x) { for (;true;) { if (x > 0) return x; } }\n\
+                  int k(int x) { { return x; } x++; }\n\
+                  void v(int x) { if (x > 0) return; x++; }\n\
+                  int b(int x) { while (true) { if (x > 0) { for (;true;) break; continue; } else break; } return x; }";
+        assert_eq!(errors(ok), Vec::<String>::new());
+    }
+
+    #[test]
+    fn missing_returns() {
+        let cases = vec![
+            ("int f() { }", "control reaches the end of `f` without returning a value"),
+            ("int f(int x) { if (x > 0) return 1; }", "control reaches the end of `f` without returning a value"),
+            ("int f(int x) { while (x > 0) return 1; }",
+             "control reaches the end of `f` without returning a value"),
+            ("void f() { return 1; }", "`f` returns `void`, it cannot return a value"),
+            ("int f() { return; }", "`f` must return a value"),
+            ("int f(int x) { while (true) { if (x > 0) break; x++; } }",
+             "control reaches the end of `f` without returning a value"),
+            ("void f() { break; }", "`break` outside of a loop"),
+            ("void f(bool b) { if (b) { continue; } }", "`continue` outside of a loop"),
+        ];
+        for (source, message) in cases {
+            assert_eq!(errors(source), vec![String::from(message)], "{}", source);
+        }
+    }
+
+    #[test]
+    fn pointing_at_the_closing_brace() {
+        let text = "int f(int x) {\n  if (x > 0) return 1;\n}\n";
+        let span = check(&tests::parse(text)).unwrap_err()[0].span;
+        assert_eq!(&text[span.lo..span.hi], "}");
+        assert_eq!(span.lo, text.len() - 2);
+    }
+}
@@ -0,0 +1,288 @@
+use ast::ast::*;
+use ast::visit::{self, Visitor};
+use semant::typeck::Typing;
+use semant::types::Type;
+use source::diagnostic::Diagnostic;
+use source::source_map::Span;
+use std::collections::HashMap;
+
+/*
+Struct definitions and the layout of their values in memory.
+
+A struct must be defined before a value of it is needed, that is before it is
+allocated or included by value in another struct (the type checker already
+requires a definition to select a field). As definitions only refer to
+earlier ones, a struct can never contain itself. Field names are unique
+within a struct.
+
+Layouts are for a 64-bit target, where
+
+    type             size  alignment
+    bool, char          1          1
+    int                 4          4
+    pointers, arrays,   8          8
+    strings
+    struct s         sum of fields, each aligned, rounded up to the largest
+                     alignment of a field
+
+Arrays and strings are references to the heap, like pointers.
+ */
+#[derive(Clone, Debug, PartialEq)]
+pub struct FieldLayout {
+    pub name: String,
+    pub ty: Type,
+    pub offset: usize,
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct StructLayout {
+    pub size: usize,
+    pub align: usize,
+    pub fields: Vec<FieldLayout>,
+}
+
+impl StructLayout {
+    pub fn field(&self, name: &str) -> Option<&FieldLayout> {
+        self.fields.iter().find(|f| f.name == name)
+    }
+}
+
+#[derive(Clone, Debug, Default)]
+pub struct Layouts {
+    pub structs: HashMap<String, StructLayout>,
+}
+
+impl Layouts {
+    // Size and alignment of a value of type `ty`, structs must have a layout
+    pub fn size_align(&self, ty: &Type) -> (usize, usize) {
+        match *ty {
+            Type::Bool | Type::Char => (1, 1),
+            Type::Int => (4, 4),
+            Type::String | Type::Pointer(_) | Type::Array(_) | Type::Null => (8, 8),
+            Type::Struct(ref s) => {
+                let layout = &self.structs[s];
+                (layout.size, layout.align)
+            }
+            Type::Void => (0, 1),
+        }
+    }
+
+    pub fn size_of(&self, ty: &Type) -> usize {
+        self.size_align(ty).0
+    }
+}
+
+fn align_to(offset: usize, align: usize) -> usize {
+    offset.div_ceil(align) * align
+}
+
+pub fn check(p: &Program, typing: &Typing) -> Result<Layouts, Vec<Diagnostic>> {
+    let mut checker = StructChecker {
+        typing,
+        layouts: Layouts::default(),
+        defined: HashMap::new(),
+        errors: Vec::new(),
+    };
+    checker.visit_program(p);
+    if checker.errors.is_empty() {
+        Ok(checker.layouts)
+    } else {
+        Err(checker.errors)
+    }
+}
+
+struct StructChecker<'a> {
+    typing: &'a Typing,
+    layouts: Layouts,
+    // Structs defined so far, with where
+    defined: HashMap<String, Span>,
+    errors: Vec<Diagnostic>,
+}
+
+impl<'a> StructChecker<'a> {
+    // A value of `ty` is needed at `span`, if it is a struct it must be defined by now
+    fn require(&mut self, ty: &Type, span: Span, what: &str) -> bool {
+        match *ty {
+            Type::Struct(ref s) if !self.defined.contains_key(s) => {
+                self.errors.push(
+                    Diagnostic::error(
+                        span,
+                        format!("struct `{}` is not defined, {}", s, what),
+                    )
+                    .help(format!("define `struct {}` before this point", s)),
+                );
+                false
+            }
+            _ => true,
+        }
+    }
+
+    fn define(&mut self, name: &Ident, fields: &[Field]) {
+        let mut seen: HashMap<&str, Span> = HashMap::new();
+        let mut complete = true;
+        for f in fields {
+            if let Some(&previous) = seen.get(&f.name.name[..]) {
+                self.errors.push(
+                    Diagnostic::error(
+                        f.name.span,
+                        format!(
+                            "duplicate field `{}` in struct `{}`",
+                            f.name.name, name.name
+                        ),
+                    )
+                    .note(previous, "first declared here"),
+                );
+                complete = false;
+            }
+            seen.insert(&f.name.name, f.name.span);
+        }
+        for (f, (_, ty)) in fields.iter().zip(&self.typing.structs[&name.name]) {
+            match *ty {
+                Type::Struct(ref s) if *s == name.name => {
+                    self.errors.push(
+                        Diagnostic::error(
+                            f.tp.span,
+                            format!("struct `{}` cannot contain itself", s),
+                        )
+                        .help(format!("use a pointer, `struct {}*`", s)),
+                    );
+                    complete = false;
+                }
+                // Defined with errors of its own, so without a layout to include
+                Type::Struct(ref s) if self.defined.contains_key(s) && !self.layouts.structs.contains_key(s) => {
+                    complete = false;
+                }
+                _ => complete &= self.require(ty, f.tp.span, "it cannot be included in another struct"),
+            }
+        }
+        self.defined.insert(name.name.clone(), name.span);
+        if complete {
+            let layout = self.layout(&self.typing.structs[&name.name]);
+            self.layouts.structs.insert(name.name.clone(), layout);
+        }
+    }
+
+    fn layout(&self, fields: &[(String, Type)]) -> StructLayout {
+        let mut offset = 0;
+        let mut align = 1;
+        let mut laid = Vec::new();
+        for (name, ty) in fields {
+            let (size, field_align) = self.layouts.size_align(ty);
+            offset = align_to(offset, field_align);
+            laid.push(FieldLayout {
+                name: name.clone(),
+                ty: ty.clone(),
+                offset,
+            });
+            offset += size;
+            align = align.max(field_align);
+        }
+        StructLayout {
+            size: align_to(offset, align),
+            align,
+            fields: laid,
+        }
+    }
+}
+
+impl<'a> Visitor for StructChecker<'a> {
+    fn visit_gdecl(&mut self, d: &GDecl) {
+        match d.kind {
+            GDeclKind::StructDef(ref name, ref fields) => self.define(name, fields),
+            _ => visit::walk_gdecl(self, d),
+        }
+    }
+
+    fn visit_exp(&mut self, e: &Exp) {
+        match (&e.kind, self.typing.exps.get(&e.id)) {
+            (&ExpKind::Alloc(_), Some(&Type::Pointer(ref ty)))
+            | (&ExpKind::AllocArray(..), Some(&Type::Array(ref ty))) => {
+                self.require(ty, e.span, "it cannot be allocated");
+            }
+            _ => (),
+        }
+        visit::walk_exp(self, e)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use semant::resolve::resolve;
+    use semant::tests::parse;
+    use semant::typeck;
+
+    fn layouts(text: &str) -> Result<Layouts, Vec<String>> {
+        let p = parse(text);
+        let resolution = resolve(&p).unwrap();
+        let typing = typeck::check(&p, &resolution).unwrap();
+        check(&p, &typing).map_err(|errors| errors.into_iter().map(|d| d.message).collect())
+    }
+
+    #[test]
+    fn laying_out_structs() {
+        let layouts = layouts(
+            "struct point { char tag; int x; int y; };\n\
+             struct shape { bool closed; struct point origin; string name; struct shape* next; char c; };\n\
+             struct empty { };",
+        )
+        .unwrap();
+        let point = &layouts.structs["point"];
+        let offsets: Vec<usize> = point.fields.iter().map(|f| f.offset).collect();
+        assert_eq!((point.size, point.align, offsets), (12, 4, vec![0, 4, 8]));
+        let shape = &layouts.structs["shape"];
+        let offsets: Vec<usize> = shape.fields.iter().map(|f| f.offset).collect();
+        assert_eq!(
+            (shape.size, shape.align, offsets),
+            (40, 8, vec![0, 4, 16, 24, 32])
+        );
+        assert_eq!(
+            shape.field("next").unwrap().ty,
+            Type::Pointer(Box::new(Type::Struct(String::from("shape"))))
+        );
+        assert_eq!(layouts.structs["empty"].size, 0);
+    }
+
+    #[test]
+    fn struct_errors() {
+        let cases = vec![
+            (
+                "struct s { int x; bool x; };",
+                "duplicate field `x` in struct `s`",
+            ),
+            (
+                "struct s { struct s inner; };",
+                "struct `s` cannot contain itself",
+            ),
+            (
+                "struct s { struct t inner; };\nstruct t { int x; };",
+                "struct `t` is not defined, it cannot be included in another struct",
+            ),
+            (
+                "struct s;\nstruct s* f() { return alloc(struct s); }\nstruct s { int x; };",
+                "struct `s` is not defined, it cannot be allocated",
+            ),
+            (
+                "typedef struct s t;\nt[] f() { return alloc_array(t, 2); }",
+                "struct `s` is not defined, it cannot be allocated",
+            ),
+            // A struct with errors is reported once, not again in those including it
+            (
+                "struct a { struct b x; };\nstruct b { struct a y; };",
+                "struct `b` is not defined, it cannot be included in another struct",
+            ),
+            (
+                "struct a { int x; int x; };\nstruct b { struct a y; };",
+                "duplicate field `x` in struct `a`",
+            ),
+        ];
+        for (source, message) in cases {
+            assert_eq!(
+                layouts(source).unwrap_err(),
+                vec![String::from(message)],
+                "{}",
+                source
+            );
+        }
+    }
+}
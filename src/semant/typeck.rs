@@ -0,0 +1,717 @@
+use ast::ast::*;
+use ast::visit::{self, Visitor};
+use semant::resolve::{DeclId, Resolution};
+use semant::types::{self, show, Alias, Signature, Type};
+use source::diagnostic::Diagnostic;
+use std::collections::HashMap;
+
+/*
+What the checker learned, for later passes: the type of every expression by
+its `NodeId`, of every variable and the signature of every function by their
+declaration, and the fields of every struct defined.
+ */
+#[derive(Clone, Debug, Default)]
+pub struct Typing {
+    pub exps: HashMap<NodeId, Type>,
+    pub vars: HashMap<DeclId, Type>,
+    pub functions: HashMap<DeclId, Signature>,
+    pub structs: HashMap<String, Vec<(String, Type)>>,
+}
+
+impl Typing {
+    pub fn exp(&self, e: &Exp) -> &Type {
+        &self.exps[&e.id]
+    }
+}
+
+/*
+The static semantics of C0 types, following the language reference
+
+- there are no implicit conversions, `int`, `bool`, `char` and `string` are
+  all distinct
+- values of small types, everything but structs, may be assigned, passed,
+  returned and stored in variables; structs live on the heap and are only
+  reached through pointers, `p->f`, or as a whole with `*p` to select a field
+- `==` and `!=` compare ints, bools, chars, pointers and arrays, not strings
+  or structs; `<`, `<=`, `>=` and `>` compare ints or chars
+- `NULL` has every pointer type, but cannot be dereferenced
+- `void` is only the return type of a function, there are no void variables,
+  pointers or arrays, and the result of a void function can only be
+  discarded
+- contracts, conditions and assertions are bools, `error` takes a string
+
+Whether `return` has a value when it should, and where `\result` may appear,
+are checked by other passes.
+ */
+pub fn check(p: &Program, resolution: &Resolution) -> Result<Typing, Vec<Diagnostic>> {
+    let mut checker = Checker {
+        resolution,
+        typing: Typing::default(),
+        errors: Vec::new(),
+        typedefs: HashMap::new(),
+        aliases: HashMap::new(),
+        ret: Type::Void,
+        ret_alias: None,
+    };
+    checker.visit_program(p);
+    if checker.errors.is_empty() {
+        Ok(checker.typing)
+    } else {
+        Err(checker.errors)
+    }
+}
+
+/*
+Expressions which fail to check have no type, `None`, and the error has been
+reported, so expressions around them are not reported again.
+ */
+struct Checker<'a> {
+    resolution: &'a Resolution,
+    typing: Typing,
+    errors: Vec<Diagnostic>,
+    typedefs: HashMap<String, Type>,
+    // How declarations wrote their types, when through a typedef
+    aliases: HashMap<Declared, String>,
+    // The return type of the function being checked
+    ret: Type,
+    ret_alias: Alias,
+}
+
+#[derive(Clone, Debug, PartialEq, Eq, Hash)]
+enum Declared {
+    Var(DeclId),
+    Ret(DeclId),
+    Param(DeclId, usize),
+    Field(String, String),
+}
+
+impl<'a> Checker<'a> {
+    fn error(&mut self, d: Diagnostic) -> Option<Type> {
+        self.errors.push(d);
+        None
+    }
+
+    // The type a `Tp` denotes, void is only accepted on its own
+    fn tp(&mut self, t: &Tp) -> Option<Type> {
+        let ty = match t.kind {
+            TpKind::Int => Type::Int,
+            TpKind::Bool => Type::Bool,
+            TpKind::String => Type::String,
+            TpKind::Char => Type::Char,
+            TpKind::Void => Type::Void,
+            TpKind::Pointer(ref inner) => match self.tp(inner)? {
+                Type::Void => return self.error(Diagnostic::error(t.span, "pointers to void are not allowed")),
+                inner => Type::Pointer(Box::new(inner)),
+            },
+            TpKind::Array(ref inner) => match self.tp(inner)? {
+                Type::Void => return self.error(Diagnostic::error(t.span, "arrays of void are not allowed")),
+                inner => Type::Array(Box::new(inner)),
+            },
+            TpKind::Struct(ref s) => Type::Struct(s.name.clone()),
+            TpKind::Name(ref x) => self.typedefs.get(&x.name)?.clone(),
+        };
+        Some(ty)
+    }
+
+    // The type of a variable or parameter, which must be small
+    fn var_tp(&mut self, t: &Tp, what: &str) -> Option<Type> {
+        match self.tp(t)? {
+            Type::Void => self.error(Diagnostic::error(t.span, format!("{} cannot have type `void`", what))),
+            ty @ Type::Struct(_) => self.error(
+                Diagnostic::error(t.span, format!("{} cannot have type {}", what, show(&ty, &types::alias(t))))
+                    .help(format!("structs are only used through pointers, try `{}*`", ty)),
+            ),
+            ty => Some(ty),
+        }
+    }
+
+    fn declare_var(&mut self, x: &Ident, t: &Tp, ty: Option<Type>) {
+        if let (Some(decl), Some(ty)) = (self.resolution.decl_id(x), ty) {
+            self.typing.vars.insert(decl, ty);
+            self.remember(Declared::Var(decl), types::alias(t));
+        }
+    }
+
+    fn remember(&mut self, declared: Declared, alias: Alias) {
+        if let Some(alias) = alias {
+            self.aliases.insert(declared, alias);
+        }
+    }
+
+    // How the type of `e` was written, when it comes straight from a declaration
+    fn exp_alias(&self, e: &Exp) -> Alias {
+        let declared = match e.kind {
+            ExpKind::Var(ref x) => Declared::Var(self.resolution.decl_id(x)?),
+            ExpKind::Call(ref f, _) => Declared::Ret(self.resolution.decl_id(f)?),
+            ExpKind::Field(ref s, ref f) | ExpKind::Arrow(ref s, ref f) => match *self.typing.exps.get(&s.id)? {
+                Type::Struct(ref name) => Declared::Field(name.clone(), f.name.clone()),
+                Type::Pointer(ref t) => match **t {
+                    Type::Struct(ref name) => Declared::Field(name.clone(), f.name.clone()),
+                    _ => return None,
+                },
+                _ => return None,
+            },
+            _ => return None,
+        };
+        self.aliases.get(&declared).cloned()
+    }
+
+    // The type of `e` for messages
+    fn show(&self, e: &Exp, ty: &Type) -> String {
+        show(ty, &self.exp_alias(e))
+    }
+
+    fn mismatch(&self, e: &Exp, expected: &Type, alias: &Alias, found: &Type) -> Diagnostic {
+        Diagnostic::error(
+            e.span,
+            format!("expected {}, found {}", show(expected, alias), self.show(e, found)),
+        )
+    }
+
+    fn expect(&mut self, e: &Exp, expected: &Type) -> Option<Type> {
+        self.expect_as(e, expected, &None)
+    }
+
+    // Expects `e` to have a type declared as `alias`
+    fn expect_as(&mut self, e: &Exp, expected: &Type, alias: &Alias) -> Option<Type> {
+        let found = self.exp(e)?;
+        if expected.accepts(&found) {
+            Some(found)
+        } else {
+            let d = self.mismatch(e, expected, alias, &found);
+            self.error(d)
+        }
+    }
+
+    fn exp(&mut self, e: &Exp) -> Option<Type> {
+        let ty = self.infer(e)?;
+        self.typing.exps.insert(e.id, ty.clone());
+        Some(ty)
+    }
+
+    fn infer(&mut self, e: &Exp) -> Option<Type> {
+        match e.kind {
+            ExpKind::Num(..) => Some(Type::Int),
+            ExpKind::Bool(_) => Some(Type::Bool),
+            ExpKind::Null => Some(Type::Null),
+            ExpKind::Char(_) => Some(Type::Char),
+            ExpKind::Str(_) => Some(Type::String),
+            ExpKind::Result => Some(self.ret.clone()),
+            ExpKind::Var(ref x) => {
+                let decl = self.resolution.decl_id(x)?;
+                self.typing.vars.get(&decl).cloned()
+            }
+            ExpKind::Unop(op, ref operand) => match op {
+                UnOp::Not => self.expect(operand, &Type::Bool),
+                UnOp::BitNot | UnOp::Neg => self.expect(operand, &Type::Int),
+                UnOp::Deref => match self.exp(operand)? {
+                    Type::Pointer(t) => Some(*t),
+                    Type::Null => self.error(Diagnostic::error(e.span, "cannot dereference `NULL`")),
+                    t => {
+                        let found = self.show(operand, &t);
+                        let d = Diagnostic::error(operand.span, format!("expected a pointer, found {}", found));
+                        self.error(d)
+                    }
+                },
+            },
+            ExpKind::Binop(op, ref l, ref r) => self.binop(e, op, l, r),
+            ExpKind::Ternary(ref c, ref t, ref f) => {
+                let c = self.expect(c, &Type::Bool);
+                let (t_ty, f_ty) = (self.exp(t), self.exp(f));
+                let (t_ty, f_ty) = (t_ty?, f_ty?);
+                c?;
+                match Type::unify(&t_ty, &f_ty) {
+                    Some(ref ty) if !ty.is_small() => self.error(Diagnostic::error(
+                        e.span,
+                        format!("conditional expressions cannot have type `{}`", ty),
+                    )),
+                    Some(ty) => Some(ty),
+                    None => {
+                        let d = Diagnostic::error(
+                            f.span,
+                            format!(
+                                "the branches of a conditional must have the same type, found {} and {}",
+                                self.show(t, &t_ty),
+                                self.show(f, &f_ty)
+                            ),
+                        );
+                        self.error(d)
+                    }
+                }
+            }
+            ExpKind::Call(ref f, ref args) => self.call(e, f, args),
+            ExpKind::Field(ref s, ref f) => match self.exp(s)? {
+                Type::Struct(name) => self.field(&name, f),
+                t => {
+                    let mut d = Diagnostic::error(s.span, format!("expected a struct, found {}", self.show(s, &t)));
+                    if let Type::Pointer(ref t) = t {
+                        if let Type::Struct(_) = **t {
+                            d = d.help(format!("use `->{}` to select a field through a pointer", f.name));
+                        }
+                    }
+                    self.error(d)
+                }
+            },
+            ExpKind::Arrow(ref s, ref f) => match self.exp(s)? {
+                Type::Pointer(ref t) if matches!(**t, Type::Struct(_)) => match **t {
+                    Type::Struct(ref name) => self.field(name, f),
+                    _ => unreachable!(),
+                },
+                Type::Null => self.error(Diagnostic::error(s.span, "cannot dereference `NULL`")),
+                t => {
+                    let d = Diagnostic::error(
+                        s.span,
+                        format!("expected a pointer to a struct, found {}", self.show(s, &t)),
+                    );
+                    self.error(d)
+                }
+            },
+            ExpKind::Index(ref a, ref i) => {
+                let a_ty = self.exp(a);
+                let i_ty = self.expect(i, &Type::Int);
+                let ty = match a_ty? {
+                    Type::Array(t) => *t,
+                    t => {
+                        let d = Diagnostic::error(a.span, format!("expected an array, found {}", self.show(a, &t)));
+                        return self.error(d);
+                    }
+                };
+                i_ty?;
+                Some(ty)
+            }
+            ExpKind::Alloc(ref t) => match self.tp(t)? {
+                Type::Void => self.error(Diagnostic::error(t.span, "cannot allocate `void`")),
+                ty => Some(Type::Pointer(Box::new(ty))),
+            },
+            ExpKind::AllocArray(ref t, ref len) => {
+                let ty = self.tp(t);
+                let len = self.expect(len, &Type::Int);
+                match ty? {
+                    Type::Void => self.error(Diagnostic::error(t.span, "cannot allocate `void`")),
+                    ty => {
+                        len?;
+                        Some(Type::Array(Box::new(ty)))
+                    }
+                }
+            }
+            ExpKind::Length(ref a) => match self.exp(a)? {
+                Type::Array(_) => Some(Type::Int),
+                t => {
+                    let d = Diagnostic::error(a.span, format!("expected an array, found {}", self.show(a, &t)));
+                    self.error(d)
+                }
+            },
+        }
+    }
+
+    fn binop(&mut self, e: &Exp, op: BinOp, l: &Exp, r: &Exp) -> Option<Type> {
+        match op {
+            BinOp::Mul
+            | BinOp::Div
+            | BinOp::Mod
+            | BinOp::Add
+            | BinOp::Sub
+            | BinOp::Shl
+            | BinOp::Shr
+            | BinOp::BitAnd
+            | BinOp::BitXor
+            | BinOp::BitOr => {
+                let (l, r) = (self.expect(l, &Type::Int), self.expect(r, &Type::Int));
+                l.and(r)
+            }
+            BinOp::And | BinOp::Or => {
+                let (l, r) = (self.expect(l, &Type::Bool), self.expect(r, &Type::Bool));
+                l.and(r).map(|_| Type::Bool)
+            }
+            BinOp::Lt | BinOp::Le | BinOp::Ge | BinOp::Gt => {
+                let (l_ty, r_ty) = (self.exp(l), self.exp(r));
+                match l_ty? {
+                    ty @ Type::Int | ty @ Type::Char => {
+                        let r_ty = r_ty?;
+                        if r_ty != ty {
+                            let d = self.mismatch(r, &ty, &self.exp_alias(l), &r_ty);
+                            return self.error(d);
+                        }
+                        Some(Type::Bool)
+                    }
+                    ty => {
+                        let found = self.show(l, &ty);
+                        let d = Diagnostic::error(l.span, format!("expected `int` or `char`, found {}", found));
+                        self.error(d)
+                    }
+                }
+            }
+            BinOp::Eq | BinOp::Ne => {
+                let (l_ty, r_ty) = (self.exp(l), self.exp(r));
+                let (l_ty, r_ty) = (l_ty?, r_ty?);
+                match Type::unify(&l_ty, &r_ty) {
+                    Some(Type::String) => self.error(
+                        Diagnostic::error(e.span, format!("strings cannot be compared with `{}`", op))
+                            .help("use `string_equal` from <string>"),
+                    ),
+                    Some(ref ty) if !ty.is_small() => self.error(Diagnostic::error(
+                        e.span,
+                        format!("values of type `{}` cannot be compared with `{}`", ty, op),
+                    )),
+                    Some(_) => Some(Type::Bool),
+                    None => {
+                        let d = self.mismatch(r, &l_ty, &self.exp_alias(l), &r_ty);
+                        self.error(d)
+                    }
+                }
+            }
+        }
+    }
+
+    fn call(&mut self, e: &Exp, f: &Ident, args: &[Exp]) -> Option<Type> {
+        let signature = self
+            .resolution
+            .decl_id(f)
+            .and_then(|decl| self.typing.functions.get(&decl))
+            .cloned();
+        let arg_types: Vec<Option<Type>> = args.iter().map(|arg| self.exp(arg)).collect();
+        let signature = signature?;
+        if args.len() != signature.params.len() {
+            let d = Diagnostic::error(
+                e.span,
+                format!(
+                    "function `{}` expects {} argument{}, found {}",
+                    f.name,
+                    signature.params.len(),
+                    if signature.params.len() == 1 { "" } else { "s" },
+                    args.len()
+                ),
+            );
+            let span = self.resolution.decl(f).map(|d| d.span);
+            return match span {
+                Some(span) => self.error(d.note(span, "declared here")),
+                None => self.error(d),
+            };
+        }
+        let decl = self.resolution.decl_id(f)?;
+        let mut ok = true;
+        for (i, ((arg, ty), expected)) in args.iter().zip(arg_types).zip(&signature.params).enumerate() {
+            match ty {
+                Some(ref ty) if !expected.accepts(ty) => {
+                    let alias = self.aliases.get(&Declared::Param(decl, i)).cloned();
+                    let d = self.mismatch(arg, expected, &alias, ty);
+                    self.error(d);
+                    ok = false;
+                }
+                Some(_) => (),
+                None => ok = false,
+            }
+        }
+        if ok {
+            Some(signature.ret)
+        } else {
+            None
+        }
+    }
+
+    fn field(&mut self, s: &str, f: &Ident) -> Option<Type> {
+        let fields = match self.typing.structs.get(s) {
+            Some(fields) => fields,
+            None => {
+                return self.error(Diagnostic::error(
+                    f.span,
+                    format!("struct `{}` is not defined, its fields are unknown", s),
+                ))
+            }
+        };
+        match fields.iter().find(|&(name, _)| *name == f.name) {
+            Some((_, ty)) => Some(ty.clone()),
+            None => self.error(Diagnostic::error(
+                f.span,
+                format!("struct `{}` has no field `{}`", s, f.name),
+            )),
+        }
+    }
+
+    fn condition(&mut self, e: &Exp) {
+        self.expect(e, &Type::Bool);
+    }
+}
+
+impl<'a> Visitor for Checker<'a> {
+    fn visit_gdecl(&mut self, d: &GDecl) {
+        match d.kind {
+            GDeclKind::StructDef(ref name, ref fields) => {
+                let mut typed = Vec::new();
+                for f in fields {
+                    match self.tp(&f.tp) {
+                        Some(Type::Void) => {
+                            self.error(Diagnostic::error(f.tp.span, "fields cannot have type `void`"));
+                        }
+                        Some(ty) => {
+                            let field = Declared::Field(name.name.clone(), f.name.name.clone());
+                            self.remember(field, types::alias(&f.tp));
+                            typed.push((f.name.name.clone(), ty));
+                        }
+                        None => (),
+                    }
+                }
+                self.typing.structs.insert(name.name.clone(), typed);
+            }
+            GDeclKind::Typedef(ref t, ref name) => {
+                if let Some(ty) = self.tp(t) {
+                    self.typedefs.insert(name.name.clone(), ty);
+                }
+            }
+            _ => visit::walk_gdecl(self, d),
+        }
+    }
+
+    fn visit_function(&mut self, f: &Function) {
+        let ret = match self.tp(&f.ret) {
+            Some(ty @ Type::Struct(_)) => self.error(
+                Diagnostic::error(f.ret.span, format!("functions cannot return {}", show(&ty, &types::alias(&f.ret))))
+                    .help(format!("return a pointer, `{}*`", ty)),
+            ),
+            ret => ret,
+        };
+        let params: Vec<Option<Type>> = f
+            .params
+            .iter()
+            .map(|p| {
+                let ty = self.var_tp(&p.tp, "parameters");
+                self.declare_var(&p.name, &p.tp, ty.clone());
+                ty
+            })
+            .collect();
+        // The first declaration gives the signature, the others must agree
+        let params: Option<Vec<Type>> = params.into_iter().collect();
+        if let (Some(decl), Some(params), Some(ret)) = (self.resolution.decl_id(&f.name), params, ret.clone()) {
+            let signature = Signature { params, ret };
+            match self.typing.functions.get(&decl).cloned() {
+                Some(ref first) if *first != signature => {
+                    self.error(
+                        Diagnostic::error(f.name.span, format!("conflicting declarations of `{}`", f.name.name))
+                            .note(
+                                self.resolution.decls[decl].span,
+                                format!("first declared as `{}`", first.show(&f.name.name)),
+                            )
+                            .help(format!("here it is `{}`", signature.show(&f.name.name))),
+                    );
+                }
+                Some(_) => (),
+                None => {
+                    self.typing.functions.insert(decl, signature);
+                    self.remember(Declared::Ret(decl), types::alias(&f.ret));
+                    for (i, p) in f.params.iter().enumerate() {
+                        self.remember(Declared::Param(decl, i), types::alias(&p.tp));
+                    }
+                }
+            }
+        }
+        self.ret = ret.unwrap_or(Type::Void);
+        self.ret_alias = types::alias(&f.ret);
+        for s in &f.specs {
+            self.visit_spec(s);
+        }
+        if let Some(ref body) = f.body {
+            self.visit_block(body);
+        }
+    }
+
+    fn visit_spec(&mut self, s: &Spec) {
+        self.condition(&s.exp);
+    }
+
+    fn visit_stmt(&mut self, s: &Stmt) {
+        match s.kind {
+            StmtKind::If(ref cond, _, _) | StmtKind::While(ref cond, _, _) => self.condition(cond),
+            StmtKind::For(ref init, ref cond, _, _, _) => {
+                if let Some(ref init) = *init {
+                    self.visit_simple(init);
+                }
+                self.condition(cond);
+            }
+            StmtKind::Return(Some(ref e)) => {
+                if self.ret == Type::Void {
+                    self.exp(e);
+                } else {
+                    let (ret, alias) = (self.ret.clone(), self.ret_alias.clone());
+                    self.expect_as(e, &ret, &alias);
+                }
+            }
+            StmtKind::Assert(ref e) => self.condition(e),
+            StmtKind::Error(ref e) => {
+                self.expect(e, &Type::String);
+            }
+            _ => return visit::walk_stmt(self, s),
+        }
+        // The children left, conditions have been checked
+        match s.kind {
+            StmtKind::If(_, ref then, ref otherwise) => {
+                self.visit_stmt(then);
+                if let Some(ref otherwise) = *otherwise {
+                    self.visit_stmt(otherwise);
+                }
+            }
+            StmtKind::While(_, ref invariants, ref body) => {
+                for s in invariants {
+                    self.visit_spec(s);
+                }
+                self.visit_stmt(body);
+            }
+            StmtKind::For(_, _, ref step, ref invariants, ref body) => {
+                if let Some(ref step) = *step {
+                    self.visit_simple(step);
+                }
+                for s in invariants {
+                    self.visit_spec(s);
+                }
+                self.visit_stmt(body);
+            }
+            _ => (),
+        }
+    }
+
+    fn visit_simple(&mut self, s: &Simple) {
+        match s.kind {
+            SimpleKind::Assign(AsnOp::Assign, ref lhs, ref rhs) => {
+                let ty = self.exp(lhs);
+                match ty {
+                    Some(ref ty) if !ty.is_small() => {
+                        let found = self.show(lhs, ty);
+                        let d = Diagnostic::error(lhs.span, format!("values of type {} cannot be assigned", found))
+                            .help("assign their fields one by one");
+                        self.error(d);
+                        self.exp(rhs);
+                    }
+                    Some(ty) => {
+                        let alias = self.exp_alias(lhs);
+                        self.expect_as(rhs, &ty, &alias);
+                    }
+                    None => {
+                        self.exp(rhs);
+                    }
+                }
+            }
+            SimpleKind::Assign(AsnOp::Compound(_), ref lhs, ref rhs) => {
+                self.expect(lhs, &Type::Int);
+                self.expect(rhs, &Type::Int);
+            }
+            SimpleKind::Post(_, ref lhs) => {
+                self.expect(lhs, &Type::Int);
+            }
+            SimpleKind::Exp(ref e) => {
+                if let Some(ty @ Type::Struct(_)) = self.exp(e) {
+                    self.error(Diagnostic::error(
+                        e.span,
+                        format!("values of type `{}` cannot be used as statements", ty),
+                    ));
+                }
+            }
+            SimpleKind::Decl(ref t, ref name, ref init) => {
+                let ty = self.var_tp(t, "variables");
+                if let Some(ref init) = *init {
+                    match ty {
+                        Some(ref ty) => {
+                            self.expect_as(init, ty, &types::alias(t));
+                        }
+                        None => {
+                            self.exp(init);
+                        }
+                    }
+                }
+                self.declare_var(name, t, ty);
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use semant::tests::errors;
+
+    #[test]
+    fn checking_programs() {
+        let ok = "typedef struct node* list;\n\
+                  struct node { int head; list tail; char[] tag; };\n\
+                  int length(list l) { int n = 0; while (l != NULL) { n++; l = l->tail; } return n; }\n\
+                  void fill(int[] a, int n)\n//@requires \\length(a) == n;\n\
+                  { for (int i = 0; i < n; i++) a[i] = i * 2 % 7 << 1; }\n\
+                  bool first(string s, char c, list l) { return c < 'z' && (*l).head == 1 ? true : l->tag[0] == c; }\n\
+                  int main() { list l = alloc(struct node); l->tail = NULL; l->tag = alloc_array(char, 1);\n\
+                  int[] a = alloc_array(int, 3); fill(a, 3); int* p = NULL; if (p == NULL) error(\"null\");\n\
+                  assert(first(\"s\", 'c', l)); return length(l) + a[2]; }";
+        assert_eq!(errors(check, ok), Vec::<String>::new());
+    }
+
+    #[test]
+    fn type_errors() {
+        let cases = vec![
+            ("int f() { return true; }", "expected `int`, found `bool`"),
+            ("int f(int x) { if (x) return 1; return 0; }", "expected `bool`, found `int`"),
+            ("int f(int x) { return x + 'a'; }", "expected `int`, found `char`"),
+            ("bool f(string a, string b) { return a == b; }", "strings cannot be compared with `==`"),
+            ("struct s { int x; };\nbool f(struct s* a, struct s* b) { return *a == *b; }",
+             "values of type `struct s` cannot be compared with `==`"),
+            ("struct s { int x; };\nvoid f(struct s* a, struct s* b) { *a = *b; }",
+             "values of type `struct s` cannot be assigned"),
+            ("struct s { int x; };\nvoid f(struct s a) { }", "parameters cannot have type `struct s`"),
+            ("void f() { void x; }", "variables cannot have type `void`"),
+            ("void f() { void* x = NULL; }", "pointers to void are not allowed"),
+            ("int f() { return *NULL; }", "cannot dereference `NULL`"),
+            ("int g(int x, bool b);\nint f() { return g(1); }", "function `g` expects 2 arguments, found 1"),
+            ("int g(int x, bool b);\nint f() { return g(1, 2); }", "expected `bool`, found `int`"),
+            ("void g();\nint f() { return g(); }", "expected `int`, found `void`"),
+            ("struct s { int x; };\nint f(struct s* p) { return p->y; }", "struct `s` has no field `y`"),
+            ("int f(struct s* p) { return p->y; }", "struct `s` is not defined, its fields are unknown"),
+            ("struct s { int x; };\nint f(struct s* p) { return p.x; }", "expected a struct, found `struct s*`"),
+            ("int f(int[] a) { return a[true]; }", "expected `int`, found `bool`"),
+            ("int f(int a) { return a[0]; }", "expected an array, found `int`"),
+            ("int f(int x) { return x ? 1 : 0; }", "expected `bool`, found `int`"),
+            ("int f(bool b) { return b ? 1 : 'c'; }",
+             "the branches of a conditional must have the same type, found `int` and `char`"),
+            ("void f() { error(1); }", "expected `string`, found `int`"),
+            ("void f(bool b) { b++; }", "expected `int`, found `bool`"),
+            ("struct s { int x; };\nstruct s f() { return 1; }", "functions cannot return `struct s`"),
+            ("int f(bool b)\n//@requires 1;\n{ return 1; }", "expected `bool`, found `int`"),
+            ("int f(int n)\n//@requires \\length(n) > 0;\n{ return 1; }", "expected an array, found `int`"),
+            ("int f(int x);\nint f(bool x) { return 1; }", "conflicting declarations of `f`"),
+            ("int f(int x);\nbool f(int y);", "conflicting declarations of `f`"),
+        ];
+        for (source, message) in cases {
+            assert_eq!(errors(check, source), vec![String::from(message)], "{}", source);
+        }
+    }
+
+    #[test]
+    fn null_is_any_pointer() {
+        assert_eq!(
+            errors(
+                check,
+                "int* f(bool b, int* p) { int*[] a = alloc_array(int*, 1); a[0] = NULL; return b ? NULL : p; }",
+            ),
+            Vec::<String>::new()
+        );
+        assert_eq!(errors(check, "int f() { int x = NULL; return x; }"), vec!["expected `int`, found `NULL`"]);
+    }
+
+    #[test]
+    fn typedefs_are_expanded() {
+        let ok = "typedef int* ptr;\ntypedef ptr[] ptrs;\ntypedef struct list_node list;\n\
+                  struct list_node { int head; list* tail; };\n\
+                  int* f(ptr p, int*[] a, ptrs b) { a = b; b[0] = p; return a[0]; }\n\
+                  list* g() { list* l = alloc(struct list_node); l->tail = l; return l; }";
+        assert_eq!(errors(check, ok), Vec::<String>::new());
+        let cases = vec![
+            ("struct a { int x; };\nstruct b { int x; };\nstruct a* f(struct b* p) { return p; }",
+             "expected `struct a*`, found `struct b*`"),
+            ("typedef struct list_node list;\nint f(list* l) { return l; }",
+             "expected `int`, found `list*` (aka `struct list_node*`)"),
+            ("typedef int* ptr;\nvoid f(ptr p) { p = true; }", "expected `ptr` (aka `int*`), found `bool`"),
+            ("typedef bool flag;\nvoid g(flag f);\nvoid f() { g(1); }", "expected `flag` (aka `bool`), found `int`"),
+            ("typedef char[] str;\nstruct s { str name; };\nint f(struct s* p) { return p->name; }",
+             "expected `int`, found `str` (aka `char[]`)"),
+            ("typedef struct s t;\nvoid f(t x) { }", "parameters cannot have type `t` (aka `struct s`)"),
+        ];
+        for (source, message) in cases {
+            assert_eq!(errors(check, source), vec![String::from(message)], "{}", source);
+        }
+    }
+}
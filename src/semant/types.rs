@@ -0,0 +1,124 @@
+use ast::ast::*;
+use ast::pretty;
+use std::fmt;
+
+/*
+Canonical C0 types, what a `Tp` denotes once typedefs have been expanded. Two
+spellings of a type are the same type exactly when their canonical forms are
+equal, which is
+
+- structural for pointers and arrays, `ptr` and `int*` after
+  `typedef int* ptr;` are both `Pointer(Int)`
+- nominal for structs, `struct a` and `struct b` differ even with the same
+  fields
+
+Messages should say how the program wrote a type as well as what it is, see
+`Alias`.
+ */
+#[derive(Clone, Debug, PartialEq)]
+pub enum Type {
+    Int,
+    Bool,
+    Char,
+    String,
+    Void,
+    Pointer(Box<Type>),
+    Array(Box<Type>),
+    Struct(String),
+    // The type of `NULL`, which may be used as any pointer
+    Null,
+}
+
+impl Type {
+    // Values that fit in a variable
+    pub fn is_small(&self) -> bool {
+        !matches!(*self, Type::Struct(_) | Type::Void)
+    }
+
+    // Whether a value of type `found` may be used where `self` is expected
+    pub fn accepts(&self, found: &Type) -> bool {
+        self == found || (*found == Type::Null && matches!(*self, Type::Pointer(_)))
+    }
+
+    // The type of both `a` and `b`, if they have one
+    pub fn unify(a: &Type, b: &Type) -> Option<Type> {
+        if a.accepts(b) {
+            Some(a.clone())
+        } else if b.accepts(a) {
+            Some(b.clone())
+        } else {
+            None
+        }
+    }
+}
+
+impl fmt::Display for Type {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            Type::Int => write!(f, "int"),
+            Type::Bool => write!(f, "bool"),
+            Type::Char => write!(f, "char"),
+            Type::String => write!(f, "string"),
+            Type::Void => write!(f, "void"),
+            Type::Pointer(ref t) => write!(f, "{}*", t),
+            Type::Array(ref t) => write!(f, "{}[]", t),
+            Type::Struct(ref s) => write!(f, "struct {}", s),
+            Type::Null => write!(f, "NULL"),
+        }
+    }
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct Signature {
+    pub params: Vec<Type>,
+    pub ret: Type,
+}
+
+impl Signature {
+    // As a prototype for function `f`, `int f(int, bool)`
+    pub fn show(&self, f: &str) -> String {
+        let params: Vec<String> = self.params.iter().map(Type::to_string).collect();
+        format!("{} {}({})", self.ret, f, params.join(", "))
+    }
+}
+
+// How a type was written, when that went through a typedef, as `list[]`
+pub type Alias = Option<String>;
+
+pub fn alias(t: &Tp) -> Alias {
+    fn mentions_typedef(t: &Tp) -> bool {
+        match t.kind {
+            TpKind::Name(_) => true,
+            TpKind::Pointer(ref inner) | TpKind::Array(ref inner) => mentions_typedef(inner),
+            _ => false,
+        }
+    }
+    if mentions_typedef(t) {
+        Some(pretty::tp(t))
+    } else {
+        None
+    }
+}
+
+// A type for messages, "`list` (aka `struct list_node*`)" when it has an alias
+pub fn show(ty: &Type, alias: &Alias) -> String {
+    match *alias {
+        Some(ref alias) => format!("`{}` (aka `{}`)", alias, ty),
+        None => format!("`{}`", ty),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn showing_aliases() {
+        let list = Type::Pointer(Box::new(Type::Struct(String::from("list_node"))));
+        assert_eq!(show(&list, &None), "`struct list_node*`");
+        assert_eq!(
+            show(&Type::Array(Box::new(list)), &Some(String::from("list[]"))),
+            "`list[]` (aka `struct list_node*[]`)"
+        );
+    }
+}
@@ -0,0 +1,138 @@
+use source::source_map::{SourceMap, Span};
+use std::fmt::Write;
+
+/*
+Diagnostics are the single way every stage of the compiler reports a problem
+in the user's program. They carry the span of the offending code and any
+number of notes pointing at related code (a previous declaration, the
+definition of a type, ...).
+ */
+#[derive(Clone, Debug, PartialEq)]
+pub enum Level {
+    Error,
+    Warning,
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct Note {
+    pub span: Option<Span>,
+    pub message: String,
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct Diagnostic {
+    pub level: Level,
+    pub span: Span,
+    pub message: String,
+    pub notes: Vec<Note>,
+}
+
+impl Diagnostic {
+    pub fn error(span: Span, message: impl Into<String>) -> Diagnostic {
+        Diagnostic {
+            level: Level::Error,
+            span,
+            message: message.into(),
+            notes: Vec::new(),
+        }
+    }
+
+    pub fn warning(span: Span, message: impl Into<String>) -> Diagnostic {
+        Diagnostic {
+            level: Level::Warning,
+            span,
+            message: message.into(),
+            notes: Vec::new(),
+        }
+    }
+
+    pub fn note(mut self, span: Span, message: impl Into<String>) -> Diagnostic {
+        self.notes.push(Note {
+            span: Some(span),
+            message: message.into(),
+        });
+        self
+    }
+
+    pub fn help(mut self, message: impl Into<String>) -> Diagnostic {
+        self.notes.push(Note {
+            span: None,
+            message: message.into(),
+        });
+        self
+    }
+
+    pub fn is_error(&self) -> bool {
+        self.level == Level::Error
+    }
+
+    /*
+    Renders in the traditional compiler format followed by the offending line
+
+    file.c0:3:9: error: undeclared variable `y`
+        x = y + 1;
+            ^
+     */
+    pub fn render(&self, sources: &SourceMap) -> String {
+        let mut out = String::new();
+        let level = match self.level {
+            Level::Error => "error",
+            Level::Warning => "warning",
+        };
+        let _ = writeln!(out, "{}: {}: {}", sources.location(self.span), level, self.message);
+        out.push_str(&excerpt(sources, self.span));
+        for note in &self.notes {
+            match note.span {
+                Some(span) => {
+                    let _ = writeln!(out, "{}: note: {}", sources.location(span), note.message);
+                    out.push_str(&excerpt(sources, span));
+                }
+                None => {
+                    let _ = writeln!(out, "note: {}", note.message);
+                }
+            }
+        }
+        out
+    }
+}
+
+fn excerpt(sources: &SourceMap, span: Span) -> String {
+    let file = sources.file(span.file);
+    let (line, col) = file.position(span.lo);
+    if line > file.lines() {
+        return String::new();
+    }
+    let text = file.line(line);
+    let (end_line, end_col) = file.position(span.hi);
+    let width = if end_line == line && end_col > col {
+        end_col - col
+    } else {
+        1
+    };
+    let mut out = String::new();
+    let _ = writeln!(out, "    {}", text);
+    let indent: String = text
+        .chars()
+        .take(col - 1)
+        .map(|c| if c == '\t' { '\t' } else { ' ' })
+        .collect();
+    let _ = writeln!(out, "    {}{}", indent, "^".repeat(width));
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn rendering() {
+        let mut sources = SourceMap::new();
+        let id = sources.add("t.c0", String::from("int main() {\n  return y;\n}\n"));
+        let diagnostic = Diagnostic::error(Span::new(id, 22, 23), "undeclared variable `y`")
+            .help("declare it first");
+        assert_eq!(
+            diagnostic.render(&sources),
+            "t.c0:2:10: error: undeclared variable `y`\n      return y;\n             ^\nnote: declare it first\n"
+        );
+    }
+}
@@ -0,0 +1,2 @@
+pub mod source_map;
+pub mod diagnostic;
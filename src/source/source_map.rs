@@ -0,0 +1,145 @@
+use std::fs;
+use std::io;
+
+/*
+Every lexeme and syntax tree node remembers where it came from so that later
+passes can point back into the original source when something goes wrong.
+Offsets are byte offsets into the text of the file identified by `file`.
+ */
+pub type FileId = usize;
+
+#[derive(Copy, Clone, Debug, Default)]
+pub struct Span {
+    pub file: FileId,
+    pub lo: usize,
+    pub hi: usize,
+}
+
+impl Span {
+    pub fn new(file: FileId, lo: usize, hi: usize) -> Span {
+        Span { file, lo, hi }
+    }
+
+    // The smallest span covering both `self` and `other`
+    pub fn to(self, other: Span) -> Span {
+        Span {
+            file: self.file,
+            lo: self.lo.min(other.lo),
+            hi: self.hi.max(other.hi),
+        }
+    }
+}
+
+// Spans never take part in comparisons, two trees parsed from differently
+// formatted sources are equal if they have the same shape.
+impl PartialEq for Span {
+    fn eq(&self, _other: &Span) -> bool {
+        true
+    }
+}
+
+pub struct SourceFile {
+    pub name: String,
+    pub text: String,
+    line_starts: Vec<usize>,
+}
+
+impl SourceFile {
+    pub fn new(name: String, text: String) -> SourceFile {
+        let mut line_starts = vec![0];
+        for (i, c) in text.char_indices() {
+            if c == '\n' {
+                line_starts.push(i + 1);
+            }
+        }
+        SourceFile {
+            name,
+            text,
+            line_starts,
+        }
+    }
+
+    // 1-based line and column of a byte offset
+    pub fn position(&self, offset: usize) -> (usize, usize) {
+        let line = match self.line_starts.binary_search(&offset) {
+            Ok(line) => line,
+            Err(next) => next - 1,
+        };
+        let start = self.line_starts[line];
+        let end = offset.min(self.text.len());
+        let col = self.text[start..end].chars().count();
+        (line + 1, col + 1)
+    }
+
+    // Text of a 1-based line without its terminating newline
+    pub fn line(&self, line: usize) -> &str {
+        let start = self.line_starts[line - 1];
+        let end = match self.line_starts.get(line) {
+            Some(next) => next - 1,
+            None => self.text.len(),
+        };
+        self.text[start..end].trim_end_matches('\r')
+    }
+
+    pub fn lines(&self) -> usize {
+        self.line_starts.len()
+    }
+}
+
+#[derive(Default)]
+pub struct SourceMap {
+    files: Vec<SourceFile>,
+}
+
+impl SourceMap {
+    pub fn new() -> SourceMap {
+        SourceMap { files: Vec::new() }
+    }
+
+    pub fn add(&mut self, name: &str, text: String) -> FileId {
+        self.files.push(SourceFile::new(String::from(name), text));
+        self.files.len() - 1
+    }
+
+    pub fn load(&mut self, path: &str) -> io::Result<FileId> {
+        let text = fs::read_to_string(path)?;
+        Ok(self.add(path, text))
+    }
+
+    pub fn file(&self, id: FileId) -> &SourceFile {
+        &self.files[id]
+    }
+
+    pub fn files(&self) -> &[SourceFile] {
+        &self.files
+    }
+
+    // `file:line:col` of the start of a span
+    pub fn location(&self, span: Span) -> String {
+        let file = self.file(span.file);
+        let (line, col) = file.position(span.lo);
+        format!("{}:{}:{}", file.name, line, col)
+    }
+
+    pub fn snippet(&self, span: Span) -> &str {
+        let file = self.file(span.file);
+        &file.text[span.lo.min(file.text.len())..span.hi.min(file.text.len())]
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn positions() {
+        let mut sources = SourceMap::new();
+        let id = sources.add("a.c0", String::from("int x;\n  x = 1;\n"));
+        let file = sources.file(id);
+        assert_eq!(file.position(0), (1, 1));
+        assert_eq!(file.position(9), (2, 3));
+        assert_eq!(file.line(2), "  x = 1;");
+        assert_eq!(sources.location(Span::new(id, 9, 10)), "a.c0:2:3");
+        assert_eq!(sources.snippet(Span::new(id, 4, 5)), "x");
+    }
+}